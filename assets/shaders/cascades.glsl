@@ -0,0 +1,38 @@
+// Cascaded shadow map lookups, the uniforms come from `shadows::Cascades`. `#include "shadows.glsl"` first,
+// and define MAX_CASCADES (the preprocessor in stage18.rs does).
+
+uniform sampler2DArrayShadow shadow_maps;
+uniform mat4 cascade_matrix[MAX_CASCADES]; // world space -> each cascade's light clip space
+uniform float cascade_far[MAX_CASCADES];   // view space depth each cascade reaches to
+uniform int cascade_count;
+uniform float cascade_blend;               // part of a cascade, at its far end, that fades into the next
+
+float cascade_lookup(vec3 world_position, int cascade, int radius) {
+    return shadow_pcf_layer(shadow_maps, cascade_matrix[cascade] * vec4(world_position, 1.0), float(cascade), radius);
+}
+
+// 1.0 lit, 0.0 in shadow, like `shadow_pcf`. `depth` is the fragment's view space z, `cascade` says which
+// cascade it came from (cascade_count past the last one, where everything is lit).
+float cascaded_shadow(vec3 world_position, float depth, int radius, out int cascade) {
+    cascade = cascade_count;
+    for (int i = 0; i < cascade_count; i++) {
+        if (depth < cascade_far[i]) {
+            cascade = i;
+            break;
+        }
+    }
+    if (cascade == cascade_count) {
+        return 1.0;
+    }
+
+    float lit = cascade_lookup(world_position, cascade, radius);
+
+    // near the end of a cascade, fade over to the next one so the seam between them doesn't show.
+    float near = cascade == 0 ? 0.0 : cascade_far[cascade - 1];
+    float blend_start = cascade_far[cascade] - cascade_blend * (cascade_far[cascade] - near);
+    if (cascade + 1 < cascade_count && depth > blend_start) {
+        float t = (depth - blend_start) / (cascade_far[cascade] - blend_start);
+        lit = mix(lit, cascade_lookup(world_position, cascade + 1, radius), t);
+    }
+    return lit;
+}
@@ -0,0 +1,43 @@
+// Clustered lighting, the buffers and grid come from `clusters::ClusterBuffers`. `#include "lighting.glsl"`
+// first. Everything is in view space, like lights.glsl, and `screen_size` has to be set for the tiles.
+
+uniform samplerBuffer cluster_lights;   // two texels per light: xyz position, w range, then rgb color * intensity
+uniform usamplerBuffer cluster_cells;   // per cluster: x where its lights start in cluster_indices, y how many
+uniform usamplerBuffer cluster_indices; // lights, by their place in cluster_lights
+uniform ivec2 cluster_tiles;
+uniform int cluster_slices;
+uniform float cluster_near;
+uniform float cluster_far;
+uniform vec2 screen_size;
+
+// the same as `ClusterGrid::index` and `ClusterGrid::slice`.
+int cluster_index(vec2 frag_coord, float depth) {
+    ivec2 tile = clamp(ivec2(frag_coord / screen_size * vec2(cluster_tiles)), ivec2(0), cluster_tiles - 1);
+    float t = log(max(depth, cluster_near) / cluster_near) / log(cluster_far / cluster_near);
+    int slice = min(int(t * float(cluster_slices)), cluster_slices - 1);
+    return tile.x + cluster_tiles.x * (tile.y + cluster_tiles.y * slice);
+}
+
+// how many lights this fragment's cluster has.
+uint cluster_light_count(vec3 position) {
+    return texelFetch(cluster_cells, cluster_index(gl_FragCoord.xy, position.z)).y;
+}
+
+// diffuse and specular of every light in this fragment's cluster, like `shade_lights`.
+void shade_clustered(vec3 normal, vec3 position, out vec3 diffuse, out vec3 specular) {
+    diffuse = vec3(0.0);
+    specular = vec3(0.0);
+
+    uvec2 cell = texelFetch(cluster_cells, cluster_index(gl_FragCoord.xy, position.z)).xy;
+    for (uint i = 0u; i < cell.y; i++) {
+        int light = int(texelFetch(cluster_indices, int(cell.x + i)).r);
+        vec4 position_range = texelFetch(cluster_lights, 2 * light);
+        vec3 light_color = texelFetch(cluster_lights, 2 * light + 1).rgb;
+
+        vec3 to_light = position_range.xyz - position;
+        float distance = length(to_light);
+        vec3 color = light_color * range_attenuation(distance, position_range.w);
+        diffuse += lambert(normal, to_light) * color;
+        specular += blinn_phong_specular(normal, to_light, position) * color;
+    }
+}
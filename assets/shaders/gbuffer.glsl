@@ -0,0 +1,34 @@
+// Reading the G-buffer back, for the passes after the geometry one. Everything is in view space.
+
+uniform sampler2D albedo_tex;   // rgb: base color
+uniform sampler2D normal_tex;   // xyz: normal
+uniform sampler2D material_tex; // r: specular intensity, g: specular exponent / 256
+uniform sampler2D depth_tex;
+uniform mat4 inverse_perspective;
+
+// nothing was drawn there, it's still the cleared depth.
+bool is_background(vec2 uv) {
+    return texture(depth_tex, uv).r == 1.0;
+}
+
+// where the fragment at `uv` (0 to 1 across the screen) is, undoing the projection on its depth.
+vec3 gbuffer_position(vec2 uv) {
+    float depth = texture(depth_tex, uv).r;
+    vec4 ndc = vec4(uv * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+    vec4 position = inverse_perspective * ndc;
+    return position.xyz / position.w;
+}
+
+vec3 gbuffer_normal(vec2 uv) {
+    return texture(normal_tex, uv).xyz;
+}
+
+vec3 gbuffer_albedo(vec2 uv) {
+    return texture(albedo_tex, uv).rgb;
+}
+
+// x: specular intensity, y: specular exponent
+vec2 gbuffer_material(vec2 uv) {
+    vec2 material = texture(material_tex, uv).rg;
+    return vec2(material.r, material.g * 256.0);
+}
@@ -0,0 +1,28 @@
+// Image based lighting out of `ibl::Environment`'s maps. `#include "pbr.glsl"` first. The maps are in
+// world space, so the directions handed in have to be too.
+
+uniform samplerCube irradiance_map;
+uniform samplerCube prefiltered_map;
+uniform sampler2D brdf_lut;
+uniform float prefiltered_levels;
+
+// fresnel_schlick, but rough surfaces don't get the bright rim: it's averaged away over all their microfacets.
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness) {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// the light from everywhere around, diffuse out of the irradiance map and specular out of the prefiltered
+// one and the BRDF table.
+vec3 ambient_ibl(vec3 normal, vec3 to_camera, vec3 albedo, float metallic, float roughness) {
+    float n_dot_v = max(dot(normal, to_camera), 0.0);
+    vec3 fresnel = fresnel_schlick_roughness(n_dot_v, base_reflectivity(albedo, metallic), roughness);
+
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo * texture(irradiance_map, normal).rgb;
+
+    vec3 reflected = reflect(-to_camera, normal);
+    vec3 prefiltered = textureLod(prefiltered_map, reflected, roughness * (prefiltered_levels - 1.0)).rgb;
+    vec2 brdf = texture(brdf_lut, vec2(n_dot_v, roughness)).rg;
+    vec3 specular = prefiltered * (fresnel * brdf.x + brdf.y);
+
+    return diffuse + specular;
+}
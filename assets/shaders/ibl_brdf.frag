@@ -0,0 +1,43 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+#include "pbr.glsl"
+#include "ibl_sampling.glsl"
+
+const uint SAMPLE_COUNT = 1024u;
+
+// Smith's geometry term with the remapping for image based lighting, not the one for direct lights.
+float geometry_ibl(float n_dot_v, float n_dot_l, float roughness) {
+    float k = roughness * roughness / 2.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+// the other half of the split sum: how much of the prefiltered environment a surface reflects, as a scale
+// (r) and a bias (g) on its base reflectivity. x is the cosine of the viewing angle, y the roughness.
+void main() {
+    float n_dot_v = max(v_uv.x, 0.001);
+    float roughness = v_uv.y;
+    vec3 normal = vec3(0.0, 0.0, 1.0);
+    vec3 to_camera = vec3(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    float scale = 0.0;
+    float bias = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 half_direction = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+        vec3 to_light = normalize(2.0 * dot(to_camera, half_direction) * half_direction - to_camera);
+        float n_dot_l = max(to_light.z, 0.0);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+
+        float n_dot_h = max(half_direction.z, 0.0);
+        float v_dot_h = max(dot(to_camera, half_direction), 0.0);
+        float visibility = geometry_ibl(n_dot_v, n_dot_l, roughness) * v_dot_h / (n_dot_h * n_dot_v);
+        float fresnel = pow(1.0 - v_dot_h, 5.0);
+        scale += (1.0 - fresnel) * visibility;
+        bias += fresnel * visibility;
+    }
+    color = vec4(scale / float(SAMPLE_COUNT), bias / float(SAMPLE_COUNT), 0.0, 1.0);
+}
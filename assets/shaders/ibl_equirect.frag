@@ -0,0 +1,14 @@
+#version 140
+
+in vec3 v_direction;
+out vec4 color;
+
+uniform sampler2D equirect;
+
+#include "pbr.glsl"
+#include "ibl_sampling.glsl"
+
+// the equirectangular image wrapped onto a cube face.
+void main() {
+    color = vec4(texture(equirect, equirect_uv(normalize(v_direction))).rgb, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 140
+
+// the fullscreen quad, drawn once per cube face.
+in vec2 position;
+out vec3 v_direction;
+
+// columns: where the face's s and t go, and which way it faces, see `ibl::FACES`.
+uniform mat3 face;
+
+void main() {
+    v_direction = face * vec3(position, 1.0);
+    gl_Position = vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,31 @@
+#version 140
+
+in vec3 v_direction;
+out vec4 color;
+
+uniform samplerCube environment_map;
+uniform float sample_delta; // radians between samples, both around and up
+uniform float sample_level; // environment mip the samples come from, blurry enough to not miss anything in between
+
+#include "pbr.glsl"
+
+// all the light arriving at a surface facing `v_direction`, weighted by the cosine: what a perfectly diffuse
+// surface facing that way reflects (times its albedo).
+void main() {
+    vec3 normal = normalize(v_direction);
+    vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 right = normalize(cross(up, normal));
+    up = cross(normal, right);
+
+    vec3 sum = vec3(0.0);
+    float count = 0.0;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += sample_delta) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += sample_delta) {
+            vec3 direction = sin(theta) * cos(phi) * right + sin(theta) * sin(phi) * up + cos(theta) * normal;
+            // sin(theta): the rings near the top are smaller, and get as many samples as the big ones.
+            sum += textureLod(environment_map, direction, sample_level).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    color = vec4(PI * sum / count, 1.0);
+}
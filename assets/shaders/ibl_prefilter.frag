@@ -0,0 +1,45 @@
+#version 140
+
+in vec3 v_direction;
+out vec4 color;
+
+uniform samplerCube environment_map;
+uniform float environment_size; // width of the environment's faces at mip 0
+uniform float roughness;
+
+#include "pbr.glsl"
+#include "ibl_sampling.glsl"
+
+const uint SAMPLE_COUNT = 1024u;
+
+// the environment as a surface of `roughness` reflects it, seen head on (the split sum approximation: the
+// viewing angle goes into the BRDF lookup table instead).
+void main() {
+    vec3 normal = normalize(v_direction);
+    vec3 to_camera = normal;
+
+    vec3 sum = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+        vec3 half_direction = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+        vec3 to_light = normalize(2.0 * dot(to_camera, half_direction) * half_direction - to_camera);
+        float n_dot_l = dot(normal, to_light);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+
+        // where the samples are sparse each one stands for a bigger patch of sky, so it reads a blurrier
+        // mip. Otherwise a small bright sun turns into a scatter of dots.
+        float level = 0.0;
+        if (roughness > 0.0) {
+            float n_dot_h = max(dot(normal, half_direction), 0.0);
+            float pdf = distribution_ggx(normal, half_direction, roughness) * n_dot_h / (4.0 * max(dot(half_direction, to_camera), 0.0001)) + 0.0001;
+            float texel_angle = 4.0 * PI / (6.0 * environment_size * environment_size);
+            float sample_angle = 1.0 / (float(SAMPLE_COUNT) * pdf + 0.0001);
+            level = max(0.5 * log2(sample_angle / texel_angle), 0.0);
+        }
+        sum += textureLod(environment_map, to_light, level).rgb * n_dot_l;
+        weight += n_dot_l;
+    }
+    color = vec4(sum / max(weight, 0.0001), 1.0);
+}
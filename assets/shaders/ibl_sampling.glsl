@@ -0,0 +1,35 @@
+// What the passes making the image based lighting maps share, see ibl.rs. `#include "pbr.glsl"` first.
+
+// where `direction` ends up on an equirectangular image, the top row is straight up and the middle is +z.
+vec2 equirect_uv(vec3 direction) {
+    return vec2(atan(direction.x, direction.z) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+}
+
+// the bits of `i` mirrored around the binary point: 1 -> 0.5, 2 -> 0.25, 3 -> 0.75, ...
+float radical_inverse(uint bits) {
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return float(bits) * 2.3283064365386963e-10;
+}
+
+// sample `i` of `count`, spread evenly over the unit square without any randomness.
+vec2 hammersley(uint i, uint count) {
+    return vec2(float(i) / float(count), radical_inverse(i));
+}
+
+// a half vector around `normal`, as likely as the GGX distribution says it is, so the samples go where the
+// highlight is instead of all over the hemisphere.
+vec3 importance_sample_ggx(vec2 xi, vec3 normal, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2.0 * PI * xi.x;
+    float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+
+    vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, normal));
+    vec3 bitangent = cross(normal, tangent);
+    return normalize(tangent * cos(phi) * sin_theta + bitangent * sin(phi) * sin_theta + normal * cos_theta);
+}
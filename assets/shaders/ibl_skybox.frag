@@ -0,0 +1,31 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+uniform samplerCube environment_map;
+uniform samplerCube irradiance_map;
+uniform samplerCube prefiltered_map;
+uniform int background;          // 0: the environment, 1: its irradiance, 2: prefiltered at `background_level`
+uniform float background_level;
+uniform float environment_intensity;
+uniform mat4 inverse_perspective;
+uniform mat4 view;
+
+#include "pbr.glsl"
+
+// the environment behind everything: which way each pixel looks, in world space.
+void main() {
+    vec4 target = inverse_perspective * vec4(v_uv * 2.0 - 1.0, 1.0, 1.0);
+    vec3 direction = transpose(mat3(view)) * normalize(target.xyz / target.w);
+
+    vec3 sky;
+    if (background == 1) {
+        sky = texture(irradiance_map, direction).rgb;
+    } else if (background == 2) {
+        sky = textureLod(prefiltered_map, direction, background_level).rgb;
+    } else {
+        sky = textureLod(environment_map, direction, 0.0).rgb;
+    }
+    color = vec4(tone_map(sky * environment_intensity), 1.0);
+}
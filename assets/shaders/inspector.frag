@@ -0,0 +1,13 @@
+#version 140
+
+in vec2 v_tex_coord;
+in vec4 v_rgba;
+
+out vec4 color;
+
+uniform sampler2D u_sampler;
+
+void main() {
+    // everything stays in gamma space, that's what egui's colors and blending are tuned for.
+    color = v_rgba * texture(u_sampler, v_tex_coord);
+}
@@ -0,0 +1,22 @@
+#version 140
+
+// egui meshes: positions in points, top left origin.
+uniform vec2 u_screen_size;
+
+in vec2 a_pos;
+in vec2 a_tex_coord;
+in vec4 a_srgba; // premultiplied, gamma space
+
+out vec2 v_tex_coord;
+out vec4 v_rgba;
+
+void main() {
+    gl_Position = vec4(
+        2.0 * a_pos.x / u_screen_size.x - 1.0,
+        1.0 - 2.0 * a_pos.y / u_screen_size.y,
+        0.0,
+        1.0
+    );
+    v_tex_coord = a_tex_coord;
+    v_rgba = a_srgba;
+}
@@ -0,0 +1,30 @@
+// Blinn-Phong pieces shared by the lit stages. `#include "lighting.glsl"` after the uniforms.
+
+#ifndef SPECULAR_EXPONENT
+#define SPECULAR_EXPONENT 16.0 // determines the "dropoff rate" for specular reflection.
+#endif
+
+// dot = cosine
+float lambert(vec3 normal, vec3 light_dir) {
+    return max(dot(normalize(normal), normalize(light_dir)), 0.0);
+}
+
+// `position` has to be in a space where the camera sits at the origin (view space), the camera direction is
+// derived from it. `normal` and `light_dir` have to be in the same space.
+float blinn_phong_specular(vec3 normal, vec3 light_dir, vec3 position) {
+    vec3 camera_dir = normalize(-position);
+    vec3 half_direction = normalize(normalize(light_dir) + camera_dir); // relationship between lightsource and camera angle for the object/fragment
+
+    return pow(max(dot(half_direction, normalize(normal)), 0.0), SPECULAR_EXPONENT);
+}
+
+// inverse square falloff, windowed so it actually reaches 0 at `range` instead of just getting small.
+float range_attenuation(float distance, float range) {
+    float window = clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// 1 inside the inner cone, 0 outside the outer one, smooth in between.
+float cone_falloff(vec3 to_light, vec3 spot_direction, vec2 cone) {
+    return smoothstep(cone.y, cone.x, dot(-to_light, normalize(spot_direction)));
+}
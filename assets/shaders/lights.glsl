@@ -0,0 +1,43 @@
+// Directional, point and spot lights out of the `Lights` block, see lights.rs for what goes where.
+// `#include "lighting.glsl"` first, then this. Needs MAX_LIGHTS and the LIGHT_* kinds defined,
+// `lights::preprocessor()` does that. Everything is in view space, so the camera sits at the origin.
+
+layout(std140) uniform Lights {
+    vec4 light_position[MAX_LIGHTS];  // xyz: position, w: kind
+    vec4 light_direction[MAX_LIGHTS]; // xyz: direction the light shines in, w: range
+    vec4 light_color[MAX_LIGHTS];     // rgb: color * intensity
+    vec4 light_cone[MAX_LIGHTS];      // x: cos(inner angle), y: cos(outer angle)
+    int light_count;
+};
+
+// what light `i` brings to `position`: its color, already faded by distance and cone, and which way it comes
+// from (normalized, towards the light).
+vec3 light_radiance(int i, vec3 position, out vec3 to_light) {
+    float kind = light_position[i].w;
+    if (kind == LIGHT_DIRECTIONAL) {
+        to_light = -normalize(light_direction[i].xyz);
+        return light_color[i].rgb;
+    }
+
+    vec3 offset = light_position[i].xyz - position;
+    float distance = length(offset);
+    to_light = offset / distance;
+    float attenuation = range_attenuation(distance, light_direction[i].w);
+    if (kind == LIGHT_SPOT) {
+        attenuation *= cone_falloff(to_light, light_direction[i].xyz, light_cone[i].xy);
+    }
+    return light_color[i].rgb * attenuation;
+}
+
+// diffuse and specular of every light added up, both already tinted by the light's color.
+void shade_lights(vec3 normal, vec3 position, out vec3 diffuse, out vec3 specular) {
+    diffuse = vec3(0.0);
+    specular = vec3(0.0);
+
+    for (int i = 0; i < light_count; i++) {
+        vec3 to_light;
+        vec3 color = light_radiance(i, position, to_light);
+        diffuse += lambert(normal, to_light) * color;
+        specular += blinn_phong_specular(normal, to_light, position) * color;
+    }
+}
@@ -0,0 +1,56 @@
+// Cook-Torrance, the metallic-roughness way: a GGX normal distribution, Smith's geometry term with
+// Schlick-GGX for each direction and Schlick's Fresnel. All the directions are normalized and in the
+// same space, `to_camera` and `to_light` pointing away from the surface.
+
+const float PI = 3.14159265;
+
+// how many microfacets are turned just right to reflect `to_light` into the camera, `roughness` 0 is a mirror.
+float distribution_ggx(vec3 normal, vec3 half_direction, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float n_dot_h = max(dot(normal, half_direction), 0.0);
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// how much of the surface isn't hidden by its own microfacets, looking from one direction.
+float geometry_schlick_ggx(float n_dot_v, float roughness) {
+    float r = roughness + 1.0;
+    float k = r * r / 8.0; // the remapping for direct lights
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// ...from both the camera's and the light's direction.
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+// how much gets reflected instead of going in, more at grazing angles. `f0` is head on.
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// dielectrics all reflect about 4% head on, metals reflect their own color and have no diffuse at all.
+vec3 base_reflectivity(vec3 albedo, float metallic) {
+    return mix(vec3(0.04), albedo, metallic);
+}
+
+// the light reflected towards the camera per unit of light arriving from `to_light`, times the cosine.
+vec3 cook_torrance(vec3 normal, vec3 to_camera, vec3 to_light, vec3 albedo, float metallic, float roughness) {
+    vec3 half_direction = normalize(to_camera + to_light);
+    float n_dot_v = max(dot(normal, to_camera), 0.0001);
+    float n_dot_l = max(dot(normal, to_light), 0.0);
+
+    vec3 fresnel = fresnel_schlick(max(dot(half_direction, to_camera), 0.0), base_reflectivity(albedo, metallic));
+    vec3 specular = distribution_ggx(normal, half_direction, roughness) * geometry_smith(n_dot_v, n_dot_l, roughness) * fresnel
+        / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+
+    // whatever isn't reflected goes in and comes back out diffuse, unless it's a metal.
+    vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / PI;
+    return (diffuse + specular) * n_dot_l;
+}
+
+// HDR to something a screen can show, Reinhard.
+vec3 tone_map(vec3 color) {
+    return color / (color + 1.0);
+}
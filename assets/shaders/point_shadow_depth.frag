@@ -0,0 +1,12 @@
+#version 140
+
+in vec3 v_world_position;
+
+uniform vec3 light_position;
+uniform float far;
+
+// the distance to the light instead of the projection's depth: the same in every direction, and linear,
+// so comparing it is simple.
+void main() {
+    gl_FragDepth = length(v_world_position - light_position) / far;
+}
@@ -0,0 +1,15 @@
+#version 150
+
+in vec3 position;
+
+out vec3 v_world_position;
+
+// one cube face's view and projection, see PointShadowMap::face_matrices.
+uniform mat4 face_matrix;
+uniform mat4 model;
+
+void main() {
+    vec4 world_position = model * vec4(position, 1.0);
+    v_world_position = world_position.xyz;
+    gl_Position = face_matrix * world_position;
+}
@@ -0,0 +1,33 @@
+#version 140
+
+// What every shadertoy pass gets wrapped in. The pass itself comes in through the include, so compile log
+// line numbers still point into the pass' file. SHADERTOY_IMAGE is defined for the image pass.
+
+uniform vec3 iResolution;
+uniform float iTime;
+uniform float iTimeDelta;
+uniform float iFrameRate;
+uniform int iFrame;
+uniform vec4 iMouse;
+uniform vec4 iDate;
+uniform float iSampleRate;
+uniform vec3 iChannelResolution[4];
+uniform float iChannelTime[4];
+uniform sampler2D iChannel0;
+uniform sampler2D iChannel1;
+uniform sampler2D iChannel2;
+uniform sampler2D iChannel3;
+
+out vec4 shadertoy_color;
+
+#include "shadertoy_pass"
+
+void main() {
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    mainImage(color, gl_FragCoord.xy);
+#ifdef SHADERTOY_IMAGE
+    shadertoy_color = vec4(color.rgb, 1.0);
+#else
+    shadertoy_color = color;
+#endif
+}
@@ -0,0 +1,17 @@
+// Single pass example: cargo run -- shader assets/shaders/shadertoy/plasma.frag
+// iChannel0: hamster.jpg
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+
+    float v = sin(uv.x * 10.0 + iTime)
+            + sin((uv.y * 10.0 + iTime) * 0.5)
+            + sin(length(uv - 0.5) * 20.0 - iTime * 2.0);
+    vec3 plasma = 0.5 + 0.5 * cos(v + vec3(0.0, 2.1, 4.2));
+
+    // the hamster peeks through where the mouse is (or was last clicked).
+    float hole = smoothstep(0.15, 0.1, length((fragCoord - iMouse.xy) / iResolution.y));
+    vec3 hamster = texture(iChannel0, uv).rgb;
+
+    fragColor = vec4(mix(plasma, hamster, hole), 1.0);
+}
@@ -0,0 +1,9 @@
+// Image pass of the multipass example, colors whatever buffer A accumulated.
+// iChannel0: buffer-a
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+    float trail = texture(iChannel0, uv).r;
+    vec3 color = mix(vec3(0.06, 0.075, 0.95) * 0.2, vec3(1.0, 0.5, 0.1), trail);
+    fragColor = vec4(color, 1.0);
+}
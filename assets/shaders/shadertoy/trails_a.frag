@@ -0,0 +1,13 @@
+// Buffer A of the multipass example, draws a moving dot on top of its own faded last frame.
+// cargo run -- shader assets/shaders/shadertoy/trails.frag --buffer-a assets/shaders/shadertoy/trails_a.frag
+// iChannel0: buffer-a
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+    vec4 previous = texture(iChannel0, uv);
+
+    vec2 dot_position = iResolution.xy * (0.5 + 0.35 * vec2(cos(iTime * 1.3), sin(iTime * 2.1)));
+    float dot_mask = smoothstep(12.0, 8.0, length(fragCoord - dot_position));
+
+    fragColor = max(previous * 0.985, vec4(dot_mask));
+}
@@ -0,0 +1,11 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+// the shadow map as plain depth: near the light is dark, far away (and nothing at all) is white.
+uniform sampler2D shadow_map;
+
+void main() {
+    color = vec4(vec3(texture(shadow_map, v_uv).r), 1.0);
+}
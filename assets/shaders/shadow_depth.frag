@@ -0,0 +1,5 @@
+#version 140
+
+// nothing to do, the depth gets written on its own.
+void main() {
+}
@@ -0,0 +1,11 @@
+#version 150
+
+in vec3 position;
+
+// the light's projection * view, see shadows.rs
+uniform mat4 light_space;
+uniform mat4 model;
+
+void main() {
+    gl_Position = light_space * model * vec4(position, 1.0);
+}
@@ -0,0 +1,66 @@
+// Shadow map lookups. `#include "shadows.glsl"` after the uniforms, `map` is a ShadowMap::sampled().
+
+// 1.0 where `light_space_position` is lit, 0.0 where it's in shadow. PCF: averages the comparisons of a
+// (2 * radius + 1)^2 block of texels around it, each of which the sampler already blends 2x2, so the
+// edges come out soft instead of blocky.
+float shadow_pcf(sampler2DShadow map, vec4 light_space_position, int radius) {
+    vec3 coords = light_space_position.xyz / light_space_position.w * 0.5 + 0.5;
+    // behind the light's far plane, nothing there cast a shadow into the map.
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(map, 0));
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            lit += texture(map, vec3(coords.xy + vec2(x, y) * texel, coords.z));
+        }
+    }
+    float samples = float((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// `shadow_pcf` for one layer of a texture array of shadow maps (a CascadedShadowMap::sampled()).
+float shadow_pcf_layer(sampler2DArrayShadow maps, vec4 light_space_position, float layer, int radius) {
+    vec3 coords = light_space_position.xyz / light_space_position.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(maps, 0).xy);
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            lit += texture(maps, vec4(coords.xy + vec2(x, y) * texel, layer, coords.z));
+        }
+    }
+    float samples = float((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// Offsets for `point_shadow`, spread out so that a few of them cover the disk around the lookup direction.
+const vec3 POINT_SHADOW_OFFSETS[20] = vec3[20](
+    vec3( 1,  1,  1), vec3( 1, -1,  1), vec3(-1, -1,  1), vec3(-1,  1,  1),
+    vec3( 1,  1, -1), vec3( 1, -1, -1), vec3(-1, -1, -1), vec3(-1,  1, -1),
+    vec3( 1,  1,  0), vec3( 1, -1,  0), vec3(-1, -1,  0), vec3(-1,  1,  0),
+    vec3( 1,  0,  1), vec3(-1,  0,  1), vec3( 1,  0, -1), vec3(-1,  0, -1),
+    vec3( 0,  1,  1), vec3( 0, -1,  1), vec3( 0, -1, -1), vec3( 0,  1, -1)
+);
+
+// 1.0 lit, 0.0 in shadow, for a point light's PointShadowMap::sampled(). `light_to_fragment` is the
+// fragment's position minus the light's. The map holds distances divided by `far`. `softness` is how
+// wide (in world units) the lookups spread around the direction, `bias` is subtracted from the distance.
+float point_shadow(samplerCube map, vec3 light_to_fragment, float far, float bias, float softness) {
+    float distance = length(light_to_fragment);
+    if (distance > far) {
+        return 1.0;
+    }
+
+    float lit = 0.0;
+    for (int i = 0; i < 20; i++) {
+        float closest = texture(map, light_to_fragment + POINT_SHADOW_OFFSETS[i] * softness).r * far;
+        lit += distance - bias > closest ? 0.0 : 1.0;
+    }
+    return lit / 20.0;
+}
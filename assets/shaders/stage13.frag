@@ -0,0 +1,32 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_position;
+
+out vec4 color;
+
+uniform vec3 u_light;
+uniform mat4 view;
+uniform bool view_space_lighting;
+
+uniform vec3 ambient_color;
+uniform vec3 diffuse_color;
+uniform vec3 specular_color;
+uniform float specular_exponent;
+
+//old values
+const vec3 dark_color = vec3(0.555, 0.007, 0.075);
+const vec3 regular_color = vec3(1.0, 0.09, 0.045);
+
+#define SPECULAR_EXPONENT specular_exponent
+#include "lighting.glsl"
+
+void main() {
+    // u_light is a world space direction, the normal and position are in view space.
+    vec3 light_dir = view_space_lighting ? mat3(view) * u_light : u_light;
+    float diffuse = lambert(v_normal, light_dir);
+    float specular = blinn_phong_specular(v_normal, light_dir, v_position);
+    
+    // things are auto-clamped to 1.0
+    color = vec4(ambient_color + diffuse * diffuse_color + specular * specular_color, 1.0);
+}
@@ -0,0 +1,32 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_position;
+in vec2 v_tex_coords;
+
+out vec4 color;
+
+uniform vec3 u_light;
+uniform mat4 view;
+uniform bool view_space_lighting;
+uniform sampler2D diffuse_tex;
+uniform sampler2D normal_tex;
+
+uniform vec3 specular_color;
+
+#include "lighting.glsl"
+#include "normal_mapping.glsl"
+
+void main() {
+    vec3 normal_map = texture(normal_tex, v_tex_coords).rgb;
+    mat3 tbn = cotangent_frame(v_normal, v_position, v_tex_coords);
+    vec3 real_normal = normalize(tbn * (normal_map * 2.0 - 1.0));
+    // u_light is a world space direction, the normal and position are in view space.
+    vec3 light_dir = view_space_lighting ? mat3(view) * u_light : u_light;
+    float diffuse = lambert(real_normal, light_dir);
+    float specular = blinn_phong_specular(real_normal, light_dir, v_position);
+    
+    vec3 diffuse_color = texture(diffuse_tex, v_tex_coords).rgb;
+    vec3 ambient_color = diffuse_color * 0.1;
+    color = vec4(ambient_color + diffuse * diffuse_color + specular * specular_color, 1.0);
+}
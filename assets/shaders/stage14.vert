@@ -0,0 +1,25 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+in vec2 tex_coords;
+
+out vec3 v_normal;
+out vec3 v_position;
+out vec2 v_tex_coords;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+// false gives the old behavior for comparison: NDC positions, which aren't a space the light or the normals are in.
+uniform bool view_space_lighting;
+
+void main() {
+    mat4 modelview = view * model;
+    v_normal = transpose(inverse(mat3(modelview))) * normal;
+    vec4 position_view = modelview * vec4(position, 1.0);
+    gl_Position = perspective * position_view;
+    // the camera sits at the origin of view space, which is what blinn_phong_specular assumes.
+    v_position = view_space_lighting ? position_view.xyz : gl_Position.xyz / gl_Position.w;
+    v_tex_coords = tex_coords;
+}
@@ -0,0 +1,12 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+uniform sampler2D accumulation;
+
+void main() {
+    vec3 hdr = texture(accumulation, v_uv).rgb;
+    vec3 mapped = hdr / (hdr + vec3(1.0)); // Reinhard
+    color = vec4(pow(mapped, vec3(1.0 / 2.2)), 1.0);
+}
@@ -0,0 +1,180 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+uniform sampler2D accumulation;     // the running average so far
+uniform sampler2D teapot_triangles; // 3 texels (vertex positions) per row, one row per triangle
+uniform int triangle_count;
+uniform vec3 teapot_min;
+uniform vec3 teapot_max;
+
+uniform vec3 camera_position;
+uniform vec3 camera_right;
+uniform vec3 camera_up;
+uniform vec3 camera_forward;
+uniform float fov_scale;
+uniform vec2 resolution;
+uniform int sample_index; // how many samples are already in the accumulation texture
+
+// MAX_BOUNCES, EPSILON and FAR are #defined from the constants in stage15.rs, the CPU tracer uses those too.
+
+struct Material {
+    vec3 albedo;
+    vec3 emission;
+    float roughness;
+    float glossiness;
+};
+
+struct Hit {
+    float t;
+    vec3 normal;
+    int material;
+};
+
+// PCG hash, cheap and good enough to not show patterns after a few hundred samples.
+uint rng_state;
+uint pcg_hash(uint v) {
+    uint state = v * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+float rand() {
+    rng_state = pcg_hash(rng_state);
+    // the top 24 bits, so it never rounds up to 1.
+    return float(rng_state >> 8u) / 16777216.0;
+}
+
+vec3 random_unit_vector() {
+    float z = rand() * 2.0 - 1.0;
+    float a = rand() * 6.2831853;
+    float r = sqrt(max(1.0 - z * z, 0.0));
+    return vec3(r * cos(a), r * sin(a), z);
+}
+
+void hit_sphere(vec3 ro, vec3 rd, vec3 center, float radius, int material, inout Hit hit) {
+    vec3 oc = ro - center;
+    float b = dot(oc, rd);
+    float c = dot(oc, oc) - radius * radius;
+    float h = b * b - c;
+    if (h < 0.0) return;
+    h = sqrt(h);
+    float t = -b - h;
+    if (t < EPSILON) t = -b + h; // we're inside the sphere
+    if (t > EPSILON && t < hit.t) {
+        hit.t = t;
+        hit.normal = (ro + rd * t - center) / radius;
+        hit.material = material;
+    }
+}
+
+void hit_plane(vec3 ro, vec3 rd, float height, int material, inout Hit hit) {
+    if (abs(rd.y) < 1e-6) return;
+    float t = (height - ro.y) / rd.y;
+    if (t > EPSILON && t < hit.t) {
+        hit.t = t;
+        hit.normal = vec3(0.0, 1.0, 0.0);
+        hit.material = material;
+    }
+}
+
+bool hit_box(vec3 ro, vec3 rd, vec3 box_min, vec3 box_max, float t_max) {
+    vec3 inv = 1.0 / rd;
+    vec3 t0 = (box_min - ro) * inv;
+    vec3 t1 = (box_max - ro) * inv;
+    vec3 t_near = min(t0, t1);
+    vec3 t_far = max(t0, t1);
+    float near = max(max(t_near.x, t_near.y), t_near.z);
+    float far = min(min(t_far.x, t_far.y), t_far.z);
+    return far >= max(near, 0.0) && near < t_max;
+}
+
+// Brute force over every triangle, the bounding box keeps it bearable.
+void hit_teapot(vec3 ro, vec3 rd, int material, inout Hit hit) {
+    if (!hit_box(ro, rd, teapot_min, teapot_max, hit.t)) return;
+
+    for (int i = 0; i < triangle_count; i++) {
+        vec3 v0 = texelFetch(teapot_triangles, ivec2(0, i), 0).xyz;
+        vec3 v1 = texelFetch(teapot_triangles, ivec2(1, i), 0).xyz;
+        vec3 v2 = texelFetch(teapot_triangles, ivec2(2, i), 0).xyz;
+
+        // Moller-Trumbore
+        vec3 e1 = v1 - v0;
+        vec3 e2 = v2 - v0;
+        vec3 p = cross(rd, e2);
+        float det = dot(e1, p);
+        if (abs(det) < 1e-9) continue;
+        float inv_det = 1.0 / det;
+        vec3 s = ro - v0;
+        float u = dot(s, p) * inv_det;
+        if (u < 0.0 || u > 1.0) continue;
+        vec3 q = cross(s, e1);
+        float v = dot(rd, q) * inv_det;
+        if (v < 0.0 || u + v > 1.0) continue;
+        float t = dot(e2, q) * inv_det;
+        if (t > EPSILON && t < hit.t) {
+            hit.t = t;
+            hit.normal = normalize(cross(e1, e2));
+            hit.material = material;
+        }
+    }
+}
+
+vec3 sky(vec3 rd) {
+    return mix(vec3(0.01, 0.01, 0.015), vec3(0.03, 0.04, 0.1), clamp(rd.y * 0.5 + 0.5, 0.0, 1.0));
+}
+
+#include "scene.glsl" // generated by scene_glsl()
+
+vec3 trace(vec3 ro, vec3 rd) {
+    vec3 radiance = vec3(0.0);
+    vec3 throughput = vec3(1.0);
+
+    for (int bounce = 0; bounce < MAX_BOUNCES; bounce++) {
+        Hit hit = Hit(FAR, vec3(0.0), -1);
+        scene_hits(ro, rd, hit);
+
+        if (hit.material < 0) {
+            radiance += throughput * sky(rd);
+            break;
+        }
+
+        Material m = material(hit.material);
+        // the teapot isn't closed, so always shade the side we're looking at.
+        vec3 n = dot(hit.normal, rd) < 0.0 ? hit.normal : -hit.normal;
+
+        radiance += throughput * m.emission;
+
+        ro = ro + rd * hit.t + n * EPSILON;
+        vec3 diffuse_dir = normalize(n + random_unit_vector()); // cosine weighted, so no pdf bookkeeping
+        if (rand() < m.glossiness) {
+            rd = normalize(mix(reflect(rd, n), diffuse_dir, m.roughness * m.roughness));
+        } else {
+            rd = diffuse_dir;
+        }
+        throughput *= m.albedo;
+    }
+
+    return radiance;
+}
+
+void main() {
+    rng_state = uint(gl_FragCoord.x) * 1973u + uint(gl_FragCoord.y) * 9277u + uint(sample_index) * 26699u;
+    rng_state = pcg_hash(rng_state | 1u);
+
+    vec2 jitter = vec2(rand(), rand()); // anti-aliasing for free
+    vec2 ndc = (floor(gl_FragCoord.xy) + jitter) / resolution * 2.0 - 1.0;
+    float aspect_ratio = resolution.x / resolution.y;
+
+    vec3 rd = normalize(camera_forward
+        + camera_right * ndc.x * aspect_ratio * fov_scale
+        + camera_up * ndc.y * fov_scale);
+
+    vec3 sample_color = trace(camera_position, rd);
+    if (any(isnan(sample_color))) sample_color = vec3(0.0);
+
+    vec3 previous = texelFetch(accumulation, ivec2(gl_FragCoord.xy), 0).rgb;
+    // sample_index == 0 gives a weight of 1, which is how the accumulation gets reset.
+    color = vec4(mix(previous, sample_color, 1.0 / float(sample_index + 1)), 1.0);
+}
@@ -0,0 +1,23 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_position;
+
+out vec4 color;
+
+uniform vec3 ambient_color;
+uniform vec3 diffuse_color;
+uniform vec3 specular_color;
+uniform float specular_exponent;
+
+#define SPECULAR_EXPONENT specular_exponent
+#include "lighting.glsl"
+#include "lights.glsl"
+
+void main() {
+    vec3 diffuse;
+    vec3 specular;
+    shade_lights(v_normal, v_position, diffuse, specular);
+
+    color = vec4(ambient_color + diffuse * diffuse_color + specular * specular_color, 1.0);
+}
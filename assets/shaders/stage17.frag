@@ -0,0 +1,26 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_position;
+in vec4 v_light_space;
+
+out vec4 color;
+
+uniform vec3 u_light; // world space, towards the light
+uniform mat4 view;
+uniform vec3 base_color;
+uniform sampler2DShadow shadow_map;
+uniform int pcf_radius;
+
+#include "lighting.glsl"
+#include "shadows.glsl"
+
+void main() {
+    vec3 light_dir = mat3(view) * u_light;
+    float diffuse = lambert(v_normal, light_dir);
+    float specular = blinn_phong_specular(v_normal, light_dir, v_position);
+    float lit = shadow_pcf(shadow_map, v_light_space, pcf_radius);
+
+    vec3 ambient = base_color * 0.15;
+    color = vec4(ambient + lit * (diffuse * base_color + specular * vec3(0.6)), 1.0);
+}
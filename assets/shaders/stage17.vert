@@ -0,0 +1,23 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+
+out vec3 v_normal;
+out vec3 v_position;
+out vec4 v_light_space;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+uniform mat4 light_space;
+
+void main() {
+    mat4 modelview = view * model;
+    v_normal = transpose(inverse(mat3(modelview))) * normal;
+    vec4 position_view = modelview * vec4(position, 1.0);
+    v_position = position_view.xyz;
+    // where the shadow map has this vertex.
+    v_light_space = light_space * model * vec4(position, 1.0);
+    gl_Position = perspective * position_view;
+}
@@ -0,0 +1,36 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_position;
+in vec3 v_world_position;
+
+out vec4 color;
+
+uniform vec3 u_light; // world space, towards the light
+uniform mat4 view;
+uniform vec3 base_color;
+uniform int pcf_radius;
+uniform bool show_cascades;
+
+#include "lighting.glsl"
+#include "shadows.glsl"
+#include "cascades.glsl"
+
+// red, green, blue, yellow for cascades 0 to 3.
+const vec3 cascade_colors[4] = vec3[4](vec3(1.0, 0.2, 0.2), vec3(0.2, 1.0, 0.2), vec3(0.3, 0.3, 1.0), vec3(1.0, 1.0, 0.2));
+
+void main() {
+    vec3 light_dir = mat3(view) * u_light;
+    float diffuse = lambert(v_normal, light_dir);
+    float specular = blinn_phong_specular(v_normal, light_dir, v_position);
+    int cascade;
+    // view space z is the distance along the view direction, which is what the cascades are cut by.
+    float lit = cascaded_shadow(v_world_position, v_position.z, pcf_radius, cascade);
+
+    vec3 ambient = base_color * 0.15;
+    vec3 shaded = ambient + lit * (diffuse * base_color + specular * vec3(0.6));
+    if (show_cascades && cascade < cascade_count) {
+        shaded = mix(shaded, cascade_colors[cascade % 4], 0.35);
+    }
+    color = vec4(shaded, 1.0);
+}
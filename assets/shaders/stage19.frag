@@ -0,0 +1,38 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_world_position;
+
+out vec4 color;
+
+uniform vec3 camera_position;
+uniform vec3 light_position;
+uniform vec3 light_color;
+uniform float light_range;
+uniform vec3 base_color;
+
+uniform samplerCube shadow_cube;
+uniform float far;
+uniform float shadow_bias;
+uniform float shadow_softness;
+
+#include "lighting.glsl"
+#include "shadows.glsl"
+
+void main() {
+    vec3 to_light = light_position - v_world_position;
+    float distance = length(to_light);
+    // blinn_phong_specular wants the camera at the origin, so positions relative to the camera.
+    vec3 position = v_world_position - camera_position;
+
+    float diffuse = lambert(v_normal, to_light);
+    float specular = blinn_phong_specular(v_normal, to_light, position);
+    float attenuation = range_attenuation(distance, light_range);
+    // softer the further the fragment is from the camera, up close the spread would show.
+    float softness = shadow_softness * (1.0 + length(position) / far);
+    float lit = point_shadow(shadow_cube, -to_light, far, shadow_bias, softness);
+
+    vec3 ambient = base_color * 0.05;
+    vec3 light = lit * attenuation * light_color;
+    color = vec4(ambient + light * (diffuse * base_color + specular * vec3(0.5)), 1.0);
+}
@@ -0,0 +1,19 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+
+out vec3 v_normal;
+out vec3 v_world_position;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+
+// lighting happens in world space this time, the shadow map is looked up in world space directions anyway.
+void main() {
+    v_normal = transpose(inverse(mat3(model))) * normal;
+    vec4 world_position = model * vec4(position, 1.0);
+    v_world_position = world_position.xyz;
+    gl_Position = perspective * view * world_position;
+}
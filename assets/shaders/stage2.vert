@@ -0,0 +1,7 @@
+#version 140
+
+in vec2 position; // vec2 = [f32; 2] in Rust, name only required to match struct data field, name in-and-of-itself doesn't matter.
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0); // coordinates are actually 4D, x/y/z/? is this for quarternion-ing?
+}
@@ -0,0 +1,33 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+uniform vec3 ambient_color;
+uniform vec3 u_light;     // world space, towards the (dim) moon
+uniform vec3 light_color;
+uniform mat4 view;
+
+float surface_exponent;
+#define SPECULAR_EXPONENT surface_exponent
+#include "lighting.glsl"
+#include "gbuffer.glsl"
+
+// the light every pixel gets: ambient and one directional light, once over the whole screen before the
+// light volumes add theirs.
+void main() {
+    if (is_background(v_uv)) {
+        discard;
+    }
+
+    vec3 position = gbuffer_position(v_uv);
+    vec3 normal = gbuffer_normal(v_uv);
+    vec3 albedo = gbuffer_albedo(v_uv);
+    vec2 material = gbuffer_material(v_uv);
+    surface_exponent = material.y;
+
+    vec3 light_dir = mat3(view) * u_light;
+    float diffuse = lambert(normal, light_dir);
+    float specular = blinn_phong_specular(normal, light_dir, position) * material.x;
+    color = vec4(ambient_color * albedo + (diffuse * albedo + specular) * light_color, 1.0);
+}
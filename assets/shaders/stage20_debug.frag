@@ -0,0 +1,24 @@
+#version 140
+
+in vec2 v_uv;
+out vec4 color;
+
+// 1: albedo, 2: normals, 3: material, 4: depth
+uniform int attachment;
+uniform float depth_scale; // view space distance that shows as white
+
+#include "gbuffer.glsl"
+
+void main() {
+    if (attachment == 1) {
+        color = vec4(gbuffer_albedo(v_uv), 1.0);
+    } else if (attachment == 2) {
+        color = vec4(gbuffer_normal(v_uv) * 0.5 + 0.5, 1.0);
+    } else if (attachment == 3) {
+        color = vec4(texture(material_tex, v_uv).rg, 0.0, 1.0);
+    } else {
+        // the raw depth is all near 1, the distance is easier to look at.
+        float distance = is_background(v_uv) ? depth_scale : gbuffer_position(v_uv).z;
+        color = vec4(vec3(distance / depth_scale), 1.0);
+    }
+}
@@ -0,0 +1,20 @@
+#version 140
+
+in vec3 v_normal;
+
+// one output per G-buffer texture, glium matches them up by name.
+out vec4 albedo;
+out vec4 normal;
+out vec4 material;
+
+uniform vec3 base_color;
+uniform float specular_intensity;
+uniform float specular_exponent;
+
+// no lighting at all here, just writing down what the lighting pass will need. The position isn't
+// stored, the depth buffer has it (see gbuffer.glsl).
+void main() {
+    albedo = vec4(base_color, 1.0);
+    normal = vec4(normalize(v_normal), 0.0);
+    material = vec4(specular_intensity, specular_exponent / 256.0, 0.0, 1.0);
+}
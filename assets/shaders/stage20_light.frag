@@ -0,0 +1,39 @@
+#version 140
+
+flat in vec3 v_light_position;
+flat in vec3 v_light_color;
+flat in float v_light_range;
+
+out vec4 color;
+
+uniform vec2 screen_size;
+
+// the exponent is different for every fragment now, it comes out of the G-buffer.
+float surface_exponent;
+#define SPECULAR_EXPONENT surface_exponent
+#include "lighting.glsl"
+#include "gbuffer.glsl"
+
+// one light's share of whatever is behind this pixel, added on top of the others by blending.
+void main() {
+    vec2 uv = gl_FragCoord.xy / screen_size;
+    if (is_background(uv)) {
+        discard;
+    }
+
+    vec3 position = gbuffer_position(uv);
+    vec3 to_light = v_light_position - position;
+    float distance = length(to_light);
+    if (distance > v_light_range) {
+        discard;
+    }
+
+    vec3 normal = gbuffer_normal(uv);
+    vec2 material = gbuffer_material(uv);
+    surface_exponent = material.y;
+
+    float diffuse = lambert(normal, to_light);
+    float specular = blinn_phong_specular(normal, to_light, position) * material.x;
+    float attenuation = range_attenuation(distance, v_light_range);
+    color = vec4((diffuse * gbuffer_albedo(uv) + specular) * v_light_color * attenuation, 1.0);
+}
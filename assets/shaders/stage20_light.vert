@@ -0,0 +1,24 @@
+#version 150
+
+// a unit sphere...
+in vec3 position;
+// ...stretched over one light's reach, once per light.
+in vec3 light_position;
+in vec3 light_color;
+in float light_range;
+
+flat out vec3 v_light_position;
+flat out vec3 v_light_color;
+flat out float v_light_range;
+
+uniform mat4 perspective;
+uniform mat4 view;
+
+void main() {
+    vec4 center = view * vec4(light_position, 1.0);
+    v_light_position = center.xyz;
+    v_light_color = light_color;
+    v_light_range = light_range;
+    // a bit bigger than the range, the flat triangles of the sphere cut corners off the real one.
+    gl_Position = perspective * (center + vec4(position * light_range * 1.1, 0.0));
+}
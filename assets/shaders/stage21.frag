@@ -0,0 +1,34 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_position;
+
+out vec4 color;
+
+uniform vec3 ambient_color;
+uniform vec3 base_color;
+uniform bool show_heatmap;
+uniform float heatmap_max; // light count that shows as full red
+
+#define SPECULAR_EXPONENT 32.0
+#include "lighting.glsl"
+#include "clusters.glsl"
+
+// blue for no lights through green to red for heatmap_max or more.
+vec3 heat(float t) {
+    t = clamp(t, 0.0, 1.0);
+    return t < 0.5 ? mix(vec3(0.0, 0.0, 0.4), vec3(0.0, 1.0, 0.0), t * 2.0) : mix(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), t * 2.0 - 1.0);
+}
+
+void main() {
+    if (show_heatmap) {
+        color = vec4(heat(float(cluster_light_count(v_position)) / heatmap_max), 1.0);
+        return;
+    }
+
+    vec3 diffuse;
+    vec3 specular;
+    shade_clustered(v_normal, v_position, diffuse, specular);
+
+    color = vec4((ambient_color + diffuse) * base_color + specular * 0.5, 1.0);
+}
@@ -0,0 +1,20 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+// per instance, every teapot has its own.
+in mat4 model;
+
+out vec3 v_normal;
+out vec3 v_position;
+
+uniform mat4 perspective;
+uniform mat4 view;
+
+void main() {
+    mat4 modelview = view * model;
+    v_normal = transpose(inverse(mat3(modelview))) * normal;
+    vec4 position_view = modelview * vec4(position, 1.0);
+    v_position = position_view.xyz;
+    gl_Position = perspective * position_view;
+}
@@ -0,0 +1,60 @@
+#version 140
+
+in vec3 v_normal;
+in vec3 v_position;
+in vec2 v_tex_coords;
+
+out vec4 color;
+
+// each texture times its factor, so a white texture leaves it all to the factor.
+uniform sampler2D albedo_tex;    // sRGB, like every color texture out of an image editor
+uniform sampler2D metallic_tex;  // r
+uniform sampler2D roughness_tex; // r
+uniform sampler2D ao_tex;        // r: how much of the ambient light gets into the crevices
+uniform sampler2D normal_tex;
+uniform vec3 albedo_factor;
+uniform float metallic_factor;
+uniform float roughness_factor;
+#ifdef IBL
+uniform float environment_intensity;
+uniform mat4 view;
+#else
+uniform vec3 ambient_color;
+#endif
+
+#include "lighting.glsl"
+#include "lights.glsl"
+#include "normal_mapping.glsl"
+#include "pbr.glsl"
+#ifdef IBL
+#include "ibl.glsl"
+#endif
+
+void main() {
+    // the lighting math wants linear colors, the texture has them gamma encoded.
+    vec3 albedo = pow(texture(albedo_tex, v_tex_coords).rgb, vec3(2.2)) * albedo_factor;
+    float metallic = texture(metallic_tex, v_tex_coords).r * metallic_factor;
+    // a perfectly smooth surface makes the highlight of a point light infinitely small.
+    float roughness = clamp(texture(roughness_tex, v_tex_coords).r * roughness_factor, 0.05, 1.0);
+    float ao = texture(ao_tex, v_tex_coords).r;
+
+    mat3 tbn = cotangent_frame(v_normal, v_position, v_tex_coords);
+    vec3 normal = normalize(tbn * (texture(normal_tex, v_tex_coords).rgb * 2.0 - 1.0));
+    vec3 to_camera = normalize(-v_position);
+
+    vec3 radiance = vec3(0.0);
+    for (int i = 0; i < light_count; i++) {
+        vec3 to_light;
+        vec3 light = light_radiance(i, v_position, to_light);
+        radiance += cook_torrance(normal, to_camera, to_light, albedo, metallic, roughness) * light;
+    }
+
+#ifdef IBL
+    // the maps are in world space. `view` only turns and moves, so turning back is its transpose.
+    mat3 to_world = transpose(mat3(view));
+    radiance += ambient_ibl(to_world * normal, to_world * to_camera, albedo, metallic, roughness) * environment_intensity * ao;
+#else
+    radiance += ambient_color * albedo * ao;
+#endif
+    color = vec4(tone_map(radiance), 1.0);
+}
@@ -0,0 +1,22 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+in vec2 tex_coords;
+
+out vec3 v_normal;
+out vec3 v_position;
+out vec2 v_tex_coords;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+
+void main() {
+    mat4 modelview = view * model;
+    v_normal = transpose(inverse(mat3(modelview))) * normal;
+    vec4 position_view = modelview * vec4(position, 1.0);
+    v_position = position_view.xyz;
+    v_tex_coords = tex_coords;
+    gl_Position = perspective * position_view;
+}
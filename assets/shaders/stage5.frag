@@ -0,0 +1,13 @@
+#version 140
+
+// like the rest: it just needs to be the same as in the vertex shader.
+in vec2 my_attr; // HERE IT IS :D the interpolated fragment position the vertex shader stored for us.
+
+out vec4 color;
+
+void main() {
+    // moving the color spectrum up above 0 to remove the black third.
+    vec2 c = (my_attr + 0.5001) * 0.45;
+    // this just sets x to red and y to green, blue to 0.0 and opacity to 1.0
+    color = vec4(c, 0.0, 1.0); // awesome
+}
@@ -0,0 +1,25 @@
+#version 140
+
+// like the rest: it just needs to be the same as in the vertex shader.
+in vec2 my_attr; // HERE IT IS :D the interpolated fragment position the vertex shader stored for us.
+
+in vec2 v_tex_coords; // THIS COMING IN RIGHT HERE!
+
+out vec4 color;
+
+uniform float tex_zoom_sin;
+uniform sampler2D tex_img;
+uniform sampler2D tex_noise;
+
+void main() {
+    // getting the sampled interpolated pixel attached to the corresponding position within the texture (image)
+    vec4 c_tex = texture(tex_noise, v_tex_coords * tex_zoom_sin);
+
+    // moving the color spectrum up above 0 to remove the black third for the attrib.
+    // vec2 c_attrib = (my_attr + 0.5001) * 0.45;
+
+    // vec2 c = c_tex.rg + (c_attrib * 0.1);
+    // this just sets x to red and y to green, blue to 0.0 and opacity to 1.0
+    // color = vec4(c, 0.0, 1.0); // awesome
+    color = vec4(c_tex.rgb, c_tex.r);
+}
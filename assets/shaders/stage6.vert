@@ -0,0 +1,21 @@
+#version 140
+
+in vec2 position;
+in vec2 texture_coordinates; // THIS!
+
+out vec2 my_attr; // out variables are communication channels from vertex to fragment shader programs!
+out vec2 v_tex_coords; // OUT TO THIS!
+
+uniform mat4 translation_matrix; // UNIFORM meaning: global variable whose value is set for a draw call. aka. draw-context const variable.
+uniform mat4 rotation_matrix;
+
+void main() {
+    // Need to do this so that it can be "passed along" to the fragment shader so that the pixel-in-texture-position can be interpolated from the vertex pos.
+    v_tex_coords = texture_coordinates;
+
+    // NOTE: the interpolation happens because the default for variables like this is the "smooth" setting.
+    my_attr = position; // this is only the vertex position, but when the fragment shader reads it it is an interpolated value corresponding automatically to the pixels relative position! :D <3 
+
+    vec4 rot_pos = rotation_matrix * vec4(position, 0.0, 1.0); // order is important, remember 4x4 * 4x1 vs 4x1 * 4x4
+    gl_Position = translation_matrix * rot_pos; // order is important, remember 4x4 * 4x1 vs 4x1 * 4x4
+}
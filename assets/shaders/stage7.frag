@@ -0,0 +1,7 @@
+#version 140
+
+out vec4 color;
+
+void main() {
+    color = vec4(0.98, 0.15, 0.1, 1.0);
+}
@@ -0,0 +1,10 @@
+#version 140
+
+in vec3 position;
+in vec3 normal;
+
+uniform mat4 matrix;
+
+void main() {
+    gl_Position = matrix * vec4(position, 1.0); // the position is 4D because: the window space is 3D, and the 3 dimensions are divided by the 4th after the shader has been executed, and is then discarded.
+}
@@ -0,0 +1,16 @@
+#version 140
+
+in vec3 v_normal;
+out vec4 color;
+
+uniform vec3 u_light;
+
+// what the teapot looks like facing away from / towards the light, set by the Gouraud material.
+uniform vec3 dark_color;
+uniform vec3 regular_color;
+
+void main() {
+    float brightness = dot(normalize(v_normal), normalize(u_light));
+    // mix = lerp
+    color = vec4(mix(dark_color, regular_color, brightness), 1.0);
+}
@@ -0,0 +1,10 @@
+#version 140
+
+out vec4 color;
+
+// no light at all, for things that are light themselves (or just markers).
+uniform vec3 base_color;
+
+void main() {
+    color = vec4(base_color, 1.0);
+}
@@ -0,0 +1,183 @@
+use std::{fmt, path::PathBuf};
+
+/// Everything that can go wrong while getting a tutorial stage on screen.
+#[derive(Debug)]
+pub enum Error {
+    UnknownTutorial(String),
+    UnknownStage { tutorial: String, stage: usize },
+    Param { name: String, message: String },
+    NotImplemented(String),
+    ContextCreation(String),
+    ShaderCompile { name: String, shader: String, log: String },
+    ShaderLink { name: String, log: String },
+    Shader { name: String, message: String },
+    Bindings { name: String, problems: Vec<String> },
+    Asset { path: PathBuf, message: String },
+    Write { path: PathBuf, message: String },
+    Texture(String),
+    Buffer(String),
+    Framebuffer(String),
+    ReadBack(String),
+    ThreadPanicked(String),
+    Draw(glium::DrawError),
+    SwapBuffers(glium::SwapBuffersError),
+    Io(std::io::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// A program that didn't build, `name` says which shader files it came from.
+    pub fn program(name: impl Into<String>, error: glium::program::ProgramCreationError) -> Self {
+        use glium::program::ProgramCreationError;
+
+        let name = name.into();
+        match error {
+            ProgramCreationError::CompilationError(log, shader) => Error::ShaderCompile { name, shader: format!("{:?}", shader).to_lowercase(), log },
+            ProgramCreationError::LinkingError(log) => Error::ShaderLink { name, log },
+            other => Error::Shader { name, message: other.to_string() },
+        }
+    }
+
+    pub fn asset(path: impl Into<PathBuf>, message: impl fmt::Display) -> Self {
+        Error::Asset { path: path.into(), message: message.to_string() }
+    }
+
+    /// Something the program writes out (captures, reports) that couldn't be written.
+    pub fn write(path: impl Into<PathBuf>, message: impl fmt::Display) -> Self {
+        Error::Write { path: path.into(), message: message.to_string() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownTutorial(tutorial) => write!(
+                f,
+                "'{}' is not a tutorial, pick one of: {}, {}",
+                tutorial, crate::glium_book::GLIUM, crate::learn_wgpu::LEARN_WGPU,
+            ),
+            Error::UnknownStage { tutorial, stage } => write!(
+                f,
+                "the {} tutorial has no stage {} (yet), leave out --stage to run the latest completed one",
+                tutorial, stage,
+            ),
+            Error::Param { name, message } => write!(
+                f,
+                "bad parameter `{}`: {}\nrun with --describe to see what the stage takes",
+                name, message,
+            ),
+            Error::NotImplemented(what) => write!(f, "{} isn't implemented yet", what),
+            Error::ContextCreation(message) => write!(
+                f,
+                "couldn't open an OpenGL window: {}\n\
+                 check that a display is available (DISPLAY / WAYLAND_DISPLAY) and the GL drivers are installed",
+                message,
+            ),
+            Error::ShaderCompile { name, shader, log } => write!(
+                f,
+                "the {} shader of {} failed to compile:\n{}\nfix the source in assets/shaders/ and run again",
+                shader, name, log.trim_end(),
+            ),
+            Error::ShaderLink { name, log } => write!(
+                f,
+                "{} failed to link:\n{}\ncheck that the vertex shader outputs match the fragment shader inputs",
+                name, log.trim_end(),
+            ),
+            Error::Shader { name, message } => write!(f, "couldn't build {}: {}", name, message),
+            Error::Bindings { name, problems } => write!(
+                f,
+                "{} doesn't match what the stage binds to it:\n  {}\nthe names in implement_vertex!/uniform! and the shader have to agree",
+                name, problems.join("\n  "),
+            ),
+            Error::Asset { path, message } => write!(
+                f,
+                "couldn't load {}: {}\nassets are read from {}/assets",
+                path.display(), message, env!("CARGO_MANIFEST_DIR"),
+            ),
+            Error::Write { path, message } => write!(f, "couldn't write {}: {}", path.display(), message),
+            Error::Texture(message) => write!(f, "couldn't create a texture: {}", message),
+            Error::Buffer(message) => write!(f, "couldn't create a buffer: {}", message),
+            Error::Framebuffer(message) => write!(f, "couldn't create a framebuffer: {}", message),
+            Error::ReadBack(message) => write!(f, "couldn't read the frame back: {}", message),
+            Error::ThreadPanicked(name) => write!(f, "the {} thread panicked", name),
+            Error::Draw(error) => write!(f, "draw call failed: {}", error),
+            Error::SwapBuffers(error) => write!(f, "couldn't present the frame: {}", error),
+            Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<glium::backend::glutin::DisplayCreationError> for Error {
+    fn from(error: glium::backend::glutin::DisplayCreationError) -> Self {
+        Error::ContextCreation(error.to_string())
+    }
+}
+
+impl From<glium::program::ProgramCreationError> for Error {
+    fn from(error: glium::program::ProgramCreationError) -> Self {
+        Error::program("program", error)
+    }
+}
+
+impl From<glium::texture::TextureCreationError> for Error {
+    fn from(error: glium::texture::TextureCreationError) -> Self {
+        Error::Texture(format!("{:?}", error))
+    }
+}
+
+impl From<glium::vertex::BufferCreationError> for Error {
+    fn from(error: glium::vertex::BufferCreationError) -> Self {
+        Error::Buffer(error.to_string())
+    }
+}
+
+impl From<glium::index::BufferCreationError> for Error {
+    fn from(error: glium::index::BufferCreationError) -> Self {
+        Error::Buffer(error.to_string())
+    }
+}
+
+impl From<glium::buffer::BufferCreationError> for Error {
+    fn from(error: glium::buffer::BufferCreationError) -> Self {
+        Error::Buffer(error.to_string())
+    }
+}
+
+impl From<glium::texture::buffer_texture::CreationError> for Error {
+    fn from(error: glium::texture::buffer_texture::CreationError) -> Self {
+        Error::Buffer(format!("{:?}", error))
+    }
+}
+
+impl From<glium::framebuffer::ValidationError> for Error {
+    fn from(error: glium::framebuffer::ValidationError) -> Self {
+        Error::Framebuffer(error.to_string())
+    }
+}
+
+impl From<glium::ReadError> for Error {
+    fn from(error: glium::ReadError) -> Self {
+        Error::ReadBack(format!("{:?}", error))
+    }
+}
+
+impl From<glium::DrawError> for Error {
+    fn from(error: glium::DrawError) -> Self {
+        Error::Draw(error)
+    }
+}
+
+impl From<glium::SwapBuffersError> for Error {
+    fn from(error: glium::SwapBuffersError) -> Self {
+        Error::SwapBuffers(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
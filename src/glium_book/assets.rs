@@ -0,0 +1,19 @@
+#![allow(dead_code)]
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+pub fn texture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("assets").join("textures")
+}
+
+/// Opens `assets/textures/<name>`, the format is guessed from the contents.
+pub fn load_image(name: &str) -> Result<image::DynamicImage> {
+    let path = texture_dir().join(name);
+    let reader = image::ImageReader::open(&path)
+        .map_err(|error| Error::asset(&path, error))?
+        .with_guessed_format()
+        .map_err(|error| Error::asset(&path, error))?;
+
+    reader.decode().map_err(|error| Error::asset(&path, error))
+}
@@ -0,0 +1,211 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use glium::{glutin::event::{ElementState, VirtualKeyCode}, texture::RawImage2d, Display};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops, Delay, RgbaImage,
+};
+
+use crate::error::{Error, Result};
+use super::trace::Target;
+use super::util::civil_date;
+
+// Getting what a stage drew out of it: F12 saves the current frame as a png, F9 starts/stops recording a
+// png sequence and F10 an animated gif. Everything ends up in captures/ next to assets/, named after the
+// time it was taken. Recording grabs frames at `RECORD_FPS` no matter how often the stage draws, and the
+// encoding happens on another thread so the stage doesn't stutter while a gif is being quantized.
+
+pub const SCREENSHOT_KEY: VirtualKeyCode = VirtualKeyCode::F12;
+pub const RECORD_PNG_KEY: VirtualKeyCode = VirtualKeyCode::F9;
+pub const RECORD_GIF_KEY: VirtualKeyCode = VirtualKeyCode::F10;
+
+const RECORD_FPS: u32 = 30;
+
+pub fn capture_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("captures")
+}
+
+/// Pixels as GL reads them back, as an image the right way up.
+pub fn upright(raw: RawImage2d<u8>) -> Result<RgbaImage> {
+    let mut image = RgbaImage::from_raw(raw.width, raw.height, raw.data.into_owned())
+        .ok_or_else(|| Error::ReadBack("the frame came back the wrong size".to_string()))?;
+
+    // GL's first row is the bottom one, and the window's alpha is whatever blending left there.
+    imageops::flip_vertical_in_place(&mut image);
+    for pixel in image.pixels_mut() {
+        pixel[3] = 255;
+    }
+    Ok(image)
+}
+
+/// UTC `yyyymmdd-hhmmss-mmm`, enough to sort captures and keep them apart.
+pub(super) fn timestamp() -> String {
+    let date = civil_date(SystemTime::now());
+    let seconds = date.seconds_of_day;
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        date.year, date.month, date.day, seconds / 3600, seconds / 60 % 60, seconds % 60, date.millis,
+    )
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Format {
+    Png,
+    Gif,
+}
+
+/// A recording in progress, frames go to the encoder thread over `frames`.
+struct Recording {
+    format: Format,
+    path: PathBuf,
+    frames: Sender<RgbaImage>,
+    encoder: JoinHandle<Result<usize>>,
+    next_frame: Instant,
+}
+
+impl Recording {
+    fn start(format: Format) -> Result<Self> {
+        let dir = capture_dir();
+        let (frames, received) = mpsc::channel::<RgbaImage>();
+
+        let (path, encoder) = match format {
+            Format::Png => {
+                let path = dir.join(format!("recording-{}", timestamp()));
+                fs::create_dir_all(&path)?;
+                let frame_dir = path.clone();
+                let encoder = thread::spawn(move || -> Result<usize> {
+                    let mut count = 0;
+                    for frame in received {
+                        let frame_path = frame_dir.join(format!("{:06}.png", count));
+                        frame.save(&frame_path).map_err(|error| Error::write(&frame_path, error))?;
+                        count += 1;
+                    }
+                    Ok(count)
+                });
+                (path, encoder)
+            }
+            Format::Gif => {
+                fs::create_dir_all(&dir)?;
+                let path = dir.join(format!("recording-{}.gif", timestamp()));
+                let file = File::create(&path).map_err(|error| Error::write(&path, error))?;
+                let gif_path = path.clone();
+                let encoder = thread::spawn(move || -> Result<usize> {
+                    let mut gif = GifEncoder::new(BufWriter::new(file));
+                    gif.set_repeat(Repeat::Infinite).map_err(|error| Error::write(&gif_path, error))?;
+                    let mut count = 0;
+                    for frame in received {
+                        let delay = Delay::from_numer_denom_ms(1000, RECORD_FPS);
+                        gif.encode_frame(image::Frame::from_parts(frame, 0, 0, delay))
+                            .map_err(|error| Error::write(&gif_path, error))?;
+                        count += 1;
+                    }
+                    Ok(count)
+                });
+                (path, encoder)
+            }
+        };
+
+        println!("recording to {}, press the key again to stop", path.display());
+        Ok(Recording { format, path, frames, encoder, next_frame: Instant::now() })
+    }
+
+    /// Waits for the encoder to get through everything it was sent.
+    fn stop(self) -> Result<()> {
+        drop(self.frames);
+        let count = self.encoder.join().unwrap_or_else(|_| Err(Error::ThreadPanicked("encoder".to_string())))?;
+        println!("recorded {} frames to {}", count, self.path.display());
+        Ok(())
+    }
+}
+
+/// The runner's capture state: a pending screenshot and maybe a recording.
+#[derive(Default)]
+pub struct Capture {
+    screenshot: bool,
+    recording: Option<Recording>,
+}
+
+impl Capture {
+    /// Whether `key` was one of the capture keys (they don't get passed on to the stage).
+    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) -> Result<bool> {
+        if ![SCREENSHOT_KEY, RECORD_PNG_KEY, RECORD_GIF_KEY].contains(&key) {
+            return Ok(false);
+        }
+        if state == ElementState::Released {
+            return Ok(true);
+        }
+
+        let format = match key {
+            SCREENSHOT_KEY => {
+                self.screenshot = true;
+                return Ok(true);
+            }
+            RECORD_PNG_KEY => Format::Png,
+            RECORD_GIF_KEY => Format::Gif,
+            _ => return Ok(false),
+        };
+
+        match self.recording.take() {
+            // the other record key while recording stops this one and starts the other kind.
+            Some(recording) => {
+                let restart = recording.format != format;
+                recording.stop()?;
+                if restart {
+                    self.recording = Some(Recording::start(format)?);
+                }
+            }
+            None => self.recording = Some(Recording::start(format)?),
+        }
+        Ok(true)
+    }
+
+    /// Called after every frame the stage drew, before it's presented.
+    pub fn frame_drawn(&mut self, display: &Display, target: &Target) -> Result<()> {
+        let record = matches!(&self.recording, Some(recording) if Instant::now() >= recording.next_frame);
+        if !self.screenshot && !record {
+            return Ok(());
+        }
+
+        let image = target.read(display)?;
+
+        if self.screenshot {
+            self.screenshot = false;
+            let dir = capture_dir();
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(format!("screenshot-{}.png", timestamp()));
+            image.save(&path).map_err(|error| Error::write(&path, error))?;
+            println!("saved {}", path.display());
+        }
+
+        if let (true, Some(recording)) = (record, &mut self.recording) {
+            // fixed steps rather than "now + interval" so the gif plays back at the speed it was recorded.
+            let interval = Duration::from_secs(1) / RECORD_FPS;
+            recording.next_frame += interval;
+            if recording.next_frame < Instant::now() {
+                recording.next_frame = Instant::now() + interval;
+            }
+            if recording.frames.send(image).is_err() {
+                // the encoder gave up, stopping hands back why.
+                if let Some(recording) = self.recording.take() {
+                    recording.stop()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes a recording that is still going when the stage closes.
+    pub fn finish(&mut self) -> Result<()> {
+        match self.recording.take() {
+            Some(recording) => recording.stop(),
+            None => Ok(()),
+        }
+    }
+}
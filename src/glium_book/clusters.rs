@@ -0,0 +1,283 @@
+#![allow(dead_code)]
+use cgmath::{Matrix4, Vector4};
+use glium::texture::buffer_texture::{BufferTexture, BufferTextureType};
+use glium::uniforms::{AsUniformValue, UniformValue, Uniforms};
+use glium::Display;
+
+use crate::error::Result;
+use super::lights::Light;
+
+// Clustered forward shading, the other way to have hundreds of lights: instead of lighting from a G-buffer
+// afterwards (stage 20), every fragment still lights itself, but only with the few lights that can reach it.
+// The view frustum gets cut into a grid of clusters ("froxels", frustum voxels): screen tiles in x and y,
+// slices in depth that get longer further away. Every frame the CPU works out which lights touch which
+// clusters (`ClusterGrid::bin`) and uploads that as buffer textures, and the fragment shader finds its
+// cluster from where it is on screen and how far away (clusters.glsl) and loops over just those lights.
+// Lights past the grid's far end don't get binned at all.
+
+/// More lights than this in one cluster and the rest get left out of it.
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClusterGrid {
+    /// Screen tiles across and up.
+    pub tiles: [usize; 2],
+    /// Depth slices between `near` and `far`.
+    pub slices: usize,
+    /// View space depth the second slice starts at, the first one covers everything nearer.
+    pub near: f32,
+    /// View space depth the last slice ends at.
+    pub far: f32,
+}
+
+impl ClusterGrid {
+    pub fn new(tiles: [usize; 2], slices: usize, near: f32, far: f32) -> Self {
+        let near = near.max(0.001);
+        ClusterGrid { tiles: [tiles[0].max(1), tiles[1].max(1)], slices: slices.max(1), near, far: far.max(near * 1.01) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles[0] * self.tiles[1] * self.slices
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Where cluster (x, y, slice) is in the flattened grid, x first.
+    pub fn index(&self, x: usize, y: usize, slice: usize) -> usize {
+        x + self.tiles[0] * (y + self.tiles[1] * slice)
+    }
+
+    /// The slice a view space depth falls in, the same as `cluster_index` in clusters.glsl. Each slice is
+    /// the same factor longer than the one before, so near ones are thin and far ones don't waste memory.
+    pub fn slice(&self, depth: f32) -> usize {
+        let t = (depth.max(self.near) / self.near).ln() / (self.far / self.near).ln();
+        ((t * self.slices as f32) as usize).min(self.slices - 1)
+    }
+
+    /// Where slice `slice` starts (and the one before it ends).
+    fn slice_depth(&self, slice: usize) -> f32 {
+        self.near * (self.far / self.near).powf(slice as f32 / self.slices as f32)
+    }
+
+    /// A view space box around every cluster, indexed like the grid. `tan_half` is the tangent of half the
+    /// field of view, horizontally and vertically.
+    fn boxes(&self, tan_half: [f32; 2]) -> Vec<([f32; 3], [f32; 3])> {
+        let mut boxes = vec![([0.0; 3], [0.0; 3]); self.len()];
+        let span = |tile: usize, tiles: usize, tan: f32| {
+            let ndc = |i: usize| i as f32 / tiles as f32 * 2.0 - 1.0;
+            (ndc(tile) * tan, ndc(tile + 1) * tan)
+        };
+
+        for slice in 0..self.slices {
+            let near = if slice == 0 { 0.0 } else { self.slice_depth(slice) };
+            let far = self.slice_depth(slice + 1);
+            for y in 0..self.tiles[1] {
+                for x in 0..self.tiles[0] {
+                    // the tile's sides are planes through the camera, so it's widest at one end or the other.
+                    let (x0, x1) = span(x, self.tiles[0], tan_half[0]);
+                    let (y0, y1) = span(y, self.tiles[1], tan_half[1]);
+                    let min = [(x0 * near).min(x0 * far), (y0 * near).min(y0 * far), near];
+                    let max = [(x1 * near).max(x1 * far), (y1 * near).max(y1 * far), far];
+                    boxes[self.index(x, y, slice)] = (min, max);
+                }
+            }
+        }
+        boxes
+    }
+
+    /// Which lights reach which clusters, for a camera with vertical `fov` (radians) and `aspect` (width over
+    /// height). `lights` are view space positions and ranges, `Bins::indices` points into it.
+    pub fn bin(&self, lights: &[([f32; 3], f32)], fov: f32, aspect: f32) -> Bins {
+        let tan_y = (fov / 2.0).tan();
+        let boxes = self.boxes([tan_y * aspect, tan_y]);
+        let mut clusters = vec![Vec::new(); self.len()];
+
+        for (i, &(position, range)) in lights.iter().enumerate() {
+            // behind the camera, or past the grid.
+            if position[2] + range < 0.0 || position[2] - range > self.far {
+                continue;
+            }
+            for slice in self.slice(position[2] - range)..=self.slice(position[2] + range) {
+                for y in 0..self.tiles[1] {
+                    for x in 0..self.tiles[0] {
+                        let index = self.index(x, y, slice);
+                        let (min, max) = boxes[index];
+                        if sphere_touches_box(position, range, min, max) && clusters[index].len() < MAX_LIGHTS_PER_CLUSTER {
+                            clusters[index].push(i as u32);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut bins = Bins { cells: Vec::with_capacity(self.len()), indices: Vec::new() };
+        for cluster in clusters {
+            bins.cells.push([bins.indices.len() as u32, cluster.len() as u32]);
+            bins.indices.extend(cluster);
+        }
+        bins
+    }
+}
+
+fn sphere_touches_box(center: [f32; 3], radius: f32, min: [f32; 3], max: [f32; 3]) -> bool {
+    let distance_squared: f32 = (0..3)
+        .map(|i| {
+            let nearest = center[i].clamp(min[i], max[i]);
+            (center[i] - nearest) * (center[i] - nearest)
+        })
+        .sum();
+    distance_squared <= radius * radius
+}
+
+/// Light lists for every cluster, all in one array.
+#[derive(Clone, Debug, Default)]
+pub struct Bins {
+    /// Per cluster, in grid order: where its lights start in `indices`, and how many there are.
+    pub cells: Vec<[u32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+/// The lights and the grid on the GPU, as the buffer textures clusters.glsl reads.
+pub struct ClusterBuffers {
+    grid: ClusterGrid,
+    /// Two texels per light: view space position and range, then color times intensity.
+    lights: BufferTexture<[f32; 4]>,
+    cells: BufferTexture<[u32; 2]>,
+    indices: BufferTexture<u32>,
+    max_lights: usize,
+}
+
+impl ClusterBuffers {
+    /// Room for `max_lights` lights, more get left out.
+    pub fn new(display: &Display, grid: ClusterGrid, max_lights: usize) -> Result<Self> {
+        let max_lights = max_lights.max(1);
+        Ok(ClusterBuffers {
+            grid,
+            lights: BufferTexture::empty_dynamic(display, max_lights * 2, BufferTextureType::Float)?,
+            cells: BufferTexture::empty_dynamic(display, grid.len(), BufferTextureType::Unsigned)?,
+            indices: BufferTexture::empty_dynamic(display, grid.len() * MAX_LIGHTS_PER_CLUSTER, BufferTextureType::Unsigned)?,
+            max_lights,
+        })
+    }
+
+    pub fn grid(&self) -> ClusterGrid {
+        self.grid
+    }
+
+    /// Moves the point and spot lights into view space, bins them and uploads it all. Spot lights are lit
+    /// like point lights here, directional ones are skipped. Returns the bins, for whoever wants to count.
+    pub fn upload(&mut self, lights: &[Light], view: [[f32; 4]; 4], fov: f32, aspect: f32) -> Bins {
+        let view = Matrix4::from(view);
+        let mut spheres = Vec::with_capacity(lights.len().min(self.max_lights));
+        let mut data = Vec::with_capacity(spheres.capacity() * 2);
+        for light in lights {
+            let position = match light.position() {
+                Some(position) if spheres.len() < self.max_lights => position,
+                _ => continue,
+            };
+            let p = view * Vector4::new(position[0], position[1], position[2], 1.0);
+            let [r, g, b] = light.color;
+            spheres.push(([p.x, p.y, p.z], light.range));
+            data.push([p.x, p.y, p.z, light.range]);
+            data.push([r * light.intensity, g * light.intensity, b * light.intensity, 1.0]);
+        }
+
+        let bins = self.grid.bin(&spheres, fov, aspect);
+        self.cells.write(&bins.cells);
+        if let Some(slice) = self.indices.slice(0..bins.indices.len()).filter(|_| !bins.indices.is_empty()) {
+            slice.write(&bins.indices);
+        }
+        if let Some(slice) = self.lights.slice(0..data.len()).filter(|_| !data.is_empty()) {
+            slice.write(&data);
+        }
+        bins
+    }
+
+    /// The buffer textures and grid size as clusters.glsl wants them, then everything in `extra`.
+    pub fn uniforms<'a, U: Uniforms>(&'a self, extra: &'a U) -> ClusterUniforms<'a, U> {
+        ClusterUniforms { buffers: self, extra }
+    }
+}
+
+pub struct ClusterUniforms<'a, U> {
+    buffers: &'a ClusterBuffers,
+    extra: &'a U,
+}
+
+impl<'b, U: Uniforms> Uniforms for ClusterUniforms<'b, U> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        let grid = self.buffers.grid;
+        f("cluster_lights", self.buffers.lights.as_uniform_value());
+        f("cluster_cells", self.buffers.cells.as_uniform_value());
+        f("cluster_indices", self.buffers.indices.as_uniform_value());
+        f("cluster_tiles", UniformValue::IntVec2([grid.tiles[0] as i32, grid.tiles[1] as i32]));
+        f("cluster_slices", UniformValue::SignedInt(grid.slices as i32));
+        f("cluster_near", UniformValue::Float(grid.near));
+        f("cluster_far", UniformValue::Float(grid.far));
+        self.extra.visit_values(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // slices start at 1, 2, 4 and 8 and the last one ends at 16.
+    fn grid() -> ClusterGrid {
+        ClusterGrid::new([1, 1], 4, 1.0, 16.0)
+    }
+
+    fn count(bins: &Bins, grid: &ClusterGrid, slice: usize) -> u32 {
+        bins.cells[grid.index(0, 0, slice)][1]
+    }
+
+    #[test]
+    fn depths_past_either_end_go_in_the_first_or_last_slice() {
+        let grid = grid();
+        assert_eq!(grid.slice(0.0), 0);
+        assert_eq!(grid.slice(1.0), 0);
+        assert_eq!(grid.slice(1.99), 0);
+        assert_eq!(grid.slice(2.01), 1);
+        assert_eq!(grid.slice(7.99), 2);
+        assert_eq!(grid.slice(8.01), 3);
+        assert_eq!(grid.slice(16.0), 3);
+        assert_eq!(grid.slice(100.0), 3);
+    }
+
+    #[test]
+    fn a_sphere_touches_a_box_it_only_grazes() {
+        assert!(sphere_touches_box([0.0, 0.0, 2.5], 0.5, [-1.0; 3], [1.0, 1.0, 2.0]));
+        assert!(!sphere_touches_box([0.0, 0.0, 2.6], 0.5, [-1.0; 3], [1.0, 1.0, 2.0]));
+        // half a unit out along two axes is further than half a unit away.
+        assert!(!sphere_touches_box([1.4, 1.4, 0.0], 0.5, [-1.0; 3], [1.0; 3]));
+    }
+
+    #[test]
+    fn a_light_on_a_slice_boundary_is_in_both_slices() {
+        let grid = grid();
+        let bins = grid.bin(&[([0.0, 0.0, 2.0], 0.5)], 1.0, 1.0);
+        assert_eq!(count(&bins, &grid, 0), 1);
+        assert_eq!(count(&bins, &grid, 1), 1);
+        assert_eq!(count(&bins, &grid, 2), 0);
+        assert_eq!(count(&bins, &grid, 3), 0);
+        assert_eq!(bins.indices, vec![0, 0]);
+    }
+
+    #[test]
+    fn lights_behind_the_camera_or_past_the_grid_are_skipped() {
+        let grid = grid();
+        let bins = grid.bin(&[([0.0, 0.0, -5.0], 1.0), ([0.0, 0.0, 20.0], 1.0), ([0.0, 0.0, 3.0], 0.5)], 1.0, 1.0);
+        assert_eq!(bins.indices, vec![2]);
+    }
+
+    #[test]
+    fn a_cluster_holds_at_most_max_lights_per_cluster() {
+        let grid = ClusterGrid::new([1, 1], 1, 1.0, 16.0);
+        let lights = vec![([0.0, 0.0, 4.0], 1.0); MAX_LIGHTS_PER_CLUSTER + 10];
+        let bins = grid.bin(&lights, 1.0, 1.0);
+        assert_eq!(bins.cells, vec![[0, MAX_LIGHTS_PER_CLUSTER as u32]]);
+        assert_eq!(bins.indices, (0..MAX_LIGHTS_PER_CLUSTER as u32).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,151 @@
+use std::ffi::{c_void, CStr};
+
+use glium::{
+    debug::{DebugCallbackBehavior, MessageType, Severity, Source},
+    glutin::{self, event_loop::EventLoop, ContextCurrentState},
+    Api, CapabilitiesSource, Display, Version,
+};
+use log::Level;
+
+use crate::error::{Error, Result};
+
+// Making the GL context every stage draws with. It's `Display::new` plus a debug callback that sends
+// everything the driver reports (KHR_debug) to `log`, so `RUST_LOG=gl=debug` shows driver chatter and
+// errors show up even when nothing was watching for them. Also the `--info` report.
+
+/// `Display::new`, with the driver's debug messages going to `log` under the `gl` target.
+pub fn create_display<T: ContextCurrentState, E>(
+    window_builder: glutin::window::WindowBuilder,
+    context_builder: glutin::ContextBuilder<'_, T>,
+    event_loop: &glutin::event_loop::EventLoopWindowTarget<E>,
+) -> Result<Display> {
+    // a debug context is what makes drivers actually say things, don't pay for it in release builds.
+    let gl_window = context_builder
+        .with_gl_debug_flag(cfg!(debug_assertions))
+        .build_windowed(window_builder, event_loop)
+        .map_err(|error| Error::ContextCreation(error.to_string()))?;
+
+    let debug = DebugCallbackBehavior::Custom {
+        callback: Box::new(log_message),
+        // synchronous means the message arrives inside the call that caused it, handy with a debugger.
+        synchronous: cfg!(debug_assertions),
+    };
+    let display = Display::with_debug(gl_window, debug).map_err(|error| Error::ContextCreation(error.to_string()))?;
+
+    log::info!(
+        target: "gl",
+        "{} on {}, OpenGL {}",
+        display.get_opengl_vendor_string(),
+        display.get_opengl_renderer_string(),
+        display.get_opengl_version_string(),
+    );
+    Ok(display)
+}
+
+fn log_message(source: Source, ty: MessageType, severity: Severity, id: u32, report_errors: bool, message: &str) {
+    let level = match (ty, severity) {
+        // glium provokes errors on purpose while it checks shader compiles, and says so with `report_errors`.
+        (MessageType::Error, _) if !report_errors => Level::Debug,
+        (MessageType::Error, _) | (_, Severity::High) => Level::Error,
+        (_, Severity::Medium) => Level::Warn,
+        (_, Severity::Low) => Level::Info,
+        (_, Severity::Notification) => Level::Debug,
+    };
+    log::log!(target: "gl", level, "[{:?} {:?} {}] {}", source, ty, id, message.trim_end());
+}
+
+/// "4.60" or "3.20 es", the way a `#version` line spells it.
+fn glsl_version(version: &Version) -> String {
+    let Version(api, major, minor) = *version;
+    match api {
+        Api::Gl => format!("{}.{}0", major, minor),
+        Api::GlEs => format!("{}.{}0 es", major, minor),
+    }
+}
+
+const GL_EXTENSIONS: u32 = 0x1F03;
+const GL_NUM_EXTENSIONS: u32 = 0x821D;
+
+type GetString = unsafe extern "system" fn(u32) -> *const u8;
+type GetStringi = unsafe extern "system" fn(u32, u32) -> *const u8;
+type GetIntegerv = unsafe extern "system" fn(u32, *mut i32);
+
+/// A GL entry point glium doesn't expose, as the `unsafe extern "system" fn` type `F`.
+///
+/// # Safety
+/// `F` has to be the function's real signature, and it may only be called with the context current.
+pub unsafe fn gl_function<F: Copy>(display: &Display, name: &str) -> Option<F> {
+    assert_eq!(std::mem::size_of::<F>(), std::mem::size_of::<*const c_void>());
+    let address = display.gl_window().get_proc_address(name);
+    (!address.is_null()).then(|| std::mem::transmute_copy::<*const c_void, F>(&address))
+}
+
+/// Every extension the driver has, by name. glium only keeps a bool for each one it knows about.
+fn extensions(display: &Display) -> Vec<String> {
+    let string = |pointer: *const u8| -> Option<String> {
+        (!pointer.is_null()).then(|| unsafe { CStr::from_ptr(pointer as *const _) }.to_string_lossy().into_owned())
+    };
+
+    let mut names = unsafe {
+        let get_stringi = gl_function::<GetStringi>(display, "glGetStringi");
+        let get_integerv = gl_function::<GetIntegerv>(display, "glGetIntegerv");
+        let get_string = gl_function::<GetString>(display, "glGetString");
+        match (get_stringi, get_integerv, get_string) {
+            // GL 3+ lists them one at a time.
+            (Some(get_stringi), Some(get_integerv), _) => {
+                display.exec_in_context(|| {
+                    let mut count = 0;
+                    get_integerv(GL_NUM_EXTENSIONS, &mut count);
+                    (0..count.max(0) as u32).filter_map(|index| string(get_stringi(GL_EXTENSIONS, index))).collect()
+                })
+            }
+            // older ones have one long space separated string.
+            (None, _, Some(get_string)) => {
+                display.exec_in_context(|| string(get_string(GL_EXTENSIONS)).unwrap_or_default())
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    };
+    names.sort();
+    names
+}
+
+/// `--info`: what the GL context (and wgpu, see `learn_wgpu::print_adapters`) can do on this machine.
+pub fn print_info() -> Result<()> {
+    let event_loop = EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new().with_visible(false);
+    let context_builder = glutin::ContextBuilder::new();
+    let display = create_display(window_builder, context_builder, &event_loop)?;
+    let capabilities = display.get_capabilities();
+
+    println!("OpenGL");
+    println!("  vendor:   {}", display.get_opengl_vendor_string());
+    println!("  renderer: {}", display.get_opengl_renderer_string());
+    println!("  version:  {}", display.get_opengl_version_string());
+    println!("  profile:  {:?}", capabilities.profile);
+
+    let glsl: Vec<String> = capabilities.supported_glsl_versions.iter().map(glsl_version).collect();
+    println!("  GLSL:     {} (newest {})", glsl.join(", "), glsl_version(&display.get_supported_glsl_version()));
+
+    println!("  max texture size:     {}", capabilities.max_texture_size);
+    println!("  max viewport:         {} x {}", capabilities.max_viewport_dims.0, capabilities.max_viewport_dims.1);
+    println!("  max draw buffers:     {}", capabilities.max_draw_buffers);
+    println!("  max texture units:    {}", capabilities.max_combined_texture_image_units);
+    if let Some(anisotropy) = capabilities.max_texture_max_anisotropy {
+        println!("  max anisotropy:       {}", anisotropy);
+    }
+    if let Some(memory) = display.get_free_video_memory() {
+        println!("  free video memory:    {} MiB", memory / (1024 * 1024));
+    }
+    println!("  debug output:         {}", if display.get_extensions().gl_khr_debug { "yes, KHR_debug" } else { "no" });
+
+    let extensions = extensions(&display);
+    println!("  extensions ({}):", extensions.len());
+    for extension in &extensions {
+        println!("    {}", extension);
+    }
+    Ok(())
+}
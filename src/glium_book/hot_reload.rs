@@ -0,0 +1,287 @@
+#![allow(dead_code)]
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use glium::{
+    program::{ProgramCreationError, ProgramCreationInput},
+    uniforms::Uniforms,
+    vertex::VertexFormat,
+    Display,
+    Program,
+};
+
+use crate::error::{Error, Result};
+use super::reflect::{self, Problem};
+use super::trace;
+use super::preprocess::{Origin, PreprocessError, Preprocessor, SourceMap};
+
+// Shader sources live in `assets/shaders/` and get compiled into the binary as well, so a stage still runs
+// when started from somewhere the assets folder can't be found. While running, the files on disk are polled
+// and the program is rebuilt whenever one of them changes. Sources go through `preprocess` first.
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// One shader source file: its name inside `assets/shaders/` and the copy that was embedded at compile time.
+#[derive(Copy, Clone, Debug)]
+pub struct ShaderFile {
+    pub name: &'static str,
+    pub embedded: &'static str,
+}
+
+/// `shader_file!("stage13.frag")` -> a `ShaderFile` with the file embedded as fallback.
+macro_rules! shader_file {
+    ($name:literal) => {
+        crate::glium_book::hot_reload::ShaderFile {
+            name: $name,
+            embedded: include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders/", $name)),
+        }
+    };
+}
+
+pub(crate) use shader_file;
+
+pub fn shader_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("assets").join("shaders")
+}
+
+impl ShaderFile {
+    pub fn path(&self) -> PathBuf {
+        shader_dir().join(self.name)
+    }
+
+    /// The source on disk if there is one, the embedded copy otherwise.
+    pub fn load(&self) -> String {
+        fs::read_to_string(self.path()).unwrap_or_else(|_| self.embedded.to_string())
+    }
+}
+
+/// A `Program` that recompiles itself when its shader files (or anything they `#include`) change.
+/// If the new source doesn't compile the compile log is printed and the last working program stays in use.
+pub struct HotProgram {
+    vertex: ShaderFile,
+    fragment: ShaderFile,
+    outputs_srgb: bool,
+    preprocessor: Preprocessor,
+    program: Program,
+    dependencies: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+    reloaded: bool,
+    /// The vertex layouts the current build has been checked against, one draw call can't speak for another.
+    checked: Vec<Vec<VertexFormat>>,
+}
+
+/// Why a (re)build didn't give us a program.
+pub enum BuildError {
+    Preprocess(PreprocessError),
+    Program(ProgramCreationError, SourceMap, SourceMap),
+}
+
+impl BuildError {
+    fn into_error(self, vertex: &ShaderFile, fragment: &ShaderFile) -> Error {
+        let name = format!("{} + {}", vertex.name, fragment.name);
+        match self {
+            BuildError::Preprocess(error) => Error::Shader { name, message: error.to_string() },
+            BuildError::Program(ProgramCreationError::CompilationError(log, shader_type), vertex_map, fragment_map) => {
+                let source_map = match shader_type {
+                    glium::program::ShaderType::Vertex => vertex_map,
+                    _ => fragment_map,
+                };
+                Error::program(name, ProgramCreationError::CompilationError(source_map.annotate(&log), shader_type))
+            }
+            BuildError::Program(error, _, _) => Error::program(name, error),
+        }
+    }
+}
+
+impl HotProgram {
+    pub fn new(display: &Display, vertex: ShaderFile, fragment: ShaderFile) -> Result<Self> {
+        Self::with_preprocessor(display, vertex, fragment, false, Preprocessor::new())
+    }
+
+    /// `preprocessor` carries the `#define`s and generated includes for both shaders.
+    /// `outputs_srgb` is passed on to glium, see `ProgramCreationInput::SourceCode`.
+    pub fn with_preprocessor(
+        display: &Display,
+        vertex: ShaderFile,
+        fragment: ShaderFile,
+        outputs_srgb: bool,
+        preprocessor: Preprocessor,
+    ) -> Result<Self> {
+        let (program, dependencies) = match build(display, &vertex, &fragment, outputs_srgb, &preprocessor, Origin::Disk) {
+            Ok(built) => built,
+            Err(error) => {
+                // the files on disk are broken, the embedded ones are what the binary was built with.
+                report(&vertex, &fragment, &error);
+                log::warn!("falling back to the embedded shader sources");
+                build(display, &vertex, &fragment, outputs_srgb, &preprocessor, Origin::Embedded)
+                    .map_err(|error| error.into_error(&vertex, &fragment))?
+            }
+        };
+
+        Ok(HotProgram {
+            vertex,
+            fragment,
+            outputs_srgb,
+            preprocessor,
+            program,
+            dependencies: stamp(&dependencies),
+            last_poll: Instant::now(),
+            reloaded: false,
+            checked: Vec::new(),
+        })
+    }
+
+    /// Checks (at most every `POLL_INTERVAL`) whether a source file changed and recompiles if so.
+    /// Returns true when a new program was swapped in.
+    pub fn reload_if_changed(&mut self, display: &Display) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let changed = self.dependencies.iter().any(|(path, stamp)| modified(path) != *stamp);
+        if !changed {
+            return false;
+        }
+
+        self.reload(display)
+    }
+
+    /// Recompiles from disk right away.
+    pub fn reload(&mut self, display: &Display) -> bool {
+        match build(display, &self.vertex, &self.fragment, self.outputs_srgb, &self.preprocessor, Origin::Disk) {
+            Ok((program, dependencies)) => {
+                log::info!("reloaded {} + {}", self.vertex.name, self.fragment.name);
+                self.program = program;
+                self.dependencies = stamp(&dependencies);
+                self.reloaded = true;
+                self.checked.clear();
+                true
+            }
+            Err(error) => {
+                report(&self.vertex, &self.fragment, &error);
+                log::warn!("keeping the previous program");
+                // don't keep retrying the same broken files every poll, wait for the next save.
+                for (path, stamp) in self.dependencies.iter_mut() {
+                    *stamp = modified(path);
+                }
+                false
+            }
+        }
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Compares the program's attributes and uniforms with what the draw call binds, once per build and set
+    /// of vertex formats, so a program drawn with two different vertex layouts gets both checked.
+    /// Unused vertex fields and uniforms are only warned about. Anything missing or of the wrong type is an
+    /// error for the program the stage started with; after a hot reload it's printed and drawing goes on.
+    pub fn check_bindings<U: Uniforms>(&mut self, formats: &[&VertexFormat], uniforms: &U) -> Result<()> {
+        if self.checked.iter().any(|checked| checked.iter().eq(formats.iter().copied())) {
+            return Ok(());
+        }
+        self.checked.push(formats.iter().map(|&format| format.clone()).collect());
+
+        let name = format!("{} + {}", self.vertex.name, self.fragment.name);
+        let (errors, warnings): (Vec<Problem>, Vec<Problem>) = reflect::check(&self.program, formats, uniforms)
+            .into_iter()
+            .partition(Problem::is_error);
+
+        for warning in &warnings {
+            log::warn!("{}: {}", name, warning);
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let error = Error::Bindings { name, problems: errors.iter().map(Problem::to_string).collect() };
+        if self.reloaded {
+            log::error!("{}", error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+}
+
+impl Deref for HotProgram {
+    type Target = Program;
+
+    fn deref(&self) -> &Program {
+        &self.program
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn stamp(files: &[String]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    files.iter().map(|name| {
+        let path = shader_dir().join(name);
+        let stamp = modified(&path);
+        (path, stamp)
+    }).collect()
+}
+
+/// Preprocesses and compiles both shaders, returns the program and every file it was built from.
+fn build(
+    display: &Display,
+    vertex: &ShaderFile,
+    fragment: &ShaderFile,
+    outputs_srgb: bool,
+    preprocessor: &Preprocessor,
+    origin: Origin,
+) -> Result<(Program, Vec<String>), BuildError> {
+    let vertex_src = preprocessor.process(vertex, origin).map_err(BuildError::Preprocess)?;
+    let fragment_src = preprocessor.process(fragment, origin).map_err(BuildError::Preprocess)?;
+
+    let program = compile(display, &vertex_src.source, &fragment_src.source, outputs_srgb)
+        .map_err(|error| BuildError::Program(error, vertex_src.source_map.clone(), fragment_src.source_map.clone()))?;
+
+    let mut files = Vec::new();
+    for name in vertex_src.source_map.files.into_iter().chain(fragment_src.source_map.files) {
+        if !files.contains(&name) && !preprocessor.is_virtual(&name) {
+            files.push(name);
+        }
+    }
+
+    Ok((program, files))
+}
+
+pub fn compile(display: &Display, vertex: &str, fragment: &str, outputs_srgb: bool) -> Result<Program, ProgramCreationError> {
+    let program = Program::new(display, ProgramCreationInput::SourceCode {
+        vertex_shader: vertex,
+        tessellation_control_shader: None,
+        tessellation_evaluation_shader: None,
+        geometry_shader: None,
+        fragment_shader: fragment,
+        transform_feedback_varyings: None,
+        outputs_srgb,
+        uses_point_size: false,
+    })?;
+    trace::register_program(&program);
+    Ok(program)
+}
+
+fn report(vertex: &ShaderFile, fragment: &ShaderFile, error: &BuildError) {
+    let details = match error {
+        BuildError::Preprocess(error) => error.to_string(),
+        BuildError::Program(ProgramCreationError::CompilationError(log, shader_type), vertex_map, fragment_map) => {
+            let source_map = match shader_type {
+                glium::program::ShaderType::Vertex => vertex_map,
+                _ => fragment_map,
+            };
+            format!("{:?} shader didn't compile:\n{}", shader_type, source_map.annotate(log))
+        }
+        BuildError::Program(ProgramCreationError::LinkingError(log), _, _) => format!("linking failed:\n{}", log),
+        BuildError::Program(other, _, _) => other.to_string(),
+    };
+    log::error!("failed to build {} + {}:\n{}", vertex.name, fragment.name, details);
+}
@@ -0,0 +1,431 @@
+#![allow(dead_code)]
+use std::{
+    f32::consts::{PI, TAU},
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{CubeLayer, Cubemap, MipmapsOption, RawImage2d, Texture2d, TextureAny, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue, Uniforms};
+use glium::{uniform, Display, Rect, Surface};
+use image::{DynamicImage, Rgb32FImage};
+
+use crate::error::{Error, Result};
+use super::assets;
+use super::hot_reload::{shader_file, HotProgram};
+use super::shadows::CUBE_FACES;
+use super::stage15::fullscreen_quad;
+
+// Image based lighting: the light coming from everywhere around, out of a picture of everywhere around
+// (an equirectangular .hdr, the kind a 360° camera makes). Stage 22's flat ambient color becomes
+//  - an irradiance cubemap, what a diffuse surface facing each way gets from the whole hemisphere,
+//  - a prefiltered cubemap, the environment blurred as much as a surface of roughness 0, 0.25, ... 1 blurs
+//    its reflection, one mip level each,
+//  - and a lookup table for the rest of the specular BRDF (the split sum approximation, see ibl_brdf.frag).
+// All of it is drawn on the GPU, one cube face at a time, which takes a few seconds for a big image. So
+// it's read back and saved (as .hdr files, in ibl-cache/) and loaded from there the next time.
+
+/// Mip levels of the prefiltered map, roughness 0 to 1.
+pub const PREFILTERED_LEVELS: u32 = 5;
+
+/// Goes into every cache key, bump it when the ibl_*.frag shaders change what they make.
+const CACHE_VERSION: u32 = 1;
+
+/// Written last, so if it's there the rest of the cache is too.
+const BRDF_FILE: &str = "brdf.hdr";
+
+/// Width (and height) of every map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IblSizes {
+    pub environment: u32,
+    pub irradiance: u32,
+    pub prefiltered: u32,
+    pub brdf_lut: u32,
+}
+
+impl Default for IblSizes {
+    fn default() -> Self {
+        IblSizes { environment: 512, irradiance: 32, prefiltered: 128, brdf_lut: 256 }
+    }
+}
+
+pub fn cache_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("ibl-cache")
+}
+
+/// `assets/textures/<name>` as linear floats, top row first.
+pub fn load_equirect(name: &str) -> Result<Rgb32FImage> {
+    Ok(assets::load_image(name)?.to_rgb32f())
+}
+
+/// An equirectangular sky for when there's no .hdr around: blue fading to a bright horizon, a dark brown
+/// ground and a sun much brighter than anything a screen can show.
+pub fn procedural_sky(width: u32, height: u32) -> Rgb32FImage {
+    let sun = {
+        let [x, y, z] = [-0.5f32, 0.45, 0.75];
+        let length = (x * x + y * y + z * z).sqrt();
+        [x / length, y / length, z / length]
+    };
+    Rgb32FImage::from_fn(width, height, |column, row| {
+        let direction = equirect_direction((column as f32 + 0.5) / width as f32, (row as f32 + 0.5) / height as f32);
+        let up = direction[1];
+
+        let mix = |a: [f32; 3], b: [f32; 3], t: f32| [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t);
+        let mut color = if up > 0.0 {
+            mix([1.6, 1.5, 1.4], [0.25, 0.45, 1.0], up.powf(0.4))
+        } else {
+            mix([0.35, 0.3, 0.25], [0.08, 0.06, 0.05], (-up).powf(0.5))
+        };
+        let towards_sun: f32 = (0..3).map(|i| direction[i] * sun[i]).sum();
+        if towards_sun > 0.9995 {
+            color = [800.0, 750.0, 650.0];
+        } else {
+            // a little glow around it.
+            let glow = towards_sun.max(0.0).powf(200.0) * 4.0;
+            color = [color[0] + glow, color[1] + glow * 0.9, color[2] + glow * 0.7];
+        }
+        image::Rgb(color)
+    })
+}
+
+/// Which way `(u, v)` of an equirectangular image looks, the inverse of `equirect_uv` in ibl_sampling.glsl.
+fn equirect_direction(u: f32, v: f32) -> [f32; 3] {
+    let (phi, theta) = ((u - 0.5) * TAU, v * PI);
+    [theta.sin() * phi.sin(), theta.cos(), theta.sin() * phi.cos()]
+}
+
+/// The maps, ready to light with.
+pub struct Environment {
+    /// The environment itself, for the background and to make the others from. Has mipmaps.
+    pub environment: Cubemap,
+    pub irradiance: Cubemap,
+    /// Mip level i is roughness i / (PREFILTERED_LEVELS - 1).
+    pub prefiltered: Cubemap,
+    /// r: scale, g: bias on the base reflectivity. x is the cosine of the viewing angle, y the roughness.
+    pub brdf_lut: Texture2d,
+}
+
+impl Environment {
+    /// From the equirectangular image `assets/textures/<name>`, out of the cache if it's been done before
+    /// (with the same file and sizes).
+    pub fn load(display: &Display, name: &str, sizes: IblSizes) -> Result<Self> {
+        let path = assets::texture_dir().join(name);
+        let metadata = fs::metadata(&path).map_err(|error| Error::asset(&path, error))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        let source = format!("{}-{}-{}", name.replace(['/', '\\', '.'], "_"), metadata.len(), modified);
+        Self::cached(display, &cache_key(&source, sizes), sizes, || load_equirect(name))
+    }
+
+    /// From `procedural_sky`.
+    pub fn procedural(display: &Display, sizes: IblSizes) -> Result<Self> {
+        Self::cached(display, &cache_key("procedural_sky", sizes), sizes, || Ok(procedural_sky(2048, 1024)))
+    }
+
+    fn cached(display: &Display, key: &str, sizes: IblSizes, equirect: impl FnOnce() -> Result<Rgb32FImage>) -> Result<Self> {
+        let dir = cache_dir().join(key);
+        let environment = Self::empty(display, sizes)?;
+        if dir.join(BRDF_FILE).exists() {
+            match environment.read_cache(display, &dir) {
+                Ok(()) => return Ok(environment),
+                Err(error) => log::warn!("{}, making the image based lighting maps again", error),
+            }
+        }
+
+        log::info!("making the image based lighting maps, they'll be cached in {}", dir.display());
+        environment.generate(display, &equirect()?)?;
+        environment.write_cache(&dir)?;
+        Ok(environment)
+    }
+
+    fn empty(display: &Display, sizes: IblSizes) -> Result<Self> {
+        // RGB16F doesn't have to be something you can draw to, RGBA16F does.
+        let format = UncompressedFloatFormat::F16F16F16F16;
+        Ok(Environment {
+            environment: Cubemap::empty_with_format(display, format, MipmapsOption::EmptyMipmaps, sizes.environment)?,
+            irradiance: Cubemap::empty_with_format(display, format, MipmapsOption::NoMipmap, sizes.irradiance)?,
+            prefiltered: Cubemap::empty_with_format(
+                display,
+                format,
+                MipmapsOption::EmptyMipmapsMax(PREFILTERED_LEVELS - 1),
+                sizes.prefiltered,
+            )?,
+            brdf_lut: Texture2d::empty_with_format(
+                display,
+                UncompressedFloatFormat::F16F16,
+                MipmapsOption::NoMipmap,
+                sizes.brdf_lut,
+                sizes.brdf_lut,
+            )?,
+        })
+    }
+
+    /// Every map, drawn from scratch.
+    fn generate(&self, display: &Display, equirect: &Rgb32FImage) -> Result<()> {
+        let quad = glium::VertexBuffer::new(display, &fullscreen_quad())?;
+        let strip = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+
+        // the equirectangular image onto the cube.
+        let (width, height) = equirect.dimensions();
+        let raw = RawImage2d::from_raw_rgb(equirect.as_raw().clone(), (width, height));
+        let source = Texture2d::with_format(display, raw, UncompressedFloatFormat::F32F32F32, MipmapsOption::NoMipmap)?;
+        let mut program = HotProgram::new(display, shader_file!("ibl_face.vert"), shader_file!("ibl_equirect.frag"))?;
+        let sampler = source.sampled().minify_filter(MinifySamplerFilter::Linear).wrap_function(SamplerWrapFunction::Repeat);
+        draw_faces(display, &self.environment, 0, &mut program, &uniform! { equirect: sampler })?;
+        self.generate_environment_mipmaps();
+
+        // the mips keep the sparse samples of the next two passes from missing small bright things.
+        let environment_sampler = self
+            .environment
+            .sampled()
+            .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+            .magnify_filter(MagnifySamplerFilter::Linear);
+
+        let mut program = HotProgram::new(display, shader_file!("ibl_face.vert"), shader_file!("ibl_irradiance.frag"))?;
+        // about as big as the angle between two samples.
+        let sample_delta = 0.025f32;
+        let sample_level = (self.environment.width() as f32 * sample_delta / (PI / 2.0)).log2().max(0.0);
+        let uniforms = uniform! {
+            environment_map: environment_sampler,
+            sample_delta: sample_delta,
+            sample_level: sample_level
+        };
+        draw_faces(display, &self.irradiance, 0, &mut program, &uniforms)?;
+
+        let mut program = HotProgram::new(display, shader_file!("ibl_face.vert"), shader_file!("ibl_prefilter.frag"))?;
+        for level in 0..PREFILTERED_LEVELS {
+            let roughness = level as f32 / (PREFILTERED_LEVELS - 1) as f32;
+            let uniforms = uniform! {
+                environment_map: environment_sampler,
+                environment_size: self.environment.width() as f32,
+                roughness: roughness
+            };
+            draw_faces(display, &self.prefiltered, level, &mut program, &uniforms)?;
+        }
+
+        let mut program = HotProgram::new(display, shader_file!("stage15.vert"), shader_file!("ibl_brdf.frag"))?;
+        let mut target = SimpleFrameBuffer::new(display, &self.brdf_lut)?;
+        let uniforms = uniform! {};
+        program.check_bindings(&[quad.get_bindings()], &uniforms)?;
+        target.draw(&quad, strip, &program, &uniforms, &Default::default())?;
+
+        Ok(())
+    }
+
+    fn generate_environment_mipmaps(&self) {
+        // SAFETY: level 0 is all drawn, the others are what's being made here.
+        unsafe { self.environment.generate_mipmaps() };
+    }
+
+    fn read_cache(&self, display: &Display, dir: &Path) -> Result<()> {
+        let upload = |image: Rgb32FImage, target: SimpleFrameBuffer| -> Result<()> {
+            let dimensions = image.dimensions();
+            let raw = RawImage2d::from_raw_rgb(image.into_raw(), dimensions);
+            let texture = Texture2d::with_format(display, raw, UncompressedFloatFormat::F32F32F32, MipmapsOption::NoMipmap)?;
+            texture.as_surface().fill(&target, MagnifySamplerFilter::Nearest);
+            Ok(())
+        };
+        let cubemap = |cubemap: &Cubemap, name: &str, levels: u32| -> Result<()> {
+            for level in 0..levels {
+                let mipmap = cubemap.mipmap(level).ok_or_else(|| Error::Framebuffer(format!("the cubemap has no mip level {}", level)))?;
+                for (layer, _) in CUBE_FACES {
+                    let image = read_hdr(&dir.join(cache_file(name, level, layer)))?;
+                    upload(image, SimpleFrameBuffer::new(display, mipmap.image(layer))?)?;
+                }
+            }
+            Ok(())
+        };
+
+        cubemap(&self.environment, "environment", 1)?;
+        self.generate_environment_mipmaps();
+        cubemap(&self.irradiance, "irradiance", 1)?;
+        cubemap(&self.prefiltered, "prefiltered", PREFILTERED_LEVELS)?;
+        upload(read_hdr(&dir.join(BRDF_FILE))?, SimpleFrameBuffer::new(display, &self.brdf_lut)?)
+    }
+
+    fn write_cache(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).map_err(|error| Error::write(dir, error))?;
+        let cubemap = |cubemap: &Cubemap, name: &str, levels: u32| -> Result<()> {
+            for level in 0..levels {
+                for (layer, _) in CUBE_FACES {
+                    write_hdr(&dir.join(cache_file(name, level, layer)), read_back(cubemap, level, Some(layer))?)?;
+                }
+            }
+            Ok(())
+        };
+
+        // the environment's mips are quicker to make again than to load.
+        cubemap(&self.environment, "environment", 1)?;
+        cubemap(&self.irradiance, "irradiance", 1)?;
+        cubemap(&self.prefiltered, "prefiltered", PREFILTERED_LEVELS)?;
+        write_hdr(&dir.join(BRDF_FILE), read_back(&self.brdf_lut, 0, None)?)
+    }
+
+    /// The maps as ibl.glsl wants them (and `environment_map` for the background), then everything in `extra`.
+    pub fn uniforms<'a, U: Uniforms>(&'a self, extra: &'a U) -> EnvironmentUniforms<'a, U> {
+        EnvironmentUniforms { environment: self, extra }
+    }
+}
+
+/// Draws `program` over all six faces of mip `level` of `cubemap`, with `face` set to each one's axes.
+fn draw_faces<U: Uniforms>(display: &Display, cubemap: &Cubemap, level: u32, program: &mut HotProgram, extra: &U) -> Result<()> {
+    let quad = glium::VertexBuffer::new(display, &fullscreen_quad())?;
+    let strip = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+    let mipmap = cubemap.mipmap(level).ok_or_else(|| Error::Framebuffer(format!("the cubemap has no mip level {}", level)))?;
+    // the rows of a face's view matrix are the columns of `face`: where its s and t go, and which way it faces.
+    for (layer, face) in CUBE_FACES {
+        let mut target = SimpleFrameBuffer::new(display, mipmap.image(layer))?;
+        let uniforms = FaceUniforms { face, extra };
+        program.check_bindings(&[quad.get_bindings()], &uniforms)?;
+        target.draw(&quad, strip, &*program, &uniforms, &Default::default())?;
+    }
+    Ok(())
+}
+
+/// One directory per source image and sizes, so changing either doesn't pick up the wrong maps.
+fn cache_key(source: &str, sizes: IblSizes) -> String {
+    format!(
+        "{}-{}-{}-{}-{}-v{}",
+        source, sizes.environment, sizes.irradiance, sizes.prefiltered, sizes.brdf_lut, CACHE_VERSION,
+    )
+}
+
+fn cache_file(name: &str, level: u32, face: CubeLayer) -> String {
+    let face = match face {
+        CubeLayer::PositiveX => "px",
+        CubeLayer::NegativeX => "nx",
+        CubeLayer::PositiveY => "py",
+        CubeLayer::NegativeY => "ny",
+        CubeLayer::PositiveZ => "pz",
+        CubeLayer::NegativeZ => "nz",
+    };
+    format!("{}-{}-{}.hdr", name, level, face)
+}
+
+/// Mip `level` of `texture` (the `face` of a cubemap) back from the GPU. The rows come bottom first and go
+/// back up the same way, so the files look upside down but the textures come out right.
+fn read_back(texture: &TextureAny, level: u32, face: Option<CubeLayer>) -> Result<Rgb32FImage> {
+    let mipmap = texture.mipmap(level).ok_or_else(|| Error::ReadBack(format!("no mip level {}", level)))?;
+    let (width, height) = (mipmap.get_width(), mipmap.get_height().unwrap_or(1));
+    let image = mipmap
+        .first_layer()
+        .into_image(face)
+        .ok_or_else(|| Error::ReadBack("cube faces are only for cubemaps".to_string()))?;
+    let raw: RawImage2d<f32> = image.raw_read::<_, (f32, f32, f32, f32)>(&Rect { left: 0, bottom: 0, width, height });
+    let rgb = raw.data.chunks_exact(4).flat_map(|texel| [texel[0], texel[1], texel[2]]).collect();
+    Rgb32FImage::from_raw(width, height, rgb).ok_or_else(|| Error::ReadBack(format!("expected {}x{} texels", width, height)))
+}
+
+fn read_hdr(path: &Path) -> Result<Rgb32FImage> {
+    Ok(image::open(path).map_err(|error| Error::asset(path, error))?.to_rgb32f())
+}
+
+fn write_hdr(path: &Path, image: Rgb32FImage) -> Result<()> {
+    DynamicImage::ImageRgb32F(image).save(path).map_err(|error| Error::write(path, error))
+}
+
+/// The face being drawn, then whatever the pass needs.
+struct FaceUniforms<'a, U> {
+    face: [[f32; 3]; 3],
+    extra: &'a U,
+}
+
+impl<'b, U: Uniforms> Uniforms for FaceUniforms<'b, U> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        f("face", UniformValue::Mat3(self.face));
+        self.extra.visit_values(f);
+    }
+}
+
+pub struct EnvironmentUniforms<'a, U> {
+    environment: &'a Environment,
+    extra: &'a U,
+}
+
+impl<'b, U: Uniforms> Uniforms for EnvironmentUniforms<'b, U> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        let linear = SamplerBehavior {
+            minify_filter: MinifySamplerFilter::Linear,
+            magnify_filter: MagnifySamplerFilter::Linear,
+            ..Default::default()
+        };
+        let mipmapped = SamplerBehavior { minify_filter: MinifySamplerFilter::LinearMipmapLinear, ..linear };
+        let clamped = SamplerBehavior { wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp), ..linear };
+
+        f("environment_map", UniformValue::Cubemap(&self.environment.environment, Some(mipmapped)));
+        f("irradiance_map", UniformValue::Cubemap(&self.environment.irradiance, Some(linear)));
+        f("prefiltered_map", UniformValue::Cubemap(&self.environment.prefiltered, Some(mipmapped)));
+        f("brdf_lut", UniformValue::Texture2d(&self.environment.brdf_lut, Some(clamped)));
+        f("prefiltered_levels", UniformValue::Float(PREFILTERED_LEVELS as f32));
+        self.extra.visit_values(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ibl_sampling.glsl's `equirect_uv`.
+    fn equirect_uv(direction: [f32; 3]) -> (f32, f32) {
+        let [x, y, z] = direction;
+        (x.atan2(z) / TAU + 0.5, y.clamp(-1.0, 1.0).acos() / PI)
+    }
+
+    #[test]
+    fn equirect_directions_go_back_to_where_they_came_from() {
+        for (u, v) in [(0.5, 0.5), (0.1, 0.2), (0.9, 0.75), (0.3, 0.95), (0.74, 0.05)] {
+            let (back_u, back_v) = equirect_uv(equirect_direction(u, v));
+            assert!((back_u - u).abs() < 1e-5 && (back_v - v).abs() < 1e-5, "({}, {}) came back as ({}, {})", u, v, back_u, back_v);
+        }
+        // the top row looks up, the middle of the image down +z.
+        assert!(equirect_direction(0.5, 0.0)[1] > 0.999);
+        let forward = equirect_direction(0.5, 0.5);
+        assert!((forward[2] - 1.0).abs() < 1e-6, "{:?}", forward);
+    }
+
+    #[test]
+    fn the_procedural_sky_is_brighter_above_than_below() {
+        let sky = procedural_sky(64, 32);
+        let (above, below) = (sky.get_pixel(0, 8), sky.get_pixel(0, 24));
+        assert!(above.0[2] > below.0[2], "{:?} above, {:?} below", above, below);
+    }
+
+    #[test]
+    fn cache_keys_change_with_every_size() {
+        let sizes = IblSizes::default();
+        let key = cache_key("sky", sizes);
+        assert_eq!(key, format!("sky-512-32-128-256-v{}", CACHE_VERSION));
+        for other in [
+            IblSizes { environment: 256, ..sizes },
+            IblSizes { irradiance: 16, ..sizes },
+            IblSizes { prefiltered: 64, ..sizes },
+            IblSizes { brdf_lut: 128, ..sizes },
+        ] {
+            assert_ne!(cache_key("sky", other), key);
+        }
+        assert_ne!(cache_key("other_sky", sizes), key);
+    }
+
+    #[test]
+    fn hdr_files_round_trip() {
+        let path = std::env::temp_dir().join(format!("gp-tuts-ibl-{}.hdr", std::process::id()));
+        let image = Rgb32FImage::from_fn(4, 3, |x, y| image::Rgb([x as f32 * 0.5, y as f32 * 100.0, 0.125]));
+        write_hdr(&path, image.clone()).unwrap();
+        let back = read_hdr(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(back.dimensions(), image.dimensions());
+        // .hdr keeps 8 bits of mantissa per channel, with one exponent for the pixel's brightest channel.
+        for (ours, theirs) in image.pixels().zip(back.pixels()) {
+            let brightest = ours.0.iter().fold(0.0f32, |max, channel| max.max(*channel));
+            for (a, b) in ours.0.iter().zip(theirs.0) {
+                assert!((a - b).abs() <= brightest / 128.0, "{:?} came back as {:?}", ours, theirs);
+            }
+        }
+    }
+}
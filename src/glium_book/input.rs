@@ -0,0 +1,427 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use glium::{
+    glutin::{
+        dpi::{PhysicalPosition, PhysicalSize},
+        event::{DeviceId, ElementState, ModifiersState, MouseButton, MouseScrollDelta, TouchPhase, VirtualKeyCode, WindowEvent},
+    },
+    Display,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use super::runner::LoopEvent;
+use super::trace::OffscreenFrame;
+
+// Recording what a stage was sent and sending it again. `--record-input FILE` writes down every key and
+// window event the stage got along with which frame it arrived before, and the time of every frame.
+// `--play-input FILE` runs the stage in a hidden window, as fast as it can, with live input ignored: each
+// frame gets the events recorded for it and the runner's clock (`runner::now`) says the time the frame had
+// when it was recorded, so anything driven by either comes out the same every time. The frames are drawn
+// offscreen rather than into the hidden window. When the recording runs out the last frame is saved next
+// to it as a png and the stage exits, which makes interactive stages testable headless (under xvfb-run)
+// by comparing that png, or `--trace` output, against a known good one. The stage has to be started with
+// the same parameters it was recorded with.
+
+static RECORD: Mutex<Option<PathBuf>> = Mutex::new(None);
+static PLAY: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Makes the next stage that runs record its input to `path`.
+pub fn record(path: PathBuf) {
+    *RECORD.lock().unwrap() = Some(path);
+}
+
+/// Makes the next stage that runs play its input back from `path`.
+pub fn play(path: PathBuf) {
+    *PLAY.lock().unwrap() = Some(path);
+}
+
+/// An event the way it reaches the stage, owned so it can be kept around.
+#[derive(Debug, PartialEq)]
+pub enum Recorded {
+    Key(VirtualKeyCode, ElementState),
+    Window(WindowEvent<'static>),
+}
+
+impl Recorded {
+    pub fn loop_event(&self) -> LoopEvent<'_, 'static> {
+        match self {
+            Recorded::Key(key, state) => LoopEvent::Key(*key, *state),
+            Recorded::Window(event) => LoopEvent::Window(event),
+        }
+    }
+}
+
+fn pressed(state: ElementState) -> bool {
+    state == ElementState::Pressed
+}
+
+fn state(pressed: bool) -> ElementState {
+    if pressed { ElementState::Pressed } else { ElementState::Released }
+}
+
+macro_rules! key_names {
+    ($($key:ident)*) => {
+        /// Every key by the name it's recorded under, winit's name for it.
+        const KEYS: &[(&str, VirtualKeyCode)] = &[$((stringify!($key), VirtualKeyCode::$key)),*];
+    };
+}
+
+key_names! {
+    Key1 Key2 Key3 Key4 Key5 Key6 Key7 Key8 Key9 Key0 A B C D E F G H I J K L M N O P Q R S T U V W X Y Z Escape
+    F1 F2 F3 F4 F5 F6 F7 F8 F9 F10 F11 F12 F13 F14 F15 F16 F17 F18 F19 F20 F21 F22 F23 F24 Snapshot Scroll Pause
+    Insert Home Delete End PageDown PageUp Left Up Right Down Back Return Space Compose Caret Numlock Numpad0
+    Numpad1 Numpad2 Numpad3 Numpad4 Numpad5 Numpad6 Numpad7 Numpad8 Numpad9 NumpadAdd NumpadDivide NumpadDecimal
+    NumpadComma NumpadEnter NumpadEquals NumpadMultiply NumpadSubtract AbntC1 AbntC2 Apostrophe Apps Asterisk At
+    Ax Backslash Calculator Capital Colon Comma Convert Equals Grave Kana Kanji LAlt LBracket LControl LShift
+    LWin Mail MediaSelect MediaStop Minus Mute MyComputer NavigateForward NavigateBackward NextTrack NoConvert
+    OEM102 Period PlayPause Plus Power PrevTrack RAlt RBracket RControl RShift RWin Semicolon Slash Sleep Stop
+    Sysrq Tab Underline Unlabeled VolumeDown VolumeUp Wake WebBack WebFavorites WebForward WebHome WebRefresh
+    WebSearch WebStop Yen Copy Paste Cut
+}
+
+fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+    KEYS.iter().find(|(_, known)| *known == key).map(|(name, _)| *name)
+}
+
+fn key_by_name(name: &str) -> Option<VirtualKeyCode> {
+    KEYS.iter().find(|(known, _)| *known == name).map(|(_, key)| *key)
+}
+
+/// A recording, what `--record-input` writes.
+#[derive(Serialize, Deserialize)]
+struct Recording {
+    /// Seconds since the epoch.
+    start: f64,
+    dimensions: (u32, u32),
+    events: Vec<FrameInput>,
+    /// The time of every frame, in seconds.
+    frames: Vec<f64>,
+}
+
+/// An event and the frame it arrived before.
+#[derive(Serialize, Deserialize)]
+struct FrameInput {
+    frame: usize,
+    #[serde(flatten)]
+    input: EventRecord,
+}
+
+/// The events worth recording (window moves, file drops, IME, ... aren't), as they're written down.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum EventRecord {
+    Key { key: String, pressed: bool },
+    Resized { size: (u32, u32) },
+    Focused { focused: bool },
+    Character { character: char },
+    Modifiers { bits: u32 },
+    CursorMoved { position: (f64, f64) },
+    CursorEntered,
+    CursorLeft,
+    MouseInput { button: Button, pressed: bool },
+    WheelLines { delta: (f32, f32) },
+    WheelPixels { delta: (f64, f64) },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Button {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+
+impl EventRecord {
+    fn from_event(event: &LoopEvent) -> Option<EventRecord> {
+        Some(match event {
+            LoopEvent::Key(key, state) => EventRecord::Key { key: key_name(*key)?.to_string(), pressed: pressed(*state) },
+            LoopEvent::Window(event) => match event {
+                WindowEvent::Resized(size) => EventRecord::Resized { size: (size.width, size.height) },
+                WindowEvent::Focused(focused) => EventRecord::Focused { focused: *focused },
+                WindowEvent::ReceivedCharacter(character) => EventRecord::Character { character: *character },
+                WindowEvent::ModifiersChanged(modifiers) => EventRecord::Modifiers { bits: modifiers.bits() },
+                WindowEvent::CursorMoved { position, .. } => EventRecord::CursorMoved { position: (position.x, position.y) },
+                WindowEvent::CursorEntered { .. } => EventRecord::CursorEntered,
+                WindowEvent::CursorLeft { .. } => EventRecord::CursorLeft,
+                WindowEvent::MouseInput { state, button, .. } => {
+                    let button = match *button {
+                        MouseButton::Left => Button::Left,
+                        MouseButton::Right => Button::Right,
+                        MouseButton::Middle => Button::Middle,
+                        MouseButton::Other(other) => Button::Other(other),
+                    };
+                    EventRecord::MouseInput { button, pressed: pressed(*state) }
+                }
+                WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(x, y), .. } => EventRecord::WheelLines { delta: (*x, *y) },
+                WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(delta), .. } => EventRecord::WheelPixels { delta: (delta.x, delta.y) },
+                _ => return None,
+            },
+            LoopEvent::Draw(_) => return None,
+        })
+    }
+
+    /// The event again, None for a key this version of winit doesn't have.
+    #[allow(deprecated)] // the `modifiers` fields, winit wants them filled in even though it deprecated them.
+    fn recorded(&self) -> Option<Recorded> {
+        // winit has no way to make a real one, these events never go back to winit anyway.
+        let device_id = unsafe { DeviceId::dummy() };
+        let modifiers = ModifiersState::default();
+        let window = match *self {
+            EventRecord::Key { ref key, pressed } => return Some(Recorded::Key(key_by_name(key)?, state(pressed))),
+            EventRecord::Resized { size: (width, height) } => WindowEvent::Resized(PhysicalSize::new(width, height)),
+            EventRecord::Focused { focused } => WindowEvent::Focused(focused),
+            EventRecord::Character { character } => WindowEvent::ReceivedCharacter(character),
+            EventRecord::Modifiers { bits } => WindowEvent::ModifiersChanged(ModifiersState::from_bits_truncate(bits)),
+            EventRecord::CursorMoved { position: (x, y) } => WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(x, y), modifiers },
+            EventRecord::CursorEntered => WindowEvent::CursorEntered { device_id },
+            EventRecord::CursorLeft => WindowEvent::CursorLeft { device_id },
+            EventRecord::MouseInput { button, pressed } => {
+                let button = match button {
+                    Button::Left => MouseButton::Left,
+                    Button::Right => MouseButton::Right,
+                    Button::Middle => MouseButton::Middle,
+                    Button::Other(other) => MouseButton::Other(other),
+                };
+                WindowEvent::MouseInput { device_id, state: state(pressed), button, modifiers }
+            }
+            EventRecord::WheelLines { delta: (x, y) } => {
+                WindowEvent::MouseWheel { device_id, delta: MouseScrollDelta::LineDelta(x, y), phase: TouchPhase::Moved, modifiers }
+            }
+            EventRecord::WheelPixels { delta: (x, y) } => {
+                let delta = MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y));
+                WindowEvent::MouseWheel { device_id, delta, phase: TouchPhase::Moved, modifiers }
+            }
+        };
+        Some(Recorded::Window(window))
+    }
+}
+
+/// Writes down the events and frame times of a stage as it runs.
+pub struct InputRecorder {
+    path: PathBuf,
+    start: SystemTime,
+    dimensions: (u32, u32),
+    /// The time of every frame so far.
+    frames: Vec<f64>,
+    events: Vec<FrameInput>,
+}
+
+impl InputRecorder {
+    pub fn new(display: &Display) -> Option<Self> {
+        let path = RECORD.lock().unwrap().clone()?;
+        println!("recording input to {}", path.display());
+        Some(InputRecorder {
+            path,
+            start: SystemTime::now(),
+            dimensions: display.get_framebuffer_dimensions(),
+            frames: Vec::new(),
+            events: Vec::new(),
+        })
+    }
+
+    /// When the recording started, `runner::wall_clock` counts from here.
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+
+    /// An event that was passed on to the stage, it belongs to the frame that gets drawn next.
+    pub fn event(&mut self, event: &LoopEvent) {
+        if let Some(input) = EventRecord::from_event(event) {
+            self.events.push(FrameInput { frame: self.frames.len(), input });
+        }
+    }
+
+    pub fn frame(&mut self, time: Duration) {
+        self.frames.push(time.as_secs_f64());
+    }
+
+    pub fn finish(self) -> Result<()> {
+        let frames = self.frames.len();
+        let recording = Recording {
+            start: self.start.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64(),
+            dimensions: self.dimensions,
+            events: self.events,
+            frames: self.frames,
+        };
+        let json = serde_json::to_string_pretty(&recording).map_err(|error| Error::write(&self.path, error))?;
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, json + "\n").map_err(|error| Error::write(&self.path, error))?;
+        println!("recorded {} frames of input to {}", frames, self.path.display());
+        Ok(())
+    }
+}
+
+/// Hands a recording back to the runner a frame at a time.
+pub struct InputPlayer {
+    path: PathBuf,
+    start: SystemTime,
+    dimensions: (u32, u32),
+    frames: Vec<Duration>,
+    /// By frame, in the order they arrived.
+    events: Vec<(usize, Recorded)>,
+    frame: usize,
+    /// What the frames are drawn into, the window is hidden so its own pixels can't be read back.
+    offscreen: Option<OffscreenFrame>,
+}
+
+impl InputPlayer {
+    pub fn new(display: &Display) -> Result<Option<Self>> {
+        let path = match PLAY.lock().unwrap().clone() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let player = Self::load(&path)?;
+
+        // hidden like a benchmark, and the size it was recorded at.
+        let window = display.gl_window();
+        window.window().set_visible(false);
+        window.window().set_inner_size(PhysicalSize::new(player.dimensions.0, player.dimensions.1));
+        Ok(Some(player))
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|error| Error::asset(path, error))?;
+        let recording: Recording = serde_json::from_str(&text).map_err(|error| Error::asset(path, error))?;
+
+        let mut events = Vec::new();
+        for (index, FrameInput { frame, input }) in recording.events.into_iter().enumerate() {
+            let event = input.recorded().ok_or_else(|| Error::asset(path, format!("event {} can't be played back", index)))?;
+            events.push((frame, event));
+        }
+
+        Ok(InputPlayer {
+            path: path.to_path_buf(),
+            start: UNIX_EPOCH + Duration::from_secs_f64(recording.start.max(0.0)),
+            dimensions: recording.dimensions,
+            frames: recording.frames.into_iter().map(|time| Duration::from_secs_f64(time.max(0.0))).collect(),
+            events,
+            frame: 0,
+            offscreen: None,
+        })
+    }
+
+    pub fn start(&self) -> SystemTime {
+        self.start
+    }
+
+    /// The next frame's time and the events that came before it, None once the recording is over.
+    pub fn next_frame(&mut self, display: &Display) -> Option<(Duration, Vec<&Recorded>)> {
+        let time = *self.frames.get(self.frame)?;
+        let frame = self.frame;
+        self.frame += 1;
+
+        let events: Vec<&Recorded> = self.events.iter().filter(|(at, _)| *at == frame).map(|(_, event)| event).collect();
+        for event in &events {
+            // the stage reads the framebuffer's size, so the window has to actually change.
+            if let Recorded::Window(WindowEvent::Resized(size)) = event {
+                display.gl_window().window().set_inner_size(*size);
+                self.dimensions = (size.width, size.height);
+            }
+        }
+        Some((time, events))
+    }
+
+    /// What to draw the next frame into, the size the window had when it was recorded.
+    pub fn target(&mut self, display: &Display) -> Result<OffscreenFrame> {
+        match self.offscreen.take() {
+            Some(offscreen) if offscreen.dimensions() == self.dimensions => Ok(offscreen),
+            _ => OffscreenFrame::new(display, self.dimensions),
+        }
+    }
+
+    /// Hands back the frame `target` gave out once it's drawn.
+    pub fn presented(&mut self, frame: OffscreenFrame) {
+        self.offscreen = Some(frame);
+    }
+
+    /// Saves the frame that was drawn last next to the recording.
+    pub fn finish(&self) -> Result<()> {
+        let offscreen = self.offscreen.as_ref().ok_or_else(|| Error::ReadBack("the recording has no frames".to_string()))?;
+        let path = self.path.with_extension("png");
+        offscreen.read()?.save(&path).map_err(|error| Error::write(&path, error))?;
+        println!("played back {} frames, the last one is in {}", self.frame, path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(deprecated)] // the `modifiers` fields, see `EventRecord::recorded`.
+    fn window_events() -> Vec<WindowEvent<'static>> {
+        let device_id = unsafe { DeviceId::dummy() };
+        let modifiers = ModifiersState::default();
+        vec![
+            WindowEvent::Resized(PhysicalSize::new(640, 480)),
+            WindowEvent::Focused(true),
+            WindowEvent::ReceivedCharacter('é'),
+            WindowEvent::ModifiersChanged(ModifiersState::SHIFT | ModifiersState::CTRL),
+            WindowEvent::CursorMoved { device_id, position: PhysicalPosition::new(10.5, 20.25), modifiers },
+            WindowEvent::CursorEntered { device_id },
+            WindowEvent::CursorLeft { device_id },
+            WindowEvent::MouseInput { device_id, state: ElementState::Pressed, button: MouseButton::Other(7), modifiers },
+            WindowEvent::MouseWheel { device_id, delta: MouseScrollDelta::LineDelta(0.0, -1.0), phase: TouchPhase::Moved, modifiers },
+            WindowEvent::MouseWheel {
+                device_id,
+                delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(3.0, 4.0)),
+                phase: TouchPhase::Moved,
+                modifiers,
+            },
+        ]
+    }
+
+    #[test]
+    fn every_key_name_is_winits() {
+        for (name, key) in KEYS {
+            assert_eq!(*name, format!("{:?}", key));
+        }
+    }
+
+    #[test]
+    fn a_recording_plays_back_the_events_it_recorded() {
+        let path = std::env::temp_dir().join(format!("gp-tuts-input-{}.json", std::process::id()));
+        let mut recorder = InputRecorder {
+            path: path.clone(),
+            start: UNIX_EPOCH + Duration::from_millis(1_600_000_000_250),
+            dimensions: (800, 600),
+            frames: Vec::new(),
+            events: Vec::new(),
+        };
+
+        let keys = [(VirtualKeyCode::Space, ElementState::Pressed), (VirtualKeyCode::NumpadAdd, ElementState::Released)];
+        let windows = window_events();
+        let mut expected = Vec::new();
+        for (key, state) in keys {
+            recorder.event(&LoopEvent::Key(key, state));
+            expected.push((0, Recorded::Key(key, state)));
+        }
+        recorder.frame(Duration::from_millis(16));
+        for event in &windows {
+            recorder.event(&LoopEvent::Window(event));
+        }
+        expected.extend(window_events().into_iter().map(|event| (1, Recorded::Window(event))));
+        recorder.frame(Duration::from_millis(33));
+        // not recorded.
+        recorder.event(&LoopEvent::Window(&WindowEvent::Moved(PhysicalPosition::new(1, 2))));
+        recorder.finish().unwrap();
+
+        let player = InputPlayer::load(&path);
+        fs::remove_file(&path).unwrap();
+        let player = player.unwrap();
+        assert_eq!(player.start(), UNIX_EPOCH + Duration::from_millis(1_600_000_000_250));
+        assert_eq!(player.dimensions, (800, 600));
+        assert_eq!(player.frames, [Duration::from_millis(16), Duration::from_millis(33)]);
+        assert_eq!(player.events, expected);
+    }
+}
@@ -0,0 +1,654 @@
+#![allow(dead_code)]
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use egui::{epaint, DragValue, Pos2, TextureId};
+use glium::{
+    glutin::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent},
+    implement_vertex,
+    texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat},
+    uniform,
+    uniforms::{MagnifySamplerFilter, MinifySamplerFilter, UniformType, UniformValue, Uniforms},
+    Blend, BlendingFunction, Display, LinearBlendingFactor, Program, Rect, Surface,
+};
+
+use crate::error::{Error, Result};
+use super::hot_reload::{shader_file, HotProgram};
+use super::preprocess::Preprocessor;
+use super::material::Value;
+use super::runner::{self, LoopEvent};
+
+// An egui window listing the active uniforms of a program (through reflection) with an editor for each
+// float, vector, color and matrix. Editing a uniform pins it: from then on the edited value is what the
+// program gets instead of what the stage computes, until it's reset. Pinned values can be saved as presets
+// in assets/presets/<stage>/<preset>.toml and loaded back later. F1 shows and hides the window.
+//
+// There's no egui integration for this glium version, so the painter and the input translation are here too.
+
+const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::F1;
+
+/// One uniform of the inspected program.
+pub struct Entry {
+    pub ty: UniformType,
+    /// None for samplers, blocks and the other things that can't be edited.
+    pub value: Option<Value>,
+    /// Set when the value was edited or loaded from a preset, the stage's value is ignored then.
+    pub pinned: bool,
+    /// Whether the current program still has this uniform (it might come back after the next reload).
+    active: bool,
+}
+
+/// The stage's uniforms with the pinned ones swapped out.
+pub struct Tweaked<'a, U> {
+    uniforms: &'a U,
+    entries: &'a BTreeMap<String, Entry>,
+}
+
+impl<'a, U: Uniforms> Uniforms for Tweaked<'a, U> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut f: F) {
+        let mut visited = Vec::new();
+        self.uniforms.visit_values(|name, value| {
+            match self.entries.get(name) {
+                Some(Entry { value: Some(pinned), pinned: true, .. }) => f(name, pinned.as_uniform()),
+                _ => f(name, value),
+            }
+            visited.push(name.to_string());
+        });
+
+        // pinned uniforms the stage never sets at all.
+        for (name, entry) in self.entries {
+            if let (Some(value), true) = (entry.value, entry.pinned && entry.active) {
+                if !visited.contains(name) {
+                    f(name, value.as_uniform());
+                }
+            }
+        }
+    }
+}
+
+pub struct Inspector {
+    name: String,
+    visible: bool,
+    entries: BTreeMap<String, Entry>,
+    presets: Vec<String>,
+    preset_name: String,
+    status: String,
+    context: egui::Context,
+    painter: Painter,
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    pointer: Pos2,
+    pixels_per_point: f32,
+}
+
+impl Inspector {
+    /// `name` is used for the window title and the preset folder, usually the stage's name.
+    pub fn new(display: &Display, name: &str) -> Result<Self> {
+        log::info!("F1 toggles the uniform inspector");
+
+        Ok(Inspector {
+            name: name.to_string(),
+            visible: false,
+            entries: BTreeMap::new(),
+            presets: Vec::new(),
+            preset_name: "default".to_string(),
+            status: String::new(),
+            context: egui::Context::default(),
+            painter: Painter::new(display)?,
+            events: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            pointer: Pos2::ZERO,
+            pixels_per_point: display.gl_window().window().scale_factor() as f32,
+        })
+    }
+
+    pub fn preset_dir(&self) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets").join("presets").join(&self.name)
+    }
+
+    /// Looks at what `program` has and what the stage sets this frame, and returns the uniforms to draw with.
+    pub fn tweak<'a, U: Uniforms>(&'a mut self, program: &Program, uniforms: &'a U) -> Tweaked<'a, U> {
+        for entry in self.entries.values_mut() {
+            entry.active = false;
+        }
+        for (name, uniform) in program.uniforms() {
+            let entry = self.entries.entry(name.clone()).or_insert_with(|| Entry {
+                ty: uniform.ty,
+                value: Value::zero(uniform.ty),
+                pinned: false,
+                active: true,
+            });
+            if entry.ty != uniform.ty {
+                // the shader changed the type, whatever was pinned doesn't fit anymore.
+                *entry = Entry { ty: uniform.ty, value: Value::zero(uniform.ty), pinned: false, active: true };
+            }
+            entry.active = true;
+        }
+
+        follow(&mut self.entries, uniforms);
+        Tweaked { uniforms, entries: &self.entries }
+    }
+
+    /// Feeds an event to egui. True if the inspector used it and the stage should ignore it.
+    pub fn handle_event(&mut self, event: &LoopEvent) -> bool {
+        if let LoopEvent::Key(TOGGLE_KEY, ElementState::Pressed) = event {
+            self.visible = !self.visible;
+            if self.visible {
+                self.refresh_presets();
+            }
+            return true;
+        }
+        if !self.visible {
+            return false;
+        }
+
+        match event {
+            LoopEvent::Key(code, state) => {
+                if let Some(key) = egui_key(*code) {
+                    self.events.push(egui::Event::Key { key, pressed: *state == ElementState::Pressed, modifiers: self.modifiers });
+                }
+                self.context.wants_keyboard_input()
+            }
+            LoopEvent::Window(WindowEvent::ModifiersChanged(state)) => {
+                self.modifiers = egui::Modifiers {
+                    alt: state.alt(),
+                    ctrl: state.ctrl(),
+                    shift: state.shift(),
+                    mac_cmd: false,
+                    command: state.ctrl(),
+                };
+                false
+            }
+            LoopEvent::Window(WindowEvent::CursorMoved { position, .. }) => {
+                self.pointer = Pos2::new(position.x as f32 / self.pixels_per_point, position.y as f32 / self.pixels_per_point);
+                self.events.push(egui::Event::PointerMoved(self.pointer));
+                self.context.is_pointer_over_area()
+            }
+            LoopEvent::Window(WindowEvent::CursorLeft { .. }) => {
+                self.events.push(egui::Event::PointerGone);
+                false
+            }
+            LoopEvent::Window(WindowEvent::MouseInput { state, button, .. }) => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                self.events.push(egui::Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+                self.context.wants_pointer_input()
+            }
+            LoopEvent::Window(WindowEvent::MouseWheel { delta, .. }) => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * 50.0,
+                    MouseScrollDelta::PixelDelta(delta) => egui::vec2(delta.x as f32, delta.y as f32) / self.pixels_per_point,
+                };
+                self.events.push(egui::Event::Scroll(delta));
+                self.context.is_pointer_over_area()
+            }
+            LoopEvent::Window(WindowEvent::ReceivedCharacter(character)) => {
+                if !character.is_control() {
+                    self.events.push(egui::Event::Text(character.to_string()));
+                }
+                self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    /// Draws the window on top of whatever is in `frame`, call it after the stage's own draw calls.
+    pub fn draw(&mut self, display: &Display, frame: &mut impl Surface) -> Result<()> {
+        if !self.visible {
+            self.events.clear();
+            return Ok(());
+        }
+
+        self.pixels_per_point = display.gl_window().window().scale_factor() as f32;
+        let (width, height) = frame.get_dimensions();
+        let raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                Pos2::ZERO,
+                egui::vec2(width as f32, height as f32) / self.pixels_per_point,
+            )),
+            pixels_per_point: Some(self.pixels_per_point),
+            time: Some(runner::now().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+
+        let context = self.context.clone();
+        let output = context.run(raw_input, |context| self.window(context));
+        let primitives = context.tessellate(output.shapes);
+        self.painter.paint(display, frame, self.pixels_per_point, &primitives, output.textures_delta)
+    }
+
+    fn window(&mut self, context: &egui::Context) {
+        egui::Window::new(format!("uniforms: {}", self.name))
+            .default_pos([10.0, 10.0])
+            .show(context, |ui| {
+                egui::Grid::new("uniforms").num_columns(3).striped(true).show(ui, |ui| {
+                    for (name, entry) in self.entries.iter_mut().filter(|(_, entry)| entry.active) {
+                        ui.monospace(name);
+                        match &mut entry.value {
+                            Some(value) => {
+                                if edit(ui, name, value) {
+                                    entry.pinned = true;
+                                }
+                            }
+                            None => {
+                                ui.weak(format!("{:?}", entry.ty));
+                            }
+                        }
+                        if entry.pinned && ui.small_button("reset").clicked() {
+                            entry.pinned = false;
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("preset");
+                    ui.text_edit_singleline(&mut self.preset_name);
+                    if ui.button("save").clicked() {
+                        self.status = match self.save_preset() {
+                            Ok(path) => format!("saved {}", path.display()),
+                            Err(error) => error.to_string(),
+                        };
+                        self.refresh_presets();
+                    }
+                });
+                let mut load = None;
+                ui.horizontal_wrapped(|ui| {
+                    for preset in &self.presets {
+                        if ui.button(preset).clicked() {
+                            load = Some(preset.clone());
+                        }
+                    }
+                });
+                if let Some(preset) = load {
+                    self.status = match self.load_preset(&preset) {
+                        Ok(()) => format!("loaded {}", preset),
+                        Err(error) => error.to_string(),
+                    };
+                    self.preset_name = preset;
+                }
+                if !self.status.is_empty() {
+                    ui.weak(&self.status);
+                }
+            });
+    }
+
+    fn refresh_presets(&mut self) {
+        self.presets = fs::read_dir(self.preset_dir())
+            .map(|entries| entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+                .filter_map(|path| Some(path.file_stem()?.to_string_lossy().to_string()))
+                .collect())
+            .unwrap_or_default();
+        self.presets.sort();
+    }
+
+    /// Writes every pinned uniform to `<preset dir>/<preset name>.toml`.
+    pub fn save_preset(&self) -> Result<PathBuf> {
+        let path = preset_path(&self.preset_dir(), &self.preset_name)
+            .ok_or_else(|| Error::write(self.preset_dir(), format!("`{}` isn't a preset name", self.preset_name)))?;
+        write_preset(&self.entries, &path)?;
+        Ok(path)
+    }
+
+    /// Pins every uniform in the preset that the program has, with a matching number of components.
+    pub fn load_preset(&mut self, preset: &str) -> Result<()> {
+        let path = preset_path(&self.preset_dir(), preset)
+            .ok_or_else(|| Error::asset(self.preset_dir(), format!("`{}` isn't a preset name", preset)))?;
+        read_preset(&mut self.entries, &path)
+    }
+}
+
+/// Unpinned entries take the value the stage sets, pinned ones keep theirs.
+fn follow<U: Uniforms>(entries: &mut BTreeMap<String, Entry>, uniforms: &U) {
+    uniforms.visit_values(|name, value| {
+        if let Some(entry) = entries.get_mut(name) {
+            if !entry.pinned {
+                entry.value = Value::from_uniform(&value).or(entry.value);
+            }
+        }
+    });
+}
+
+/// `<dir>/<preset>.toml`, None if the name would put it anywhere else.
+fn preset_path(dir: &Path, preset: &str) -> Option<PathBuf> {
+    let bad = preset.is_empty() || preset.contains(['/', '\\']) || preset.contains("..");
+    (!bad).then(|| dir.join(format!("{}.toml", preset)))
+}
+
+fn write_preset(entries: &BTreeMap<String, Entry>, path: &Path) -> Result<()> {
+    let values: BTreeMap<&str, Vec<f32>> = entries
+        .iter()
+        .filter_map(|(name, entry)| Some((name.as_str(), entry.value.filter(|_| entry.pinned)?.floats())))
+        .collect();
+
+    let text = toml::to_string(&values).map_err(|error| Error::write(path, error))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|error| Error::write(dir, error))?;
+    }
+    fs::write(path, text).map_err(|error| Error::write(path, error))
+}
+
+fn read_preset(entries: &mut BTreeMap<String, Entry>, path: &Path) -> Result<()> {
+    let text = fs::read_to_string(path).map_err(|error| Error::asset(path, error))?;
+    let values: BTreeMap<String, Vec<f32>> = toml::from_str(&text).map_err(|error| Error::asset(path, error))?;
+
+    let mut skipped = Vec::new();
+    for (name, floats) in values {
+        let loaded = match entries.get_mut(&name) {
+            Some(Entry { value: Some(value), pinned, .. }) => {
+                let fits = value.set_floats(&floats);
+                *pinned |= fits;
+                fits
+            }
+            _ => false,
+        };
+        if !loaded {
+            skipped.push(name);
+        }
+    }
+
+    if skipped.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::asset(path, format!("the program has no uniform(s) {} of that size", skipped.join(", "))))
+    }
+}
+
+fn is_color(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.contains("color") || name.contains("colour")
+}
+
+// every widget has to be added whether or not an earlier one changed, so no `any` in these two.
+fn drag_values(ui: &mut egui::Ui, values: &mut [f32]) -> bool {
+    ui.horizontal(|ui| {
+        let mut changed = false;
+        for x in values.iter_mut() {
+            changed |= ui.add(DragValue::new(x).speed(0.01)).changed();
+        }
+        changed
+    }).inner
+}
+
+/// One row per column, that's how they're written down in the stages too.
+fn drag_columns<const N: usize>(ui: &mut egui::Ui, columns: &mut [[f32; N]]) -> bool {
+    ui.vertical(|ui| {
+        let mut changed = false;
+        for column in columns.iter_mut() {
+            changed |= drag_values(ui, column);
+        }
+        changed
+    }).inner
+}
+
+/// The editor widget for one value, true if it was changed.
+fn edit(ui: &mut egui::Ui, name: &str, value: &mut Value) -> bool {
+    match value {
+        Value::Float(x) => ui.add(DragValue::new(x).speed(0.01)).changed(),
+        Value::Vec3(v) if is_color(name) => ui.color_edit_button_rgb(v).changed(),
+        Value::Vec4(v) if is_color(name) => ui.color_edit_button_rgba_unmultiplied(v).changed(),
+        Value::Vec2(v) => drag_values(ui, v),
+        Value::Vec3(v) => drag_values(ui, v),
+        Value::Vec4(v) => drag_values(ui, v),
+        Value::Mat2(m) => drag_columns(ui, m),
+        Value::Mat3(m) => drag_columns(ui, m),
+        Value::Mat4(m) => drag_columns(ui, m),
+        Value::Int(i) => ui.add(DragValue::new(i)).changed(),
+        Value::Bool(b) => ui.checkbox(b, "").changed(),
+    }
+}
+
+fn egui_key(code: VirtualKeyCode) -> Option<egui::Key> {
+    use egui::Key;
+
+    Some(match code {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+#[derive(Copy, Clone)]
+struct GuiVertex {
+    a_pos: [f32; 2],
+    a_tex_coord: [f32; 2],
+    a_srgba: [f32; 4],
+}
+
+implement_vertex!(GuiVertex, a_pos, a_tex_coord, a_srgba);
+
+/// Uploads egui's textures and draws its meshes with glium.
+struct Painter {
+    program: HotProgram,
+    textures: HashMap<TextureId, (Texture2d, epaint::textures::TextureFilter)>,
+}
+
+impl Painter {
+    fn new(display: &Display) -> Result<Self> {
+        // egui's colors are already in gamma space, so no sRGB conversion on the way out.
+        let program = HotProgram::with_preprocessor(
+            display,
+            shader_file!("inspector.vert"),
+            shader_file!("inspector.frag"),
+            true,
+            Preprocessor::new(),
+        )?;
+
+        Ok(Painter { program, textures: HashMap::new() })
+    }
+
+    fn set_texture(&mut self, display: &Display, id: TextureId, delta: epaint::ImageDelta) -> Result<()> {
+        let (width, height) = (delta.image.width() as u32, delta.image.height() as u32);
+        let pixels: Vec<u8> = match &delta.image {
+            epaint::ImageData::Color(image) => image.pixels.iter().flat_map(|c| [c.r(), c.g(), c.b(), c.a()]).collect(),
+            epaint::ImageData::Font(image) => image.srgba_pixels(1.0).flat_map(|c| [c.r(), c.g(), c.b(), c.a()]).collect(),
+        };
+        // rows go in top row first, so egui's uv (0, 0) in the top left corner lines up without flipping.
+        let raw = RawImage2d { data: Cow::Owned(pixels), width, height, format: ClientFormat::U8U8U8U8 };
+
+        match (delta.pos, self.textures.get(&id)) {
+            (Some([x, y]), Some((texture, _))) => {
+                texture.write(Rect { left: x as u32, bottom: y as u32, width, height }, raw);
+            }
+            _ => {
+                let texture = Texture2d::with_format(display, raw, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap)?;
+                self.textures.insert(id, (texture, delta.filter));
+            }
+        }
+        Ok(())
+    }
+
+    fn paint(
+        &mut self,
+        display: &Display,
+        frame: &mut impl Surface,
+        pixels_per_point: f32,
+        primitives: &[epaint::ClippedPrimitive],
+        textures_delta: epaint::textures::TexturesDelta,
+    ) -> Result<()> {
+        self.program.reload_if_changed(display);
+        for (id, delta) in textures_delta.set {
+            self.set_texture(display, id, delta)?;
+        }
+
+        let (width, height) = frame.get_dimensions();
+        let screen_size = [width as f32 / pixels_per_point, height as f32 / pixels_per_point];
+
+        for primitive in primitives {
+            let mesh = match &primitive.primitive {
+                epaint::Primitive::Mesh(mesh) => mesh,
+                epaint::Primitive::Callback(_) => continue,
+            };
+            let (texture, filter) = match self.textures.get(&mesh.texture_id) {
+                Some(texture) => texture,
+                None => continue,
+            };
+
+            let vertices: Vec<GuiVertex> = mesh.vertices.iter().map(|vertex| GuiVertex {
+                a_pos: [vertex.pos.x, vertex.pos.y],
+                a_tex_coord: [vertex.uv.x, vertex.uv.y],
+                a_srgba: [vertex.color.r(), vertex.color.g(), vertex.color.b(), vertex.color.a()].map(|c| c as f32 / 255.0),
+            }).collect();
+            let vertices = glium::VertexBuffer::new(display, &vertices)?;
+            let indices = glium::IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &mesh.indices)?;
+
+            let (magnify, minify) = match filter {
+                epaint::textures::TextureFilter::Nearest => (MagnifySamplerFilter::Nearest, MinifySamplerFilter::Nearest),
+                epaint::textures::TextureFilter::Linear => (MagnifySamplerFilter::Linear, MinifySamplerFilter::Linear),
+            };
+            let uniforms = uniform! {
+                u_screen_size: screen_size,
+                u_sampler: texture.sampled().magnify_filter(magnify).minify_filter(minify),
+            };
+
+            // clip rect is in points with y going down, the scissor box in pixels with y going up.
+            let clip = primitive.clip_rect;
+            let left = (clip.min.x * pixels_per_point).round().clamp(0.0, width as f32) as u32;
+            let right = (clip.max.x * pixels_per_point).round().clamp(left as f32, width as f32) as u32;
+            let top = (clip.min.y * pixels_per_point).round().clamp(0.0, height as f32) as u32;
+            let bottom = (clip.max.y * pixels_per_point).round().clamp(top as f32, height as f32) as u32;
+
+            let params = glium::DrawParameters {
+                // premultiplied alpha.
+                blend: Blend {
+                    color: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::One,
+                        destination: LinearBlendingFactor::OneMinusSourceAlpha,
+                    },
+                    alpha: BlendingFunction::Addition {
+                        source: LinearBlendingFactor::OneMinusDestinationAlpha,
+                        destination: LinearBlendingFactor::One,
+                    },
+                    constant_value: (0.0, 0.0, 0.0, 0.0),
+                },
+                scissor: Some(Rect { left, bottom: height - bottom, width: right - left, height: bottom - top }),
+                ..Default::default()
+            };
+
+            frame.draw(&vertices, &indices, &self.program, &uniforms, &params)?;
+        }
+
+        for id in textures_delta.free {
+            self.textures.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(value: Value, pinned: bool, active: bool) -> Entry {
+        Entry { ty: UniformType::Float, value: Some(value), pinned, active }
+    }
+
+    fn entries() -> BTreeMap<String, Entry> {
+        BTreeMap::from([
+            ("scale".to_string(), entry(Value::Float(2.0), true, true)),
+            ("tint".to_string(), entry(Value::Vec3([0.0; 3]), false, true)),
+            ("extra".to_string(), entry(Value::Float(5.0), true, true)),
+            ("gone".to_string(), entry(Value::Float(7.0), true, false)),
+        ])
+    }
+
+    fn visited<U: Uniforms>(uniforms: &U) -> Vec<(String, Option<Value>)> {
+        let mut visited = Vec::new();
+        uniforms.visit_values(|name, value| visited.push((name.to_string(), Value::from_uniform(&value))));
+        visited
+    }
+
+    #[test]
+    fn pinned_values_replace_the_stages_and_the_rest_pass_through() {
+        let entries = entries();
+        let stage = uniform! { scale: 1.0f32, tint: [1.0f32, 0.5, 0.0] };
+        assert_eq!(visited(&Tweaked { uniforms: &stage, entries: &entries }), vec![
+            ("tint".to_string(), Some(Value::Vec3([1.0, 0.5, 0.0]))),
+            ("scale".to_string(), Some(Value::Float(2.0))),
+            // pinned but never set by the stage, and not there when the program dropped it.
+            ("extra".to_string(), Some(Value::Float(5.0))),
+        ]);
+    }
+
+    #[test]
+    fn only_unpinned_entries_follow_the_stage() {
+        let mut entries = entries();
+        follow(&mut entries, &uniform! { scale: 1.0f32, tint: [1.0f32, 0.5, 0.0] });
+        assert_eq!(entries["scale"].value, Some(Value::Float(2.0)));
+        assert_eq!(entries["tint"].value, Some(Value::Vec3([1.0, 0.5, 0.0])));
+    }
+
+    #[test]
+    fn presets_save_pinned_values_and_load_them_pinned() {
+        let path = std::env::temp_dir().join(format!("gp-tuts-preset-{}", std::process::id())).join("test.toml");
+        write_preset(&entries(), &path).unwrap();
+
+        let mut loaded = BTreeMap::from([
+            ("scale".to_string(), entry(Value::Float(0.0), false, true)),
+            ("extra".to_string(), entry(Value::Float(0.0), false, true)),
+            ("gone".to_string(), entry(Value::Float(0.0), false, true)),
+        ]);
+        read_preset(&mut loaded, &path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!((loaded["scale"].value, loaded["scale"].pinned), (Some(Value::Float(2.0)), true));
+        assert_eq!((loaded["extra"].value, loaded["extra"].pinned), (Some(Value::Float(5.0)), true));
+        assert_eq!((loaded["gone"].value, loaded["gone"].pinned), (Some(Value::Float(7.0)), true));
+    }
+
+    #[test]
+    fn a_preset_value_of_the_wrong_size_is_skipped() {
+        let path = std::env::temp_dir().join(format!("gp-tuts-preset-size-{}.toml", std::process::id()));
+        fs::write(&path, "scale = [1.0, 2.0]\ntint = [0.25, 0.5, 1.0]\n").unwrap();
+
+        let mut entries = entries();
+        let result = read_preset(&mut entries, &path);
+        let _ = fs::remove_file(&path);
+
+        assert!(result.unwrap_err().to_string().contains("scale"));
+        assert_eq!(entries["scale"].value, Some(Value::Float(2.0)));
+        assert_eq!((entries["tint"].value, entries["tint"].pinned), (Some(Value::Vec3([0.25, 0.5, 1.0])), true));
+    }
+
+    #[test]
+    fn preset_names_stay_inside_the_preset_folder() {
+        let dir = Path::new("presets");
+        assert_eq!(preset_path(dir, "warm light"), Some(dir.join("warm light.toml")));
+        for name in ["", "../../src/main", "a/b", "a\\b", ".."] {
+            assert_eq!(preset_path(dir, name), None, "{}", name);
+        }
+    }
+}
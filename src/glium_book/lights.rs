@@ -0,0 +1,249 @@
+#![allow(dead_code)]
+use std::f32::consts::TAU;
+
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+use glium::{implement_uniform_block, uniforms::UniformBuffer, Display};
+
+use crate::error::Result;
+use super::preprocess::Preprocessor;
+use super::util::Rng;
+
+// More than the one hardcoded directional light: directional, point and spot lights with a color, an
+// intensity, a range they fade out over and (for spots) a cone. They go to the shaders as one uniform
+// block, `Lights` in lights.glsl, which holds up to MAX_LIGHTS of them plus how many are actually used.
+// The block is a handful of vec4 arrays instead of an array of structs because glium only checks the
+// offset of an array's first element, and std140 pads structs in ways that are easy to get wrong by hand.
+// Everything in the block is in view space, `LightBuffer::upload` does the transforming, so the
+// shaders light the fragment where the camera sees it (which means `v_position` has to be view space too).
+
+pub const MAX_LIGHTS: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away, everything is lit from `direction` (pointing away from the light).
+    Directional { direction: [f32; 3] },
+    /// Shines in every direction from `position`.
+    Point { position: [f32; 3] },
+    /// Shines from `position` along `direction`, fully inside `inner_angle`, fading out until `outer_angle` (radians, half angles).
+    Spot { position: [f32; 3], direction: [f32; 3], inner_angle: f32, outer_angle: f32 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which a point or spot light has faded out completely. Directional lights ignore it.
+    pub range: f32,
+}
+
+impl Light {
+    pub fn directional(direction: [f32; 3]) -> Self {
+        Light { kind: LightKind::Directional { direction }, color: [1.0; 3], intensity: 1.0, range: 0.0 }
+    }
+
+    pub fn point(position: [f32; 3], range: f32) -> Self {
+        Light { kind: LightKind::Point { position }, color: [1.0; 3], intensity: 1.0, range }
+    }
+
+    pub fn spot(position: [f32; 3], direction: [f32; 3], inner_angle: f32, outer_angle: f32, range: f32) -> Self {
+        Light { kind: LightKind::Spot { position, direction, inner_angle, outer_angle }, color: [1.0; 3], intensity: 1.0, range }
+    }
+
+    pub fn with_color(mut self, color: [f32; 3], intensity: f32) -> Self {
+        self.color = color;
+        self.intensity = intensity;
+        self
+    }
+
+    /// Where the light is, None for directional lights.
+    pub fn position(&self) -> Option<[f32; 3]> {
+        match self.kind {
+            LightKind::Directional { .. } => None,
+            LightKind::Point { position } | LightKind::Spot { position, .. } => Some(position),
+        }
+    }
+}
+
+/// Evenly spread around the color wheel, `t` in [0, 1).
+pub fn hue(t: f32) -> [f32; 3] {
+    let channel = |offset: f32| 0.5 + 0.5 * (TAU * (t + offset)).cos();
+    [channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0)]
+}
+
+/// `count` colored point lights scattered over `extent` (width and depth) around `center`, just above the
+/// ground, each circling its spot at its own pace. Where they are at `time` seconds, the same every run.
+pub fn swarm(count: usize, center: [f32; 3], extent: [f32; 2], range: f32, intensity: f32, time: f32) -> Vec<Light> {
+    let mut rng = Rng::new(0x5eed);
+    (0..count)
+        .map(|_| {
+            let x = center[0] + (rng.next_f32() - 0.5) * extent[0];
+            let z = center[2] + (rng.next_f32() - 0.5) * extent[1];
+            let height = center[1] + 0.15 + rng.next_f32();
+            let (speed, phase) = (0.3 + rng.next_f32(), rng.next_f32() * TAU);
+            let color = hue(rng.next_f32());
+
+            let angle = phase + speed * time;
+            let position = [x + 0.6 * angle.cos(), height, z + 0.6 * angle.sin()];
+            Light::point(position, range).with_color(color, intensity)
+        })
+        .collect()
+}
+
+/// What a directional or spot light with a direction of zero shines along instead: straight down.
+pub const DEFAULT_DIRECTION: [f32; 3] = [0.0, -1.0, 0.0];
+
+// same values as the LIGHT_* defines lights.glsl gets.
+const DIRECTIONAL: f32 = 0.0;
+const POINT: f32 = 1.0;
+const SPOT: f32 = 2.0;
+
+/// The `Lights` block, std140. Has to match lights.glsl member for member.
+#[derive(Copy, Clone)]
+pub struct LightBlock {
+    /// xyz: view space position, w: the kind.
+    pub light_position: [[f32; 4]; MAX_LIGHTS],
+    /// xyz: view space direction the light shines in, w: range.
+    pub light_direction: [[f32; 4]; MAX_LIGHTS],
+    /// rgb: color times intensity.
+    pub light_color: [[f32; 4]; MAX_LIGHTS],
+    /// x: cosine of the inner angle, y: of the outer one.
+    pub light_cone: [[f32; 4]; MAX_LIGHTS],
+    pub light_count: i32,
+    _padding: [i32; 3],
+}
+
+implement_uniform_block!(LightBlock, light_position, light_direction, light_color, light_cone, light_count);
+
+impl LightBlock {
+    /// `lights` moved into view space with `view`. Past MAX_LIGHTS they're left out.
+    pub fn new(lights: &[Light], view: [[f32; 4]; 4]) -> Self {
+        let mut block = LightBlock {
+            light_position: [[0.0; 4]; MAX_LIGHTS],
+            light_direction: [[0.0; 4]; MAX_LIGHTS],
+            light_color: [[0.0; 4]; MAX_LIGHTS],
+            light_cone: [[0.0; 4]; MAX_LIGHTS],
+            light_count: lights.len().min(MAX_LIGHTS) as i32,
+            _padding: [0; 3],
+        };
+
+        let view = Matrix4::from(view);
+        let point = |p: [f32; 3]| (view * Vector4::new(p[0], p[1], p[2], 1.0)).truncate();
+        let direction = |d: [f32; 3]| {
+            // a zero direction can't be normalized, it'd light everything with NaNs.
+            let d = if Vector3::from(d).magnitude2() > 0.0 { d } else { DEFAULT_DIRECTION };
+            (view * Vector4::new(d[0], d[1], d[2], 0.0)).truncate().normalize()
+        };
+        let vec4 = |v: Vector3<f32>, w: f32| [v.x, v.y, v.z, w];
+
+        for (i, light) in lights.iter().take(MAX_LIGHTS).enumerate() {
+            let (kind, position, facing, cone) = match light.kind {
+                LightKind::Directional { direction: d } => (DIRECTIONAL, Vector3::new(0.0, 0.0, 0.0), direction(d), [1.0, 1.0]),
+                LightKind::Point { position: p } => (POINT, point(p), Vector3::new(0.0, 0.0, 1.0), [1.0, 1.0]),
+                LightKind::Spot { position: p, direction: d, inner_angle, outer_angle } => {
+                    (SPOT, point(p), direction(d), [inner_angle.cos(), outer_angle.cos()])
+                }
+            };
+            let [r, g, b] = light.color;
+            block.light_position[i] = vec4(position, kind);
+            block.light_direction[i] = vec4(facing, light.range);
+            block.light_color[i] = [r * light.intensity, g * light.intensity, b * light.intensity, 1.0];
+            block.light_cone[i] = [cone[0], cone[1], 0.0, 0.0];
+        }
+
+        block
+    }
+}
+
+/// A `UniformBuffer` holding the lights, bind it as `Lights`.
+pub struct LightBuffer {
+    buffer: UniformBuffer<LightBlock>,
+}
+
+impl LightBuffer {
+    pub fn new(display: &Display) -> Result<Self> {
+        Ok(LightBuffer { buffer: UniformBuffer::new(display, LightBlock::new(&[], [[0.0; 4]; 4]))? })
+    }
+
+    pub fn upload(&mut self, lights: &[Light], view: [[f32; 4]; 4]) {
+        self.buffer.write(&LightBlock::new(lights, view));
+    }
+
+    pub fn buffer(&self) -> &UniformBuffer<LightBlock> {
+        &self.buffer
+    }
+}
+
+/// The defines lights.glsl needs, for shaders that `#include` it.
+pub fn preprocessor() -> Preprocessor {
+    Preprocessor::new()
+        .define("MAX_LIGHTS", MAX_LIGHTS)
+        .define("LIGHT_DIRECTIONAL", format!("{:?}", DIRECTIONAL))
+        .define("LIGHT_POINT", format!("{:?}", POINT))
+        .define("LIGHT_SPOT", format!("{:?}", SPOT))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::SquareMatrix;
+
+    use super::*;
+
+    #[test]
+    fn a_zero_spot_direction_shines_straight_down() {
+        let identity: [[f32; 4]; 4] = Matrix4::identity().into();
+        let lights = [
+            Light::spot([0.0, 2.0, 0.0], [0.0; 3], 0.3, 0.5, 10.0),
+            Light::directional([0.0; 3]),
+        ];
+        let block = LightBlock::new(&lights, identity);
+        for direction in &block.light_direction[..2] {
+            assert_eq!(direction[..3], DEFAULT_DIRECTION);
+        }
+    }
+
+    #[test]
+    fn lights_past_the_maximum_are_left_out() {
+        let identity: [[f32; 4]; 4] = Matrix4::identity().into();
+        let lights: Vec<Light> = (0..MAX_LIGHTS + 3).map(|i| Light::point([i as f32, 0.0, 0.0], 1.0)).collect();
+        let block = LightBlock::new(&lights, identity);
+        assert_eq!(block.light_count, MAX_LIGHTS as i32);
+        assert_eq!(block.light_position[MAX_LIGHTS - 1], [(MAX_LIGHTS - 1) as f32, 0.0, 0.0, POINT]);
+    }
+
+    #[test]
+    fn positions_and_directions_end_up_in_view_space() {
+        // the camera 5 to the right of the origin, turned a quarter to the left (looking down -x).
+        let view = Matrix4::from_angle_y(cgmath::Deg(-90.0)) * Matrix4::from_translation(Vector3::new(-5.0, 0.0, 0.0));
+        let lights = [
+            Light::spot([0.0, 1.0, 0.0], [-2.0, 0.0, 0.0], 0.3, 0.5, 10.0).with_color([1.0, 0.5, 0.25], 2.0),
+            Light::directional([0.0, -1.0, 0.0]),
+        ];
+        let block = LightBlock::new(&lights, view.into());
+
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+        // 5 in front of the camera, a unit up.
+        assert!(close(&block.light_position[0], &[0.0, 1.0, -5.0, SPOT]), "{:?}", block.light_position[0]);
+        // shining away from the camera, normalized, the range in w.
+        assert!(close(&block.light_direction[0], &[0.0, 0.0, -1.0, 10.0]), "{:?}", block.light_direction[0]);
+        assert!(close(&block.light_color[0], &[2.0, 1.0, 0.5, 1.0]));
+        assert!(close(&block.light_cone[0][..2], &[0.3f32.cos(), 0.5f32.cos()]));
+        // directions don't move with the camera, only turn.
+        assert!(close(&block.light_direction[1][..3], &[0.0, -1.0, 0.0]));
+        assert_eq!(block.light_position[1][3], DIRECTIONAL);
+    }
+
+    #[test]
+    fn hues_stay_in_range_and_go_around_the_wheel() {
+        for i in 0..100 {
+            let color = hue(i as f32 / 100.0);
+            assert!(color.iter().all(|channel| (0.0..=1.0).contains(channel)), "{:?}", color);
+        }
+        assert_eq!(hue(0.0)[0], 1.0);
+        let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5);
+        assert!(close(hue(0.0), hue(1.0)));
+        // a third of the way round, the channels have moved over by one.
+        let (red, green) = (hue(0.0), hue(1.0 / 3.0));
+        assert!(close(green, [red[2], red[0], red[1]]), "{:?} {:?}", red, green);
+    }
+}
@@ -0,0 +1,417 @@
+#![allow(dead_code)]
+use glium::{
+    texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat},
+    uniforms::{SamplerBehavior, UniformType, UniformValue, Uniforms},
+    vertex::VertexFormat,
+    Display,
+};
+
+use crate::error::Result;
+use super::assets;
+use super::hot_reload::{shader_file, HotProgram};
+use super::lights;
+use super::preprocess::Preprocessor;
+
+// Materials: a program and everything it draws with that isn't about where the object is. The stages used
+// to bake their colors into the GLSL and put every uniform into a `uniform!{}` by hand; a `Material` keeps
+// its parameters typed (as `Value`s, so they can be changed while running, by the inspector too) next to its textures,
+// and `uniforms(extra)` gives glium those plus whatever the draw adds (matrices, the light).
+// The kinds are the tutorial's: Gouraud is stage 8 and 9's lerp between a dark and a lit color, Blinn-Phong
+// is stage 13, NormalMapped is stage 14. Unlit is a flat color for things that shouldn't be shaded at all.
+// Lit is Blinn-Phong again, but under all the lights of a `Lights` block (lights.rs) instead of `u_light`.
+// Pbr is stage 22's Cook-Torrance under the same lights, every texture scaled by a factor, and stage 23's
+// with image based lighting for the ambient part.
+//
+// Per draw the stages still pass (in `extra`):
+//  - Gouraud: `matrix`, `u_light`
+//  - Unlit, BlinnPhong, NormalMapped: `model`, `view`, `perspective`, and `u_light` for the lit ones
+//  - Lit, Pbr: `model`, `view`, `perspective` and the `Lights` block, plus the environment's maps for `pbr_ibl`
+
+/// A uniform value that can be changed while running, material parameters and what the inspector edits.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Value {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Mat2([[f32; 2]; 2]),
+    Mat3([[f32; 3]; 3]),
+    Mat4([[f32; 4]; 4]),
+    Int(i32),
+    Bool(bool),
+}
+
+impl Value {
+    /// Zero of the right shape, for uniforms the stage doesn't set.
+    pub fn zero(ty: UniformType) -> Option<Value> {
+        Some(match ty {
+            UniformType::Float => Value::Float(0.0),
+            UniformType::FloatVec2 => Value::Vec2([0.0; 2]),
+            UniformType::FloatVec3 => Value::Vec3([0.0; 3]),
+            UniformType::FloatVec4 => Value::Vec4([0.0; 4]),
+            UniformType::FloatMat2 => Value::Mat2([[0.0; 2]; 2]),
+            UniformType::FloatMat3 => Value::Mat3([[0.0; 3]; 3]),
+            UniformType::FloatMat4 => Value::Mat4([[0.0; 4]; 4]),
+            UniformType::Int => Value::Int(0),
+            UniformType::Bool => Value::Bool(false),
+            _ => return None,
+        })
+    }
+
+    pub fn from_uniform(value: &UniformValue) -> Option<Value> {
+        Some(match *value {
+            UniformValue::Float(x) => Value::Float(x),
+            UniformValue::Vec2(v) => Value::Vec2(v),
+            UniformValue::Vec3(v) => Value::Vec3(v),
+            UniformValue::Vec4(v) => Value::Vec4(v),
+            UniformValue::Mat2(m) => Value::Mat2(m),
+            UniformValue::Mat3(m) => Value::Mat3(m),
+            UniformValue::Mat4(m) => Value::Mat4(m),
+            UniformValue::SignedInt(i) => Value::Int(i),
+            UniformValue::Bool(b) => Value::Bool(b),
+            _ => return None,
+        })
+    }
+
+    pub fn as_uniform<'a>(&self) -> UniformValue<'a> {
+        match *self {
+            Value::Float(x) => UniformValue::Float(x),
+            Value::Vec2(v) => UniformValue::Vec2(v),
+            Value::Vec3(v) => UniformValue::Vec3(v),
+            Value::Vec4(v) => UniformValue::Vec4(v),
+            Value::Mat2(m) => UniformValue::Mat2(m),
+            Value::Mat3(m) => UniformValue::Mat3(m),
+            Value::Mat4(m) => UniformValue::Mat4(m),
+            Value::Int(i) => UniformValue::SignedInt(i),
+            Value::Bool(b) => UniformValue::Bool(b),
+        }
+    }
+
+    /// What draw traces call it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Float(_) => "float",
+            Value::Vec2(_) => "vec2",
+            Value::Vec3(_) => "vec3",
+            Value::Vec4(_) => "vec4",
+            Value::Mat2(_) => "mat2",
+            Value::Mat3(_) => "mat3",
+            Value::Mat4(_) => "mat4",
+            Value::Int(_) => "int",
+            Value::Bool(_) => "bool",
+        }
+    }
+
+    /// The inverse of `type_name`, zeroed.
+    pub fn of_type(name: &str) -> Option<Value> {
+        Value::zero(match name {
+            "float" => UniformType::Float,
+            "vec2" => UniformType::FloatVec2,
+            "vec3" => UniformType::FloatVec3,
+            "vec4" => UniformType::FloatVec4,
+            "mat2" => UniformType::FloatMat2,
+            "mat3" => UniformType::FloatMat3,
+            "mat4" => UniformType::FloatMat4,
+            "int" => UniformType::Int,
+            "bool" => UniformType::Bool,
+            _ => return None,
+        })
+    }
+
+    /// All components as floats, matrices column by column. This is what presets store.
+    pub fn floats(&self) -> Vec<f32> {
+        match self {
+            Value::Float(x) => vec![*x],
+            Value::Vec2(v) => v.to_vec(),
+            Value::Vec3(v) => v.to_vec(),
+            Value::Vec4(v) => v.to_vec(),
+            Value::Mat2(m) => m.iter().flatten().copied().collect(),
+            Value::Mat3(m) => m.iter().flatten().copied().collect(),
+            Value::Mat4(m) => m.iter().flatten().copied().collect(),
+            Value::Int(i) => vec![*i as f32],
+            Value::Bool(b) => vec![if *b { 1.0 } else { 0.0 }],
+        }
+    }
+
+    /// The inverse of `floats`, keeps the current shape. False if the number of components doesn't fit.
+    pub fn set_floats(&mut self, floats: &[f32]) -> bool {
+        if floats.len() != self.floats().len() {
+            return false;
+        }
+
+        let mut components = floats.iter().copied();
+        let mut next = || components.next().unwrap_or_default();
+        match self {
+            Value::Float(x) => *x = next(),
+            Value::Vec2(v) => v.iter_mut().for_each(|x| *x = next()),
+            Value::Vec3(v) => v.iter_mut().for_each(|x| *x = next()),
+            Value::Vec4(v) => v.iter_mut().for_each(|x| *x = next()),
+            Value::Mat2(m) => m.iter_mut().flatten().for_each(|x| *x = next()),
+            Value::Mat3(m) => m.iter_mut().flatten().for_each(|x| *x = next()),
+            Value::Mat4(m) => m.iter_mut().flatten().for_each(|x| *x = next()),
+            Value::Int(i) => *i = next() as i32,
+            Value::Bool(b) => *b = next() != 0.0,
+        }
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Unlit,
+    Gouraud,
+    BlinnPhong,
+    NormalMapped,
+    Lit,
+    Pbr,
+}
+
+/// Everything a `Material::pbr` samples. Metallic, roughness and ambient occlusion are read from the red channel.
+pub struct PbrTextures {
+    pub albedo: Texture2d,
+    pub metallic: Texture2d,
+    pub roughness: Texture2d,
+    pub ao: Texture2d,
+    pub normal: Texture2d,
+}
+
+impl PbrTextures {
+    /// All white, so the factors are all there is, and a normal map that doesn't bend anything.
+    pub fn flat(display: &Display) -> Result<Self> {
+        Ok(PbrTextures {
+            albedo: flat_texture(display, [255; 4])?,
+            metallic: flat_texture(display, [255; 4])?,
+            roughness: flat_texture(display, [255; 4])?,
+            ao: flat_texture(display, [255; 4])?,
+            normal: flat_texture(display, [128, 128, 255, 255])?,
+        })
+    }
+}
+
+/// A single texel, for a texture that's the same everywhere.
+pub fn flat_texture(display: &Display, color: [u8; 4]) -> Result<Texture2d> {
+    let raw = RawImage2d { data: color.to_vec().into(), width: 1, height: 1, format: ClientFormat::U8U8U8U8 };
+    Ok(Texture2d::with_format(display, raw, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap)?)
+}
+
+/// `assets/textures/<name>`, the right way up for OpenGL.
+pub fn load_texture(display: &Display, name: &str) -> Result<Texture2d> {
+    let image = assets::load_image(name)?.to_rgba8();
+    let dimensions = image.dimensions();
+    let raw = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions);
+    Ok(Texture2d::new(display, raw)?)
+}
+
+pub struct Material {
+    kind: Kind,
+    program: HotProgram,
+    params: Vec<(&'static str, Value)>,
+    textures: Vec<(&'static str, Texture2d, Option<SamplerBehavior>)>,
+}
+
+impl Material {
+    /// Just `base_color`.
+    pub fn unlit(display: &Display, base_color: [f32; 3]) -> Result<Self> {
+        let program = HotProgram::new(display, shader_file!("unlit.vert"), shader_file!("unlit.frag"))?;
+        Ok(Material { kind: Kind::Unlit, program, params: vec![("base_color", Value::Vec3(base_color))], textures: Vec::new() })
+    }
+
+    /// Stage 8 and 9: `dark_color` where the surface faces away from the light, `regular_color` facing it.
+    pub fn gouraud(display: &Display, dark_color: [f32; 3], regular_color: [f32; 3]) -> Result<Self> {
+        let program = HotProgram::new(display, shader_file!("stage8.vert"), shader_file!("stage8.frag"))?;
+        Ok(Material {
+            kind: Kind::Gouraud,
+            program,
+            params: vec![("dark_color", Value::Vec3(dark_color)), ("regular_color", Value::Vec3(regular_color))],
+            textures: Vec::new(),
+        })
+    }
+
+    /// Stage 13. `specular_exponent` is the "dropoff rate" of the highlight, higher is smaller and sharper.
+    pub fn blinn_phong(
+        display: &Display,
+        ambient_color: [f32; 3],
+        diffuse_color: [f32; 3],
+        specular_color: [f32; 3],
+        specular_exponent: f32,
+    ) -> Result<Self> {
+        let program = HotProgram::new(display, shader_file!("stage13.vert"), shader_file!("stage13.frag"))?;
+        Ok(Material {
+            kind: Kind::BlinnPhong,
+            program,
+            params: vec![
+                ("ambient_color", Value::Vec3(ambient_color)),
+                ("diffuse_color", Value::Vec3(diffuse_color)),
+                ("specular_color", Value::Vec3(specular_color)),
+                ("specular_exponent", Value::Float(specular_exponent)),
+            ],
+            textures: Vec::new(),
+        })
+    }
+
+    /// Stage 16: `blinn_phong` lit by a `LightBuffer` rather than a single direction.
+    pub fn lit(
+        display: &Display,
+        ambient_color: [f32; 3],
+        diffuse_color: [f32; 3],
+        specular_color: [f32; 3],
+        specular_exponent: f32,
+    ) -> Result<Self> {
+        let program = HotProgram::with_preprocessor(
+            display,
+            shader_file!("stage16.vert"),
+            shader_file!("stage16.frag"),
+            false,
+            lights::preprocessor(),
+        )?;
+        Ok(Material {
+            kind: Kind::Lit,
+            program,
+            params: vec![
+                ("ambient_color", Value::Vec3(ambient_color)),
+                ("diffuse_color", Value::Vec3(diffuse_color)),
+                ("specular_color", Value::Vec3(specular_color)),
+                ("specular_exponent", Value::Float(specular_exponent)),
+            ],
+            textures: Vec::new(),
+        })
+    }
+
+    /// Stage 14: the diffuse color comes from `diffuse`, the normals are bent by `normal`.
+    pub fn normal_mapped(display: &Display, diffuse: Texture2d, normal: Texture2d, specular_color: [f32; 3]) -> Result<Self> {
+        let program = HotProgram::new(display, shader_file!("stage14.vert"), shader_file!("stage14.frag"))?;
+        Ok(Material {
+            kind: Kind::NormalMapped,
+            program,
+            params: vec![("specular_color", Value::Vec3(specular_color))],
+            textures: vec![
+                ("diffuse_tex", diffuse, None),
+                ("normal_tex", normal, None),
+            ],
+        })
+    }
+
+    /// Stage 22: metallic-roughness Cook-Torrance under a `LightBuffer`. The factors multiply the textures,
+    /// `ambient_color` stands in for all the light that doesn't come from a light.
+    pub fn pbr(
+        display: &Display,
+        textures: PbrTextures,
+        albedo: [f32; 3],
+        metallic: f32,
+        roughness: f32,
+        ambient_color: [f32; 3],
+    ) -> Result<Self> {
+        let ambient = ("ambient_color", Value::Vec3(ambient_color));
+        Self::pbr_with(display, textures, albedo, metallic, roughness, ambient, lights::preprocessor())
+    }
+
+    /// Stage 23: `pbr`, with the light from all around coming out of an `ibl::Environment` (pass its
+    /// `uniforms` in `extra`) instead of a flat ambient color.
+    pub fn pbr_ibl(display: &Display, textures: PbrTextures, albedo: [f32; 3], metallic: f32, roughness: f32) -> Result<Self> {
+        let intensity = ("environment_intensity", Value::Float(1.0));
+        Self::pbr_with(display, textures, albedo, metallic, roughness, intensity, lights::preprocessor().define("IBL", 1))
+    }
+
+    fn pbr_with(
+        display: &Display,
+        textures: PbrTextures,
+        albedo: [f32; 3],
+        metallic: f32,
+        roughness: f32,
+        ambient: (&'static str, Value),
+        preprocessor: Preprocessor,
+    ) -> Result<Self> {
+        let program = HotProgram::with_preprocessor(
+            display,
+            shader_file!("stage22.vert"),
+            shader_file!("stage22.frag"),
+            false,
+            preprocessor,
+        )?;
+        let PbrTextures { albedo: albedo_tex, metallic: metallic_tex, roughness: roughness_tex, ao, normal } = textures;
+        Ok(Material {
+            kind: Kind::Pbr,
+            program,
+            params: vec![
+                ("albedo_factor", Value::Vec3(albedo)),
+                ("metallic_factor", Value::Float(metallic)),
+                ("roughness_factor", Value::Float(roughness)),
+                ambient,
+            ],
+            textures: vec![
+                ("albedo_tex", albedo_tex, None),
+                ("metallic_tex", metallic_tex, None),
+                ("roughness_tex", roughness_tex, None),
+                ("ao_tex", ao, None),
+                ("normal_tex", normal, None),
+            ],
+        })
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.params.iter().find(|(param, _)| *param == name).map(|(_, value)| *value)
+    }
+
+    /// Changes a parameter. False if the material has no such parameter or it's of another type.
+    pub fn set(&mut self, name: &str, value: Value) -> bool {
+        match self.params.iter_mut().find(|(param, _)| *param == name) {
+            Some((_, current)) if current.type_name() == value.type_name() => {
+                *current = value;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// How the texture `name` is sampled, None (the default) leaves it to the texture's own settings.
+    pub fn set_sampler(&mut self, name: &str, sampler: Option<SamplerBehavior>) -> bool {
+        match self.textures.iter_mut().find(|(texture, _, _)| *texture == name) {
+            Some((_, _, current)) => {
+                *current = sampler;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn program(&self) -> &HotProgram {
+        &self.program
+    }
+
+    pub fn reload_if_changed(&mut self, display: &Display) -> bool {
+        self.program.reload_if_changed(display)
+    }
+
+    /// `HotProgram::check_bindings` with the material's own uniforms plus `extra`.
+    pub fn check_bindings<U: Uniforms>(&mut self, formats: &[&VertexFormat], extra: &U) -> Result<()> {
+        let uniforms = MaterialUniforms { params: &self.params, textures: &self.textures, extra };
+        self.program.check_bindings(formats, &uniforms)
+    }
+
+    /// The material's parameters and textures, then everything in `extra`.
+    pub fn uniforms<'m, U: Uniforms>(&'m self, extra: &'m U) -> MaterialUniforms<'m, U> {
+        MaterialUniforms { params: &self.params, textures: &self.textures, extra }
+    }
+}
+
+pub struct MaterialUniforms<'m, U> {
+    params: &'m [(&'static str, Value)],
+    textures: &'m [(&'static str, Texture2d, Option<SamplerBehavior>)],
+    extra: &'m U,
+}
+
+impl<'m, U: Uniforms> Uniforms for MaterialUniforms<'m, U> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        for (name, value) in self.params {
+            f(name, value.as_uniform());
+        }
+        for (name, texture, sampler) in self.textures {
+            f(name, UniformValue::Texture2d(texture, *sampler));
+        }
+        self.extra.visit_values(f);
+    }
+}
@@ -0,0 +1,62 @@
+use glium::Surface;
+
+// The camera matrices every 3D stage (and the shadow passes) builds, the tutorial's own rather than cgmath's:
+// left handed, looking down +z in view space, GL's -1..1 depth range.
+
+/// `fov` in radians.
+pub fn perspective_matrix(frame: &impl Surface, fov: f32, znear: f32, zfar: f32) -> [[f32; 4]; 4] {
+    let (width, height) = frame.get_dimensions();
+    perspective(height as f32 / width as f32, fov, znear, zfar)
+}
+
+/// `perspective_matrix` for a frame `aspect_ratio` (height over width) high.
+pub fn perspective(aspect_ratio: f32, fov: f32, znear: f32, zfar: f32) -> [[f32; 4]; 4] {
+    let f = 1.0 / (fov / 2.0).tan();
+
+    [
+        [f * aspect_ratio   ,   0.0 ,           0.0     ,               0.0],
+        [   0.0             ,   f   ,           0.0     ,               0.0],
+        [   0.0             ,   0.0 ,    (zfar+znear)/(zfar-znear)  ,   1.0],
+        [   0.0             ,   0.0 , -(2.0*zfar*znear)/(zfar-znear),   0.0],
+    ]
+}
+
+pub fn view_matrix(position: &[f32; 3], direction: &[f32; 3], up: &[f32; 3]) -> [[f32; 4]; 4] {
+    let f_norm = {
+        let f = direction;
+        let len = f[0] * f[0] + f[1] * f[1] + f[2] * f[2];
+        let len = len.sqrt();
+        [f[0] / len, f[1] / len, f[2] / len]
+    };
+
+    let s = [
+        up[1] * f_norm[2] - up[2] * f_norm[1],
+        up[2] * f_norm[0] - up[0] * f_norm[2],
+        up[0] * f_norm[1] - up[1] * f_norm[0],
+    ];
+
+    let s_norm = {
+        let len = s[0] * s[0] + s[1] * s[1] + s[2] * s[2];
+        let len = len.sqrt();
+        [s[0] / len, s[1] / len, s[2] / len]
+    };
+
+    let u = [
+        f_norm[1] * s_norm[2] - f_norm[2] * s_norm[1],
+        f_norm[2] * s_norm[0] - f_norm[0] * s_norm[2],
+        f_norm[0] * s_norm[1] - f_norm[1] * s_norm[0],
+    ];
+
+    let p = [
+        -position[0] * s_norm[0] - position[1] * s_norm[1] - position[2] * s_norm[2],
+        -position[0] * u[0] - position[1] * u[1] - position[2] * u[2],
+        -position[0] * f_norm[0] - position[1] * f_norm[1] - position[2] * f_norm[2],
+    ];
+
+    [
+        [s_norm[0], u[0], f_norm[0], 0.0],
+        [s_norm[1], u[1], f_norm[1], 0.0],
+        [s_norm[2], u[2], f_norm[2], 0.0],
+        [p[0], p[1], p[2], 1.0],
+    ]
+}
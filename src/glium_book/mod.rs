@@ -1,10 +1,13 @@
 #![allow(unused_imports)]
-use std::io::stdout;
+use std::{io::stdout, path::PathBuf};
 use crossterm::{
     execute,
     style::Print,
 };
 
+use crate::error::{Error, Result};
+use params::{Overrides, Param, Params};
+
 mod stage1;
 mod stage2;
 mod stage3;
@@ -18,35 +21,111 @@ mod stage10_w_11;
 mod stage12;
 mod stage13;
 mod stage14;
+mod stage15;
+mod stage16;
+mod stage17;
+mod stage18;
+mod stage19;
+mod stage20;
+mod stage21;
+mod stage22;
+mod stage23;
 
 mod teapot;
+mod assets;
+mod util;
+mod math;
+mod runner;
+pub mod context;
+mod hot_reload;
+mod preprocess;
+mod reflect;
+mod inspector;
+mod material;
+mod lights;
+mod shadows;
+mod clusters;
+mod ibl;
+pub mod params;
+pub mod term;
+mod capture;
+mod timing;
+pub mod trace;
+pub mod input;
+pub mod replay;
+pub mod shadertoy;
 
 const LATEST_COMPLETED_STAGE: usize = 13;
 
 pub const GLIUM: &str = "glium";
 
-pub fn entrypoint(stage: Option<usize>) {
-    if stage.is_none() {
-        execute!(stdout(), Print(format!("defaulting to latest completed stage: {}", LATEST_COMPLETED_STAGE))).ok();
-        stage13::run();
-    } else {
-        let which_stage = stage.unwrap();
-        execute!(stdout(), Print(format!("doing glium tutorial stage {}! :D", stage.unwrap()))).ok();
-        match which_stage {
-            1 => stage1::run(),
-            2 => stage2::run(),
-            3 => stage3::run(),
-            4 => stage4::run(),
-            5 => stage5::run(),
-            6 => stage6::run(),
-            7 => stage7::run(),
-            8 => stage8::run(),
-            9 => stage9::run(),
-            10 | 11 => stage10_w_11::run(),
-            12 => stage12::run(),
-            13 => stage13::run(),
-            14 => stage14::run(),
-            _ => todo!("I'M WORKING ON IT!"),
+/// One runnable stage: what it shows, what it can be tweaked with and how to start it.
+pub struct Stage {
+    pub number: usize,
+    pub title: &'static str,
+    pub params: fn() -> Vec<Param>,
+    pub run: fn(&Params) -> Result<()>,
+}
+
+pub const STAGES: &[Stage] = &[
+    Stage { number: 1, title: "opening a window", params: stage1::params, run: stage1::run },
+    Stage { number: 2, title: "the first triangle", params: stage2::params, run: stage2::run },
+    Stage { number: 3, title: "uniforms", params: stage3::params, run: stage3::run },
+    Stage { number: 4, title: "matrices", params: stage4::params, run: stage4::run },
+    Stage { number: 5, title: "attributes", params: stage5::params, run: stage5::run },
+    Stage { number: 6, title: "textures", params: stage6::params, run: stage6::run },
+    Stage { number: 7, title: "the teapot", params: stage7::params, run: stage7::run },
+    Stage { number: 8, title: "gouraud shading", params: stage8::params, run: stage8::run },
+    Stage { number: 9, title: "depth testing", params: stage9::params, run: stage9::run },
+    Stage { number: 10, title: "perspective", params: stage10_w_11::params, run: stage10_w_11::run },
+    Stage { number: 11, title: "backface culling", params: stage10_w_11::params, run: stage10_w_11::run },
+    Stage { number: 12, title: "the camera", params: stage12::params, run: stage12::run },
+    Stage { number: 13, title: "blinn-phong", params: stage13::params, run: stage13::run },
+    Stage { number: 14, title: "normal mapping", params: stage14::params, run: stage14::run },
+    Stage { number: 15, title: "progressive path tracing", params: stage15::params, run: stage15::run },
+    Stage { number: 16, title: "multiple lights", params: stage16::params, run: stage16::run },
+    Stage { number: 17, title: "shadow mapping", params: stage17::params, run: stage17::run },
+    Stage { number: 18, title: "cascaded shadow maps", params: stage18::params, run: stage18::run },
+    Stage { number: 19, title: "point light shadows", params: stage19::params, run: stage19::run },
+    Stage { number: 20, title: "deferred shading", params: stage20::params, run: stage20::run },
+    Stage { number: 21, title: "clustered forward shading", params: stage21::params, run: stage21::run },
+    Stage { number: 22, title: "physically based rendering", params: stage22::params, run: stage22::run },
+    Stage { number: 23, title: "image based lighting", params: stage23::params, run: stage23::run },
+];
+
+pub fn stage(number: usize) -> Option<&'static Stage> {
+    STAGES.iter().find(|stage| stage.number == number)
+}
+
+/// Runs a stage with `overrides` applied to its parameters, or with `describe` just lists the parameters.
+pub fn entrypoint(stage: Option<usize>, overrides: &Overrides, describe: bool) -> Result<()> {
+    let number = match stage {
+        None => {
+            execute!(stdout(), Print(format!("defaulting to latest completed stage: {}\n", LATEST_COMPLETED_STAGE))).ok();
+            LATEST_COMPLETED_STAGE
+        }
+        Some(number) => {
+            execute!(stdout(), Print(format!("doing glium tutorial stage {}! :D\n", number))).ok();
+            number
         }
+    };
+    let stage = self::stage(number).ok_or(Error::UnknownStage { tutorial: GLIUM.to_string(), stage: number })?;
+
+    let mut params = Params::new((stage.params)());
+    params.apply(overrides)?;
+
+    if describe {
+        println!("stage {}: {}\n{}", stage.number, stage.title, params.describe());
+        return Ok(());
     }
-}
\ No newline at end of file
+
+    (stage.run)(&params)
+}
+
+/// Runs a stage in a hidden window, as fast as it goes, for `frames` frames (after `warmup` more) and
+/// writes how long they took to `output`.
+pub fn bench(stage: usize, overrides: &Overrides, frames: usize, warmup: usize, output: Option<PathBuf>) -> Result<()> {
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("bench-{}-stage{}.json", GLIUM, stage)));
+    timing::set_bench(timing::Bench { label: format!("{} stage {}", GLIUM, stage), frames, warmup, output });
+    entrypoint(Some(stage), overrides, false)
+}
@@ -0,0 +1,346 @@
+#![allow(dead_code)]
+use std::{collections::BTreeMap, fmt, fs, path::{Path, PathBuf}};
+
+use crate::error::{Error, Result};
+
+// Knobs a stage exposes so it can be rerun with other values without touching the code. Each stage
+// declares its parameters (name, type, default, what it does) in a `params()` function and reads them
+// back from the `Params` it gets in `run`. Values come from, in order:
+//     the declared default
+//     config files (`--config tweaks.toml`, `.ron` works too), `fov = 75` / `(fov: 75)`
+//     `--set fov=75 --set light=-1,0.8,0.9`
+// and `--describe` prints all of it for the selected stage.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParamValue {
+    Float(f32),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+}
+
+impl ParamValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ParamValue::Float(_) => "float",
+            ParamValue::Vec3(_) => "vec3",
+            ParamValue::Vec4(_) => "vec4",
+        }
+    }
+
+    /// A value shaped like `self` from a list of numbers.
+    fn reshaped(&self, numbers: &[f32]) -> Option<ParamValue> {
+        Some(match (self, numbers) {
+            (ParamValue::Float(_), &[x]) => ParamValue::Float(x),
+            (ParamValue::Vec3(_), &[x, y, z]) => ParamValue::Vec3([x, y, z]),
+            (ParamValue::Vec4(_), &[x, y, z, w]) => ParamValue::Vec4([x, y, z, w]),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let numbers: &[f32] = match self {
+            ParamValue::Float(x) => std::slice::from_ref(x),
+            ParamValue::Vec3(v) => v,
+            ParamValue::Vec4(v) => v,
+        };
+        let numbers: Vec<String> = numbers.iter().map(|x| x.to_string()).collect();
+        write!(f, "{}", numbers.join(","))
+    }
+}
+
+/// One declared parameter.
+#[derive(Copy, Clone, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub default: ParamValue,
+    pub description: &'static str,
+}
+
+pub fn float(name: &'static str, default: f32, description: &'static str) -> Param {
+    Param { name, default: ParamValue::Float(default), description }
+}
+
+pub fn vec3(name: &'static str, default: [f32; 3], description: &'static str) -> Param {
+    Param { name, default: ParamValue::Vec3(default), description }
+}
+
+pub fn vec4(name: &'static str, default: [f32; 4], description: &'static str) -> Param {
+    Param { name, default: ParamValue::Vec4(default), description }
+}
+
+/// Every stage clears the screen with something.
+pub fn clear_color(default: [f32; 4]) -> Param {
+    vec4("clear_color", default, "background color, r,g,b,a")
+}
+
+/// What the command line asked for, applied on top of a stage's defaults.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    pub files: Vec<PathBuf>,
+    pub sets: Vec<String>,
+}
+
+/// A stage's parameters with their current values.
+#[derive(Clone, Debug)]
+pub struct Params {
+    declared: Vec<Param>,
+    values: BTreeMap<&'static str, ParamValue>,
+}
+
+impl Params {
+    pub fn new(declared: Vec<Param>) -> Self {
+        let values = declared.iter().map(|param| (param.name, param.default)).collect();
+        Params { declared, values }
+    }
+
+    pub fn declared(&self) -> &[Param] {
+        &self.declared
+    }
+
+    /// Files first, then the `--set`s, so the command line wins.
+    pub fn apply(&mut self, overrides: &Overrides) -> Result<()> {
+        for file in &overrides.files {
+            self.load(file)?;
+        }
+        for set in &overrides.sets {
+            let (name, text) = set.split_once('=').ok_or_else(|| Error::Param {
+                name: set.clone(),
+                message: "expected name=value".to_string(),
+            })?;
+            self.set(name.trim(), text)?;
+        }
+        Ok(())
+    }
+
+    /// `text` is a number, or comma separated numbers for vectors: `75`, `-1,0.8,0.9`.
+    pub fn set(&mut self, name: &str, text: &str) -> Result<()> {
+        let numbers = text
+            .split(',')
+            .map(|number| number.trim().parse::<f32>())
+            .collect::<std::result::Result<Vec<f32>, _>>()
+            .map_err(|error| Error::Param { name: name.to_string(), message: format!("'{}': {}", text, error) })?;
+
+        self.set_numbers(name, &numbers)
+    }
+
+    fn set_numbers(&mut self, name: &str, numbers: &[f32]) -> Result<()> {
+        let param = self.declared.iter().find(|param| param.name == name).ok_or_else(|| Error::Param {
+            name: name.to_string(),
+            message: format!("this stage has no such parameter, it has: {}", self.names().join(", ")),
+        })?;
+        let value = param.default.reshaped(numbers).ok_or_else(|| Error::Param {
+            name: name.to_string(),
+            message: format!("expected a {}, got {} number(s)", param.default.type_name(), numbers.len()),
+        })?;
+
+        self.values.insert(param.name, value);
+        Ok(())
+    }
+
+    /// Reads `name = value` pairs from a .toml or .ron file.
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path).map_err(|error| Error::asset(path, error))?;
+        let entries = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ron") => ron_entries(&text),
+            _ => toml_entries(&text),
+        }.map_err(|message| Error::asset(path, message))?;
+
+        for (name, numbers) in entries {
+            self.set_numbers(&name, &numbers)?;
+        }
+        Ok(())
+    }
+
+    fn names(&self) -> Vec<&'static str> {
+        self.declared.iter().map(|param| param.name).collect()
+    }
+
+    // The accessors below panic instead of returning an `Error`: a stage reading a name it didn't declare in its
+    // `params()`, or reading it as the wrong type, is a bug in that stage's code. Whatever the user passes in
+    // was already checked against the declarations by `set` and `load`.
+
+    #[track_caller]
+    fn get(&self, name: &str) -> ParamValue {
+        match self.values.get(name) {
+            Some(value) => *value,
+            None => panic!("programming error: the stage reads parameter `{}` but never declared it in its params()", name),
+        }
+    }
+
+    #[track_caller]
+    pub fn float(&self, name: &str) -> f32 {
+        match self.get(name) {
+            ParamValue::Float(x) => x,
+            other => panic!("programming error: parameter `{}` is declared as a {}, but read as a float", name, other.type_name()),
+        }
+    }
+
+    #[track_caller]
+    pub fn vec3(&self, name: &str) -> [f32; 3] {
+        match self.get(name) {
+            ParamValue::Vec3(v) => v,
+            other => panic!("programming error: parameter `{}` is declared as a {}, but read as a vec3", name, other.type_name()),
+        }
+    }
+
+    #[track_caller]
+    pub fn vec4(&self, name: &str) -> [f32; 4] {
+        match self.get(name) {
+            ParamValue::Vec4(v) => v,
+            other => panic!("programming error: parameter `{}` is declared as a {}, but read as a vec4", name, other.type_name()),
+        }
+    }
+
+    /// The `--describe` table: name, type, default, current value (when overridden) and description.
+    pub fn describe(&self) -> String {
+        let width = self.declared.iter().map(|param| param.name.len()).max().unwrap_or(0);
+        let mut lines = Vec::new();
+        for param in &self.declared {
+            let value = self.get(param.name);
+            let current = if value != param.default { format!(" (set to {})", value) } else { String::new() };
+            lines.push(format!(
+                "  {:width$}  {:5}  {}{}\n  {:width$}  {}",
+                param.name, param.default.type_name(), param.default, current, "", param.description,
+                width = width,
+            ));
+        }
+        if lines.is_empty() {
+            lines.push("  (no parameters)".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+fn numbers_of<T>(values: &[T], number: impl Fn(&T) -> Option<f32>) -> Option<Vec<f32>> {
+    values.iter().map(number).collect()
+}
+
+fn toml_number(value: &toml::Value) -> Option<f32> {
+    match value {
+        toml::Value::Float(x) => Some(*x as f32),
+        toml::Value::Integer(i) => Some(*i as f32),
+        _ => None,
+    }
+}
+
+fn toml_entries(text: &str) -> std::result::Result<Vec<(String, Vec<f32>)>, String> {
+    let table: toml::value::Table = toml::from_str(text).map_err(|error| error.to_string())?;
+    table.into_iter().map(|(name, value)| {
+        let numbers = match &value {
+            toml::Value::Array(values) => numbers_of(values, toml_number),
+            single => toml_number(single).map(|x| vec![x]),
+        };
+        numbers.map(|numbers| (name.clone(), numbers)).ok_or_else(|| format!("`{}` should be a number or a list of numbers", name))
+    }).collect()
+}
+
+fn ron_number(value: &ron::Value) -> Option<f32> {
+    match value {
+        ron::Value::Number(number) => Some(number.into_f64() as f32),
+        _ => None,
+    }
+}
+
+fn ron_entries(text: &str) -> std::result::Result<Vec<(String, Vec<f32>)>, String> {
+    let map = match ron::from_str::<ron::Value>(text).map_err(|error| error.to_string())? {
+        ron::Value::Map(map) => map,
+        _ => return Err("expected `(name: value, ...)`".to_string()),
+    };
+    map.iter().map(|(name, value)| {
+        let name = match name {
+            ron::Value::String(name) => name.clone(),
+            other => return Err(format!("{:?} isn't a parameter name", other)),
+        };
+        let numbers = match value {
+            ron::Value::Seq(values) => numbers_of(values, ron_number),
+            single => ron_number(single).map(|x| vec![x]),
+        };
+        numbers.map(|numbers| (name.clone(), numbers)).ok_or_else(|| format!("`{}` should be a number or a tuple of numbers", name))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage_params() -> Params {
+        Params::new(vec![float("fov", 60.0, "vertical field of view"), vec3("light", [-1.0, 0.8, 0.9], "towards the light")])
+    }
+
+    fn temp_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gp-tuts-params-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn reshaping_needs_the_declared_number_of_components() {
+        assert_eq!(ParamValue::Float(0.0).reshaped(&[2.0]), Some(ParamValue::Float(2.0)));
+        assert_eq!(ParamValue::Vec3([0.0; 3]).reshaped(&[1.0, 2.0, 3.0]), Some(ParamValue::Vec3([1.0, 2.0, 3.0])));
+        assert_eq!(ParamValue::Vec4([0.0; 4]).reshaped(&[1.0, 2.0, 3.0]), None);
+        assert_eq!(ParamValue::Float(0.0).reshaped(&[]), None);
+    }
+
+    #[test]
+    fn sets_parse_numbers_and_comma_separated_vectors() {
+        let mut params = stage_params();
+        params.apply(&Overrides { files: Vec::new(), sets: vec!["fov=75".to_string(), " light = 0, 1 ,0.5".to_string()] }).unwrap();
+        assert_eq!(params.float("fov"), 75.0);
+        assert_eq!(params.vec3("light"), [0.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn bad_sets_say_which_parameter() {
+        let mut params = stage_params();
+        let error = |set: &str, params: &mut Params| params.apply(&Overrides { files: Vec::new(), sets: vec![set.to_string()] }).unwrap_err();
+        assert!(matches!(error("fov", &mut params), Error::Param { name, .. } if name == "fov"));
+        assert!(matches!(error("fov=wide", &mut params), Error::Param { name, .. } if name == "fov"));
+        assert!(matches!(error("light=1,2", &mut params), Error::Param { message, .. } if message.contains("expected a vec3")));
+        assert!(matches!(error("zoom=2", &mut params), Error::Param { message, .. } if message.contains("fov, light")));
+        assert_eq!(params.float("fov"), 60.0);
+    }
+
+    #[test]
+    fn toml_and_ron_configs_load_and_the_command_line_wins() {
+        let toml = temp_file("a.toml", "fov = 75\nlight = [0, 1, 0.5]\n");
+        let ron = temp_file("b.ron", "(fov: 90.5)");
+        let mut params = stage_params();
+        let result = params.apply(&Overrides { files: vec![toml.clone(), ron.clone()], sets: vec!["fov=30".to_string()] });
+        let mut ron_only = stage_params();
+        let ron_result = ron_only.load(&ron);
+        let _ = (fs::remove_file(&toml), fs::remove_file(&ron));
+
+        result.unwrap();
+        ron_result.unwrap();
+        assert_eq!(params.float("fov"), 30.0);
+        assert_eq!(params.vec3("light"), [0.0, 1.0, 0.5]);
+        assert_eq!(ron_only.float("fov"), 90.5);
+    }
+
+    #[test]
+    fn config_values_have_to_be_numbers() {
+        assert!(toml_entries("fov = \"wide\"").is_err());
+        assert!(ron_entries("(fov: \"wide\")").is_err());
+        assert!(ron_entries("[1, 2]").is_err());
+        assert_eq!(ron_entries("(light: (1, 2, 3))").unwrap(), vec![("light".to_string(), vec![1.0, 2.0, 3.0])]);
+    }
+
+    #[test]
+    fn describe_lists_defaults_and_what_was_changed() {
+        let mut params = stage_params();
+        params.set("fov", "75").unwrap();
+        assert_eq!(
+            params.describe(),
+            "  fov    float  60 (set to 75)\n         vertical field of view\n  light  vec3   -1,0.8,0.9\n         towards the light"
+        );
+        assert_eq!(Params::new(Vec::new()).describe(), "  (no parameters)");
+    }
+
+    #[test]
+    #[should_panic(expected = "declared as a float, but read as a vec3")]
+    fn reading_a_parameter_as_the_wrong_type_panics() {
+        stage_params().vec3("fov");
+    }
+}
@@ -0,0 +1,374 @@
+#![allow(dead_code)]
+use std::{collections::HashMap, fmt, fs};
+
+use super::hot_reload::{shader_dir, shader_file, ShaderFile};
+
+// A (very) small GLSL preprocessor on top of the one the driver already has:
+//  - `#include "lighting.glsl"` pastes in a file from `assets/shaders/` (every file at most once),
+//  - `#define`s handed in from Rust get inserted right after `#version`,
+//  - `#line` directives keep the line numbers of the original files, and a `SourceMap` turns the
+//    "0:12" / "0(12)" locations in a compile log back into "stage14.frag:12".
+
+/// Shared snippets, embedded so includes still resolve when `assets/shaders/` isn't around.
+pub const LIBRARY: &[ShaderFile] = &[
+    shader_file!("lighting.glsl"),
+    shader_file!("normal_mapping.glsl"),
+    shader_file!("lights.glsl"),
+    shader_file!("shadows.glsl"),
+    shader_file!("cascades.glsl"),
+    shader_file!("gbuffer.glsl"),
+    shader_file!("clusters.glsl"),
+    shader_file!("pbr.glsl"),
+    shader_file!("ibl.glsl"),
+    shader_file!("ibl_sampling.glsl"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreprocessError {
+    MissingInclude { name: String, file: String, line: usize },
+    RecursiveInclude { chain: Vec<String> },
+    Malformed { file: String, line: usize, text: String },
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreprocessError::MissingInclude { name, file, line } =>
+                write!(f, "{}:{}: can't find include \"{}\"", file, line, name),
+            PreprocessError::RecursiveInclude { chain } =>
+                write!(f, "recursive include: {}", chain.join(" -> ")),
+            PreprocessError::Malformed { file, line, text } =>
+                write!(f, "{}:{}: can't make sense of `{}`", file, line, text),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// Where sources come from: the files on disk (falling back to the embedded copies), or only the embedded copies.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Origin {
+    Disk,
+    Embedded,
+}
+
+/// Source string number -> file name, number 0 is always the file that was preprocessed.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    pub files: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Preprocessed {
+    pub source: String,
+    pub source_map: SourceMap,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Preprocessor {
+    defines: Vec<(String, String)>,
+    virtual_files: HashMap<String, String>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds `#define name value` to every shader run through this preprocessor.
+    pub fn define(mut self, name: &str, value: impl fmt::Display) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Makes `#include "name"` resolve to `source`, for code generated at runtime.
+    pub fn virtual_file(mut self, name: &str, source: impl Into<String>) -> Self {
+        self.virtual_files.insert(name.to_string(), source.into());
+        self
+    }
+
+    pub fn is_virtual(&self, name: &str) -> bool {
+        self.virtual_files.contains_key(name)
+    }
+
+    pub fn process(&self, file: &ShaderFile, origin: Origin) -> Result<Preprocessed, PreprocessError> {
+        let source = match origin {
+            Origin::Disk => file.load(),
+            Origin::Embedded => file.embedded.to_string(),
+        };
+        self.process_source(file.name, &source, origin)
+    }
+
+    pub fn process_source(&self, name: &str, source: &str, origin: Origin) -> Result<Preprocessed, PreprocessError> {
+        let mut state = State { source_map: SourceMap { files: vec![name.to_string()] }, stack: vec![name.to_string()], output: String::new() };
+
+        let mut lines = source.lines().enumerate().peekable();
+
+        // #version has to stay the very first thing the driver sees, our defines go right after it.
+        let mut version = 110;
+        while let Some((_, line)) = lines.peek() {
+            if line.trim().is_empty() || line.trim_start().starts_with("//") {
+                lines.next();
+                continue;
+            }
+            if let Some(rest) = line.trim_start().strip_prefix("#version") {
+                version = rest.split_whitespace().next().and_then(|v| v.parse().ok()).unwrap_or(110);
+                state.output.push_str(line.trim());
+                state.output.push('\n');
+                lines.next();
+            }
+            break;
+        }
+
+        for (name, value) in self.defines.iter() {
+            state.output.push_str(&format!("#define {} {}\n", name, value));
+        }
+
+        let next_line = lines.peek().map_or(1, |(index, _)| index + 1);
+        state.output.push_str(&line_directive(next_line, 0, version));
+
+        self.expand(lines, 0, version, origin, &mut state)?;
+
+        Ok(Preprocessed { source: state.output, source_map: state.source_map })
+    }
+
+    fn expand<'a>(
+        &self,
+        lines: impl Iterator<Item = (usize, &'a str)>,
+        source_number: usize,
+        version: u32,
+        origin: Origin,
+        state: &mut State,
+    ) -> Result<(), PreprocessError> {
+        let file = state.source_map.files[source_number].clone();
+
+        for (index, line) in lines {
+            let trimmed = line.trim_start();
+
+            if let Some(rest) = trimmed.strip_prefix("#include") {
+                let name = parse_include(rest).ok_or_else(|| PreprocessError::Malformed {
+                    file: file.clone(),
+                    line: index + 1,
+                    text: line.trim().to_string(),
+                })?;
+
+                if state.stack.contains(&name) {
+                    let mut chain = state.stack.clone();
+                    chain.push(name);
+                    return Err(PreprocessError::RecursiveInclude { chain });
+                }
+
+                // every file goes in once, a second include is just dropped (no include guards needed).
+                if !state.source_map.files.contains(&name) {
+                    let source = self.resolve(&name, origin).ok_or_else(|| PreprocessError::MissingInclude {
+                        name: name.clone(),
+                        file: file.clone(),
+                        line: index + 1,
+                    })?;
+
+                    state.source_map.files.push(name.clone());
+                    let number = state.source_map.files.len() - 1;
+
+                    state.stack.push(name);
+                    state.output.push_str(&line_directive(1, number, version));
+                    self.expand(source.lines().enumerate(), number, version, origin, state)?;
+                    state.stack.pop();
+                }
+
+                // and back to where we were.
+                state.output.push_str(&line_directive(index + 2, source_number, version));
+                continue;
+            }
+
+            if trimmed.starts_with("#version") && source_number != 0 {
+                return Err(PreprocessError::Malformed { file, line: index + 1, text: line.trim().to_string() });
+            }
+
+            state.output.push_str(line);
+            state.output.push('\n');
+        }
+
+        Ok(())
+    }
+
+    fn resolve(&self, name: &str, origin: Origin) -> Option<String> {
+        if let Some(source) = self.virtual_files.get(name) {
+            return Some(source.clone());
+        }
+        if origin == Origin::Disk {
+            if let Ok(source) = fs::read_to_string(shader_dir().join(name)) {
+                return Some(source);
+            }
+        }
+        LIBRARY.iter().find(|file| file.name == name).map(|file| file.embedded.to_string())
+    }
+}
+
+struct State {
+    source_map: SourceMap,
+    stack: Vec<String>,
+    output: String,
+}
+
+fn parse_include(rest: &str) -> Option<String> {
+    let rest = rest.trim();
+    let rest = rest.strip_prefix('"')?;
+    let end = rest.find('"')?;
+    Some(rest[..end].to_string())
+}
+
+/// `#line` so the line after it reports as `next_line` of source string `source_number`.
+/// Before GLSL 3.30 the directive named the number of the line *before* the next one.
+pub fn line_directive(next_line: usize, source_number: usize, version: u32) -> String {
+    let line = if version >= 330 { next_line } else { next_line - 1 };
+    format!("#line {} {}\n", line, source_number)
+}
+
+impl SourceMap {
+    /// Rewrites the source string numbers in a compile log into file names.
+    /// Understands the Mesa/Intel "0:12(5):", the NVIDIA "0(12) :" and the AMD "ERROR: 0:12:" styles.
+    pub fn annotate(&self, log: &str) -> String {
+        log.lines().map(|line| self.annotate_line(line)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn annotate_line(&self, line: &str) -> String {
+        let prefix_len = ["ERROR: ", "WARNING: "]
+            .iter()
+            .find(|prefix| line.starts_with(*prefix))
+            .map_or(0, |prefix| prefix.len());
+        let (prefix, rest) = line.split_at(prefix_len);
+
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return line.to_string();
+        }
+        let number: usize = rest[..digits].parse().unwrap();
+        let file = match self.files.get(number) {
+            Some(file) => file,
+            None => return line.to_string(),
+        };
+
+        let after = &rest[digits..];
+        let (line_number, tail) = if let Some(after) = after.strip_prefix(':') {
+            let len = after.chars().take_while(|c| c.is_ascii_digit()).count();
+            (&after[..len], &after[len..])
+        } else if let Some(after) = after.strip_prefix('(') {
+            let len = after.chars().take_while(|c| c.is_ascii_digit()).count();
+            match after[len..].strip_prefix(')') {
+                Some(tail) => (&after[..len], tail),
+                None => return line.to_string(),
+            }
+        } else {
+            return line.to_string();
+        };
+
+        if line_number.is_empty() {
+            return line.to_string();
+        }
+
+        format!("{}{}:{}{}", prefix, file, line_number, tail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_directive_switches_to_next_line_numbering_at_330() {
+        assert_eq!(line_directive(12, 1, 140), "#line 11 1\n");
+        assert_eq!(line_directive(12, 1, 150), "#line 11 1\n");
+        assert_eq!(line_directive(12, 1, 330), "#line 12 1\n");
+        assert_eq!(line_directive(12, 1, 410), "#line 12 1\n");
+    }
+
+    fn process(preprocessor: &Preprocessor, source: &str) -> Result<Preprocessed, PreprocessError> {
+        preprocessor.process_source("main.frag", source, Origin::Embedded)
+    }
+
+    #[test]
+    fn includes_are_pasted_in_with_line_directives_around_them() {
+        let preprocessor = Preprocessor::new().virtual_file("a.glsl", "float a() { return 1.0; }");
+        let out = process(&preprocessor, "#version 330\n\n#include \"a.glsl\"\nvoid main() {}").unwrap();
+        assert_eq!(
+            out.source,
+            "#version 330\n#line 2 0\n\n#line 1 1\nfloat a() { return 1.0; }\n#line 4 0\nvoid main() {}\n"
+        );
+        assert_eq!(out.source_map.files, vec!["main.frag", "a.glsl"]);
+    }
+
+    #[test]
+    fn a_file_included_twice_goes_in_once() {
+        let preprocessor = Preprocessor::new()
+            .virtual_file("a.glsl", "float a;")
+            .virtual_file("b.glsl", "#include \"a.glsl\"\nfloat b;");
+        let out = process(&preprocessor, "#version 330\n#include \"a.glsl\"\n#include \"b.glsl\"").unwrap();
+        assert_eq!(out.source.matches("float a;").count(), 1);
+        assert!(out.source.contains("float b;"));
+        assert_eq!(out.source_map.files, vec!["main.frag", "a.glsl", "b.glsl"]);
+    }
+
+    #[test]
+    fn recursive_includes_are_an_error() {
+        let preprocessor = Preprocessor::new()
+            .virtual_file("a.glsl", "#include \"b.glsl\"")
+            .virtual_file("b.glsl", "#include \"a.glsl\"");
+        let chain = ["main.frag", "a.glsl", "b.glsl", "a.glsl"].iter().map(|name| name.to_string()).collect();
+        assert_eq!(process(&preprocessor, "#include \"a.glsl\"").unwrap_err(), PreprocessError::RecursiveInclude { chain });
+    }
+
+    #[test]
+    fn missing_and_malformed_includes_say_where() {
+        let preprocessor = Preprocessor::new();
+        assert_eq!(
+            process(&preprocessor, "#version 330\n\n#include \"nope.glsl\"").unwrap_err(),
+            PreprocessError::MissingInclude { name: "nope.glsl".to_string(), file: "main.frag".to_string(), line: 3 }
+        );
+        assert_eq!(
+            process(&preprocessor, "#include <nope.glsl>").unwrap_err(),
+            PreprocessError::Malformed { file: "main.frag".to_string(), line: 1, text: "#include <nope.glsl>".to_string() }
+        );
+    }
+
+    #[test]
+    fn library_files_resolve_without_the_assets_folder() {
+        let out = process(&Preprocessor::new(), "#version 330\n#include \"lighting.glsl\"").unwrap();
+        assert_eq!(out.source_map.files, vec!["main.frag", "lighting.glsl"]);
+    }
+
+    #[test]
+    fn defines_go_right_after_version() {
+        let preprocessor = Preprocessor::new().define("LIGHTS", 4).define("SHADOWS", "");
+        let out = process(&preprocessor, "// header\n#version 140\nvoid main() {}").unwrap();
+        assert_eq!(out.source, "#version 140\n#define LIGHTS 4\n#define SHADOWS \n#line 2 0\nvoid main() {}\n");
+
+        // without a #version they're the first thing.
+        let out = process(&preprocessor, "void main() {}").unwrap();
+        assert!(out.source.starts_with("#define LIGHTS 4\n"));
+    }
+
+    #[test]
+    fn annotate_understands_mesa_nvidia_and_amd_logs() {
+        let map = SourceMap { files: vec!["stage14.frag".to_string(), "lighting.glsl".to_string()] };
+        assert_eq!(
+            map.annotate("0:12(5): error: `foo' undeclared"),
+            "stage14.frag:12(5): error: `foo' undeclared"
+        );
+        assert_eq!(
+            map.annotate("1(7) : error C1008: undefined variable \"foo\""),
+            "lighting.glsl:7 : error C1008: undefined variable \"foo\""
+        );
+        assert_eq!(
+            map.annotate("ERROR: 1:3: 'foo' : undeclared identifier\nWARNING: 0:9: unused"),
+            "ERROR: lighting.glsl:3: 'foo' : undeclared identifier\nWARNING: stage14.frag:9: unused"
+        );
+    }
+
+    #[test]
+    fn annotate_leaves_lines_it_does_not_understand_alone() {
+        let map = SourceMap { files: vec!["stage14.frag".to_string()] };
+        for line in ["error: linking failed", "3:4: no such source string", "0(12 : unclosed", "0: no line number"] {
+            assert_eq!(map.annotate(line), line);
+        }
+    }
+}
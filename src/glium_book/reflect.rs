@@ -0,0 +1,199 @@
+#![allow(dead_code)]
+use std::fmt;
+
+use glium::{
+    program::Program,
+    uniforms::{UniformType, UniformValue, Uniforms},
+    vertex::{AttributeType, VertexFormat},
+};
+
+// `implement_vertex!` fields and shader `in`s (and uniform! names and shader uniforms) are only matched up
+// by their name strings, and when they don't line up you find out at draw time, or worse, you get a black
+// screen because an unset uniform is silently zero. This asks the linked program what it actually uses and
+// compares it against the vertex formats and uniforms a draw call is about to hand it.
+
+/// Something that doesn't line up between a program and what gets bound to it.
+#[derive(Debug, Clone)]
+pub enum Problem {
+    /// The shader reads an attribute none of the vertex buffers have.
+    MissingAttribute { name: String, shader: AttributeType },
+    /// A vertex buffer has a field the shader doesn't read (or the compiler optimized it away).
+    ExtraAttribute { name: String },
+    AttributeTypeMismatch { name: String, shader: AttributeType, buffer: AttributeType },
+    /// The shader has a uniform nobody sets, it stays zero.
+    MissingUniform { name: String, shader: UniformType },
+    MissingBlock { name: String },
+    /// A uniform is set that the program doesn't have (or the compiler optimized it away).
+    ExtraUniform { name: String },
+    UniformTypeMismatch { name: String, shader: UniformType },
+}
+
+impl Problem {
+    /// Extras are harmless, everything else is a bug in either the shader or the Rust side.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Problem::ExtraAttribute { .. } | Problem::ExtraUniform { .. })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingAttribute { name, shader } => write!(
+                f,
+                "attribute `{}` ({:?}) is read by the shader but no vertex struct has a field with that name",
+                name, shader,
+            ),
+            Problem::ExtraAttribute { name } => write!(f, "vertex field `{}` isn't used by the shader", name),
+            Problem::AttributeTypeMismatch { name, shader, buffer } => write!(
+                f,
+                "attribute `{}` is {:?} in the shader but {:?} in the vertex struct",
+                name, shader, buffer,
+            ),
+            Problem::MissingUniform { name, shader } => write!(
+                f,
+                "uniform `{}` ({:?}) is never set, it will read as zero",
+                name, shader,
+            ),
+            Problem::MissingBlock { name } => write!(f, "uniform block `{}` is never bound", name),
+            Problem::ExtraUniform { name } => write!(f, "uniform `{}` is set but the program doesn't use it", name),
+            Problem::UniformTypeMismatch { name, shader } => write!(
+                f,
+                "uniform `{}` is {:?} in the shader but is set to something else",
+                name, shader,
+            ),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum ScalarKind {
+    Float,
+    Double,
+    Int,
+    Unsigned,
+}
+
+/// What the shader sees of an attribute's components. Normalized integers arrive as floats.
+fn scalar_kind(ty: AttributeType, normalized: bool) -> ScalarKind {
+    use AttributeType::*;
+
+    match ty {
+        F64 | F64F64 | F64F64F64 | F64F64F64F64
+        | F64x2x2 | F64x2x3 | F64x2x4 | F64x3x2 | F64x3x3 | F64x3x4 | F64x4x2 | F64x4x3 | F64x4x4 => ScalarKind::Double,
+
+        F16 | F16F16 | F16F16F16 | F16F16F16F16
+        | F16x2x2 | F16x2x3 | F16x2x4 | F16x3x2 | F16x3x3 | F16x3x4 | F16x4x2 | F16x4x3 | F16x4x4
+        | F32 | F32F32 | F32F32F32 | F32F32F32F32
+        | F32x2x2 | F32x2x3 | F32x2x4 | F32x3x2 | F32x3x3 | F32x3x4 | F32x4x2 | F32x4x3 | F32x4x4
+        | F10F11F11UnsignedIntReversed | FixedFloatI16U16 => ScalarKind::Float,
+
+        _ if normalized => ScalarKind::Float,
+
+        I8 | I8I8 | I8I8I8 | I8I8I8I8
+        | I16 | I16I16 | I16I16I16 | I16I16I16I16
+        | I32 | I32I32 | I32I32I32 | I32I32I32I32
+        | I64 | I64I64 | I64I64I64 | I64I64I64I64
+        | I2I10I10I10Reversed | I10I10I10I2 => ScalarKind::Int,
+
+        U8 | U8U8 | U8U8U8 | U8U8U8U8
+        | U16 | U16U16 | U16U16U16 | U16U16U16U16
+        | U32 | U32U32 | U32U32U32 | U32U32U32U32
+        | U64 | U64U64 | U64U64U64 | U64U64U64U64
+        | U2U10U10U10Reversed | U10U10U10U2 => ScalarKind::Unsigned,
+    }
+}
+
+/// GL fills in components the buffer doesn't have (with 0, 0, 1), so a `vec4` can read a `[f32; 3]`.
+fn attribute_compatible(shader: AttributeType, buffer: AttributeType, normalized: bool) -> bool {
+    shader == buffer || (
+        scalar_kind(shader, false) == scalar_kind(buffer, normalized)
+            && buffer.get_num_components() <= shader.get_num_components()
+            && shader.get_num_components() <= 4
+    )
+}
+
+pub fn check_attributes(program: &Program, formats: &[&VertexFormat]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let provided = || formats.iter().flat_map(|format| format.iter());
+
+    for (name, attribute) in program.attributes() {
+        if name.starts_with("gl_") {
+            continue;
+        }
+
+        match provided().find(|binding| binding.0 == name.as_str()) {
+            None => problems.push(Problem::MissingAttribute { name: name.clone(), shader: attribute.ty }),
+            Some(&(_, _, _, buffer, normalized)) if !attribute_compatible(attribute.ty, buffer, normalized) => {
+                problems.push(Problem::AttributeTypeMismatch { name: name.clone(), shader: attribute.ty, buffer })
+            }
+            Some(_) => (),
+        }
+    }
+
+    for (name, ..) in provided() {
+        if program.get_attribute(name).is_none() {
+            problems.push(Problem::ExtraAttribute { name: name.to_string() });
+        }
+    }
+
+    problems
+}
+
+pub fn check_uniforms<U: Uniforms>(program: &Program, uniforms: &U) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut provided = Vec::new();
+
+    uniforms.visit_values(|name, value| {
+        if let Some(uniform) = program.get_uniform(name) {
+            if !value.is_usable_with(&uniform.ty) {
+                problems.push(Problem::UniformTypeMismatch { name: name.to_string(), shader: uniform.ty });
+            }
+        } else if !matches!(value, UniformValue::Block(..)) || !program.get_uniform_blocks().contains_key(name) {
+            problems.push(Problem::ExtraUniform { name: name.to_string() });
+        }
+        provided.push(name.to_string());
+    });
+
+    for (name, uniform) in program.uniforms() {
+        if !name.starts_with("gl_") && !provided.contains(name) {
+            problems.push(Problem::MissingUniform { name: name.clone(), shader: uniform.ty });
+        }
+    }
+    for name in program.get_uniform_blocks().keys() {
+        if !provided.contains(name) {
+            problems.push(Problem::MissingBlock { name: name.clone() });
+        }
+    }
+
+    problems
+}
+
+/// Everything `check_attributes` and `check_uniforms` find, errors first.
+pub fn check<U: Uniforms>(program: &Program, formats: &[&VertexFormat], uniforms: &U) -> Vec<Problem> {
+    let mut problems = check_attributes(program, formats);
+    problems.extend(check_uniforms(program, uniforms));
+    problems.sort_by_key(|problem| !problem.is_error());
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalar_kinds_follow_the_component_type() {
+        assert_eq!(scalar_kind(AttributeType::F32F32F32, false), ScalarKind::Float);
+        assert_eq!(scalar_kind(AttributeType::F64x4x4, false), ScalarKind::Double);
+        assert_eq!(scalar_kind(AttributeType::I2I10I10I10Reversed, false), ScalarKind::Int);
+        assert_eq!(scalar_kind(AttributeType::U8U8U8U8, false), ScalarKind::Unsigned);
+        assert_eq!(scalar_kind(AttributeType::U8U8U8U8, true), ScalarKind::Float);
+    }
+
+    #[test]
+    fn shorter_buffers_fill_wider_shader_inputs() {
+        assert!(attribute_compatible(AttributeType::F32F32F32F32, AttributeType::F32F32F32, false));
+        assert!(!attribute_compatible(AttributeType::F32F32, AttributeType::F32F32F32, false));
+        assert!(!attribute_compatible(AttributeType::F32, AttributeType::I32, false));
+        assert!(attribute_compatible(AttributeType::F32F32F32F32, AttributeType::U8U8U8U8, true));
+    }
+}
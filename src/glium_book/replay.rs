@@ -0,0 +1,690 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use glium::{
+    draw_parameters::{BackfaceCullingMode, Depth, DepthTest, PolygonMode},
+    framebuffer::{DepthRenderBuffer, SimpleFrameBuffer},
+    glutin::{self, event_loop::EventLoop},
+    index::{IndicesSource, NoIndices, PrimitiveType},
+    program::{Binary, ProgramCreationInput},
+    texture::{DepthFormat, RawImage2d, SrgbTexture2d, Texture2d},
+    uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue, Uniforms},
+    vertex::{AttributeType, EmptyInstanceAttributes, EmptyVertexAttributes, VertexBufferAny, VerticesSource},
+    Blend, BlendingFunction, Display, DrawParameters, IndexBuffer, LinearBlendingFactor, Program, Rect, Surface,
+    VertexBuffer,
+};
+use image::{Rgba, RgbaImage};
+
+use crate::error::{Error, Result};
+use super::capture::upright;
+use super::context::create_display;
+use super::material::Value;
+use super::trace::{
+    debug_name, draw_sources, Op, FRAME, TracedBlendingFunction, TracedDraw, TracedFloat, TracedFrame, TracedIndices,
+    TracedParameters, TracedProgram, TracedSampler, TracedTexture, TracedUniform, TracedVertices,
+};
+
+// `replay <frame.json>`: draws a frame written by trace.rs again, offscreen, and saves what came out as a
+// png. Programs come back from their binaries (so only on the driver that traced them), buffers and
+// textures from the blobs next to the trace. Whatever can't be rebuilt is skipped with a note rather
+// than failing the whole frame. `--diff` compares against a png, or against another trace, in which case
+// both get replayed and the draw calls are compared too: which uniforms, parameters and buffers changed.
+// Passes a stage drew into its own framebuffers are replayed into color and depth of the size they had,
+// in the same order as the frame's ops, and each one's color is saved too, next to the frame's png.
+// It still opens a (hidden) window for the context, headless machines need xvfb-run or similar.
+
+pub fn run(frame: &Path, output: Option<PathBuf>, diff: Option<PathBuf>) -> Result<()> {
+    let event_loop = EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new().with_visible(false);
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(window_builder, context_builder, &event_loop)?;
+
+    let trace = Trace::load(frame)?;
+    let (image, passes) = trace.replay(&display)?;
+    let output = output.unwrap_or_else(|| frame.with_extension("replay.png"));
+    image.save(&output).map_err(|error| Error::write(&output, error))?;
+    println!("replayed {} to {}", frame.display(), output.display());
+    for (name, pass) in &passes {
+        let path = output.with_extension(format!("{}.png", name));
+        pass.save(&path).map_err(|error| Error::write(&path, error))?;
+        println!("  pass {} to {}", name, path.display());
+    }
+
+    let diff = match diff {
+        Some(diff) => diff,
+        None => return Ok(()),
+    };
+    let other = if diff.extension().is_some_and(|extension| extension == "png") {
+        image::open(&diff).map_err(|error| Error::asset(&diff, error))?.into_rgba8()
+    } else {
+        let other = Trace::load(&diff)?;
+        let differences = trace.diff(&other);
+        if differences.is_empty() {
+            println!("the draw calls are the same");
+        }
+        for difference in &differences {
+            println!("  {}", difference);
+        }
+        other.replay(&display)?.0
+    };
+    diff_images(&image, &other, &output.with_extension("diff.png"))
+}
+
+/// A traced frame and where its blobs are.
+struct Trace {
+    dir: PathBuf,
+    frame: TracedFrame,
+}
+
+/// Something that couldn't be rebuilt, the draw it belongs to gets skipped.
+type Skip = String;
+
+impl Trace {
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|error| Error::asset(path, error))?;
+        let frame = serde_json::from_str(&text).map_err(|error| Error::asset(path, error))?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(Trace { dir, frame })
+    }
+
+    fn blob(&self, name: &Option<String>) -> std::result::Result<PathBuf, Skip> {
+        name.as_ref().map(|name| self.dir.join(name)).ok_or_else(|| "the data wasn't traced".to_string())
+    }
+
+    fn bytes(&self, name: &Option<String>) -> std::result::Result<Vec<u8>, Skip> {
+        let path = self.blob(name)?;
+        fs::read(&path).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// The frame, and every pass by name.
+    fn replay(&self, display: &Display) -> Result<(RgbaImage, BTreeMap<String, RgbaImage>)> {
+        for problem in &self.frame.problems {
+            println!("  traced with a problem: {}", problem);
+        }
+
+        let mut attachments = BTreeMap::new();
+        attachments.insert(FRAME, target_attachments(display, self.frame.dimensions)?);
+        for (name, dimensions) in &self.frame.targets {
+            attachments.insert(name.as_str(), target_attachments(display, *dimensions)?);
+        }
+        let mut targets = BTreeMap::new();
+        for (name, (color, depth)) in &attachments {
+            targets.insert(*name, SimpleFrameBuffer::with_depth_buffer(display, color, depth)?);
+        }
+
+        let programs: HashMap<&str, _> = self.frame.programs.iter()
+            .map(|(id, program)| (id.as_str(), self.program(display, program)))
+            .collect();
+
+        for (index, op) in self.frame.ops.iter().enumerate() {
+            let name = match op {
+                Op::Clear { target, .. } => target,
+                Op::Draw(draw) => &draw.target,
+            };
+            let replayed = match (targets.get_mut(name.as_str()), op) {
+                (None, _) => Err(format!("its target, {}, isn't in the trace", name)),
+                (Some(target), Op::Clear { rect: area, color, srgb, depth, stencil, .. }) => {
+                    let color = color.map(|[r, g, b, a]| (r, g, b, a));
+                    target.clear(rect(area).as_ref(), color, *srgb, *depth, *stencil);
+                    Ok(())
+                }
+                (Some(target), Op::Draw(draw)) => match programs.get(draw.program.as_str()) {
+                    Some(Ok(program)) => self.draw(display, target, program, draw),
+                    Some(Err(skip)) => Err(skip.clone()),
+                    None => Err("its program isn't in the trace".to_string()),
+                },
+            };
+            if let Err(skip) = replayed {
+                println!("  skipped op {}: {}", index, skip);
+            }
+        }
+        drop(targets);
+
+        let mut images = BTreeMap::new();
+        for (name, (color, _)) in attachments {
+            images.insert(name.to_string(), upright(color.read())?);
+        }
+        let frame = images.remove(FRAME).unwrap_or_default();
+        Ok((frame, images))
+    }
+
+    fn program(&self, display: &Display, program: &TracedProgram) -> std::result::Result<Program, Skip> {
+        let content = self.bytes(&program.binary)?;
+        let format = program.binary_format.ok_or("the program binary has no format")?;
+        let input = ProgramCreationInput::Binary { data: Binary { format, content }, outputs_srgb: false, uses_point_size: false };
+        // a binary only loads on the driver (and version of it) that made it.
+        Program::new(display, input).map_err(|error| format!("the program binary didn't load, {}", error))
+    }
+
+    fn draw(&self, display: &Display, target: &mut SimpleFrameBuffer, program: &Program, draw: &TracedDraw) -> std::result::Result<(), Skip> {
+        let buffers = draw.vertices.iter()
+            .map(|traced| self.vertices(display, traced))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let sources: Vec<VerticesSource> = buffers.iter().map(Vertices::source).collect();
+
+        let indices = self.indices(display, &draw.indices)?;
+        let uniforms = self.uniforms(display, &draw.uniforms)?;
+        let parameters = draw_parameters(&draw.parameters);
+
+        draw_sources(target, sources, indices.source(), program, &uniforms, &parameters).map_err(|error| error.to_string())
+    }
+
+    fn vertices(&self, display: &Display, traced: &TracedVertices) -> std::result::Result<Vertices, Skip> {
+        let (format, stride, per_instance, data) = match traced {
+            TracedVertices::Marker { len, per_instance } => return Ok(Vertices::Marker(*len, *per_instance)),
+            TracedVertices::Buffer { format, stride, per_instance, data, .. } => (format, *stride, *per_instance, data),
+        };
+
+        let mut attributes = Vec::new();
+        for attribute in format {
+            let ty = by_name(ATTRIBUTE_TYPES, &attribute.ty)
+                .ok_or_else(|| format!("attribute {} has a type that can't be replayed", attribute.name))?;
+            attributes.push((attribute.name.clone().into(), attribute.offset, attribute.location, ty, attribute.normalize));
+        }
+
+        let bytes = self.bytes(data)?;
+        let buffer = vertex_buffer(display, &bytes, stride, attributes.into())?;
+        Ok(Vertices::Buffer(Box::new(buffer), per_instance))
+    }
+
+    fn indices(&self, display: &Display, traced: &TracedIndices) -> std::result::Result<Indices, Skip> {
+        let (primitive, ty, data) = match traced {
+            TracedIndices::Multidraw { .. } => return Err("multidraw indices weren't traced".to_string()),
+            TracedIndices::None { primitive } => {
+                let primitive = by_name(PRIMITIVES, primitive).ok_or("the primitive type can't be replayed")?;
+                return Ok(Indices::None(NoIndices(primitive)));
+            }
+            TracedIndices::Buffer { primitive, ty, data, .. } => (primitive, ty, data),
+        };
+
+        let primitive = by_name(PRIMITIVES, primitive).ok_or("the primitive type can't be replayed")?;
+        let bytes = self.bytes(data)?;
+        let error = |error: glium::index::BufferCreationError| error.to_string();
+        Ok(match ty.as_str() {
+            "U8" => Indices::U8(IndexBuffer::new(display, primitive, &bytes).map_err(error)?),
+            "U16" => {
+                let indices: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_ne_bytes([b[0], b[1]])).collect();
+                Indices::U16(IndexBuffer::new(display, primitive, &indices).map_err(error)?)
+            }
+            "U32" => {
+                let indices: Vec<u32> = bytes.chunks_exact(4).map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect();
+                Indices::U32(IndexBuffer::new(display, primitive, &indices).map_err(error)?)
+            }
+            other => return Err(format!("{} indices can't be replayed", other)),
+        })
+    }
+
+    fn texture<T>(
+        &self,
+        traced: &TracedTexture,
+        upload: impl FnOnce(RawImage2d<u8>) -> std::result::Result<T, glium::texture::TextureCreationError>,
+    ) -> std::result::Result<T, Skip> {
+        let path = self.blob(&traced.data)?;
+        let image = image::open(&path).map_err(|error| format!("{}: {}", path.display(), error))?.into_rgba8();
+        let size = image.dimensions();
+        // traced the right way up, GL wants the bottom row first.
+        upload(RawImage2d::from_raw_rgba_reversed(&image.into_raw(), size)).map_err(|error| format!("{:?}", error))
+    }
+
+    fn uniforms(&self, display: &Display, traced: &BTreeMap<String, TracedUniform>) -> std::result::Result<ReplayUniforms, Skip> {
+        let mut uniforms = Vec::new();
+        for (name, uniform) in traced {
+            let value = match uniform {
+                TracedUniform::Uint { value } => Uniform::UnsignedInt(*value),
+                TracedUniform::Texture2d(texture) => {
+                    let sampler = texture.sampler.as_ref().and_then(sampler);
+                    Uniform::Texture(self.texture(texture, |raw| Texture2d::new(display, raw))?, sampler)
+                }
+                TracedUniform::SrgbTexture2d(texture) => {
+                    let sampler = texture.sampler.as_ref().and_then(sampler);
+                    Uniform::SrgbTexture(self.texture(texture, |raw| SrgbTexture2d::new(display, raw))?, sampler)
+                }
+                TracedUniform::Value { ty, value } => {
+                    let floats: Vec<f32> = value.iter().map(TracedFloat::value).collect();
+                    match Value::of_type(ty).map(|mut value| (value.set_floats(&floats), value)) {
+                        Some((true, value)) => Uniform::Value(value),
+                        _ => {
+                            println!("  uniform {} ({}) isn't replayed", name, ty);
+                            continue;
+                        }
+                    }
+                }
+                // the program still draws with the uniform left at zero, say so and carry on.
+                TracedUniform::DepthTexture2d { .. } | TracedUniform::Block | TracedUniform::Unsupported => {
+                    println!("  uniform {} isn't replayed", name);
+                    continue;
+                }
+            };
+            uniforms.push((name.clone(), value));
+        }
+        Ok(ReplayUniforms(uniforms))
+    }
+
+    /// What changed in the draw calls between two traces, one line per difference.
+    fn diff(&self, other: &Trace) -> Vec<String> {
+        let ops = |trace: &Trace| -> Vec<serde_json::Value> {
+            trace.frame.ops.iter().map(|op| serde_json::to_value(trace.normalized(op)).unwrap_or_default()).collect()
+        };
+        let (ours, theirs) = (ops(self), ops(other));
+
+        let mut differences = Vec::new();
+        if ours.len() != theirs.len() {
+            differences.push(format!("{} ops vs {}", ours.len(), theirs.len()));
+        }
+        for (index, (a, b)) in ours.iter().zip(&theirs).enumerate() {
+            diff_json(&format!("op {}", index), a, b, &mut differences);
+        }
+        differences
+    }
+
+    /// An op with its program named by the binary rather than a generation, which depends on the order
+    /// programs were built in.
+    fn normalized(&self, op: &Op) -> Op {
+        let mut op = op.clone();
+        if let Op::Draw(draw) = &mut op {
+            if let Some(binary) = self.frame.programs.get(draw.program.as_str()).and_then(|traced| traced.binary.clone()) {
+                draw.program = binary;
+            }
+        }
+        op
+    }
+}
+
+/// Color and depth for one of the traced targets. The window is sRGB, so are these, that way the shaders'
+/// output gets converted the same way.
+fn target_attachments(display: &Display, (width, height): (u32, u32)) -> Result<(SrgbTexture2d, DepthRenderBuffer)> {
+    let color = SrgbTexture2d::empty(display, width, height)?;
+    let depth = DepthRenderBuffer::new(display, DepthFormat::F32, width, height)
+        .map_err(|error| Error::Framebuffer(format!("{:?}", error)))?;
+    Ok((color, depth))
+}
+
+fn diff_json(path: &str, a: &serde_json::Value, b: &serde_json::Value, differences: &mut Vec<String>) {
+    use serde_json::Value::{Array, Number, Object};
+    let numbers = |values: &[serde_json::Value]| values.iter().all(|value| matches!(value, Number(_)));
+    match (a, b) {
+        (Object(ours), Object(theirs)) => {
+            for (key, value) in ours {
+                match theirs.get(key) {
+                    Some(other) => diff_json(&format!("{}.{}", path, key), value, other, differences),
+                    None => differences.push(format!("{}.{}: only in the first trace", path, key)),
+                }
+            }
+            for key in theirs.keys().filter(|key| !ours.contains_key(*key)) {
+                differences.push(format!("{}.{}: only in the second trace", path, key));
+            }
+        }
+        // vectors and matrices are shown whole, element by element would be noise.
+        (Array(ours), Array(theirs)) if ours.len() == theirs.len() && !(numbers(ours) && numbers(theirs)) => {
+            for (index, (a, b)) in ours.iter().zip(theirs).enumerate() {
+                diff_json(&format!("{}[{}]", path, index), a, b, differences);
+            }
+        }
+        (a, b) if a != b => differences.push(format!("{}: {} -> {}", path, a, b)),
+        _ => (),
+    }
+}
+
+fn diff_images(ours: &RgbaImage, theirs: &RgbaImage, path: &Path) -> Result<()> {
+    if ours.dimensions() != theirs.dimensions() {
+        println!("the images are different sizes, {:?} vs {:?}", ours.dimensions(), theirs.dimensions());
+        return Ok(());
+    }
+
+    let (mut differing, mut largest) = (0, 0);
+    // the first image dimmed, with every pixel that changed in red, brighter the more it changed.
+    let diff = RgbaImage::from_fn(ours.width(), ours.height(), |x, y| {
+        let (a, b) = (ours.get_pixel(x, y), theirs.get_pixel(x, y));
+        let delta = a.0.iter().zip(b.0).map(|(a, b)| a.abs_diff(b)).max().unwrap_or(0);
+        if delta == 0 {
+            Rgba([a[0] / 4, a[1] / 4, a[2] / 4, 255])
+        } else {
+            differing += 1;
+            largest = largest.max(delta);
+            Rgba([128 + delta / 2, 0, 0, 255])
+        }
+    });
+
+    if differing == 0 {
+        println!("the images are identical");
+        return Ok(());
+    }
+    diff.save(path).map_err(|error| Error::write(path, error))?;
+    println!(
+        "{} of {} pixels differ (by up to {} of 255), see {}",
+        differing, ours.width() * ours.height(), largest, path.display(),
+    );
+    Ok(())
+}
+
+enum Vertices {
+    Buffer(Box<VertexBufferAny>, bool),
+    Marker(usize, bool),
+}
+
+impl Vertices {
+    fn source(&self) -> VerticesSource<'_> {
+        match *self {
+            Vertices::Buffer(ref buffer, per_instance) => match (&**buffer).into() {
+                VerticesSource::VertexBuffer(slice, format, _) => VerticesSource::VertexBuffer(slice, format, per_instance),
+                marker => marker,
+            },
+            Vertices::Marker(len, false) => EmptyVertexAttributes { len }.into(),
+            Vertices::Marker(len, true) => EmptyInstanceAttributes { len }.into(),
+        }
+    }
+}
+
+enum Indices {
+    U8(IndexBuffer<u8>),
+    U16(IndexBuffer<u16>),
+    U32(IndexBuffer<u32>),
+    None(NoIndices),
+}
+
+impl Indices {
+    fn source(&self) -> IndicesSource<'_> {
+        match self {
+            Indices::U8(buffer) => buffer.into(),
+            Indices::U16(buffer) => buffer.into(),
+            Indices::U32(buffer) => buffer.into(),
+            Indices::None(none) => none.into(),
+        }
+    }
+}
+
+/// A vertex of `N` bytes, what the traced buffers get uploaded as since their real type is long gone.
+#[derive(Copy, Clone)]
+struct Bytes<const N: usize>([u8; N]);
+
+fn vertex_buffer_of<const N: usize>(display: &Display, bytes: &[u8], format: glium::vertex::VertexFormat) -> std::result::Result<VertexBufferAny, Skip> {
+    let vertices: Vec<Bytes<N>> = bytes.chunks_exact(N).map(|chunk| Bytes(chunk.try_into().unwrap())).collect();
+    // safe as long as the format fits in a vertex, it came from a buffer that had this layout.
+    let buffer = unsafe { VertexBuffer::new_raw(display, &vertices, format, N) };
+    buffer.map(VertexBufferAny::from).map_err(|error| error.to_string())
+}
+
+fn vertex_buffer(display: &Display, bytes: &[u8], stride: usize, format: glium::vertex::VertexFormat) -> std::result::Result<VertexBufferAny, Skip> {
+    macro_rules! strides {
+        ($($n:literal)*) => {
+            match stride {
+                $($n => vertex_buffer_of::<$n>(display, bytes, format),)*
+                other => Err(format!("vertices of {} bytes can't be replayed", other)),
+            }
+        };
+    }
+    strides!(4 8 12 16 20 24 28 32 36 40 44 48 52 56 60 64 68 72 76 80 84 88 92 96 100 104 108 112 116 120 124 128)
+}
+
+/// The uniforms of a draw, rebuilt.
+struct ReplayUniforms(Vec<(String, Uniform)>);
+
+enum Uniform {
+    Value(Value),
+    UnsignedInt(u32),
+    Texture(Texture2d, Option<SamplerBehavior>),
+    SrgbTexture(SrgbTexture2d, Option<SamplerBehavior>),
+}
+
+impl Uniforms for ReplayUniforms {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        for (name, uniform) in &self.0 {
+            f(name, match uniform {
+                Uniform::Value(value) => value.as_uniform(),
+                Uniform::UnsignedInt(x) => UniformValue::UnsignedInt(*x),
+                Uniform::Texture(texture, sampler) => UniformValue::Texture2d(texture, *sampler),
+                Uniform::SrgbTexture(texture, sampler) => UniformValue::SrgbTexture2d(texture, *sampler),
+            });
+        }
+    }
+}
+
+/// The option whose `Debug` is `name`, traces spell glium's enums that way.
+fn by_name<T: Copy + Debug>(options: &[T], name: &str) -> Option<T> {
+    options.iter().copied().find(|option| debug_name(option) == name)
+}
+
+fn rect(rect: &Option<[u32; 4]>) -> Option<Rect> {
+    rect.map(|[left, bottom, width, height]| Rect { left, bottom, width, height })
+}
+
+fn sampler(traced: &TracedSampler) -> Option<SamplerBehavior> {
+    let wrap = |index: usize| by_name(WRAP_FUNCTIONS, &traced.wrap[index]);
+    Some(SamplerBehavior {
+        wrap_function: (wrap(0)?, wrap(1)?, wrap(2)?),
+        minify_filter: by_name(MINIFY_FILTERS, &traced.minify)?,
+        magnify_filter: by_name(MAGNIFY_FILTERS, &traced.magnify)?,
+        max_anisotropy: traced.max_anisotropy,
+        ..Default::default()
+    })
+}
+
+fn blending_function(traced: &TracedBlendingFunction) -> Option<BlendingFunction> {
+    let factor = |name: &Option<String>| by_name(BLENDING_FACTORS, name.as_deref()?);
+    let (source, destination) = (&traced.source, &traced.destination);
+    Some(match traced.function.as_str() {
+        "AlwaysReplace" => BlendingFunction::AlwaysReplace,
+        "Min" => BlendingFunction::Min,
+        "Max" => BlendingFunction::Max,
+        "Addition" => BlendingFunction::Addition { source: factor(source)?, destination: factor(destination)? },
+        "Subtraction" => BlendingFunction::Subtraction { source: factor(source)?, destination: factor(destination)? },
+        "ReverseSubtraction" => BlendingFunction::ReverseSubtraction { source: factor(source)?, destination: factor(destination)? },
+        _ => return None,
+    })
+}
+
+/// Anything that doesn't name one of glium's options stays at glium's default.
+fn draw_parameters(traced: &TracedParameters) -> DrawParameters<'static> {
+    let mut parameters = DrawParameters::default();
+    parameters.depth = Depth {
+        test: by_name(DEPTH_TESTS, &traced.depth_test).unwrap_or(DepthTest::Overwrite),
+        write: traced.depth_write,
+        range: traced.depth_range,
+        ..Default::default()
+    };
+    parameters.blend = Blend {
+        color: blending_function(&traced.blend.color).unwrap_or(BlendingFunction::AlwaysReplace),
+        alpha: blending_function(&traced.blend.alpha).unwrap_or(BlendingFunction::AlwaysReplace),
+        constant_value: traced.blend.constant,
+    };
+    parameters.color_mask = traced.color_mask;
+    parameters.backface_culling = by_name(CULLING_MODES, &traced.backface_culling).unwrap_or(parameters.backface_culling);
+    parameters.polygon_mode = by_name(POLYGON_MODES, &traced.polygon_mode).unwrap_or(parameters.polygon_mode);
+    parameters.line_width = traced.line_width;
+    parameters.point_size = traced.point_size;
+    parameters.multisampling = traced.multisampling;
+    parameters.dithering = traced.dithering;
+    parameters.viewport = rect(&traced.viewport);
+    parameters.scissor = rect(&traced.scissor);
+    parameters
+}
+
+const PRIMITIVES: &[PrimitiveType] = &[
+    PrimitiveType::Points, PrimitiveType::LinesList, PrimitiveType::LinesListAdjacency, PrimitiveType::LineStrip,
+    PrimitiveType::LineStripAdjacency, PrimitiveType::LineLoop, PrimitiveType::TrianglesList,
+    PrimitiveType::TrianglesListAdjacency, PrimitiveType::TriangleStrip, PrimitiveType::TriangleStripAdjacency,
+    PrimitiveType::TriangleFan,
+];
+
+const ATTRIBUTE_TYPES: &[AttributeType] = &[
+    AttributeType::I8, AttributeType::I8I8, AttributeType::I8I8I8, AttributeType::I8I8I8I8,
+    AttributeType::U8, AttributeType::U8U8, AttributeType::U8U8U8, AttributeType::U8U8U8U8,
+    AttributeType::I16, AttributeType::I16I16, AttributeType::I16I16I16, AttributeType::I16I16I16I16,
+    AttributeType::U16, AttributeType::U16U16, AttributeType::U16U16U16, AttributeType::U16U16U16U16,
+    AttributeType::I32, AttributeType::I32I32, AttributeType::I32I32I32, AttributeType::I32I32I32I32,
+    AttributeType::U32, AttributeType::U32U32, AttributeType::U32U32U32, AttributeType::U32U32U32U32,
+    AttributeType::F32, AttributeType::F32F32, AttributeType::F32F32F32, AttributeType::F32F32F32F32,
+    AttributeType::F32x2x2, AttributeType::F32x3x3, AttributeType::F32x4x4,
+    AttributeType::F64, AttributeType::F64F64, AttributeType::F64F64F64, AttributeType::F64F64F64F64,
+];
+
+const DEPTH_TESTS: &[DepthTest] = &[
+    DepthTest::Ignore, DepthTest::Overwrite, DepthTest::IfEqual, DepthTest::IfNotEqual, DepthTest::IfMore,
+    DepthTest::IfMoreOrEqual, DepthTest::IfLess, DepthTest::IfLessOrEqual,
+];
+
+const CULLING_MODES: &[BackfaceCullingMode] = &[
+    BackfaceCullingMode::CullingDisabled, BackfaceCullingMode::CullCounterClockwise, BackfaceCullingMode::CullClockwise,
+];
+
+const POLYGON_MODES: &[PolygonMode] = &[PolygonMode::Point, PolygonMode::Line, PolygonMode::Fill];
+
+const BLENDING_FACTORS: &[LinearBlendingFactor] = &[
+    LinearBlendingFactor::Zero, LinearBlendingFactor::One, LinearBlendingFactor::SourceColor,
+    LinearBlendingFactor::OneMinusSourceColor, LinearBlendingFactor::DestinationColor,
+    LinearBlendingFactor::OneMinusDestinationColor, LinearBlendingFactor::SourceAlpha,
+    LinearBlendingFactor::SourceAlphaSaturate, LinearBlendingFactor::OneMinusSourceAlpha,
+    LinearBlendingFactor::DestinationAlpha, LinearBlendingFactor::OneMinusDestinationAlpha,
+    LinearBlendingFactor::ConstantColor, LinearBlendingFactor::OneMinusConstantColor,
+    LinearBlendingFactor::ConstantAlpha, LinearBlendingFactor::OneMinusConstantAlpha,
+    LinearBlendingFactor::SourceOneColor, LinearBlendingFactor::OneMinusSourceOneColor,
+    LinearBlendingFactor::SourceOneAlpha, LinearBlendingFactor::OneMinusSourceOneAlpha,
+];
+
+const WRAP_FUNCTIONS: &[SamplerWrapFunction] = &[
+    SamplerWrapFunction::Repeat, SamplerWrapFunction::Mirror, SamplerWrapFunction::Clamp,
+    SamplerWrapFunction::BorderClamp,
+];
+
+const MINIFY_FILTERS: &[MinifySamplerFilter] = &[
+    MinifySamplerFilter::Nearest, MinifySamplerFilter::Linear, MinifySamplerFilter::NearestMipmapNearest,
+    MinifySamplerFilter::LinearMipmapNearest, MinifySamplerFilter::NearestMipmapLinear,
+    MinifySamplerFilter::LinearMipmapLinear,
+];
+
+const MAGNIFY_FILTERS: &[MagnifySamplerFilter] = &[MagnifySamplerFilter::Nearest, MagnifySamplerFilter::Linear];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use super::super::trace::{traced_blending_function, traced_parameters, traced_sampler};
+
+    fn round_trips<T: Copy + Debug + PartialEq>(options: &[T]) {
+        for option in options {
+            assert_eq!(by_name(options, &debug_name(option)), Some(*option));
+        }
+    }
+
+    #[test]
+    fn every_table_finds_its_options_by_their_debug_name() {
+        round_trips(PRIMITIVES);
+        round_trips(ATTRIBUTE_TYPES);
+        round_trips(DEPTH_TESTS);
+        round_trips(CULLING_MODES);
+        round_trips(POLYGON_MODES);
+        round_trips(BLENDING_FACTORS);
+        round_trips(WRAP_FUNCTIONS);
+        round_trips(MINIFY_FILTERS);
+        round_trips(MAGNIFY_FILTERS);
+        assert_eq!(by_name(DEPTH_TESTS, "IfLessThanSomething"), None);
+    }
+
+    #[test]
+    fn blending_functions_round_trip() {
+        let factor = LinearBlendingFactor::OneMinusSourceAlpha;
+        let functions = [
+            BlendingFunction::AlwaysReplace,
+            BlendingFunction::Min,
+            BlendingFunction::Max,
+            BlendingFunction::Addition { source: LinearBlendingFactor::SourceAlpha, destination: factor },
+            BlendingFunction::Subtraction { source: LinearBlendingFactor::One, destination: factor },
+            BlendingFunction::ReverseSubtraction { source: LinearBlendingFactor::Zero, destination: factor },
+        ];
+        for function in functions {
+            assert_eq!(blending_function(&traced_blending_function(&function)), Some(function));
+        }
+    }
+
+    #[test]
+    fn samplers_round_trip() {
+        let behavior = SamplerBehavior {
+            wrap_function: (SamplerWrapFunction::Mirror, SamplerWrapFunction::Clamp, SamplerWrapFunction::BorderClamp),
+            minify_filter: MinifySamplerFilter::LinearMipmapNearest,
+            magnify_filter: MagnifySamplerFilter::Nearest,
+            max_anisotropy: 4,
+            ..Default::default()
+        };
+        assert_eq!(sampler(&traced_sampler(behavior)), Some(behavior));
+    }
+
+    #[test]
+    fn draw_parameters_round_trip() {
+        let additive = BlendingFunction::Addition { source: LinearBlendingFactor::One, destination: LinearBlendingFactor::One };
+        let parameters = DrawParameters {
+            depth: Depth { test: DepthTest::IfLessOrEqual, write: true, range: (0.1, 0.9), ..Default::default() },
+            blend: Blend { color: additive, alpha: BlendingFunction::Max, constant_value: (0.5, 0.25, 0.0, 1.0) },
+            color_mask: (true, false, true, false),
+            backface_culling: BackfaceCullingMode::CullClockwise,
+            polygon_mode: PolygonMode::Line,
+            line_width: Some(2.0),
+            point_size: Some(3.0),
+            multisampling: false,
+            dithering: false,
+            viewport: Some(Rect { left: 1, bottom: 2, width: 3, height: 4 }),
+            scissor: Some(Rect { left: 5, bottom: 6, width: 7, height: 8 }),
+            ..Default::default()
+        };
+
+        let back = draw_parameters(&traced_parameters(&parameters));
+        assert_eq!(back.depth.test, parameters.depth.test);
+        assert_eq!(back.depth.write, parameters.depth.write);
+        assert_eq!(back.depth.range, parameters.depth.range);
+        assert_eq!(back.blend.color, parameters.blend.color);
+        assert_eq!(back.blend.alpha, parameters.blend.alpha);
+        assert_eq!(back.blend.constant_value, parameters.blend.constant_value);
+        assert_eq!(back.color_mask, parameters.color_mask);
+        assert_eq!(back.backface_culling, parameters.backface_culling);
+        assert_eq!(back.polygon_mode, parameters.polygon_mode);
+        assert_eq!(back.line_width, parameters.line_width);
+        assert_eq!(back.point_size, parameters.point_size);
+        assert_eq!(back.multisampling, parameters.multisampling);
+        assert_eq!(back.dithering, parameters.dithering);
+        assert_eq!(back.viewport, parameters.viewport);
+        assert_eq!(back.scissor, parameters.scissor);
+    }
+
+    fn differences(a: serde_json::Value, b: serde_json::Value) -> Vec<String> {
+        let mut differences = Vec::new();
+        diff_json("op 0", &a, &b, &mut differences);
+        differences
+    }
+
+    #[test]
+    fn equal_values_have_no_differences() {
+        let value = json!({ "target": "frame", "uniforms": { "model": [1.0, 0.0], "tint": { "kind": "uint", "value": 3 } } });
+        assert!(differences(value.clone(), value).is_empty());
+    }
+
+    #[test]
+    fn nested_differences_are_named_by_their_path() {
+        let a = json!({ "target": "frame", "parameters": { "depth_test": "IfLess" }, "vertices": [{ "count": 3 }] });
+        let b = json!({ "target": "gbuffer", "parameters": { "depth_test": "Overwrite" }, "vertices": [{ "count": 4 }] });
+        assert_eq!(differences(a, b), [
+            r#"op 0.parameters.depth_test: "IfLess" -> "Overwrite""#,
+            r#"op 0.target: "frame" -> "gbuffer""#,
+            "op 0.vertices[0].count: 3 -> 4",
+        ]);
+    }
+
+    #[test]
+    fn keys_in_only_one_trace_are_named() {
+        let a = json!({ "uniforms": { "model": 1, "view": 2 } });
+        let b = json!({ "uniforms": { "model": 1, "light": 3 } });
+        assert_eq!(differences(a, b), [
+            "op 0.uniforms.view: only in the first trace",
+            "op 0.uniforms.light: only in the second trace",
+        ]);
+    }
+
+    #[test]
+    fn numeric_arrays_are_shown_whole() {
+        let a = json!({ "value": [1.0, 2.0, 3.0] });
+        let b = json!({ "value": [1.0, 2.5, 3.0] });
+        assert_eq!(differences(a, b), ["op 0.value: [1.0,2.0,3.0] -> [1.0,2.5,3.0]"]);
+    }
+}
@@ -0,0 +1,223 @@
+#![allow(dead_code)]
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use glium::{glutin::{
+    self,
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+}, Display};
+
+use crate::error::{Error, Result};
+use super::capture::Capture;
+use super::input::{InputPlayer, InputRecorder};
+use super::term::{self, TermOutput};
+use super::timing::{self, FrameTimer};
+use super::trace::{Target, TargetSurface, Tracer};
+
+// The event loop every stage used to write out by hand: close on Escape or the window's X, draw a frame
+// every `frame_interval`, and hand everything else to the stage. Errors from the stage stop the loop
+// and come back out of `run_loop`, and because it's `run_return` the caller gets control back afterwards.
+// With `--term` the window is hidden, frames are drawn offscreen and printed to the terminal instead.
+// The screenshot and record keys (see capture.rs) are handled here too, so every stage has them, and so
+// is frame timing (timing.rs), which under `bench` also hides the window and draws as fast as it can.
+// Stages draw into a `Target` rather than the `Frame` itself so F8 / `--trace` can write down their draw
+// calls (trace.rs). Input recording and playback (input.rs) happen here as well, which is why stages
+// should take the time from `now` rather than an `Instant` of their own.
+
+pub enum LoopEvent<'a, 'e> {
+    /// A key with a virtual keycode was pressed or released (Escape never gets here).
+    Key(VirtualKeyCode, ElementState),
+    /// Any other window event.
+    Window(&'a WindowEvent<'e>),
+    /// Time for a new frame, the runner finishes it afterwards.
+    Draw(&'a mut Target),
+}
+
+static FRAME_TIME: AtomicU64 = AtomicU64::new(0);
+static START: AtomicU64 = AtomicU64::new(0);
+
+/// How far into the stage the frame being drawn is. Usually the time since the loop started, but while
+/// input is played back it's the time the frame had when it was recorded, so the frames come out the same.
+pub fn now() -> Duration {
+    Duration::from_nanos(FRAME_TIME.load(Ordering::Relaxed))
+}
+
+/// `now` as a date, for things like shadertoy's iDate. Played back too.
+pub fn wall_clock() -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(START.load(Ordering::Relaxed)) + now()
+}
+
+/// Hands an event to the stage, writing it down first when input is being recorded.
+fn pass<F>(handler: &mut F, display: &Display, recorder: &mut Option<InputRecorder>, event: LoopEvent) -> Result<()>
+where
+    F: FnMut(&Display, LoopEvent) -> Result<()>,
+{
+    if let Some(recorder) = recorder {
+        recorder.event(&event);
+    }
+    handler(display, event)
+}
+
+pub fn run_loop<F>(mut event_loop: EventLoop<()>, display: &Display, frame_interval: Duration, mut handler: F) -> Result<()>
+where
+    F: FnMut(&Display, LoopEvent) -> Result<()>,
+{
+    let mut result = Ok(());
+    let mut next_frame_time = Instant::now();
+    let mut term = if term::enabled() { Some(TermOutput::new(display)?) } else { None };
+    let mut capture = Capture::default();
+    let mut tracer = Tracer::default();
+
+    let mut recorder = InputRecorder::new(display);
+    let mut player = InputPlayer::new(display)?;
+    let start = Instant::now();
+    let start_date = match (&player, &recorder) {
+        (Some(player), _) => player.start(),
+        (None, Some(recorder)) => recorder.start(),
+        (None, None) => SystemTime::now(),
+    };
+    START.store(start_date.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64, Ordering::Relaxed);
+    FRAME_TIME.store(0, Ordering::Relaxed);
+
+    let bench = timing::bench();
+    // played back input has its own times, there's no reason to wait between frames for it.
+    let frame_interval = if bench.is_some() || player.is_some() { Duration::ZERO } else { frame_interval };
+    if bench.is_some() {
+        display.gl_window().window().set_visible(false);
+    }
+    let mut timer = FrameTimer::new(display, bench.is_some());
+
+    if term.is_none() && bench.is_none() && player.is_none() {
+        println!("F12 takes a screenshot, F9 / F10 start and stop recording a png sequence / gif, F3 shows frame times, F8 traces the next frame's draw calls");
+    }
+
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(next_frame_time);
+
+        let handled = match event {
+            glutin::event::Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput { input: KeyboardInput { virtual_keycode: Some(VirtualKeyCode::Escape), .. }, .. } => {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                },
+                // only the recorded input counts during playback.
+                _ if player.is_some() => Ok(()),
+                WindowEvent::KeyboardInput {
+                    input: KeyboardInput { virtual_keycode: Some(virtual_code), state, .. },
+                    ..
+                } => match capture.handle_key(virtual_code, state) {
+                    Ok(true) => Ok(()),
+                    Ok(false) if timer.handle_key(virtual_code, state) || tracer.handle_key(virtual_code, state) => Ok(()),
+                    Ok(false) => pass(&mut handler, display, &mut recorder, LoopEvent::Key(virtual_code, state)),
+                    Err(error) => Err(error),
+                },
+                event => pass(&mut handler, display, &mut recorder, LoopEvent::Window(&event)),
+            },
+            Event::NewEvents(_) if Instant::now() >= next_frame_time => {
+                next_frame_time = Instant::now() + frame_interval;
+                *control_flow = ControlFlow::WaitUntil(next_frame_time);
+
+                if let Some(term) = &mut term {
+                    match term.poll(display) {
+                        Ok(true) => (),
+                        Ok(false) => {
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                        Err(error) => {
+                            result = Err(error);
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    }
+                }
+
+                let time = match &mut player {
+                    Some(player) => match player.next_frame(display) {
+                        Some((time, events)) => {
+                            if let Err(error) = events.iter().try_for_each(|event| handler(display, event.loop_event())) {
+                                result = Err(error);
+                                *control_flow = ControlFlow::Exit;
+                                return;
+                            }
+                            time
+                        }
+                        None => {
+                            result = player.finish();
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    },
+                    None => start.elapsed(),
+                };
+                FRAME_TIME.store(time.as_nanos() as u64, Ordering::Relaxed);
+                if let Some(recorder) = &mut recorder {
+                    recorder.frame(time);
+                }
+
+                let recorder = tracer.begin_frame(display);
+                // played back and terminal frames go offscreen, the window is hidden for both.
+                let offscreen = match (&mut player, &mut term) {
+                    (Some(player), _) => Some(player.target(display)),
+                    (None, Some(term)) => Some(term.target(display)),
+                    (None, None) => None,
+                };
+                let mut target = match offscreen {
+                    Some(Ok(offscreen)) => Target::offscreen(offscreen, recorder),
+                    Some(Err(error)) => {
+                        result = Err(error);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    None => Target::new(display.draw(), recorder),
+                };
+                timer.begin(display);
+                let drawn = handler(display, LoopEvent::Draw(&mut target));
+                timer.end(display);
+                let drawn = drawn.and_then(|_| capture.frame_drawn(display, &target));
+                // a Frame has to be finished even when drawing into it failed.
+                let (surface, recorder) = target.into_parts();
+                let finished = match surface {
+                    TargetSurface::Frame(frame) => frame.finish().map_err(Error::from),
+                    TargetSurface::Offscreen(offscreen) => match (&mut player, &mut term) {
+                        (Some(player), term) => {
+                            let printed = term.as_mut().map_or(Ok(()), |term| term.print(&offscreen));
+                            player.presented(offscreen);
+                            printed
+                        }
+                        (None, Some(term)) => term.present(offscreen),
+                        (None, None) => Ok(()),
+                    },
+                };
+                let traced = match recorder {
+                    Some(recorder) => tracer.save(recorder),
+                    None => Ok(()),
+                };
+                if matches!(&bench, Some(bench) if timer.frames() >= bench.warmup + bench.frames) {
+                    *control_flow = ControlFlow::Exit;
+                }
+                drawn.and(finished).and(traced)
+            },
+            _ => Ok(()),
+        };
+
+        if let Err(error) = handled {
+            result = Err(error);
+            *control_flow = ControlFlow::Exit;
+        }
+    });
+
+    let samples = timer.finish(display);
+    // a recording still running gets finished, even when the stage stopped because of an error.
+    let result = result.and(capture.finish());
+    let result = result.and(recorder.map_or(Ok(()), InputRecorder::finish));
+    match &bench {
+        Some(bench) => result.and_then(|_| timing::write_report(bench, display, &samples)),
+        None => result,
+    }
+}
@@ -0,0 +1,482 @@
+#![allow(dead_code)]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
+
+use glium::{glutin::{
+    self,
+    event::{ElementState, MouseButton, VirtualKeyCode, WindowEvent},
+}, program::ProgramCreationError, Display, Program, Surface};
+use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{
+    MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue, Uniforms,
+};
+
+use crate::error::{Error, Result};
+use super::hot_reload::{compile, shader_file, ShaderFile, POLL_INTERVAL};
+use super::runner::{self, run_loop, LoopEvent};
+use super::assets::texture_dir;
+use super::context::create_display;
+use super::preprocess::{Origin, PreprocessError, Preprocessed, Preprocessor};
+use super::stage15::fullscreen_quad;
+use super::util::civil_date;
+
+// Runs Shadertoy style fragment shaders: a `mainImage(out vec4 fragColor, in vec2 fragCoord)` per pass,
+// with the usual iTime / iResolution / iMouse / iFrame / iChannel0..3 uniforms.
+//
+// Every pass can say what its channels are bound to with comments like
+//     // iChannel0: hamster.jpg     (a file in assets/textures)
+//     // iChannel1: buffer-a        (the output of another pass, or this pass' own last frame)
+//     // iChannel2: none
+// Channels that aren't mentioned get the textures in assets/textures, in alphabetical order.
+// Buffers A-D render (in that order) before the image pass into float textures; reading a buffer that
+// hasn't been drawn yet this frame gives last frame's contents, same as on the website.
+
+const BUFFER_NAMES: [&str; 4] = ["buffer-a", "buffer-b", "buffer-c", "buffer-d"];
+
+const VERTEX: ShaderFile = shader_file!("shadertoy.vert");
+/// What the pass' own code gets wrapped in, it `#include`s the pass as `PASS`.
+const WRAPPER: ShaderFile = shader_file!("shadertoy.frag");
+const PASS: &str = "shadertoy_pass";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelSource {
+    Texture(String),
+    Buffer(usize),
+    Empty,
+}
+
+/// Reads the `// iChannelN: ...` comments of a pass.
+pub fn parse_channels(src: &str) -> [Option<ChannelSource>; 4] {
+    let mut channels = [None, None, None, None];
+
+    for line in src.lines() {
+        let line = match line.trim().strip_prefix("//") {
+            Some(comment) => comment.trim(),
+            None => continue,
+        };
+        let rest = match line.strip_prefix("iChannel") {
+            Some(rest) => rest,
+            None => continue,
+        };
+        let (index, value) = match rest.split_once(':') {
+            Some((index, value)) => (index.trim(), value.trim()),
+            None => continue,
+        };
+        let index: usize = match index.parse() {
+            Ok(index) if index < 4 => index,
+            _ => continue,
+        };
+
+        channels[index] = Some(match BUFFER_NAMES.iter().position(|name| name.eq_ignore_ascii_case(value)) {
+            Some(buffer) => ChannelSource::Buffer(buffer),
+            None if value.eq_ignore_ascii_case("none") => ChannelSource::Empty,
+            None => ChannelSource::Texture(value.to_string()),
+        });
+    }
+
+    channels
+}
+
+/// The pass' source `src` inside `WRAPPER`, with the source map naming the pass `name`.
+fn wrap(name: &str, src: String, is_image: bool, origin: Origin) -> Result<Preprocessed, PreprocessError> {
+    let mut preprocessor = Preprocessor::new().virtual_file(PASS, src);
+    if is_image {
+        preprocessor = preprocessor.define("SHADERTOY_IMAGE", 1);
+    }
+    let mut preprocessed = preprocessor.process(&WRAPPER, origin)?;
+    // so the compile log names the pass' file rather than the include.
+    for file in preprocessed.source_map.files.iter_mut().filter(|file| *file == PASS) {
+        *file = name.to_string();
+    }
+    Ok(preprocessed)
+}
+
+struct Pass {
+    path: PathBuf,
+    is_image: bool,
+    program: Program,
+    channels: [ChannelSource; 4],
+    stamp: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl Pass {
+    fn load(display: &Display, path: &Path, is_image: bool, textures: &[(String, Texture2d)]) -> Result<Self> {
+        let src = fs::read_to_string(path).map_err(|error| Error::asset(path, error))?;
+        let name = path.file_name().map_or("pass.frag".into(), |name| name.to_string_lossy().to_string());
+
+        let parsed = parse_channels(&src);
+        let mut channels = [ChannelSource::Empty, ChannelSource::Empty, ChannelSource::Empty, ChannelSource::Empty];
+        for (index, channel) in channels.iter_mut().enumerate() {
+            *channel = match &parsed[index] {
+                Some(source) => source.clone(),
+                None => textures.get(index).map_or(ChannelSource::Empty, |(name, _)| ChannelSource::Texture(name.clone())),
+            };
+        }
+
+        let preprocessed = wrap(&name, src, is_image, Origin::Disk)
+            .map_err(|error| Error::Shader { name: name.clone(), message: error.to_string() })?;
+
+        // shadertoy doesn't do any sRGB conversion, so we don't either.
+        let program = compile(display, &VERTEX.load(), &preprocessed.source, true).map_err(|error| match error {
+            ProgramCreationError::CompilationError(log, shader_type) => {
+                Error::program(&name, ProgramCreationError::CompilationError(preprocessed.source_map.annotate(&log), shader_type))
+            }
+            other => Error::program(&name, other),
+        })?;
+
+        Ok(Pass { path: path.to_path_buf(), is_image, program, channels, stamp: modified(path), last_poll: Instant::now() })
+    }
+
+    /// Rebuilds the pass if its file changed (checked at most every `POLL_INTERVAL`), keeps the old program
+    /// if the new one doesn't compile.
+    fn reload_if_changed(&mut self, display: &Display, textures: &[(String, Texture2d)]) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let stamp = modified(&self.path);
+        if stamp == self.stamp {
+            return false;
+        }
+        self.stamp = stamp;
+
+        match Pass::load(display, &self.path, self.is_image, textures) {
+            Ok(pass) => {
+                log::info!("reloaded {}", self.path.display());
+                *self = pass;
+                true
+            }
+            Err(error) => {
+                log::warn!("{}\nkeeping the previous program", error);
+                false
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Everything in assets/textures that `image` can open, sorted by file name.
+pub fn load_textures(display: &Display) -> Vec<(String, Texture2d)> {
+    let dir = texture_dir();
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .map(|entries| entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect())
+        .unwrap_or_default();
+    names.sort();
+
+    names.into_iter().filter_map(|name| {
+        let image = image::open(dir.join(&name)).ok()?.to_rgba8();
+        let dimensions = image.dimensions();
+        // reversed, so uv (0, 0) is the bottom left corner like on shadertoy.
+        let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions);
+        Some((name, Texture2d::new(display, raw).ok()?))
+    }).collect()
+}
+
+fn buffer_textures(display: &Display, (width, height): (u32, u32)) -> Result<[Texture2d; 2]> {
+    let make = || -> Result<Texture2d> {
+        let texture = Texture2d::empty_with_format(
+            display,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            width,
+            height,
+        )?;
+        texture.as_surface().clear_color(0.0, 0.0, 0.0, 0.0);
+        Ok(texture)
+    };
+
+    Ok([make()?, make()?])
+}
+
+/// `iMouse`: xy is the position while a button is held, zw where it was clicked.
+/// z is negative once the button is released, w is only positive on the frame of the click.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Mouse {
+    pub position: [f32; 2],
+    pub click: [f32; 2],
+    pub down: bool,
+    pub clicked_this_frame: bool,
+    /// Where the cursor is right now, button or not.
+    pub cursor: [f32; 2],
+}
+
+impl Mouse {
+    /// The cursor moved to `cursor` (origin bottom left), `position` only follows it while the button is held.
+    pub fn moved(&mut self, cursor: [f32; 2]) {
+        self.cursor = cursor;
+        if self.down {
+            self.position = cursor;
+        }
+    }
+
+    pub fn button(&mut self, down: bool) {
+        self.down = down;
+        if down {
+            self.position = self.cursor;
+            self.click = self.cursor;
+            self.clicked_this_frame = true;
+        }
+    }
+
+    pub fn uniform(&self) -> [f32; 4] {
+        let z = if self.down { self.click[0] } else { -self.click[0] };
+        let w = if self.clicked_this_frame { self.click[1] } else { -self.click[1] };
+        [self.position[0], self.position[1], z, w]
+    }
+}
+
+/// `iDate`: year, month (0 based), day and seconds since midnight, all UTC.
+pub fn date_uniform(now: SystemTime) -> [f32; 4] {
+    let date = civil_date(now);
+    let seconds = date.seconds_of_day as f32 + date.millis as f32 / 1000.0;
+    [date.year as f32, (date.month - 1) as f32, date.day as f32, seconds]
+}
+
+struct ToyUniforms<'a> {
+    resolution: [f32; 3],
+    time: f32,
+    time_delta: f32,
+    frame: i32,
+    mouse: [f32; 4],
+    date: [f32; 4],
+    channels: [(&'a Texture2d, SamplerBehavior); 4],
+}
+
+impl<'a> Uniforms for ToyUniforms<'a> {
+    // uniform! can't name array elements, so this one is written out.
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut f: F) {
+        f("iResolution", UniformValue::Vec3(self.resolution));
+        f("iTime", UniformValue::Float(self.time));
+        f("iTimeDelta", UniformValue::Float(self.time_delta));
+        f("iFrameRate", UniformValue::Float(if self.time_delta > 0.0 { 1.0 / self.time_delta } else { 60.0 }));
+        f("iFrame", UniformValue::SignedInt(self.frame));
+        f("iMouse", UniformValue::Vec4(self.mouse));
+        f("iDate", UniformValue::Vec4(self.date));
+        f("iSampleRate", UniformValue::Float(44100.0));
+
+        let names = ["iChannel0", "iChannel1", "iChannel2", "iChannel3"];
+        let resolution_names = ["iChannelResolution[0]", "iChannelResolution[1]", "iChannelResolution[2]", "iChannelResolution[3]"];
+        let time_names = ["iChannelTime[0]", "iChannelTime[1]", "iChannelTime[2]", "iChannelTime[3]"];
+        for (index, (texture, sampler)) in self.channels.iter().enumerate() {
+            f(names[index], UniformValue::Texture2d(texture, Some(*sampler)));
+            f(resolution_names[index], UniformValue::Vec3([texture.width() as f32, texture.height() as f32, 1.0]));
+            f(time_names[index], UniformValue::Float(self.time));
+        }
+    }
+}
+
+fn texture_sampler() -> SamplerBehavior {
+    SamplerBehavior {
+        wrap_function: (SamplerWrapFunction::Repeat, SamplerWrapFunction::Repeat, SamplerWrapFunction::Repeat),
+        ..Default::default()
+    }
+}
+
+fn buffer_sampler() -> SamplerBehavior {
+    SamplerBehavior {
+        wrap_function: (SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp, SamplerWrapFunction::Clamp),
+        minify_filter: MinifySamplerFilter::Linear,
+        magnify_filter: MagnifySamplerFilter::Linear,
+        ..Default::default()
+    }
+}
+
+/// `image` is the image pass, `buffers` the optional buffer A-D passes.
+pub fn run(image: PathBuf, buffers: [Option<PathBuf>; 4]) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new()
+        .with_title(format!("shadertoy: {}", image.display()));
+    let context_builder = glutin::ContextBuilder::new();
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let quad = glium::VertexBuffer::new(&display, &fullscreen_quad())?;
+    let textures = load_textures(&display);
+    let black = Texture2d::empty_with_format(&display, UncompressedFloatFormat::U8U8U8U8, MipmapsOption::NoMipmap, 1, 1)?;
+    black.as_surface().clear_color(0.0, 0.0, 0.0, 1.0);
+
+    let mut image_pass = Pass::load(&display, &image, true, &textures)?;
+    let mut buffer_passes = Vec::new();
+    for path in buffers.iter() {
+        buffer_passes.push(match path {
+            Some(path) => Some(Pass::load(&display, path, false, &textures)?),
+            None => None,
+        });
+    }
+
+    let all_buffer_textures = |display: &Display, dimensions| -> Result<Vec<[Texture2d; 2]>> {
+        (0..4).map(|_| buffer_textures(display, dimensions)).collect()
+    };
+    let mut dimensions = display.get_framebuffer_dimensions();
+    let mut buffer_targets = all_buffer_textures(&display, dimensions)?;
+    let mut front = [0usize; 4];
+
+    // runner::now rather than Instants, so recorded input plays back to the same frames.
+    let mut start = runner::now();
+    let mut last_frame = runner::now();
+    let mut frame_index: i32 = 0;
+    let mut mouse = Mouse::default();
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(16_666_667), move |display, event| {
+        let frame = match event {
+            // rewind, like the button under the shadertoy canvas.
+            LoopEvent::Key(VirtualKeyCode::R, ElementState::Pressed) => {
+                start = runner::now();
+                frame_index = 0;
+                buffer_targets = all_buffer_textures(display, dimensions)?;
+                return Ok(());
+            }
+            LoopEvent::Window(WindowEvent::CursorMoved { position, .. }) => {
+                // shadertoy's origin is the bottom left corner.
+                mouse.moved([position.x as f32, dimensions.1 as f32 - position.y as f32]);
+                return Ok(());
+            }
+            LoopEvent::Window(WindowEvent::MouseInput { state, button: MouseButton::Left, .. }) => {
+                mouse.button(*state == ElementState::Pressed);
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        image_pass.reload_if_changed(display, &textures);
+        for pass in buffer_passes.iter_mut().flatten() {
+            pass.reload_if_changed(display, &textures);
+        }
+
+        let frame_dimensions = display.get_framebuffer_dimensions();
+        if frame_dimensions != dimensions {
+            dimensions = frame_dimensions;
+            buffer_targets = all_buffer_textures(display, dimensions)?;
+        }
+
+        let time_delta = runner::now().saturating_sub(last_frame).as_secs_f32();
+        last_frame = runner::now();
+
+        let uniforms_for = |pass: &Pass, front: &[usize; 4]| -> ToyUniforms {
+            let mut channels = [(&black, texture_sampler()); 4];
+            for (index, source) in pass.channels.iter().enumerate() {
+                channels[index] = match source {
+                    ChannelSource::Texture(name) => textures
+                        .iter()
+                        .find(|(texture_name, _)| texture_name == name)
+                        .map_or((&black, texture_sampler()), |(_, texture)| (texture, texture_sampler())),
+                    ChannelSource::Buffer(buffer) => (&buffer_targets[*buffer][front[*buffer]], buffer_sampler()),
+                    ChannelSource::Empty => (&black, texture_sampler()),
+                };
+            }
+
+            ToyUniforms {
+                resolution: [dimensions.0 as f32, dimensions.1 as f32, 1.0],
+                time: runner::now().saturating_sub(start).as_secs_f32(),
+                time_delta,
+                frame: frame_index,
+                mouse: mouse.uniform(),
+                date: date_uniform(runner::wall_clock()),
+                channels,
+            }
+        };
+
+        // Buffers A to D first, each one writes into its back texture and then becomes the front one.
+        for (index, pass) in buffer_passes.iter().enumerate() {
+            let pass = match pass {
+                Some(pass) => pass,
+                None => continue,
+            };
+
+            let uniforms = uniforms_for(pass, &front);
+            let buffer = glium::framebuffer::SimpleFrameBuffer::new(display, &buffer_targets[index][1 - front[index]])?;
+            let mut target = frame.pass(&format!("buffer-{}", index), buffer);
+            target.draw(
+                &quad,
+                glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+                &pass.program,
+                &uniforms,
+                &Default::default(),
+            )?;
+            front[index] = 1 - front[index];
+        }
+
+        frame.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        frame.draw(
+            &quad,
+            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+            &image_pass.program,
+            &uniforms_for(&image_pass, &front),
+            &Default::default(),
+        )?;
+
+        frame_index += 1;
+        mouse.clicked_this_frame = false;
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_position_only_follows_the_cursor_while_held() {
+        let mut mouse = Mouse::default();
+        mouse.moved([10.0, 20.0]);
+        assert_eq!(mouse.uniform(), [0.0, 0.0, -0.0, -0.0]);
+
+        mouse.button(true);
+        mouse.moved([30.0, 40.0]);
+        assert_eq!(mouse.uniform(), [30.0, 40.0, 10.0, 20.0]);
+
+        mouse.clicked_this_frame = false;
+        mouse.button(false);
+        mouse.moved([50.0, 60.0]);
+        assert_eq!(mouse.uniform(), [30.0, 40.0, -10.0, -20.0]);
+    }
+
+    #[test]
+    fn channels_come_from_comments() {
+        let src = "// iChannel0: hamster.jpg\n  //iChannel1 : Buffer-B\n// iChannel3: none\nvoid mainImage() {}";
+        assert_eq!(
+            parse_channels(src),
+            [Some(ChannelSource::Texture("hamster.jpg".to_string())), Some(ChannelSource::Buffer(1)), None, Some(ChannelSource::Empty)]
+        );
+    }
+
+    #[test]
+    fn channel_comments_that_do_not_fit_are_ignored() {
+        let src = "// iChannel4: buffer-a\n// iChannelX: buffer-a\n// iChannel0 buffer-a\nint iChannel1: 2;";
+        assert_eq!(parse_channels(src), [None, None, None, None]);
+    }
+
+    #[test]
+    fn date_uniform_has_a_zero_based_month() {
+        let leap_day = std::time::UNIX_EPOCH + std::time::Duration::from_millis(951_782_400_000 + 45_296_500);
+        assert_eq!(date_uniform(leap_day), [2000.0, 1.0, 29.0, 45_296.5]);
+    }
+
+    #[test]
+    fn passes_are_wrapped_under_their_own_name() {
+        let image = wrap("plasma.frag", "void mainImage(out vec4 c, in vec2 p) {}".to_string(), true, Origin::Embedded).unwrap();
+        assert!(image.source.starts_with("#version 140\n#define SHADERTOY_IMAGE 1\n"));
+        assert!(image.source.contains("void mainImage(out vec4 c, in vec2 p) {}"));
+        assert_eq!(image.source_map.files, vec!["shadertoy.frag", "plasma.frag"]);
+
+        let buffer = wrap("trails_a.frag", String::new(), false, Origin::Embedded).unwrap();
+        assert!(!buffer.source.contains("#define SHADERTOY_IMAGE"));
+    }
+}
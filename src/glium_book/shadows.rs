@@ -0,0 +1,450 @@
+#![allow(dead_code)]
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use glium::{
+    draw_parameters::PolygonOffset,
+    framebuffer::SimpleFrameBuffer,
+    texture::{CubeLayer, DepthCubemap, DepthFormat, DepthTexture2d, DepthTexture2dArray, MipmapsOption},
+    uniforms::{
+        DepthTextureComparison, MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction, UniformValue,
+        Uniforms,
+    },
+    Display,
+};
+
+use crate::error::{Error, Result};
+use super::math::view_matrix;
+
+// Shadow maps: render the scene's depth from the light's point of view, then for every fragment check
+// whether something was nearer to the light than it is. The depth pass is the depth buffer from stage 9,
+// just into a texture instead of the window's. Shading programs `#include "shadows.glsl"` for the lookup.
+//
+// Acne (surfaces shadowing themselves because the map only has so many texels) is fought with a
+// slope-scaled bias: glium's `polygon_offset` pushes the depth pass back further the more a triangle is
+// tilted away from the light, which is where acne is worst.
+//
+// One map stretched over a big scene has texels the size of bricks near the camera. Cascaded shadow maps
+// cut the view frustum into slices along the view direction, each with its own map (layers of one
+// texture array) fitted around just that slice, so the near slices get most of the resolution.
+// Each slice is fitted with a sphere rather than a box, so its size doesn't change when the camera turns,
+// and the sphere's center is snapped to whole texels, so the shadows don't shimmer when the camera moves.
+//
+// Point lights shine every which way, so their map is a cubemap, drawn one face at a time with a 90°
+// camera looking down each axis. Instead of depth it keeps the distance to the light (divided by `far`,
+// written through gl_FragDepth): the same no matter which face it landed on, and linear, which makes the
+// bias one number in world units. Polygon offset doesn't touch gl_FragDepth, so the bias goes in the lookup.
+
+/// The light's depth, as seen from the light.
+pub struct ShadowMap {
+    texture: DepthTexture2d,
+}
+
+impl ShadowMap {
+    pub fn new(display: &Display, size: u32) -> Result<Self> {
+        let texture = DepthTexture2d::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, size, size)?;
+        Ok(ShadowMap { texture })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.texture.width()
+    }
+
+    /// Something to draw the depth pass into, clear it with `clear_depth(1.0)` first.
+    pub fn framebuffer<'t>(&'t self, display: &Display) -> Result<SimpleFrameBuffer<'t>> {
+        Ok(SimpleFrameBuffer::depth_only(display, &self.texture)?)
+    }
+
+    /// For a `sampler2DShadow`: the texture compares for us, and with linear filtering it even blends the
+    /// results of the 4 nearest texels, so it's a bit of PCF for free. Outside the map counts as lit.
+    pub fn sampled(&self) -> Sampler<'_, DepthTexture2d> {
+        self.texture
+            .sampled()
+            .depth_texture_comparison(Some(DepthTextureComparison::LessOrEqual))
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+
+    /// The plain depth values, for looking at the map itself (a `sampler2D`).
+    pub fn raw(&self) -> Sampler<'_, DepthTexture2d> {
+        self.texture
+            .sampled()
+            .magnify_filter(MagnifySamplerFilter::Nearest)
+            .minify_filter(MinifySamplerFilter::Nearest)
+    }
+}
+
+/// Draw parameters for the depth pass, `slope_bias` and `constant_bias` go to `glPolygonOffset`.
+pub fn depth_pass_parameters<'a>(slope_bias: f32, constant_bias: f32) -> glium::DrawParameters<'a> {
+    glium::DrawParameters {
+        depth: glium::Depth {
+            test: glium::draw_parameters::DepthTest::IfLess,
+            write: true,
+            ..Default::default()
+        },
+        polygon_offset: PolygonOffset { factor: slope_bias, units: constant_bias, fill: true, ..Default::default() },
+        ..Default::default()
+    }
+}
+
+/// A box of `width` x `height` x (`zfar` - `znear`) in front of the camera, left handed like `perspective_matrix`.
+pub fn orthographic_matrix(width: f32, height: f32, znear: f32, zfar: f32) -> [[f32; 4]; 4] {
+    [
+        [2.0 / width,   0.0         ,   0.0                 , 0.0],
+        [0.0        ,   2.0 / height,   0.0                 , 0.0],
+        [0.0        ,   0.0         ,   2.0 / (zfar - znear), 0.0],
+        [0.0        ,   0.0         , -(zfar + znear) / (zfar - znear), 1.0],
+    ]
+}
+
+/// A directional light's view and projection in one: looking along `direction` at `center`, seeing
+/// everything within `radius` of it.
+pub fn directional_light_matrix(direction: [f32; 3], center: [f32; 3], radius: f32) -> [[f32; 4]; 4] {
+    let len = (direction[0] * direction[0] + direction[1] * direction[1] + direction[2] * direction[2]).sqrt();
+    let direction = [direction[0] / len, direction[1] / len, direction[2] / len];
+    let eye = [center[0] - direction[0] * radius, center[1] - direction[1] * radius, center[2] - direction[2] * radius];
+    // straight up or down, "up" can't be up.
+    let up = if direction[1].abs() > 0.99 { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+
+    let view = view_matrix(&eye, &direction, &up);
+    let projection = orthographic_matrix(2.0 * radius, 2.0 * radius, 0.0, 2.0 * radius);
+    multiply(projection, view)
+}
+
+pub fn multiply(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    (Matrix4::from(a) * Matrix4::from(b)).into()
+}
+
+/// Like `directional_light_matrix`, but `center` is moved to whole texels of a `size` map first (so moving
+/// it around doesn't make the shadows crawl), and the light sits `depth_margin` further back so whatever
+/// is between the light and the sphere still casts shadows into it.
+pub fn stable_light_matrix(direction: [f32; 3], center: [f32; 3], radius: f32, depth_margin: f32, size: u32) -> [[f32; 4]; 4] {
+    let up = if direction[1].abs() > 0.99 * Vector3::from(direction).magnitude() { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+    // just the light's orientation, centered on the world's origin.
+    let rotation = view_matrix(&[0.0, 0.0, 0.0], &direction, &up);
+
+    let center = Matrix4::from(rotation) * Vector4::new(center[0], center[1], center[2], 1.0);
+    let texel = 2.0 * radius / size as f32;
+    let (x, y) = ((center.x / texel).floor() * texel, (center.y / texel).floor() * texel);
+    let z = center.z - radius - depth_margin;
+    let translation = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [-x, -y, -z, 1.0],
+    ];
+
+    let projection = orthographic_matrix(2.0 * radius, 2.0 * radius, 0.0, 2.0 * radius + depth_margin);
+    multiply(projection, multiply(translation, rotation))
+}
+
+pub const MAX_CASCADES: usize = 4;
+
+/// Where the view frustum is cut: `count` + 1 distances from `znear` to `zfar`. `lambda` 0 cuts it into
+/// evenly long slices, 1 into slices growing by the same factor each time, in between mixes the two.
+pub fn cascade_splits(count: usize, znear: f32, zfar: f32, lambda: f32) -> Vec<f32> {
+    (0..=count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let logarithmic = znear * (zfar / znear).powf(t);
+            let uniform = znear + (zfar - znear) * t;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// The smallest sphere (well, nearly) around the part of the view frustum between `near` and `far`, in
+/// world space. `fov` in radians, `aspect_ratio` is width / height.
+pub fn frustum_slice_sphere(view: [[f32; 4]; 4], fov: f32, aspect_ratio: f32, near: f32, far: f32) -> ([f32; 3], f32) {
+    let to_world = Matrix4::from(view).invert().unwrap_or_else(Matrix4::identity);
+    let tan = (fov / 2.0).tan();
+
+    let corners: Vec<Vector3<f32>> = [near, far]
+        .iter()
+        .flat_map(|&z| {
+            let (w, h) = (z * tan * aspect_ratio, z * tan);
+            [(-w, -h), (w, -h), (-w, h), (w, h)].map(|(x, y)| (to_world * Vector4::new(x, y, z, 1.0)).truncate())
+        })
+        .collect();
+
+    let center = corners.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, corner| sum + corner) / corners.len() as f32;
+    let radius = corners.iter().map(|corner| (corner - center).magnitude()).fold(0.0, f32::max);
+    (center.into(), radius)
+}
+
+/// One light matrix per layer of a `CascadedShadowMap`.
+pub struct CascadedShadowMap {
+    texture: DepthTexture2dArray,
+}
+
+impl CascadedShadowMap {
+    pub fn new(display: &Display, size: u32, cascades: usize) -> Result<Self> {
+        let cascades = cascades.clamp(1, MAX_CASCADES) as u32;
+        let texture = DepthTexture2dArray::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, size, size, cascades)?;
+        Ok(CascadedShadowMap { texture })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn cascades(&self) -> usize {
+        self.texture.array_size() as usize
+    }
+
+    /// The depth pass target for one cascade.
+    pub fn framebuffer<'t>(&'t self, display: &Display, cascade: usize) -> Result<SimpleFrameBuffer<'t>> {
+        let layer = self.texture.main_level().layer(cascade as u32).ok_or_else(|| {
+            Error::Framebuffer(format!("there's no cascade {}, only {}", cascade, self.cascades()))
+        })?;
+        Ok(SimpleFrameBuffer::depth_only(display, layer)?)
+    }
+
+    /// For a `sampler2DArrayShadow`, see `ShadowMap::sampled`.
+    pub fn sampled(&self) -> Sampler<'_, DepthTexture2dArray> {
+        self.texture
+            .sampled()
+            .depth_texture_comparison(Some(DepthTextureComparison::LessOrEqual))
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+}
+
+/// What cascades.glsl needs to know about the cascades. `uniforms` hands them to glium as
+/// `cascade_matrix[]`, `cascade_far[]`, `cascade_count` and `cascade_blend`.
+#[derive(Copy, Clone, Debug)]
+pub struct Cascades {
+    matrices: [[[f32; 4]; 4]; MAX_CASCADES],
+    far: [f32; MAX_CASCADES],
+    count: usize,
+    /// How much of each cascade, at its far end, fades into the next one.
+    pub blend: f32,
+}
+
+impl Cascades {
+    pub fn new(blend: f32) -> Self {
+        Cascades { matrices: [[[0.0; 4]; 4]; MAX_CASCADES], far: [0.0; MAX_CASCADES], count: 0, blend }
+    }
+
+    /// Adds the next cascade, reaching up to `far` (view space depth). Past MAX_CASCADES it's ignored.
+    pub fn push(&mut self, light_matrix: [[f32; 4]; 4], far: f32) {
+        if self.count < MAX_CASCADES {
+            self.matrices[self.count] = light_matrix;
+            self.far[self.count] = far;
+            self.count += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn matrix(&self, cascade: usize) -> [[f32; 4]; 4] {
+        self.matrices[cascade]
+    }
+
+    /// The cascades' uniforms, then everything in `extra`.
+    pub fn uniforms<'c, U: Uniforms>(&'c self, extra: &'c U) -> CascadeUniforms<'c, U> {
+        CascadeUniforms { cascades: self, extra }
+    }
+}
+
+const CASCADE_MATRIX: [&str; MAX_CASCADES] = ["cascade_matrix[0]", "cascade_matrix[1]", "cascade_matrix[2]", "cascade_matrix[3]"];
+const CASCADE_FAR: [&str; MAX_CASCADES] = ["cascade_far[0]", "cascade_far[1]", "cascade_far[2]", "cascade_far[3]"];
+
+pub struct CascadeUniforms<'c, U> {
+    cascades: &'c Cascades,
+    extra: &'c U,
+}
+
+impl<'c, U: Uniforms> Uniforms for CascadeUniforms<'c, U> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        // glium knows uniform arrays element by element.
+        for i in 0..MAX_CASCADES {
+            f(CASCADE_MATRIX[i], UniformValue::Mat4(self.cascades.matrices[i]));
+            f(CASCADE_FAR[i], UniformValue::Float(self.cascades.far[i]));
+        }
+        f("cascade_count", UniformValue::SignedInt(self.cascades.count as i32));
+        f("cascade_blend", UniformValue::Float(self.cascades.blend));
+        self.extra.visit_values(f);
+    }
+}
+
+/// Looking down each axis from the light, with which rows of the view matrix say so. These are the cubemap
+/// faces' texture coordinate axes from the GL spec (a mirror image of what `view_matrix` makes, hence by hand),
+/// ibl.rs draws its cubemaps' faces with them too.
+pub const CUBE_FACES: [(CubeLayer, [[f32; 3]; 3]); 6] = [
+    (CubeLayer::PositiveX, [[ 0.0,  0.0, -1.0], [0.0, -1.0,  0.0], [ 1.0,  0.0,  0.0]]),
+    (CubeLayer::NegativeX, [[ 0.0,  0.0,  1.0], [0.0, -1.0,  0.0], [-1.0,  0.0,  0.0]]),
+    (CubeLayer::PositiveY, [[ 1.0,  0.0,  0.0], [0.0,  0.0,  1.0], [ 0.0,  1.0,  0.0]]),
+    (CubeLayer::NegativeY, [[ 1.0,  0.0,  0.0], [0.0,  0.0, -1.0], [ 0.0, -1.0,  0.0]]),
+    (CubeLayer::PositiveZ, [[ 1.0,  0.0,  0.0], [0.0, -1.0,  0.0], [ 0.0,  0.0,  1.0]]),
+    (CubeLayer::NegativeZ, [[-1.0,  0.0,  0.0], [0.0, -1.0,  0.0], [ 0.0,  0.0, -1.0]]),
+];
+
+/// A point light's distances in every direction.
+pub struct PointShadowMap {
+    texture: DepthCubemap,
+    far: f32,
+}
+
+impl PointShadowMap {
+    /// `far` is the furthest from the light anything can still be shadowed.
+    pub fn new(display: &Display, size: u32, far: f32) -> Result<Self> {
+        let texture = DepthCubemap::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, size)?;
+        Ok(PointShadowMap { texture, far })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    /// The depth pass target for one face.
+    pub fn framebuffer<'t>(&'t self, display: &Display, face: CubeLayer) -> Result<SimpleFrameBuffer<'t>> {
+        Ok(SimpleFrameBuffer::depth_only(display, self.texture.main_level().image(face))?)
+    }
+
+    /// The six faces and the view and projection to draw each with, for a light at `light_position`.
+    pub fn face_matrices(&self, light_position: [f32; 3]) -> [(CubeLayer, [[f32; 4]; 4]); 6] {
+        let (znear, zfar) = (0.05, self.far);
+        // 90 degrees and square, f = 1 / tan(45°) = 1.
+        let projection = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, (zfar + znear) / (zfar - znear), 1.0],
+            [0.0, 0.0, -(2.0 * zfar * znear) / (zfar - znear), 0.0],
+        ];
+        let [x, y, z] = light_position;
+
+        CUBE_FACES.map(|(face, [s, u, f])| {
+            let dot = |axis: [f32; 3]| axis[0] * x + axis[1] * y + axis[2] * z;
+            let view = [
+                [s[0], u[0], f[0], 0.0],
+                [s[1], u[1], f[1], 0.0],
+                [s[2], u[2], f[2], 0.0],
+                [-dot(s), -dot(u), -dot(f), 1.0],
+            ];
+            (face, multiply(projection, view))
+        })
+    }
+
+    /// For a `samplerCube`, the distances come out of the red channel.
+    pub fn sampled(&self) -> Sampler<'_, DepthCubemap> {
+        self.texture
+            .sampled()
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where `point` lands in the light's clip space.
+    fn project(matrix: [[f32; 4]; 4], point: [f32; 3]) -> Vector3<f32> {
+        let clip = Matrix4::from(matrix) * Vector4::new(point[0], point[1], point[2], 1.0);
+        clip.truncate() / clip.w
+    }
+
+    fn close(a: Vector3<f32>, b: [f32; 3]) -> bool {
+        (a - Vector3::from(b)).magnitude() < 1e-4
+    }
+
+    #[test]
+    fn a_directional_light_sees_its_sphere_edge_to_edge() {
+        let (direction, center, radius) = ([1.0, -2.0, 0.5], [3.0, 0.5, -1.0], 2.0);
+        let matrix = directional_light_matrix(direction, center, radius);
+        let direction = Vector3::from(direction).normalize();
+
+        assert!(close(project(matrix, center), [0.0, 0.0, 0.0]));
+        // the nearest and furthest bits of the sphere are at the near and far planes.
+        let toward_light: [f32; 3] = (Vector3::from(center) - direction * radius).into();
+        let away: [f32; 3] = (Vector3::from(center) + direction * radius).into();
+        assert!((project(matrix, toward_light).z + 1.0).abs() < 1e-4);
+        assert!((project(matrix, away).z - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lambda_0_cuts_even_slices_and_1_growing_ones() {
+        let even = cascade_splits(4, 1.0, 101.0, 0.0);
+        assert_eq!(even, [1.0, 26.0, 51.0, 76.0, 101.0]);
+
+        let growing = cascade_splits(4, 1.0, 10000.0, 1.0);
+        for (split, expected) in growing.iter().zip([1.0, 10.0, 100.0, 1000.0, 10000.0]) {
+            assert!((split / expected - 1.0).abs() < 1e-4, "{:?}", growing);
+        }
+    }
+
+    #[test]
+    fn stable_light_matrices_move_in_whole_texels() {
+        let (direction, radius, size) = ([0.3, -1.0, 0.6], 5.0, 1024);
+        for center in [[0.0, 0.0, 0.0], [0.123, 0.4, -7.77], [31.4, 2.0, 15.9]] {
+            let matrix = stable_light_matrix(direction, center, radius, 10.0, size);
+            // the world's origin, which the rotation leaves alone, always lands on a texel's corner.
+            let origin = project(matrix, [0.0, 0.0, 0.0]) * size as f32 / 2.0;
+            assert!((origin.x - origin.x.round()).abs() < 1e-2 && (origin.y - origin.y.round()).abs() < 1e-2, "{:?}", origin);
+            // and the center is less than a texel from the middle.
+            let middle = project(matrix, center);
+            let texel = 2.0 / size as f32;
+            assert!(middle.x.abs() < texel + 1e-4 && middle.y.abs() < texel + 1e-4, "{:?}", middle);
+        }
+    }
+
+    #[test]
+    fn a_frustum_slice_fits_in_its_sphere() {
+        let (fov, aspect_ratio, near, far) = (std::f32::consts::FRAC_PI_2, 2.0, 1.0, 3.0);
+        let (center, radius) = frustum_slice_sphere(Matrix4::identity().into(), fov, aspect_ratio, near, far);
+
+        // the identity view looks down +z, so the slice is centered on it.
+        assert!(close(Vector3::from(center), [0.0, 0.0, 2.0]), "{:?}", center);
+        let mut farthest = 0.0f32;
+        for z in [near, far] {
+            for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                let corner = Vector3::new(x * z * aspect_ratio, y * z, z);
+                farthest = farthest.max((corner - Vector3::from(center)).magnitude());
+            }
+        }
+        assert!((radius - farthest).abs() < 1e-4, "radius {} but the farthest corner is {} away", radius, farthest);
+    }
+
+    #[test]
+    fn cascades_past_the_maximum_are_ignored() {
+        let mut cascades = Cascades::new(0.1);
+        for i in 0..MAX_CASCADES + 2 {
+            cascades.push([[i as f32; 4]; 4], i as f32);
+        }
+        assert_eq!(cascades.len(), MAX_CASCADES);
+        assert_eq!(cascades.matrix(MAX_CASCADES - 1), [[(MAX_CASCADES - 1) as f32; 4]; 4]);
+    }
+
+    #[test]
+    fn cube_faces_look_down_their_axis_in_a_mirrored_frame() {
+        for (face, [s, t, forward]) in CUBE_FACES {
+            let axis = match face {
+                CubeLayer::PositiveX => [1.0, 0.0, 0.0],
+                CubeLayer::NegativeX => [-1.0, 0.0, 0.0],
+                CubeLayer::PositiveY => [0.0, 1.0, 0.0],
+                CubeLayer::NegativeY => [0.0, -1.0, 0.0],
+                CubeLayer::PositiveZ => [0.0, 0.0, 1.0],
+                CubeLayer::NegativeZ => [0.0, 0.0, -1.0],
+            };
+            assert_eq!(forward, axis, "{:?}", face);
+
+            let (s, t, forward) = (Vector3::from(s), Vector3::from(t), Vector3::from(forward));
+            assert_eq!((s.magnitude(), t.magnitude()), (1.0, 1.0), "{:?}", face);
+            assert_eq!((s.dot(t), s.dot(forward), t.dot(forward)), (0.0, 0.0, 0.0), "{:?}", face);
+            // the mirror image: s and t turn the other way round the view direction than view_matrix's do.
+            assert_eq!(s.cross(t), -forward, "{:?}", face);
+        }
+    }
+}
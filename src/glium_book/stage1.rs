@@ -7,41 +7,33 @@ use glium::glutin::{
 };
 use glium::Surface;
 
-pub fn run() {
+use crate::error::Result;
+use super::runner::{run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.1, 0.1, 0.9, 1.0]),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
     let event_loop = glutin::event_loop::EventLoop::new();
     let window_builder = glutin::window::WindowBuilder::new();
     let context_builder = glutin::ContextBuilder::new();
-    let display = glium::Display::new(
+    let display = create_display(
         window_builder,
         context_builder,
         &event_loop
-    ).unwrap();
-
-    event_loop.run(move |event, _, control_flow| {
-        let mut frame = display.draw();
-        frame.clear_color(0.1, 0.1, 0.9, 1.0);
-        frame.finish().unwrap();
-
-        let next_frame_time = std::time::Instant::now() + std::time::Duration::from_nanos(16_666_667);
+    )?;
 
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
+    let [r, g, b, a] = params.vec4("clear_color");
 
-        match event {
-            glutin::event::Event::WindowEvent { event, .. } => match event {
-                glutin::event::WindowEvent::CloseRequested => {
-                    *control_flow = glutin::event_loop::ControlFlow::Exit;
-                    return;
-                },
-                glutin::event::WindowEvent::KeyboardInput {
-                    input: KeyboardInput { virtual_keycode: Some(virtual_code), state, .. },
-                    ..
-                } => match (virtual_code, state) {
-                    (VirtualKeyCode::Escape, _) => *control_flow = glutin::event_loop::ControlFlow::Exit,
-                    _ => return,
-                },
-                _ => return,
-            },
-            _ => (),
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(16_666_667), |_, event| {
+        if let LoopEvent::Draw(frame) = event {
+            frame.clear_color(r, g, b, a);
         }
-    });
-}
\ No newline at end of file
+        Ok(())
+    })
+}
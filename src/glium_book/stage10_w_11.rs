@@ -8,104 +8,64 @@ use glium::{glutin::{
 }, buffer, uniform};
 use glium::{
     implement_vertex,
-    Display,
-    backend::Facade,
-    Program,
     Surface,
 };
 
 use super::teapot;
-
-pub fn vertex_shader_src() -> &'static str {
-    r#"
-        #version 150
-
-        in vec3 position;
-        in vec3 normal;
-
-        out vec3 v_normal;
-
-        uniform mat4 perspective;
-        uniform mat4 matrix;
-
-        void main() {
-            v_normal = transpose(inverse(mat3(matrix))) * normal;
-            gl_Position = perspective * matrix * vec4(position, 1.0);
-        }
-    "#
-}
-
-pub fn fragment_shader_src() -> &'static str {
-    r#"
-        #version 140
-
-        in vec3 v_normal;
-        out vec4 color;
-
-        uniform vec3 u_light;
-
-        void main() {
-            float brightness = dot(normalize(v_normal), normalize(u_light));
-            vec3 dark_color = vec3(0.555, 0.007, 0.075);
-            vec3 regular_color = vec3(1.0, 0.09, 0.045);
-            // mix = lerp
-            color = vec4(mix(dark_color, regular_color, brightness), 1.0);
-        }
-    "#
-}
-
-pub fn the_stage10_program(display: &Display) -> Program {
-    Program::from_source(display as &dyn Facade, vertex_shader_src(), fragment_shader_src(), None).unwrap()
+use crate::error::Result;
+use super::hot_reload::{HotProgram, shader_file};
+use super::runner::{run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.06, 0.075, 0.95, 1.0]),
+        params::float("model_scale", 0.01, "size of the model"),
+        params::vec3("light", [-1.0, 0.8, 0.9], "direction towards the light"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
 }
 
-pub fn run() {
+pub fn run(params: &Params) -> Result<()> {
     let event_loop = glutin::event_loop::EventLoop::new();
     let window_builder = glutin::window::WindowBuilder::new();
     let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24); // 24 is apparently just a "common value"
-    let display = glium::Display::new(
+    let display = create_display(
         window_builder,
         context_builder,
         &event_loop
-    ).unwrap();
-
-    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES).unwrap();
-    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS).unwrap();
-    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES).unwrap();
-
-    event_loop.run(move |event, _, control_flow| {
-
-        match event {
-            glutin::event::Event::WindowEvent { event, .. } => match event {
-                glutin::event::WindowEvent::CloseRequested => {
-                    *control_flow = glutin::event_loop::ControlFlow::Exit;
-                    return;
-                },
-                glutin::event::WindowEvent::KeyboardInput {
-                    input: KeyboardInput { virtual_keycode: Some(virtual_code), state, .. },
-                    ..
-                } => match (virtual_code, state) {
-                    (VirtualKeyCode::Escape, _) => {
-                        *control_flow = glutin::event_loop::ControlFlow::Exit;
-                        return;
-                    }
-                    _ => return,
-                },
-                _ => return,
-            },
-            _ => (),
-        }
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let mut program = HotProgram::new(&display, shader_file!("stage10.vert"), shader_file!("stage10.frag"))?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let scale = params.float("model_scale");
+    let light = params.vec3("light");
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
 
-        let mut frame = display.draw();
-        frame.clear_color_and_depth((0.06, 0.075, 0.95, 1.0), 1.0);
+        program.reload_if_changed(display);
+
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
             
         let perspective = {
             let (width, height) = frame.get_dimensions();
             let aspect_ratio = height as f32 / width as f32;
 
-            let fov: f32 = 3.141592 / 3.0; // user parameter
-            let zfar = 1024.0; // can't move object farther or nearer than these
-            let znear = 0.1;  // two values here.
-
+            // fov, znear and zfar are stage parameters, see --describe.
             let f = 1.0 / (fov / 2.0).tan();
 
             [
@@ -117,14 +77,14 @@ pub fn run() {
         };
 
         let matrix = [
-            [0.01, 0.0, 0.0, 0.0],
-            [0.0, 0.01, 0.0, 0.0],
-            [0.0, 0.0, 0.01, 0.0],
+            [scale, 0.0, 0.0, 0.0],
+            [0.0, scale, 0.0, 0.0],
+            [0.0, 0.0, scale, 0.0],
             [0.0, 0.0, 2.0, 1.0f32],
         ];
 
         let uniforms = uniform! {
-            u_light: [-1.0, 0.8, 0.9f32],
+            u_light: light,
             matrix: matrix,
             perspective: perspective
         };
@@ -140,19 +100,17 @@ pub fn run() {
             ..Default::default()
         };
 
+        program.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
+
         // Drawing the Teapot!
         frame.draw(
             (&positions, &normals),
             &indices,
-            &the_stage10_program(&display),
+            &program,
             &uniforms,
             &params,
-        ).unwrap();
-
-        frame.finish().unwrap();
-
-        let next_frame_time = std::time::Instant::now() + std::time::Duration::from_nanos(666_667);
+        )?;
 
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
-    });
+        Ok(())
+    })
 }
\ No newline at end of file
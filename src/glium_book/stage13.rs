@@ -4,186 +4,89 @@ use std::hash::Hasher;
 use crossterm::event::KeyCode;
 use glium::{glutin::{
     self,
-    event::{KeyboardInput, VirtualKeyCode},
-}, buffer, uniform, Frame};
+    event::{ElementState, KeyboardInput, VirtualKeyCode},
+}, buffer, uniform};
 use glium::{
     implement_vertex,
-    Display,
-    backend::Facade,
-    Program,
     Surface,
 };
 
 use super::teapot;
-
-pub fn vertex_shader_src() -> &'static str {
-    r#"
-        #version 150
-
-        in vec3 position;
-        in vec3 normal;
-
-        out vec3 v_normal;
-        out vec3 v_position;
-
-        uniform mat4 perspective;
-        uniform mat4 view;
-        uniform mat4 model;
-
-        void main() {
-            mat4 modelview = view * model;
-            v_normal = transpose(inverse(mat3(modelview))) * normal;
-            gl_Position = perspective * modelview * vec4(position, 1.0);
-            v_position = gl_Position.xyz / gl_Position.w;
-        }
-    "#
-}
-
-pub fn fragment_shader_src() -> &'static str {
-    r#"
-        #version 140
-
-        in vec3 v_normal;
-        in vec3 v_position;
-
-        out vec4 color;
-
-        uniform vec3 u_light;
-
-        const vec3 ambient_color = vec3(0.28, 0.015, 0.0);
-        const vec3 diffuse_color = vec3(0.95, 0.01, 0.06);
-        const vec3 specular_color = vec3(1.0, 0.975, 0.925);
-
-        //old values
-        const vec3 dark_color = vec3(0.555, 0.007, 0.075);
-        const vec3 regular_color = vec3(1.0, 0.09, 0.045);
-
-        void main() {
-            float diffuse = max(dot(normalize(v_normal), normalize(u_light)), 0.0);
-
-            vec3 camera_dir = normalize(-v_position);
-            vec3 half_direction = normalize(normalize(u_light) + camera_dir); // relationship between lightsource and camera angle for the object/fragment
-
-            // dot = cosine
-            float specular = pow(max(dot(half_direction, normalize(v_normal)), 0.0), 16.0); // 16 = specular coefficient, determining the "dropoff rate" for specular reflectio.
-            
-            // things are auto-clamped to 1.0
-            color = vec4(ambient_color + diffuse * diffuse_color + specular * specular_color, 1.0);
-        }
-    "#
-}
-
-pub fn the_stage13_program(display: &Display) -> Program {
-    Program::from_source(display as &dyn Facade, vertex_shader_src(), fragment_shader_src(), None).unwrap()
-}
-
-pub fn perspective_matrix(frame: &Frame) -> [[f32; 4]; 4] {
-    
-    let perspective = {
-        let (width, height) = frame.get_dimensions();
-        let aspect_ratio = height as f32 / width as f32;
-
-        let fov: f32 = 3.141592 / 3.0; // user parameter
-        let zfar = 1024.0; // can't move object farther or nearer than these
-        let znear = 0.1;  // two values here.
-
-        let f = 1.0 / (fov / 2.0).tan();
-
-        [
-            [f * aspect_ratio   ,   0.0 ,           0.0     ,               0.0],
-            [   0.0             ,   f   ,           0.0     ,               0.0],
-            [   0.0             ,   0.0 ,    (zfar+znear)/(zfar-znear)  ,   1.0],
-            [   0.0             ,   0.0 , -(2.0*zfar*znear)/(zfar-znear),   0.0],
-        ]
-    };
-
-    perspective
-}
-
-pub fn view_matrix(position: &[f32; 3], direction: &[f32; 3], up: &[f32; 3]) -> [[f32; 4]; 4] {
-    let f_norm = {
-        let f = direction;
-        let len = f[0] * f[0] + f[1] * f[1] + f[2] * f[2];
-        let len = len.sqrt();
-        [f[0] / len, f[1] / len, f[2] / len]
-    };
-
-    let s = [
-        up[1] * f_norm[2] - up[2] * f_norm[1],
-        up[2] * f_norm[0] - up[0] * f_norm[2],
-        up[0] * f_norm[1] - up[1] * f_norm[0],
-    ];
-
-    let s_norm = {
-        let len = s[0] * s[0] + s[1] * s[1] + s[2] * s[2];
-        let len = len.sqrt();
-        [s[0] / len, s[1] / len, s[2] / len]
-    };
-
-    let u = [
-        f_norm[1] * s_norm[2] - f_norm[2] * s_norm[1],
-        f_norm[2] * s_norm[0] - f_norm[0] * s_norm[2],
-        f_norm[0] * s_norm[1] - f_norm[1] * s_norm[0],
-    ];
-
-    let p = [
-        -position[0] * s_norm[0] - position[1] * s_norm[1] - position[2] * s_norm[2],
-        -position[0] * u[0] - position[1] * u[1] - position[2] * u[2],
-        -position[0] * f_norm[0] - position[1] * f_norm[1] - position[2] * f_norm[2],
-    ];
-
-    [
-        [s_norm[0], u[0], f_norm[0], 0.0],
-        [s_norm[1], u[1], f_norm[1], 0.0],
-        [s_norm[2], u[2], f_norm[2], 0.0],
-        [p[0], p[1], p[2], 1.0],
+use crate::error::Result;
+use super::material::Material;
+use super::runner::{run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::math::{perspective_matrix, view_matrix};
+use super::inspector::Inspector;
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.06, 0.075, 0.95, 1.0]),
+        params::float("model_scale", 0.01, "size of the model"),
+        params::vec3("light", [-1.0, 0.8, 0.9], "direction towards the light"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
     ]
 }
 
-pub fn run() {
+pub fn run(params: &Params) -> Result<()> {
     let event_loop = glutin::event_loop::EventLoop::new();
     let window_builder = glutin::window::WindowBuilder::new();
     let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24); // 24 is apparently just a "common value"
-    let display = glium::Display::new(
+    let display = create_display(
         window_builder,
         context_builder,
         &event_loop
-    ).unwrap();
-
-    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES).unwrap();
-    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS).unwrap();
-    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES).unwrap();
-
-    event_loop.run(move |event, _, control_flow| {
-
-        match event {
-            glutin::event::Event::WindowEvent { event, .. } => match event {
-                glutin::event::WindowEvent::CloseRequested => {
-                    *control_flow = glutin::event_loop::ControlFlow::Exit;
-                    return;
-                },
-                glutin::event::WindowEvent::KeyboardInput {
-                    input: KeyboardInput { virtual_keycode: Some(virtual_code), state, .. },
-                    ..
-                } => match (virtual_code, state) {
-                    (VirtualKeyCode::Escape, _) => {
-                        *control_flow = glutin::event_loop::ControlFlow::Exit;
-                        return;
-                    }
-                    _ => return,
-                },
-                _ => return,
-            },
-            _ => (),
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let mut material = Material::blinn_phong(
+        &display,
+        [0.28, 0.015, 0.0],
+        [0.95, 0.01, 0.06],
+        [1.0, 0.975, 0.925],
+        16.0, // determines the "dropoff rate" for specular reflection.
+    )?;
+    let mut inspector = Inspector::new(&display, "stage13")?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let scale = params.float("model_scale");
+    let light = params.vec3("light");
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    // stages 13 and 14 used to light in NDC, L flips back to that to see the difference.
+    let mut view_space_lighting = true;
+    log::info!("L switches between view space lighting and the old NDC version");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        if inspector.handle_event(&event) {
+            return Ok(());
         }
 
-        let mut frame = display.draw();
-        frame.clear_color_and_depth((0.06, 0.075, 0.95, 1.0), 1.0);
+        let frame = match event {
+            LoopEvent::Key(VirtualKeyCode::L, ElementState::Pressed) => {
+                view_space_lighting = !view_space_lighting;
+                log::info!("{}", if view_space_lighting { "view space lighting" } else { "NDC lighting (the old, wrong one)" });
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        material.reload_if_changed(display);
+
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
 
         let model = [
-            [0.01, 0.0, 0.0, 0.0],
-            [0.0, 0.01, 0.0, 0.0],
-            [0.0, 0.0, 0.01, 0.0],
+            [scale, 0.0, 0.0, 0.0],
+            [0.0, scale, 0.0, 0.0],
+            [0.0, 0.0, scale, 0.0],
             [0.0, 0.0, 2.0, 1.0f32],
         ];
 
@@ -194,10 +97,11 @@ pub fn run() {
         );
 
         let uniforms = uniform! {
-            u_light: [-1.0, 0.8, 0.9f32],
+            u_light: light,
             model: model,
             view: view,
-            perspective: perspective_matrix(&frame)
+            perspective: perspective_matrix(&*frame, fov, znear, zfar),
+            view_space_lighting: view_space_lighting
         };
 
         // from here on we're finally getting into all of this! :D
@@ -211,19 +115,114 @@ pub fn run() {
             ..Default::default()
         };
 
+        material.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
+        let uniforms = material.uniforms(&uniforms);
+        let uniforms = inspector.tweak(material.program(), &uniforms);
+
         // Drawing the Teapot!
         frame.draw(
             (&positions, &normals),
             &indices,
-            &the_stage13_program(&display),
+            material.program(),
             &uniforms,
             &params,
-        ).unwrap();
+        )?;
+
+        inspector.draw(display, frame)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Vector3, vec3};
+
+    use crate::glium_book::math::perspective;
 
-        frame.finish().unwrap();
+    use super::*;
 
-        let next_frame_time = std::time::Instant::now() + std::time::Duration::from_nanos(666_667);
+    // These run a Rust copy of the shaders' math, not the shaders themselves (that takes a GL context), so a
+    // change to stage13.vert, stage13.frag or lighting.glsl has to be made to the copy too.
 
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
-    });
-}
\ No newline at end of file
+    const SPECULAR_EXPONENT: f32 = 16.0;
+
+    /// lighting.glsl's `blinn_phong_specular`.
+    fn blinn_phong_specular(normal: Vector3<f32>, light_dir: Vector3<f32>, position: Vector3<f32>) -> f32 {
+        let camera_dir = (-position).normalize();
+        let half_direction = (light_dir.normalize() + camera_dir).normalize();
+        half_direction.dot(normal.normalize()).max(0.0).powf(SPECULAR_EXPONENT)
+    }
+
+    /// stage13.vert and stage13.frag's specular term for a point of a model, None where it's off screen.
+    fn specular_at(point: Vector3<f32>, normal: Vector3<f32>, light: Vector3<f32>, uniforms: &Uniforms, view_space_lighting: bool) -> Option<f32> {
+        let modelview = uniforms.view * uniforms.model;
+        let modelview3 = Matrix3::from_cols(modelview.x.truncate(), modelview.y.truncate(), modelview.z.truncate());
+        let v_normal = modelview3.invert()?.transpose() * normal;
+        let position_view = modelview * point.extend(1.0);
+        let clip = uniforms.perspective * position_view;
+        let ndc = clip.truncate() / clip.w;
+        if clip.w <= 0.0 || ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 {
+            return None;
+        }
+
+        let v_position = if view_space_lighting { position_view.truncate() } else { ndc };
+        let view3 = Matrix3::from_cols(uniforms.view.x.truncate(), uniforms.view.y.truncate(), uniforms.view.z.truncate());
+        let light_dir = if view_space_lighting { view3 * light } else { light };
+        Some(blinn_phong_specular(v_normal, light_dir, v_position))
+    }
+
+    struct Uniforms {
+        model: Matrix4<f32>,
+        view: Matrix4<f32>,
+        perspective: Matrix4<f32>,
+    }
+
+    /// Where on the ground (y = 0) the specular highlight peaks: a grid of points, the brightest one.
+    fn highlight(camera: [f32; 3], target: [f32; 3], light: Vector3<f32>, view_space_lighting: bool) -> Vector3<f32> {
+        let direction = [target[0] - camera[0], target[1] - camera[1], target[2] - camera[2]];
+        let uniforms = Uniforms {
+            model: Matrix4::identity(),
+            view: Matrix4::from(view_matrix(&camera, &direction, &[0.0, 1.0, 0.0])),
+            perspective: Matrix4::from(perspective(0.75, std::f32::consts::FRAC_PI_3, 0.1, 100.0)),
+        };
+
+        let up = vec3(0.0, 1.0, 0.0);
+        let mut brightest = (f32::MIN, vec3(0.0, 0.0, 0.0));
+        for i in -200..=200 {
+            for k in -200..=200 {
+                let point = vec3(i as f32 * 0.02, 0.0, k as f32 * 0.02);
+                match specular_at(point, up, light, &uniforms, view_space_lighting) {
+                    Some(specular) if specular > brightest.0 => brightest = (specular, point),
+                    _ => (),
+                }
+            }
+        }
+        brightest.1
+    }
+
+    const CAMERA: [f32; 3] = [0.0, 2.0, -3.0];
+    const TARGET: [f32; 3] = [0.0, 0.0, 1.0];
+
+    fn light() -> Vector3<f32> {
+        vec3(0.2, 1.0, 2.0)
+    }
+
+    /// u_light points towards the light. The highlight is where the camera sees the light reflected: the point
+    /// whose direction to the camera is the light's direction mirrored in the ground.
+    fn mirror_point() -> Vector3<f32> {
+        let t = CAMERA[1] / light().y;
+        vec3(CAMERA[0] + t * light().x, 0.0, CAMERA[2] + t * light().z)
+    }
+
+    #[test]
+    fn the_specular_highlight_is_where_the_ground_mirrors_the_light() {
+        let highlight = highlight(CAMERA, TARGET, light().normalize(), true);
+        assert!((highlight - mirror_point()).magnitude() < 0.05, "highlight at {:?}, the mirror point is {:?}", highlight, mirror_point());
+    }
+
+    #[test]
+    fn ndc_lighting_puts_the_highlight_somewhere_else() {
+        // what the test above would see with the old lighting, so it does tell the two apart.
+        let highlight = highlight(CAMERA, TARGET, light().normalize(), false);
+        assert!((highlight - mirror_point()).magnitude() > 0.5, "NDC lighting put the highlight at {:?}, the mirror point", highlight);
+    }
+}
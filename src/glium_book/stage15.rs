@@ -0,0 +1,660 @@
+#![allow(dead_code)]
+use std::borrow::Cow;
+use std::f32::consts::FRAC_PI_3;
+
+use cgmath::{ElementWise, InnerSpace, Vector3, vec3};
+use glium::{glutin::{
+    self,
+    event::{ElementState, VirtualKeyCode},
+}, uniform, implement_vertex, Display, Surface};
+use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::MagnifySamplerFilter;
+
+use crate::error::Result;
+use super::teapot;
+use super::runner::{run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::hot_reload::{HotProgram, shader_file};
+use super::preprocess::Preprocessor;
+use super::util::Rng;
+
+// Progressive path tracing: every frame traces ONE more sample per pixel and blends it into a float
+// accumulation texture, so the image converges over time. Moving the camera throws the history away.
+
+const MAX_BOUNCES: usize = 4;
+const EPSILON: f32 = 0.0005;
+const FAR: f32 = 1e20;
+
+#[derive(Copy, Clone)]
+pub struct QuadVertex {
+    pub position: [f32; 2],
+}
+
+implement_vertex!(QuadVertex, position);
+
+/// Two triangles covering the whole viewport, meant to be drawn as a triangle strip.
+pub fn fullscreen_quad() -> [QuadVertex; 4] {
+    [
+        QuadVertex { position: [-1.0,  1.0] },
+        QuadVertex { position: [ 1.0,  1.0] },
+        QuadVertex { position: [-1.0, -1.0] },
+        QuadVertex { position: [ 1.0, -1.0] },
+    ]
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Material {
+    pub albedo: [f32; 3],
+    pub emission: [f32; 3],
+    pub roughness: f32,
+    pub glossiness: f32, // chance of a bounce being a (rough) mirror reflection instead of a diffuse one.
+}
+
+impl Material {
+    pub fn diffuse(albedo: [f32; 3]) -> Self {
+        Material { albedo, emission: [0.0; 3], roughness: 1.0, glossiness: 0.0 }
+    }
+
+    pub fn glossy(albedo: [f32; 3], roughness: f32, glossiness: f32) -> Self {
+        Material { albedo, emission: [0.0; 3], roughness, glossiness }
+    }
+
+    pub fn emissive(color: [f32; 3], strength: f32) -> Self {
+        Material {
+            albedo: [0.0; 3],
+            emission: [color[0] * strength, color[1] * strength, color[2] * strength],
+            roughness: 1.0,
+            glossiness: 0.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Sphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+    pub material: usize,
+}
+
+pub struct Scene {
+    pub materials: Vec<Material>,
+    pub spheres: Vec<Sphere>,
+    pub ground_height: f32,
+    pub ground_material: usize,
+    pub teapot: Vec<[Vector3<f32>; 3]>,
+    pub teapot_material: usize,
+    pub teapot_min: Vector3<f32>,
+    pub teapot_max: Vector3<f32>,
+}
+
+/// The teapot as a flat list of world space triangles (scaled like the other stages, 0.01).
+pub fn teapot_triangles(scale: f32, offset: Vector3<f32>) -> Vec<[Vector3<f32>; 3]> {
+    let vertex = |index: u16| {
+        let (x, y, z) = teapot::VERTICES[index as usize].position;
+        vec3(x, y, z) * scale + offset
+    };
+
+    teapot::INDICES
+        .chunks(3)
+        .map(|tri| [vertex(tri[0]), vertex(tri[1]), vertex(tri[2])])
+        .collect()
+}
+
+pub fn default_scene() -> Scene {
+    let teapot = teapot_triangles(0.01, vec3(0.0, 0.0, 0.0));
+
+    let mut teapot_min = vec3(FAR, FAR, FAR);
+    let mut teapot_max = vec3(-FAR, -FAR, -FAR);
+    for v in teapot.iter().flatten() {
+        teapot_min = vec3(teapot_min.x.min(v.x), teapot_min.y.min(v.y), teapot_min.z.min(v.z));
+        teapot_max = vec3(teapot_max.x.max(v.x), teapot_max.y.max(v.y), teapot_max.z.max(v.z));
+    }
+
+    let materials = vec![
+        Material::diffuse([0.7, 0.7, 0.68]),                // 0: ground
+        Material::glossy([0.95, 0.01, 0.06], 0.35, 0.4),    // 1: teapot, same red as stage13
+        Material::emissive([1.0, 0.95, 0.85], 6.0),         // 2: big overhead lamp
+        Material::glossy([0.9, 0.9, 0.9], 0.05, 1.0),       // 3: mirror-ish ball
+        Material::diffuse([0.06, 0.075, 0.95]),             // 4: blue ball, the old clear color
+        Material::emissive([1.0, 0.45, 0.1], 3.0),          // 5: small orange lamp
+    ];
+
+    let spheres = vec![
+        Sphere { center: [0.0, 2.6, 0.6], radius: 1.0, material: 2 },
+        Sphere { center: [1.15, teapot_min.y + 0.35, 0.4], radius: 0.35, material: 3 },
+        Sphere { center: [-1.1, teapot_min.y + 0.3, 0.3], radius: 0.3, material: 4 },
+        Sphere { center: [-0.6, teapot_min.y + 0.1, -0.7], radius: 0.1, material: 5 },
+    ];
+
+    Scene {
+        materials,
+        spheres,
+        ground_height: teapot_min.y,
+        ground_material: 0,
+        teapot,
+        teapot_material: 1,
+        teapot_min,
+        teapot_max,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera { position: [0.0, 0.35, -2.8], yaw: 0.0, pitch: -0.1, fov: FRAC_PI_3 }
+    }
+}
+
+impl Camera {
+    /// right, up and forward vectors, left handed like `view_matrix` in the earlier stages.
+    pub fn basis(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let forward = vec3(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        let right = vec3(0.0, 1.0, 0.0).cross(forward).normalize();
+        let up = forward.cross(right);
+        (right, up, forward)
+    }
+
+    /// Direction of the ray through `ndc` ([-1, 1] in both axes) for a viewport of the given aspect ratio (w/h).
+    pub fn ray_direction(&self, ndc: [f32; 2], aspect_ratio: f32) -> Vector3<f32> {
+        let (right, up, forward) = self.basis();
+        let scale = (self.fov / 2.0).tan();
+        (forward + right * (ndc[0] * aspect_ratio * scale) + up * (ndc[1] * scale)).normalize()
+    }
+
+    /// Applies a key press, returns whether the camera actually moved (and the accumulation has to be reset).
+    pub fn handle_key(&mut self, key: VirtualKeyCode) -> bool {
+        let (right, _, forward) = self.basis();
+        let step = 0.1;
+        let turn = 0.05;
+        let mut position = Vector3::from(self.position);
+
+        match key {
+            VirtualKeyCode::W => position += forward * step,
+            VirtualKeyCode::S => position -= forward * step,
+            VirtualKeyCode::D => position += right * step,
+            VirtualKeyCode::A => position -= right * step,
+            VirtualKeyCode::E => position.y += step,
+            VirtualKeyCode::Q => position.y -= step,
+            VirtualKeyCode::Left => self.yaw -= turn,
+            VirtualKeyCode::Right => self.yaw += turn,
+            VirtualKeyCode::Up => self.pitch = (self.pitch + turn).min(1.5),
+            VirtualKeyCode::Down => self.pitch = (self.pitch - turn).max(-1.5),
+            _ => return false,
+        }
+
+        self.position = position.into();
+        true
+    }
+}
+
+/// The scene specific bits of the tracing shader, generated from the same `Scene` the CPU tracer uses.
+pub fn scene_glsl(scene: &Scene) -> String {
+    let v = |c: [f32; 3]| format!("vec3({:?}, {:?}, {:?})", c[0], c[1], c[2]);
+
+    let mut src = String::from("Material material(int id) {\n");
+    for (id, m) in scene.materials.iter().enumerate() {
+        src += &format!(
+            "    if (id == {}) return Material({}, {}, {:?}, {:?});\n",
+            id, v(m.albedo), v(m.emission), m.roughness, m.glossiness
+        );
+    }
+    src += "    return Material(vec3(1.0, 0.0, 1.0), vec3(0.0), 1.0, 0.0);\n}\n\n";
+
+    src += "void scene_hits(vec3 ro, vec3 rd, inout Hit hit) {\n";
+    src += &format!("    hit_plane(ro, rd, {:?}, {}, hit);\n", scene.ground_height, scene.ground_material);
+    for s in scene.spheres.iter() {
+        src += &format!("    hit_sphere(ro, rd, {}, {:?}, {}, hit);\n", v(s.center), s.radius, s.material);
+    }
+    src += &format!("    hit_teapot(ro, rd, {}, hit);\n", scene.teapot_material);
+    src += "}\n";
+
+    src
+}
+
+/// The defines and the generated `scene.glsl` include the tracing shader expects.
+pub fn trace_preprocessor(scene: &Scene) -> Preprocessor {
+    Preprocessor::new()
+        .define("MAX_BOUNCES", MAX_BOUNCES)
+        .define("EPSILON", format!("{:?}", EPSILON))
+        .define("FAR", format!("{:?}", FAR))
+        .virtual_file("scene.glsl", scene_glsl(scene))
+}
+
+pub fn upload_teapot(display: &Display, scene: &Scene) -> Result<Texture2d> {
+    let data: Vec<(f32, f32, f32)> = scene.teapot
+        .iter()
+        .flatten()
+        .map(|v| (v.x, v.y, v.z))
+        .collect();
+
+    let raw = RawImage2d {
+        data: Cow::Owned(data),
+        width: 3,
+        height: scene.teapot.len() as u32,
+        format: ClientFormat::F32F32F32,
+    };
+
+    Ok(Texture2d::with_format(display, raw, UncompressedFloatFormat::F32F32F32, MipmapsOption::NoMipmap)?)
+}
+
+pub fn accumulation_textures(display: &Display, (width, height): (u32, u32)) -> Result<[Texture2d; 2]> {
+    let make = || Texture2d::empty_with_format(
+        display,
+        UncompressedFloatFormat::F32F32F32F32,
+        MipmapsOption::NoMipmap,
+        width,
+        height,
+    );
+
+    Ok([make()?, make()?])
+}
+
+// ---- CPU fallback ----
+// Same scene, same materials, same estimator as the shader. Slow, but runs without a GL context.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    pub t: f32,
+    pub normal: Vector3<f32>,
+    pub material: usize,
+}
+
+fn hit_sphere(ro: Vector3<f32>, rd: Vector3<f32>, sphere: &Sphere, hit: &mut Option<Hit>) {
+    let center = Vector3::from(sphere.center);
+    let oc = ro - center;
+    let b = oc.dot(rd);
+    let c = oc.dot(oc) - sphere.radius * sphere.radius;
+    let h = b * b - c;
+    if h < 0.0 {
+        return;
+    }
+    let h = h.sqrt();
+    let mut t = -b - h;
+    if t < EPSILON {
+        t = -b + h;
+    }
+    if t > EPSILON && t < hit.map_or(FAR, |h| h.t) {
+        *hit = Some(Hit { t, normal: (ro + rd * t - center) / sphere.radius, material: sphere.material });
+    }
+}
+
+fn hit_plane(ro: Vector3<f32>, rd: Vector3<f32>, height: f32, material: usize, hit: &mut Option<Hit>) {
+    if rd.y.abs() < 1e-6 {
+        return;
+    }
+    let t = (height - ro.y) / rd.y;
+    if t > EPSILON && t < hit.map_or(FAR, |h| h.t) {
+        *hit = Some(Hit { t, normal: vec3(0.0, 1.0, 0.0), material });
+    }
+}
+
+fn hit_box(ro: Vector3<f32>, rd: Vector3<f32>, box_min: Vector3<f32>, box_max: Vector3<f32>, t_max: f32) -> bool {
+    let mut near = f32::NEG_INFINITY;
+    let mut far = f32::INFINITY;
+    for axis in 0..3 {
+        let t0 = (box_min[axis] - ro[axis]) / rd[axis];
+        let t1 = (box_max[axis] - ro[axis]) / rd[axis];
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    far >= near.max(0.0) && near < t_max
+}
+
+fn hit_teapot(ro: Vector3<f32>, rd: Vector3<f32>, scene: &Scene, hit: &mut Option<Hit>) {
+    if !hit_box(ro, rd, scene.teapot_min, scene.teapot_max, hit.map_or(FAR, |h| h.t)) {
+        return;
+    }
+
+    for [v0, v1, v2] in scene.teapot.iter() {
+        let e1 = v1 - v0;
+        let e2 = v2 - v0;
+        let p = rd.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < 1e-9 {
+            continue;
+        }
+        let inv_det = 1.0 / det;
+        let s = ro - v0;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            continue;
+        }
+        let q = s.cross(e1);
+        let v = rd.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            continue;
+        }
+        let t = e2.dot(q) * inv_det;
+        if t > EPSILON && t < hit.map_or(FAR, |h| h.t) {
+            *hit = Some(Hit { t, normal: e1.cross(e2).normalize(), material: scene.teapot_material });
+        }
+    }
+}
+
+pub fn intersect(scene: &Scene, ro: Vector3<f32>, rd: Vector3<f32>) -> Option<Hit> {
+    let mut hit = None;
+    hit_plane(ro, rd, scene.ground_height, scene.ground_material, &mut hit);
+    for sphere in scene.spheres.iter() {
+        hit_sphere(ro, rd, sphere, &mut hit);
+    }
+    hit_teapot(ro, rd, scene, &mut hit);
+    hit
+}
+
+fn sky(rd: Vector3<f32>) -> Vector3<f32> {
+    let t = (rd.y * 0.5 + 0.5).clamp(0.0, 1.0);
+    vec3(0.01, 0.01, 0.015) * (1.0 - t) + vec3(0.03, 0.04, 0.1) * t
+}
+
+pub fn trace(scene: &Scene, mut ro: Vector3<f32>, mut rd: Vector3<f32>, rng: &mut Rng) -> Vector3<f32> {
+    let mut radiance = vec3(0.0, 0.0, 0.0);
+    let mut throughput = vec3(1.0, 1.0, 1.0);
+
+    for _ in 0..MAX_BOUNCES {
+        let hit = match intersect(scene, ro, rd) {
+            Some(hit) => hit,
+            None => {
+                radiance += throughput.mul_element_wise(sky(rd));
+                break;
+            }
+        };
+
+        let m = &scene.materials[hit.material];
+        let n = if hit.normal.dot(rd) < 0.0 { hit.normal } else { -hit.normal };
+
+        radiance += throughput.mul_element_wise(Vector3::from(m.emission));
+
+        ro = ro + rd * hit.t + n * EPSILON;
+        let diffuse_dir = (n + rng.unit_vector()).normalize();
+        rd = if rng.next_f32() < m.glossiness {
+            let reflected = rd - n * 2.0 * rd.dot(n);
+            let r2 = m.roughness * m.roughness;
+            (reflected * (1.0 - r2) + diffuse_dir * r2).normalize()
+        } else {
+            diffuse_dir
+        };
+        throughput = throughput.mul_element_wise(Vector3::from(m.albedo));
+    }
+
+    radiance
+}
+
+/// CPU version of the accumulation texture.
+pub struct Accumulator {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub pixels: Vec<Vector3<f32>>,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Self {
+        Accumulator { width, height, samples: 0, pixels: vec![vec3(0.0, 0.0, 0.0); (width * height) as usize] }
+    }
+
+    pub fn reset(&mut self) {
+        self.samples = 0;
+    }
+
+    /// Traces one sample per pixel and folds it into the running average, like one frame of the GPU version.
+    pub fn add_sample(&mut self, scene: &Scene, camera: &Camera) {
+        let weight = 1.0 / (self.samples + 1) as f32;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let sample = self.sample(scene, camera, x, y);
+                let pixel = &mut self.pixels[(y * self.width + x) as usize];
+                *pixel = *pixel * (1.0 - weight) + sample * weight;
+            }
+        }
+
+        self.samples += 1;
+    }
+
+    /// The radiance of the next sample of pixel (x, y), jittered inside the pixel and seeded by the sample count.
+    pub fn sample(&self, scene: &Scene, camera: &Camera, x: u32, y: u32) -> Vector3<f32> {
+        let aspect_ratio = self.width as f32 / self.height as f32;
+        let mut rng = Rng::new(x.wrapping_mul(1973) + y.wrapping_mul(9277) + self.samples.wrapping_mul(26699));
+        let ndc = [
+            (x as f32 + rng.next_f32()) / self.width as f32 * 2.0 - 1.0,
+            (y as f32 + rng.next_f32()) / self.height as f32 * 2.0 - 1.0,
+        ];
+        let sample = trace(scene, Vector3::from(camera.position), camera.ray_direction(ndc, aspect_ratio), &mut rng);
+        if sample.x.is_nan() || sample.y.is_nan() || sample.z.is_nan() {
+            vec3(0.0, 0.0, 0.0)
+        } else {
+            sample
+        }
+    }
+
+    /// Tone mapped, gamma corrected, top row first (rows are stored bottom-up like GL does).
+    pub fn to_image(&self) -> image::RgbImage {
+        image::RgbImage::from_fn(self.width, self.height, |x, y| {
+            let hdr = self.pixels[((self.height - 1 - y) * self.width + x) as usize];
+            let channel = |c: f32| ((c / (c + 1.0)).powf(1.0 / 2.2) * 255.0).round().clamp(0.0, 255.0) as u8;
+            image::Rgb([channel(hdr.x), channel(hdr.y), channel(hdr.z)])
+        })
+    }
+}
+
+pub fn render_cpu(scene: &Scene, camera: &Camera, width: u32, height: u32, samples: u32) -> image::RgbImage {
+    let mut accumulator = Accumulator::new(width, height);
+    for _ in 0..samples {
+        accumulator.add_sample(scene, camera);
+    }
+    accumulator.to_image()
+}
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new();
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let scene = default_scene();
+    let quad = glium::VertexBuffer::new(&display, &fullscreen_quad())?;
+    let teapot_texture = upload_teapot(&display, &scene)?;
+
+    let mut trace_program = HotProgram::with_preprocessor(
+        &display,
+        shader_file!("stage15.vert"),
+        shader_file!("stage15_trace.frag"),
+        false,
+        trace_preprocessor(&scene),
+    )?;
+    // we do the gamma correction ourselves, so tell glium not to convert to sRGB a second time.
+    let mut display_program = HotProgram::with_preprocessor(
+        &display,
+        shader_file!("stage15.vert"),
+        shader_file!("stage15_display.frag"),
+        true,
+        Preprocessor::new(),
+    )?;
+
+    let mut dimensions = display.get_framebuffer_dimensions();
+    let mut accumulation = accumulation_textures(&display, dimensions)?;
+    let mut current = 0;
+    let mut sample_index: i32 = 0;
+    let mut camera = Camera { fov: params.float("fov").to_radians(), ..Camera::default() };
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(16_666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Key(key, ElementState::Pressed) => {
+                if camera.handle_key(key) {
+                    sample_index = 0;
+                }
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        if trace_program.reload_if_changed(display) {
+            sample_index = 0;
+        }
+        display_program.reload_if_changed(display);
+
+        let frame_dimensions = display.get_framebuffer_dimensions();
+        if frame_dimensions != dimensions {
+            dimensions = frame_dimensions;
+            accumulation = accumulation_textures(display, dimensions)?;
+            sample_index = 0;
+        }
+
+        // ping-pong: read last frame's average from one texture, write the new one into the other.
+        let previous = &accumulation[current];
+        let next = &accumulation[1 - current];
+        let (right, up, forward) = camera.basis();
+        let (right, up, forward): ([f32; 3], [f32; 3], [f32; 3]) = (right.into(), up.into(), forward.into());
+        let (teapot_min, teapot_max): ([f32; 3], [f32; 3]) = (scene.teapot_min.into(), scene.teapot_max.into());
+
+        let uniforms = uniform! {
+            accumulation: previous.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
+            teapot_triangles: &teapot_texture,
+            triangle_count: scene.teapot.len() as i32,
+            teapot_min: teapot_min,
+            teapot_max: teapot_max,
+            camera_position: camera.position,
+            camera_right: right,
+            camera_up: up,
+            camera_forward: forward,
+            fov_scale: (camera.fov / 2.0).tan(),
+            resolution: [dimensions.0 as f32, dimensions.1 as f32],
+            sample_index: sample_index,
+        };
+
+        trace_program.check_bindings(&[quad.get_bindings()], &uniforms)?;
+
+        let mut target = frame.pass("accumulation", glium::framebuffer::SimpleFrameBuffer::new(display, next)?);
+        target.draw(
+            &quad,
+            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+            &trace_program,
+            &uniforms,
+            &Default::default(),
+        )?;
+
+        current = 1 - current;
+        sample_index += 1;
+
+        frame.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        let uniforms = uniform! { accumulation: &accumulation[current] };
+        display_program.check_bindings(&[quad.get_bindings()], &uniforms)?;
+
+        // Drawing the accumulated image!
+        frame.draw(
+            &quad,
+            glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip),
+            &display_program,
+            &uniforms,
+            &Default::default(),
+        )?;
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn teapot_center(scene: &Scene) -> Vector3<f32> {
+        (scene.teapot_min + scene.teapot_max) * 0.5
+    }
+
+    #[test]
+    fn ray_at_the_teapot_hits_it() {
+        let scene = default_scene();
+        let origin = Vector3::from(Camera::default().position);
+        let direction = (teapot_center(&scene) - origin).normalize();
+
+        let hit = intersect(&scene, origin, direction).expect("the ray should hit the teapot");
+        assert_eq!(hit.material, scene.teapot_material);
+        assert!(hit.t > 0.0 && hit.t < (teapot_center(&scene) - origin).magnitude());
+    }
+
+    #[test]
+    fn ray_away_from_the_scene_returns_the_sky() {
+        let scene = default_scene();
+        let origin = Vector3::from(Camera::default().position);
+        let direction = vec3(0.0, 0.2, -1.0).normalize();
+
+        assert!(intersect(&scene, origin, direction).is_none());
+        let mut rng = Rng::new(42);
+        assert_eq!(trace(&scene, origin, direction, &mut rng), sky(direction));
+    }
+
+    #[test]
+    fn accumulator_is_the_mean_of_its_samples() {
+        let scene = default_scene();
+        let camera = Camera::default();
+        let (width, height, samples) = (4, 3, 5);
+
+        let mut accumulator = Accumulator::new(width, height);
+        let mut sums = vec![vec3(0.0, 0.0, 0.0); (width * height) as usize];
+        for _ in 0..samples {
+            for y in 0..height {
+                for x in 0..width {
+                    sums[(y * width + x) as usize] += accumulator.sample(&scene, &camera, x, y);
+                }
+            }
+            accumulator.add_sample(&scene, &camera);
+        }
+
+        assert_eq!(accumulator.samples, samples);
+        for (pixel, sum) in accumulator.pixels.iter().zip(sums.iter()) {
+            let mean = sum / samples as f32;
+            assert!((pixel - mean).magnitude() <= 1e-4 * (1.0 + mean.magnitude()), "{:?} != {:?}", pixel, mean);
+        }
+    }
+
+    #[test]
+    fn reset_starts_a_new_average() {
+        let scene = default_scene();
+        let camera = Camera::default();
+
+        let mut accumulator = Accumulator::new(2, 2);
+        accumulator.add_sample(&scene, &camera);
+        let first = accumulator.pixels.clone();
+        accumulator.add_sample(&scene, &camera);
+        accumulator.reset();
+        accumulator.add_sample(&scene, &camera);
+
+        assert_eq!(accumulator.samples, 1);
+        assert_eq!(accumulator.pixels, first);
+    }
+
+    #[test]
+    fn render_cpu_is_reproducible() {
+        let scene = default_scene();
+        let camera = Camera::default();
+
+        let a = render_cpu(&scene, &camera, 8, 6, 2);
+        let b = render_cpu(&scene, &camera, 8, 6, 2);
+        assert_eq!((a.width(), a.height()), (8, 6));
+        assert_eq!(a.as_raw(), b.as_raw());
+    }
+}
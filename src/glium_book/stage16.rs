@@ -0,0 +1,197 @@
+#![allow(dead_code)]
+use std::f32::consts::TAU;
+
+use glium::{glutin, implement_vertex, uniform, Surface};
+
+use super::teapot;
+use crate::error::Result;
+use super::material::Material;
+use super::lights::{hue, Light, LightBuffer, MAX_LIGHTS};
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::inspector::Inspector;
+use super::material::Value;
+use super::math::{perspective_matrix, view_matrix};
+
+// Lots of lights instead of one direction: colored point lights circling the teapot, plus a spot light
+// from above to show the cone. Each light gets a little unlit marker so you can see where it is.
+// All the light math is in lights.glsl, the lights themselves go to the shader as a uniform block (lights.rs).
+
+#[derive(Copy, Clone)]
+struct MarkerVertex {
+    position: [f32; 3],
+}
+
+implement_vertex!(MarkerVertex, position);
+
+/// An octahedron, round enough for something this small.
+const MARKER_VERTICES: [MarkerVertex; 6] = [
+    MarkerVertex { position: [ 1.0,  0.0,  0.0] },
+    MarkerVertex { position: [-1.0,  0.0,  0.0] },
+    MarkerVertex { position: [ 0.0,  1.0,  0.0] },
+    MarkerVertex { position: [ 0.0, -1.0,  0.0] },
+    MarkerVertex { position: [ 0.0,  0.0,  1.0] },
+    MarkerVertex { position: [ 0.0,  0.0, -1.0] },
+];
+
+const MARKER_INDICES: [u16; 24] = [
+    0, 2, 4,  2, 1, 4,  1, 3, 4,  3, 0, 4,
+    2, 0, 5,  1, 2, 5,  3, 1, 5,  0, 3, 5,
+];
+
+/// The orbiting point lights at `time` seconds, every other one a little higher and going the other way.
+fn orbiting_lights(count: usize, center: [f32; 3], radius: f32, speed: f32, range: f32, intensity: f32, time: f32) -> Vec<Light> {
+    (0..count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let (direction, height) = if i % 2 == 0 { (1.0, 0.15) } else { (-1.0, -0.1) };
+            let angle = TAU * t + direction * speed * time;
+            let position = [center[0] + radius * angle.cos(), center[1] + height, center[2] + radius * angle.sin()];
+            Light::point(position, range).with_color(hue(t), intensity)
+        })
+        .collect()
+}
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.01, 0.01, 0.02, 1.0]),
+        params::float("model_scale", 0.01, "size of the model"),
+        params::float("light_count", 6.0, "number of orbiting point lights (the spot light takes one more slot)"),
+        params::float("orbit_radius", 0.75, "how far from the teapot the lights circle"),
+        params::float("orbit_speed", 0.6, "radians per second"),
+        params::float("light_range", 1.5, "distance at which a point light has faded out completely"),
+        params::float("light_intensity", 1.6, "brightness of each point light"),
+        params::float("spot_intensity", 1.2, "brightness of the spot light above the teapot, 0 turns it off"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let marker_positions = glium::VertexBuffer::new(&display, &MARKER_VERTICES)?;
+    let marker_indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &MARKER_INDICES)?;
+
+    // darker and less red than stage 13's, the lights bring the color.
+    let mut material = Material::lit(&display, [0.02, 0.02, 0.025], [0.8, 0.8, 0.8], [1.0, 1.0, 1.0], 32.0)?;
+    let mut marker = Material::unlit(&display, [1.0, 1.0, 1.0])?;
+    let mut lights = LightBuffer::new(&display)?;
+    let mut inspector = Inspector::new(&display, "stage16")?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let scale = params.float("model_scale");
+    let spot_intensity = params.float("spot_intensity");
+    let spot_slots = if spot_intensity > 0.0 { 1 } else { 0 };
+    let light_count = (params.float("light_count").max(0.0) as usize).min(MAX_LIGHTS - spot_slots);
+    let (radius, speed) = (params.float("orbit_radius"), params.float("orbit_speed"));
+    let (range, intensity) = (params.float("light_range"), params.float("light_intensity"));
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    // the teapot is about 0.8 high at this scale, the lights circle around its middle.
+    let center = [0.0, 0.35, 2.0];
+    let spot = Light::spot([0.0, 1.6, 2.0], [0.0, -1.0, 0.0], 0.25, 0.45, 2.5).with_color([1.0, 0.95, 0.85], spot_intensity);
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        if inspector.handle_event(&event) {
+            return Ok(());
+        }
+
+        let frame = match event {
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        material.reload_if_changed(display);
+        marker.reload_if_changed(display);
+
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
+
+        let model = [
+            [scale, 0.0, 0.0, 0.0],
+            [0.0, scale, 0.0, 0.0],
+            [0.0, 0.0, scale, 0.0],
+            [0.0, 0.0, 2.0, 1.0f32],
+        ];
+
+        let view = view_matrix(
+            &[0.0, 1.3, 0.2],
+            &[0.0, -0.75, 1.0],
+            &[0.0, 1.0, 0.0]
+        );
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+
+        let mut scene = orbiting_lights(light_count, center, radius, speed, range, intensity, runner::now().as_secs_f32());
+        if spot_slots > 0 {
+            scene.push(spot);
+        }
+        lights.upload(&scene, view);
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let uniforms = uniform! {
+            model: model,
+            view: view,
+            perspective: perspective,
+            Lights: lights.buffer()
+        };
+
+        material.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
+        let uniforms = material.uniforms(&uniforms);
+        let uniforms = inspector.tweak(material.program(), &uniforms);
+
+        frame.draw(
+            (&positions, &normals),
+            &indices,
+            material.program(),
+            &uniforms,
+            &params,
+        )?;
+
+        for light in &scene {
+            let position = match light.position() {
+                Some(position) => position,
+                None => continue,
+            };
+            let size = 0.03;
+            let model = [
+                [size, 0.0, 0.0, 0.0],
+                [0.0, size, 0.0, 0.0],
+                [0.0, 0.0, size, 0.0],
+                [position[0], position[1], position[2], 1.0f32],
+            ];
+            marker.set("base_color", Value::Vec3(light.color));
+
+            let uniforms = uniform! {
+                model: model,
+                view: view,
+                perspective: perspective
+            };
+            marker.check_bindings(&[marker_positions.get_bindings()], &uniforms)?;
+            frame.draw(&marker_positions, &marker_indices, marker.program(), &marker.uniforms(&uniforms), &params)?;
+        }
+
+        inspector.draw(display, frame)
+    })
+}
@@ -0,0 +1,198 @@
+#![allow(dead_code)]
+use glium::{glutin::{
+    self,
+    event::{ElementState, VirtualKeyCode},
+}, implement_vertex, uniform, Rect, Surface};
+
+use super::teapot;
+use crate::error::Result;
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::hot_reload::{HotProgram, shader_file};
+use super::shadows::{self, ShadowMap};
+use super::math::{perspective_matrix, view_matrix};
+use super::stage15::fullscreen_quad;
+
+// Shadows! The teapot on a floor, lit by a directional light slowly circling it. Every frame first draws
+// the scene's depth as the light sees it into a ShadowMap (shadows.rs), then draws it for real, looking
+// up in the map whether each fragment is the nearest thing to the light. V shows the shadow map.
+
+#[derive(Copy, Clone)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+implement_vertex!(Vertex, position, normal);
+
+/// A square floor of `size` centered on `center`, facing up.
+pub fn ground(center: [f32; 3], size: f32) -> [Vertex; 4] {
+    let [x, y, z] = center;
+    let h = size / 2.0;
+    let normal = [0.0, 1.0, 0.0];
+    [
+        Vertex { position: [x - h, y, z + h], normal },
+        Vertex { position: [x + h, y, z + h], normal },
+        Vertex { position: [x - h, y, z - h], normal },
+        Vertex { position: [x + h, y, z - h], normal },
+    ]
+}
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.06, 0.075, 0.95, 1.0]),
+        params::float("model_scale", 0.01, "size of the model"),
+        params::vec3("light", [-0.8, 1.4, 0.6], "direction towards the light, it turns around the up axis"),
+        params::float("light_speed", 0.3, "radians per second the light turns around the teapot, 0 holds it still"),
+        params::float("shadow_map_size", 2048.0, "width and height of the shadow map in texels"),
+        params::float("pcf_radius", 1.0, "shadow edge softening, (2r+1)^2 lookups per fragment, 0 is a single one"),
+        params::float("slope_bias", 2.0, "depth pass polygon offset factor, more for surfaces tilted away from the light"),
+        params::float("constant_bias", 2.0, "depth pass polygon offset units"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    // the teapot's bottom is at y = 0 at every scale.
+    let center = [0.0, 0.0, 2.0];
+    let floor = glium::VertexBuffer::new(&display, &ground(center, 4.0))?;
+    let strip = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+    let quad = glium::VertexBuffer::new(&display, &fullscreen_quad())?;
+
+    let mut depth_program = HotProgram::new(&display, shader_file!("shadow_depth.vert"), shader_file!("shadow_depth.frag"))?;
+    let mut program = HotProgram::new(&display, shader_file!("stage17.vert"), shader_file!("stage17.frag"))?;
+    let mut debug_program = HotProgram::new(&display, shader_file!("stage15.vert"), shader_file!("shadow_debug.frag"))?;
+
+    let shadow_map = ShadowMap::new(&display, params.float("shadow_map_size").max(1.0) as u32)?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let scale = params.float("model_scale");
+    let light = params.vec3("light");
+    let light_speed = params.float("light_speed");
+    let pcf_radius = params.float("pcf_radius").max(0.0) as i32;
+    let depth_parameters = shadows::depth_pass_parameters(params.float("slope_bias"), params.float("constant_bias"));
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    let mut show_shadow_map = false;
+    log::info!("V shows the shadow map");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Key(VirtualKeyCode::V, ElementState::Pressed) => {
+                show_shadow_map = !show_shadow_map;
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        depth_program.reload_if_changed(display);
+        program.reload_if_changed(display);
+        debug_program.reload_if_changed(display);
+
+        let model = [
+            [scale, 0.0, 0.0, 0.0],
+            [0.0, scale, 0.0, 0.0],
+            [0.0, 0.0, scale, 0.0],
+            [center[0], center[1], center[2], 1.0f32],
+        ];
+        let identity = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0f32],
+        ];
+
+        // the light turns around the up axis.
+        let angle = light_speed * runner::now().as_secs_f32();
+        let (sin, cos) = angle.sin_cos();
+        let light = [light[0] * cos - light[2] * sin, light[1], light[0] * sin + light[2] * cos];
+        // the floor's corners are the farthest anything gets from the center.
+        let light_space = shadows::directional_light_matrix([-light[0], -light[1], -light[2]], center, 2.9);
+
+        // pass 1: depth from the light.
+        let mut target = frame.pass("shadow-map", shadow_map.framebuffer(display)?);
+        target.clear_depth(1.0);
+        let uniforms = uniform! { light_space: light_space, model: model };
+        depth_program.check_bindings(&[positions.get_bindings()], &uniforms)?;
+        target.draw(&positions, &indices, &depth_program, &uniforms, &depth_parameters)?;
+        let uniforms = uniform! { light_space: light_space, model: identity };
+        target.draw(&floor, strip, &depth_program, &uniforms, &depth_parameters)?;
+
+        // pass 2: the scene, with the shadow map.
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
+
+        let view = view_matrix(
+            &[1.2, 1.3, 0.2],
+            &[-1.2, -1.0, 1.8],
+            &[0.0, 1.0, 0.0]
+        );
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let uniforms = uniform! {
+            model: model,
+            view: view,
+            perspective: perspective,
+            light_space: light_space,
+            u_light: light,
+            base_color: [0.95f32, 0.01, 0.06],
+            shadow_map: shadow_map.sampled(),
+            pcf_radius: pcf_radius
+        };
+        program.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
+        frame.draw((&positions, &normals), &indices, &program, &uniforms, &params)?;
+
+        let uniforms = uniform! {
+            model: identity,
+            view: view,
+            perspective: perspective,
+            light_space: light_space,
+            u_light: light,
+            base_color: [0.7f32, 0.7, 0.68],
+            shadow_map: shadow_map.sampled(),
+            pcf_radius: pcf_radius
+        };
+        frame.draw(&floor, strip, &program, &uniforms, &params)?;
+
+        if show_shadow_map {
+            // bottom left corner, a third of the window's height.
+            let (_, height) = frame.get_dimensions();
+            let side = height / 3;
+            let params = glium::DrawParameters {
+                viewport: Some(Rect { left: 0, bottom: 0, width: side, height: side }),
+                ..Default::default()
+            };
+            let uniforms = uniform! { shadow_map: shadow_map.raw() };
+            debug_program.check_bindings(&[quad.get_bindings()], &uniforms)?;
+            frame.draw(&quad, strip, &debug_program, &uniforms, &params)?;
+        }
+
+        Ok(())
+    })
+}
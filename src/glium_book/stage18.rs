@@ -0,0 +1,201 @@
+#![allow(dead_code)]
+use glium::{glutin::{
+    self,
+    event::{ElementState, VirtualKeyCode},
+}, uniform, Surface};
+
+use super::teapot;
+use crate::error::Result;
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::hot_reload::{HotProgram, shader_file};
+use super::preprocess::Preprocessor;
+use super::shadows::{self, CascadedShadowMap, Cascades, MAX_CASCADES};
+use super::math::{perspective_matrix, view_matrix};
+use super::stage17::ground;
+
+// Cascaded shadow maps: a whole field of teapots, far too big for stage 17's single shadow map to look
+// any good up close. The view frustum gets cut into up to MAX_CASCADES slices (`split_lambda` decides
+// where), each gets its own layer of the shadow map texture array, and the shader picks the layer by how
+// far away the fragment is (cascades.glsl). The camera drifts sideways so you can see the shadows hold
+// still. C colors every fragment by the cascade it took its shadow from.
+
+/// Rows of teapots going off into the distance, each turned a bit differently.
+pub fn teapot_field(scale: f32, columns: usize, rows: usize, spacing: f32) -> Vec<[[f32; 4]; 4]> {
+    (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            let x = (column as f32 - (columns - 1) as f32 / 2.0) * spacing;
+            let z = 2.0 + row as f32 * spacing;
+            let (sin, cos) = ((row * columns + column) as f32 * 0.9).sin_cos();
+            [
+                [scale * cos, 0.0, -scale * sin, 0.0],
+                [0.0, scale, 0.0, 0.0],
+                [scale * sin, 0.0, scale * cos, 0.0],
+                [x, 0.0, z, 1.0f32],
+            ]
+        })
+        .collect()
+}
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.06, 0.075, 0.95, 1.0]),
+        params::float("model_scale", 0.01, "size of the models"),
+        params::vec3("light", [-0.6, 1.0, -0.5], "direction towards the light"),
+        params::float("cascades", 4.0, "how many slices the view frustum gets cut into, 1 to 4"),
+        params::float("split_lambda", 0.75, "0 cuts the slices evenly long, 1 makes each one longer by the same factor"),
+        params::float("cascade_blend", 0.1, "part of each slice, at its far end, that fades into the next one"),
+        params::float("shadow_distance", 40.0, "how far from the camera there are shadows at all"),
+        params::float("shadow_map_size", 2048.0, "width and height of each cascade's shadow map in texels"),
+        params::float("pcf_radius", 1.0, "shadow edge softening, (2r+1)^2 lookups per fragment, 0 is a single one"),
+        params::float("slope_bias", 2.0, "depth pass polygon offset factor, more for surfaces tilted away from the light"),
+        params::float("constant_bias", 2.0, "depth pass polygon offset units"),
+        params::float("camera_speed", 0.2, "how fast the camera drifts from side to side, 0 holds it still"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let models = teapot_field(params.float("model_scale"), 5, 12, 3.0);
+    let floor = glium::VertexBuffer::new(&display, &ground([0.0, 0.0, 18.0], 80.0))?;
+    let strip = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+    let identity = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0f32],
+    ];
+
+    let mut depth_program = HotProgram::new(&display, shader_file!("shadow_depth.vert"), shader_file!("shadow_depth.frag"))?;
+    let mut program = HotProgram::with_preprocessor(
+        &display,
+        shader_file!("stage18.vert"),
+        shader_file!("stage18.frag"),
+        false,
+        Preprocessor::new().define("MAX_CASCADES", MAX_CASCADES),
+    )?;
+
+    let size = params.float("shadow_map_size").max(1.0) as u32;
+    let shadow_maps = CascadedShadowMap::new(&display, size, params.float("cascades").max(1.0) as usize)?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let light = params.vec3("light");
+    let light_direction = [-light[0], -light[1], -light[2]];
+    let lambda = params.float("split_lambda").clamp(0.0, 1.0);
+    let blend = params.float("cascade_blend").clamp(0.0, 1.0);
+    let shadow_distance = params.float("shadow_distance");
+    let pcf_radius = params.float("pcf_radius").max(0.0) as i32;
+    let depth_parameters = shadows::depth_pass_parameters(params.float("slope_bias"), params.float("constant_bias"));
+    let camera_speed = params.float("camera_speed");
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+    // the teapots are less than a unit high, anything further towards the light than this casts nothing.
+    let depth_margin = 10.0;
+
+    let mut show_cascades = false;
+    log::info!("C colors the scene by cascade: red, green, blue, yellow from near to far");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Key(VirtualKeyCode::C, ElementState::Pressed) => {
+                show_cascades = !show_cascades;
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        depth_program.reload_if_changed(display);
+        program.reload_if_changed(display);
+
+        let drift = (camera_speed * runner::now().as_secs_f32()).sin() * 4.0;
+        let view = view_matrix(
+            &[drift, 2.0, -2.0],
+            &[0.0, -0.35, 1.0],
+            &[0.0, 1.0, 0.0]
+        );
+        let (width, height) = frame.get_dimensions();
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+
+        // fit every cascade around its slice of what the camera sees.
+        let splits = shadows::cascade_splits(shadow_maps.cascades(), znear, shadow_distance.min(zfar), lambda);
+        let mut cascades = Cascades::new(blend);
+        for slice in splits.windows(2) {
+            let (center, radius) = shadows::frustum_slice_sphere(view, fov, width as f32 / height as f32, slice[0], slice[1]);
+            cascades.push(shadows::stable_light_matrix(light_direction, center, radius, depth_margin, size), slice[1]);
+        }
+
+        // pass 1: depth from the light, once per cascade.
+        for cascade in 0..cascades.len() {
+            let mut target = frame.pass(&format!("cascade-{}", cascade), shadow_maps.framebuffer(display, cascade)?);
+            target.clear_depth(1.0);
+            let light_space = cascades.matrix(cascade);
+            for model in &models {
+                let uniforms = uniform! { light_space: light_space, model: *model };
+                depth_program.check_bindings(&[positions.get_bindings()], &uniforms)?;
+                target.draw(&positions, &indices, &depth_program, &uniforms, &depth_parameters)?;
+            }
+            let uniforms = uniform! { light_space: light_space, model: identity };
+            target.draw(&floor, strip, &depth_program, &uniforms, &depth_parameters)?;
+        }
+
+        // pass 2: the scene.
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for model in &models {
+            let uniforms = uniform! {
+                model: *model,
+                view: view,
+                perspective: perspective,
+                u_light: light,
+                base_color: [0.95f32, 0.01, 0.06],
+                shadow_maps: shadow_maps.sampled(),
+                pcf_radius: pcf_radius,
+                show_cascades: show_cascades
+            };
+            let uniforms = cascades.uniforms(&uniforms);
+            program.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
+            frame.draw((&positions, &normals), &indices, &program, &uniforms, &params)?;
+        }
+
+        let uniforms = uniform! {
+            model: identity,
+            view: view,
+            perspective: perspective,
+            u_light: light,
+            base_color: [0.7f32, 0.7, 0.68],
+            shadow_maps: shadow_maps.sampled(),
+            pcf_radius: pcf_radius,
+            show_cascades: show_cascades
+        };
+        frame.draw(&floor, strip, &program, &cascades.uniforms(&uniforms), &params)?;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,255 @@
+#![allow(dead_code)]
+use glium::{glutin, implement_vertex, uniform, Surface};
+
+use super::teapot;
+use crate::error::Result;
+use super::material::Value;
+use super::material::Material;
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::hot_reload::{HotProgram, shader_file};
+use super::shadows::PointShadowMap;
+use super::math::{perspective_matrix, view_matrix};
+
+// Point light shadows: a light bulb wandering around a room full of boxes, with the teapot in the middle.
+// The bulb's shadow map is a cubemap of distances (shadows.rs), drawn face by face every frame because the
+// bulb keeps moving. The shadows get softer with distance from the camera, see stage19.frag.
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+implement_vertex!(Vertex, position, normal);
+
+/// A box as a triangle list, `size` is the full width, height and depth. `inward` turns the normals (and
+/// so the lit side) inside, for walls.
+fn cuboid(center: [f32; 3], size: [f32; 3], inward: bool) -> Vec<Vertex> {
+    let half = [size[0] / 2.0, size[1] / 2.0, size[2] / 2.0];
+    let sign = if inward { -1.0 } else { 1.0 };
+    let mut vertices = Vec::with_capacity(36);
+
+    // each face: the axis it faces along and the two it spans.
+    for (axis, a, b) in [(0, 1, 2), (1, 2, 0), (2, 0, 1)] {
+        for side in [-1.0f32, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = side * sign;
+            let corner = |u: f32, v: f32| {
+                let mut position = center;
+                position[axis] += side * half[axis];
+                position[a] += u * half[a];
+                position[b] += v * half[b];
+                Vertex { position, normal }
+            };
+            let (c0, c1, c2, c3) = (corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0));
+            vertices.extend([c0, c1, c2, c0, c2, c3]);
+        }
+    }
+
+    vertices
+}
+
+/// The room and everything standing in it, the teapot goes in the middle of the floor.
+fn room(center: [f32; 3]) -> Vec<Vertex> {
+    let [x, y, z] = center;
+    let mut vertices = cuboid([x, y + 1.5, z], [8.0, 3.0, 8.0], true);
+    vertices.extend(cuboid([x + 1.5, y + 0.4, z + 1.0], [0.8, 0.8, 0.8], false));
+    vertices.extend(cuboid([x - 1.6, y + 0.6, z - 1.0], [0.6, 1.2, 0.6], false));
+    vertices.extend(cuboid([x - 1.0, y + 1.5, z + 1.8], [0.4, 3.0, 0.4], false));
+    vertices.extend(cuboid([x + 1.2, y + 0.25, z - 1.4], [0.5, 0.5, 0.5], false));
+    vertices.extend(cuboid([x + 2.8, y + 1.0, z - 2.8], [0.3, 2.0, 0.3], false));
+    vertices
+}
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.0, 0.0, 0.0, 1.0]),
+        params::float("model_scale", 0.01, "size of the model"),
+        params::vec3("light_color", [1.0, 0.85, 0.6], "color of the bulb, times its brightness"),
+        params::float("light_intensity", 6.0, "brightness of the bulb"),
+        params::float("light_range", 9.0, "distance at which the bulb's light has faded out completely"),
+        params::float("light_speed", 0.5, "how fast the bulb wanders around, 0 holds it still"),
+        params::float("shadow_map_size", 1024.0, "width and height of each cube face in texels"),
+        params::float("shadow_bias", 0.03, "distance (world units) a surface has to be behind the nearest one to be in its shadow"),
+        params::float("shadow_softness", 0.02, "how far (world units) around the direction the shadow lookups spread"),
+        params::float("fov", 75.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let center = [0.0, 0.0, 2.0];
+    let room = glium::VertexBuffer::new(&display, &room(center))?;
+    let bulb = glium::VertexBuffer::new(&display, &cuboid([0.0; 3], [1.0; 3], false))?;
+    let triangles = glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList);
+
+    let mut depth_program = HotProgram::new(&display, shader_file!("point_shadow_depth.vert"), shader_file!("point_shadow_depth.frag"))?;
+    let mut program = HotProgram::new(&display, shader_file!("stage19.vert"), shader_file!("stage19.frag"))?;
+    let mut bulb_material = Material::unlit(&display, [1.0; 3])?;
+
+    // the room's diagonal, nothing in it is further from the bulb than that.
+    let shadow_map = PointShadowMap::new(&display, params.float("shadow_map_size").max(1.0) as u32, 12.0)?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let scale = params.float("model_scale");
+    let [lr, lg, lb] = params.vec3("light_color");
+    let intensity = params.float("light_intensity");
+    let light_color = [lr * intensity, lg * intensity, lb * intensity];
+    let light_range = params.float("light_range");
+    let light_speed = params.float("light_speed");
+    let shadow_bias = params.float("shadow_bias");
+    let shadow_softness = params.float("shadow_softness");
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+    bulb_material.set("base_color", Value::Vec3([lr, lg, lb]));
+
+    let model = [
+        [scale, 0.0, 0.0, 0.0],
+        [0.0, scale, 0.0, 0.0],
+        [0.0, 0.0, scale, 0.0],
+        [center[0], center[1], center[2], 1.0f32],
+    ];
+    let identity = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0f32],
+    ];
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        depth_program.reload_if_changed(display);
+        program.reload_if_changed(display);
+        bulb_material.reload_if_changed(display);
+
+        // wanders around the teapot, never quite repeating.
+        let t = light_speed * runner::now().as_secs_f32();
+        let light_position = [
+            center[0] + 2.2 * (0.7 * t).sin(),
+            center[1] + 1.3 + 0.6 * (1.3 * t).sin(),
+            center[2] + 2.2 * (0.5 * t).cos(),
+        ];
+
+        // pass 1: the distances, one cube face at a time.
+        let depth_parameters = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for (face, face_matrix) in shadow_map.face_matrices(light_position) {
+            let mut target = frame.pass(&format!("shadow-{:?}", face), shadow_map.framebuffer(display, face)?);
+            target.clear_depth(1.0);
+
+            let uniforms = uniform! {
+                face_matrix: face_matrix,
+                model: model,
+                light_position: light_position,
+                far: shadow_map.far()
+            };
+            depth_program.check_bindings(&[positions.get_bindings()], &uniforms)?;
+            target.draw(&positions, &indices, &depth_program, &uniforms, &depth_parameters)?;
+
+            let uniforms = uniform! {
+                face_matrix: face_matrix,
+                model: identity,
+                light_position: light_position,
+                far: shadow_map.far()
+            };
+            target.draw(&room, triangles, &depth_program, &uniforms, &depth_parameters)?;
+        }
+
+        // pass 2: the room.
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
+
+        let camera_position = [center[0] + 3.4, center[1] + 2.4, center[2] - 3.4];
+        let view = view_matrix(
+            &camera_position,
+            &[-3.4, -1.9, 3.4],
+            &[0.0, 1.0, 0.0]
+        );
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let uniforms = uniform! {
+            model: model,
+            view: view,
+            perspective: perspective,
+            camera_position: camera_position,
+            light_position: light_position,
+            light_color: light_color,
+            light_range: light_range,
+            base_color: [0.95f32, 0.01, 0.06],
+            shadow_cube: shadow_map.sampled(),
+            far: shadow_map.far(),
+            shadow_bias: shadow_bias,
+            shadow_softness: shadow_softness
+        };
+        program.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
+        frame.draw((&positions, &normals), &indices, &program, &uniforms, &params)?;
+
+        let uniforms = uniform! {
+            model: identity,
+            view: view,
+            perspective: perspective,
+            camera_position: camera_position,
+            light_position: light_position,
+            light_color: light_color,
+            light_range: light_range,
+            base_color: [0.75f32, 0.72, 0.68],
+            shadow_cube: shadow_map.sampled(),
+            far: shadow_map.far(),
+            shadow_bias: shadow_bias,
+            shadow_softness: shadow_softness
+        };
+        frame.draw(&room, triangles, &program, &uniforms, &params)?;
+
+        // the bulb itself, not part of the depth pass or it would shadow everything.
+        let size = 0.08;
+        let bulb_model = [
+            [size, 0.0, 0.0, 0.0],
+            [0.0, size, 0.0, 0.0],
+            [0.0, 0.0, size, 0.0],
+            [light_position[0], light_position[1], light_position[2], 1.0f32],
+        ];
+        let uniforms = uniform! {
+            model: bulb_model,
+            view: view,
+            perspective: perspective
+        };
+        bulb_material.check_bindings(&[bulb.get_bindings()], &uniforms)?;
+        frame.draw(&bulb, triangles, bulb_material.program(), &bulb_material.uniforms(&uniforms), &params)?;
+
+        Ok(())
+    })
+}
@@ -10,10 +10,15 @@ use glium::{
     implement_vertex,
     Display,
     backend::Facade,
-    Program,
     Surface,
 };
 
+use crate::error::Result;
+use super::hot_reload::{HotProgram, shader_file};
+use super::runner::{run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+
 #[derive(Copy, Clone)]
 pub struct Vertex {
     pub position: [f32; 2],
@@ -28,9 +33,9 @@ pub fn first_triangle() -> [Vertex; 3] {
     [vertex1, vertex2, vertex3]
 }
 
-pub fn buffer_a_shape(display: &Display, shape: &[Vertex]) -> glium::vertex::VertexBuffer<Vertex> {
+pub fn buffer_a_shape(display: &Display, shape: &[Vertex]) -> Result<glium::vertex::VertexBuffer<Vertex>> {
     // Takes a CPU-memory stored shape and uploads it to the video card memory.
-    glium::vertex::VertexBuffer::new(display as &dyn Facade, shape).unwrap()
+    Ok(glium::vertex::VertexBuffer::new(display as &dyn Facade, shape)?)
 }
 
 pub fn dummy_marker() -> glium::index::NoIndices{
@@ -38,80 +43,48 @@ pub fn dummy_marker() -> glium::index::NoIndices{
     glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList)
 }
 
-pub fn vertex_shader_src() -> &'static str {
-    r#"
-        #version 140
-
-        in vec2 position; // vec2 = [f32; 2] in Rust, name only required to match struct data field, name in-and-of-itself doesn't matter.
-
-        void main() {
-            gl_Position = vec4(position, 0.0, 1.0); // coordinates are actually 4D, x/y/z/? is this for quarternion-ing?
-        }
-    "#
-}
-
-pub fn fragment_shader_src() -> &'static str {
-    // aka. pixel shader
-    r#"
-        #version 140
-
-        out vec4 color;
-
-        void main() {
-            color = vec4(0.9, 0.15, 0.1, 1.0);
-        }
-    "#
-}
-
-pub fn the_stage2_program(display: &Display) -> Program {
-    Program::from_source(display as &dyn Facade, vertex_shader_src(), fragment_shader_src(), None).unwrap()
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.1, 0.1, 0.9, 1.0]),
+    ]
 }
 
-pub fn run() {
+pub fn run(params: &Params) -> Result<()> {
     let event_loop = glutin::event_loop::EventLoop::new();
     let window_builder = glutin::window::WindowBuilder::new();
     let context_builder = glutin::ContextBuilder::new();
-    let display = glium::Display::new(
+    let display = create_display(
         window_builder,
         context_builder,
         &event_loop
-    ).unwrap();
+    )?;
+
+    let mut program = HotProgram::new(&display, shader_file!("stage2.vert"), shader_file!("stage2.frag"))?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(16_666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
 
-    event_loop.run(move |event, _, control_flow| {
-        let mut frame = display.draw();
-        frame.clear_color(0.1, 0.1, 0.9, 1.0);
+        program.reload_if_changed(display);
+
+        frame.clear_color(r, g, b, a);
+
+        let shape = buffer_a_shape(display, &first_triangle()[..])?;
+        program.check_bindings(&[shape.get_bindings()], &glium::uniforms::EmptyUniforms)?;
 
         // Drawing the Triangle!
         frame.draw(
-            &buffer_a_shape(&display, &first_triangle()[..]),
+            &shape,
             dummy_marker(),
-            &the_stage2_program(&display),
+            &program,
             &glium::uniforms::EmptyUniforms,
             &Default::default()
-        ).unwrap();
-
-        frame.finish().unwrap();
-
-        let next_frame_time = std::time::Instant::now() + std::time::Duration::from_nanos(16_666_667);
-
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
-
-        match event {
-            glutin::event::Event::WindowEvent { event, .. } => match event {
-                glutin::event::WindowEvent::CloseRequested => {
-                    *control_flow = glutin::event_loop::ControlFlow::Exit;
-                    return;
-                },
-                glutin::event::WindowEvent::KeyboardInput {
-                    input: KeyboardInput { virtual_keycode: Some(virtual_code), state, .. },
-                    ..
-                } => match (virtual_code, state) {
-                    (VirtualKeyCode::Escape, _) => *control_flow = glutin::event_loop::ControlFlow::Exit,
-                    _ => return,
-                },
-                _ => return,
-            },
-            _ => (),
-        }
-    });
-}
\ No newline at end of file
+        )?;
+
+        Ok(())
+    })
+}
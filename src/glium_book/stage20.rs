@@ -0,0 +1,352 @@
+#![allow(dead_code)]
+use std::f32::consts::{PI, TAU};
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+use glium::{glutin::{
+    self,
+    event::{ElementState, VirtualKeyCode},
+}, implement_vertex, uniform, Display, Surface};
+use glium::draw_parameters::{BackfaceCullingMode, BlendingFunction, LinearBlendingFactor};
+use glium::framebuffer::MultiOutputFrameBuffer;
+use glium::texture::{DepthFormat, DepthTexture2d, MipmapsOption, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, UniformValue, Uniforms};
+
+use super::teapot;
+use crate::error::{Error, Result};
+use super::lights;
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::hot_reload::{HotProgram, shader_file};
+use super::math::{perspective_matrix, view_matrix};
+use super::stage15::fullscreen_quad;
+use super::stage17::ground;
+use super::stage18::teapot_field;
+
+// Deferred shading: stage 16 runs every light for every fragment of every object, which stops being fun
+// somewhere around a dozen lights. Here the scene is drawn once into a G-buffer (color, normal, material,
+// depth, no lighting at all), and then every light draws a sphere as big as its reach, lighting only the
+// pixels under it from what the G-buffer says is there. Hundreds of lights, each only costing the pixels
+// it can actually touch. G cycles through the G-buffer's textures.
+
+#[derive(Copy, Clone)]
+pub struct SphereVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
+implement_vertex!(SphereVertex, position, normal, tex_coords);
+
+/// A unit sphere, `stacks` rings from pole to pole and `slices` around, with the texture wrapped around once.
+/// Indexed triangles, every one wound the same way seen from outside.
+pub fn sphere(stacks: u16, slices: u16) -> (Vec<SphereVertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity(((stacks + 1) * (slices + 1)) as usize);
+    for stack in 0..=stacks {
+        let theta = PI * stack as f32 / stacks as f32;
+        for slice in 0..=slices {
+            let phi = TAU * slice as f32 / slices as f32;
+            let position = [theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()];
+            vertices.push(SphereVertex {
+                position,
+                normal: position,
+                tex_coords: [slice as f32 / slices as f32, 1.0 - stack as f32 / stacks as f32],
+            });
+        }
+    }
+
+    let mut indices = Vec::with_capacity((stacks * slices * 6) as usize);
+    let at = |stack: u16, slice: u16| stack * (slices + 1) + slice;
+    for stack in 0..stacks {
+        for slice in 0..slices {
+            // a is above b, d is round from a the way phi goes, c from b.
+            let (a, b, c, d) = (at(stack, slice), at(stack + 1, slice), at(stack + 1, slice + 1), at(stack, slice + 1));
+            indices.extend([a, c, b, a, d, c]);
+        }
+    }
+    (vertices, indices)
+}
+
+/// One light volume, per instance.
+#[derive(Copy, Clone)]
+struct LightInstance {
+    light_position: [f32; 3],
+    light_color: [f32; 3],
+    light_range: f32,
+}
+
+implement_vertex!(LightInstance, light_position, light_color, light_range);
+
+/// What the geometry pass writes and the lighting passes read, as big as the window.
+struct GBuffer {
+    albedo: Texture2d,
+    normal: Texture2d,
+    material: Texture2d,
+    depth: DepthTexture2d,
+}
+
+impl GBuffer {
+    fn new(display: &Display, (width, height): (u32, u32)) -> Result<Self> {
+        let color = |format| Texture2d::empty_with_format(display, format, MipmapsOption::NoMipmap, width, height);
+        Ok(GBuffer {
+            albedo: color(UncompressedFloatFormat::U8U8U8U8)?,
+            // normals go negative, so not 8 bit unsigned.
+            normal: color(UncompressedFloatFormat::F16F16F16F16)?,
+            material: color(UncompressedFloatFormat::U8U8U8U8)?,
+            depth: DepthTexture2d::empty_with_format(display, DepthFormat::F32, MipmapsOption::NoMipmap, width, height)?,
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.albedo.dimensions()
+    }
+
+    /// The outputs of stage20_gbuffer.frag, by name.
+    fn framebuffer<'t>(&'t self, display: &Display) -> Result<MultiOutputFrameBuffer<'t>> {
+        let outputs = [("albedo", &self.albedo), ("normal", &self.normal), ("material", &self.material)];
+        Ok(MultiOutputFrameBuffer::with_depth_buffer(display, outputs, &self.depth)?)
+    }
+
+    /// The textures as gbuffer.glsl wants them, then everything in `extra`.
+    fn uniforms<'t, U: Uniforms>(&'t self, inverse_perspective: [[f32; 4]; 4], extra: &'t U) -> GBufferUniforms<'t, U> {
+        GBufferUniforms { gbuffer: self, inverse_perspective, extra }
+    }
+}
+
+struct GBufferUniforms<'t, U> {
+    gbuffer: &'t GBuffer,
+    inverse_perspective: [[f32; 4]; 4],
+    extra: &'t U,
+}
+
+impl<'t, U: Uniforms> Uniforms for GBufferUniforms<'t, U> {
+    fn visit_values<'a, F: FnMut(&str, UniformValue<'a>)>(&'a self, mut f: F) {
+        // one texel per pixel, nothing to filter.
+        let nearest = Some(SamplerBehavior {
+            minify_filter: MinifySamplerFilter::Nearest,
+            magnify_filter: MagnifySamplerFilter::Nearest,
+            ..Default::default()
+        });
+        f("albedo_tex", UniformValue::Texture2d(&self.gbuffer.albedo, nearest));
+        f("normal_tex", UniformValue::Texture2d(&self.gbuffer.normal, nearest));
+        f("material_tex", UniformValue::Texture2d(&self.gbuffer.material, nearest));
+        f("depth_tex", UniformValue::DepthTexture2d(&self.gbuffer.depth, nearest));
+        f("inverse_perspective", UniformValue::Mat4(self.inverse_perspective));
+        self.extra.visit_values(f);
+    }
+}
+
+/// What's on screen, G goes to the next one.
+const VIEWS: [&str; 5] = ["lit", "albedo", "normals", "material", "depth"];
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.0, 0.0, 0.01, 1.0]),
+        params::float("model_scale", 0.01, "size of the models"),
+        params::float("light_count", 300.0, "number of point lights flying around"),
+        params::float("light_range", 2.5, "distance at which a point light has faded out completely"),
+        params::float("light_intensity", 1.5, "brightness of each point light"),
+        params::vec3("ambient", [0.02, 0.02, 0.03], "light every surface gets, whatever the lights do"),
+        params::vec3("moon_light", [0.05, 0.06, 0.1], "color of the dim directional light"),
+        params::vec3("light", [-0.4, 1.0, 0.3], "direction towards the dim directional light"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new();
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let models = teapot_field(params.float("model_scale"), 5, 12, 3.0);
+    let floor = glium::VertexBuffer::new(&display, &ground([0.0, 0.0, 18.0], 80.0))?;
+    let strip = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+    let quad = glium::VertexBuffer::new(&display, &fullscreen_quad())?;
+    let (volume, volume_indices) = sphere(8, 12);
+    let volume = glium::VertexBuffer::new(&display, &volume)?;
+    let volume_indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &volume_indices)?;
+    let identity = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0f32],
+    ];
+
+    let light_count = params.float("light_count").max(0.0) as usize;
+    let (range, intensity) = (params.float("light_range"), params.float("light_intensity"));
+    let instances = glium::VertexBuffer::dynamic(&display, &vec![LightInstance {
+        light_position: [0.0; 3],
+        light_color: [0.0; 3],
+        light_range: 0.0,
+    }; light_count])?;
+
+    let mut gbuffer_program = HotProgram::new(&display, shader_file!("stage20_gbuffer.vert"), shader_file!("stage20_gbuffer.frag"))?;
+    let mut ambient_program = HotProgram::new(&display, shader_file!("stage15.vert"), shader_file!("stage20_ambient.frag"))?;
+    let mut light_program = HotProgram::new(&display, shader_file!("stage20_light.vert"), shader_file!("stage20_light.frag"))?;
+    let mut debug_program = HotProgram::new(&display, shader_file!("stage15.vert"), shader_file!("stage20_debug.frag"))?;
+
+    let mut gbuffer = GBuffer::new(&display, display.get_framebuffer_dimensions())?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let ambient = params.vec3("ambient");
+    let moon_light = params.vec3("moon_light");
+    let light = params.vec3("light");
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    let mut view_index = 0;
+    log::info!("G cycles through the views: {}", VIEWS.join(", "));
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Key(VirtualKeyCode::G, ElementState::Pressed) => {
+                view_index = (view_index + 1) % VIEWS.len();
+                log::info!("{}", VIEWS[view_index]);
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        gbuffer_program.reload_if_changed(display);
+        ambient_program.reload_if_changed(display);
+        light_program.reload_if_changed(display);
+        debug_program.reload_if_changed(display);
+
+        let dimensions = frame.get_dimensions();
+        if gbuffer.dimensions() != dimensions {
+            gbuffer = GBuffer::new(display, dimensions)?;
+        }
+
+        let view = view_matrix(
+            &[0.0, 3.5, -3.0],
+            &[0.0, -0.45, 1.0],
+            &[0.0, 1.0, 0.0]
+        );
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+        let inverse_perspective: [[f32; 4]; 4] = Matrix4::from(perspective).invert().unwrap_or_else(Matrix4::identity).into();
+
+        // pass 1: the geometry, into the G-buffer.
+        let mut target = frame.pass("gbuffer", gbuffer.framebuffer(display)?);
+        target.clear_color_and_depth((0.0, 0.0, 0.0, 0.0), 1.0);
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for model in &models {
+            let uniforms = uniform! {
+                model: *model,
+                view: view,
+                perspective: perspective,
+                base_color: [0.8f32, 0.8, 0.8],
+                specular_intensity: 0.6f32,
+                specular_exponent: 32.0f32
+            };
+            gbuffer_program.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
+            target.draw((&positions, &normals), &indices, &gbuffer_program, &uniforms, &params)?;
+        }
+        let uniforms = uniform! {
+            model: identity,
+            view: view,
+            perspective: perspective,
+            base_color: [0.45f32, 0.45, 0.45],
+            specular_intensity: 0.1f32,
+            specular_exponent: 8.0f32
+        };
+        target.draw(&floor, strip, &gbuffer_program, &uniforms, &params)?;
+
+        frame.clear_color(r, g, b, a);
+
+        if view_index > 0 {
+            let uniforms = uniform! { attachment: view_index as i32, depth_scale: 40.0f32 };
+            let uniforms = gbuffer.uniforms(inverse_perspective, &uniforms);
+            debug_program.check_bindings(&[quad.get_bindings()], &uniforms)?;
+            frame.draw(&quad, strip, &debug_program, &uniforms, &Default::default())?;
+            return Ok(());
+        }
+
+        // pass 2: ambient and the moon, everywhere.
+        let uniforms = uniform! {
+            ambient_color: ambient,
+            u_light: light,
+            light_color: moon_light,
+            view: view
+        };
+        let uniforms = gbuffer.uniforms(inverse_perspective, &uniforms);
+        ambient_program.check_bindings(&[quad.get_bindings()], &uniforms)?;
+        frame.draw(&quad, strip, &ambient_program, &uniforms, &Default::default())?;
+
+        // pass 3: every light's volume, added on top.
+        if light_count == 0 {
+            return Ok(());
+        }
+        let swarm = lights::swarm(light_count, [0.0, 0.0, 18.0], [18.0, 36.0], range, intensity, runner::now().as_secs_f32());
+        let data: Vec<LightInstance> = swarm
+            .iter()
+            .map(|light| {
+                let [r, g, b] = light.color;
+                LightInstance {
+                    light_position: light.position().unwrap_or_default(),
+                    light_color: [r * light.intensity, g * light.intensity, b * light.intensity],
+                    light_range: light.range,
+                }
+            })
+            .collect();
+        instances.write(&data);
+
+        let additive = BlendingFunction::Addition { source: LinearBlendingFactor::One, destination: LinearBlendingFactor::One };
+        let params = glium::DrawParameters {
+            blend: glium::Blend { color: additive, alpha: additive, constant_value: (0.0, 0.0, 0.0, 0.0) },
+            // only the back of each sphere: that's there whether the camera is inside the light's reach or not,
+            // and every pixel gets each light once.
+            backface_culling: BackfaceCullingMode::CullClockwise,
+            ..Default::default()
+        };
+        let uniforms = uniform! {
+            view: view,
+            perspective: perspective,
+            screen_size: [dimensions.0 as f32, dimensions.1 as f32]
+        };
+        let uniforms = gbuffer.uniforms(inverse_perspective, &uniforms);
+        let per_instance = instances
+            .per_instance()
+            .map_err(|_| Error::NotImplemented("instanced drawing, which this OpenGL doesn't have".to_string()))?;
+        light_program.check_bindings(&[volume.get_bindings(), instances.get_bindings()], &uniforms)?;
+        frame.draw((&volume, per_instance), &volume_indices, &light_program, &uniforms, &params)?;
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_sphere_triangle_faces_out() {
+        let (vertices, indices) = sphere(8, 12);
+        for triangle in indices.chunks_exact(3) {
+            let [p, q, r] = [0, 1, 2].map(|i| Vector3::from(vertices[triangle[i] as usize].position));
+            let normal = (q - p).cross(r - p);
+            // the triangles at the poles are flat.
+            if normal.magnitude() > 1e-6 {
+                assert!(normal.dot(p + q + r) > 0.0, "{:?} faces in", triangle);
+            }
+        }
+    }
+}
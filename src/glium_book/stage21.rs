@@ -0,0 +1,171 @@
+#![allow(dead_code)]
+use glium::{glutin::{
+    self,
+    event::{ElementState, VirtualKeyCode},
+}, implement_vertex, uniform, Surface};
+
+use super::teapot;
+use crate::error::{Error, Result};
+use super::lights;
+use super::clusters::{ClusterBuffers, ClusterGrid};
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::hot_reload::{HotProgram, shader_file};
+use super::math::{perspective_matrix, view_matrix};
+use super::stage17::ground;
+use super::stage18::teapot_field;
+
+// Clustered forward shading: a thousand lights over a field of teapots again, like stage 20, but drawn the
+// ordinary way, every fragment lighting itself. What keeps that affordable is that each one only loops over
+// the lights binned into its cluster (clusters.rs). The teapots are one instanced draw call, each instance
+// bringing its own model matrix. H shows how many lights every cluster ended up with instead.
+
+/// A teapot's model matrix, per instance.
+#[derive(Copy, Clone)]
+struct Instance {
+    model: [[f32; 4]; 4],
+}
+
+implement_vertex!(Instance, model);
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.0, 0.0, 0.01, 1.0]),
+        params::float("model_scale", 0.01, "size of the models"),
+        params::float("light_count", 1000.0, "number of point lights flying around"),
+        params::float("light_range", 2.0, "distance at which a point light has faded out completely"),
+        params::float("light_intensity", 1.2, "brightness of each point light"),
+        params::vec3("ambient", [0.02, 0.02, 0.03], "light every surface gets, whatever the lights do"),
+        params::float("tiles_x", 16.0, "clusters across the screen"),
+        params::float("tiles_y", 9.0, "clusters up the screen"),
+        params::float("slices", 24.0, "clusters into the distance"),
+        params::float("cluster_near", 1.0, "depth the clusters start getting sliced at, everything nearer is the first slice"),
+        params::float("cluster_far", 60.0, "depth the clusters end at, lights further away aren't drawn"),
+        params::float("heatmap_max", 24.0, "lights in a cluster that show as red in the heatmap"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let models: Vec<Instance> = teapot_field(params.float("model_scale"), 20, 20, 2.0)
+        .into_iter()
+        .map(|model| Instance { model })
+        .collect();
+    let teapots = glium::VertexBuffer::new(&display, &models)?;
+    let floor = glium::VertexBuffer::new(&display, &ground([0.0, 0.0, 21.0], 80.0))?;
+    let floor_instance = glium::VertexBuffer::new(&display, &[Instance {
+        model: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0f32],
+        ],
+    }])?;
+    let strip = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+
+    let mut program = HotProgram::new(&display, shader_file!("stage21.vert"), shader_file!("stage21.frag"))?;
+
+    let light_count = params.float("light_count").max(0.0) as usize;
+    let (range, intensity) = (params.float("light_range"), params.float("light_intensity"));
+    let grid = ClusterGrid::new(
+        [params.float("tiles_x") as usize, params.float("tiles_y") as usize],
+        params.float("slices") as usize,
+        params.float("cluster_near"),
+        params.float("cluster_far"),
+    );
+    let mut clusters = ClusterBuffers::new(&display, grid, light_count)?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let ambient = params.vec3("ambient");
+    let heatmap_max = params.float("heatmap_max").max(1.0);
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    let mut show_heatmap = false;
+    let mut busiest = 0;
+    log::info!("H toggles the heatmap of lights per cluster");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Key(VirtualKeyCode::H, ElementState::Pressed) => {
+                show_heatmap = !show_heatmap;
+                if show_heatmap {
+                    log::info!("the busiest cluster has {} lights", busiest);
+                }
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        program.reload_if_changed(display);
+
+        let view = view_matrix(
+            &[0.0, 5.0, -4.0],
+            &[0.0, -0.5, 1.0],
+            &[0.0, 1.0, 0.0]
+        );
+        let (width, height) = frame.get_dimensions();
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+
+        let swarm = lights::swarm(light_count, [0.0, 0.0, 21.0], [40.0, 40.0], range, intensity, runner::now().as_secs_f32());
+        let bins = clusters.upload(&swarm, view, fov, width as f32 / height as f32);
+        busiest = bins.cells.iter().map(|cell| cell[1]).max().unwrap_or(0);
+
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let instancing = |error| Error::NotImplemented(format!("instanced drawing, which this OpenGL doesn't have ({:?})", error));
+
+        let uniforms = uniform! {
+            view: view,
+            perspective: perspective,
+            ambient_color: ambient,
+            base_color: [0.8f32, 0.8, 0.8],
+            show_heatmap: show_heatmap,
+            heatmap_max: heatmap_max,
+            screen_size: [width as f32, height as f32]
+        };
+        let uniforms = clusters.uniforms(&uniforms);
+        program.check_bindings(&[positions.get_bindings(), normals.get_bindings(), teapots.get_bindings()], &uniforms)?;
+        let instances = teapots.per_instance().map_err(instancing)?;
+        frame.draw((&positions, &normals, instances), &indices, &program, &uniforms, &params)?;
+
+        let uniforms = uniform! {
+            view: view,
+            perspective: perspective,
+            ambient_color: ambient,
+            base_color: [0.45f32, 0.45, 0.45],
+            show_heatmap: show_heatmap,
+            heatmap_max: heatmap_max,
+            screen_size: [width as f32, height as f32]
+        };
+        let instance = floor_instance.per_instance().map_err(instancing)?;
+        frame.draw((&floor, instance), strip, &program, &clusters.uniforms(&uniforms), &params)?;
+
+        Ok(())
+    })
+}
@@ -0,0 +1,217 @@
+#![allow(dead_code)]
+use std::f32::consts::{PI, TAU};
+
+use glium::{glutin, implement_vertex, uniform, Display, Surface};
+use glium::texture::{RawImage2d, Texture2d};
+
+use super::teapot;
+use crate::error::Result;
+use super::assets;
+use super::material::Value;
+use super::lights::{Light, LightBuffer};
+use super::material::{self, Material, PbrTextures};
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::math::{perspective_matrix, view_matrix};
+use super::stage20::sphere;
+
+// Physically based rendering: stage 13's Blinn-Phong with its exponent of 16 only looks right for plastic.
+// Cook-Torrance with metallic and roughness (pbr.glsl) covers everything from rubber to polished gold with
+// two numbers that mean something. The spheres get more metallic going up and rougher going right, the
+// teapots below are gold going from polished to brushed, and the two on the sides are textured: albedo and
+// normal map from stage 14's wall, roughness and ambient occlusion made up from how dark it is.
+
+/// The teapot has none of its own.
+#[derive(Copy, Clone)]
+pub struct TexCoords {
+    pub tex_coords: [f32; 2],
+}
+
+implement_vertex!(TexCoords, tex_coords);
+
+/// Wrapped around the teapot like a label around a can.
+pub fn teapot_tex_coords() -> Vec<TexCoords> {
+    teapot::VERTICES
+        .iter()
+        .map(|vertex| {
+            let (x, y, z) = vertex.position;
+            TexCoords { tex_coords: [z.atan2(x) / TAU + 0.5, (y + 40.0) / 90.0] }
+        })
+        .collect()
+}
+
+/// A grayscale texture from how bright `name` is, from `dark` where it's black to `bright` where it's white.
+fn from_luminance(display: &Display, name: &str, dark: f32, bright: f32) -> Result<Texture2d> {
+    let image = assets::load_image(name)?.to_luma8();
+    let dimensions = image.dimensions();
+    let texels: Vec<u8> = image
+        .into_raw()
+        .into_iter()
+        .flat_map(|luma| {
+            let value = ((dark + (bright - dark) * luma as f32 / 255.0) * 255.0) as u8;
+            [value, value, value, 255]
+        })
+        .collect();
+    Ok(Texture2d::new(display, RawImage2d::from_raw_rgba_reversed(&texels, dimensions))?)
+}
+
+pub fn model_matrix(position: [f32; 3], scale: f32) -> [[f32; 4]; 4] {
+    [
+        [scale, 0.0, 0.0, 0.0],
+        [0.0, scale, 0.0, 0.0],
+        [0.0, 0.0, scale, 0.0],
+        [position[0], position[1], position[2], 1.0],
+    ]
+}
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.02, 0.02, 0.025, 1.0]),
+        params::vec3("albedo", [0.8, 0.1, 0.08], "base color of the spheres"),
+        params::vec3("ambient", [0.03, 0.03, 0.03], "light every surface gets, whatever the lights do"),
+        params::float("light_intensity", 30.0, "brightness of each of the four point lights"),
+        params::float("light_range", 15.0, "distance at which a point light has faded out completely"),
+        params::float("light_speed", 0.3, "how fast the lights circle around the middle, 0 holds them still"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let teapot_uvs = glium::VertexBuffer::new(&display, &teapot_tex_coords())?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let (sphere_vertices, sphere_indices) = sphere(32, 64);
+    let sphere = glium::VertexBuffer::new(&display, &sphere_vertices)?;
+    let sphere_indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &sphere_indices)?;
+
+    let albedo = params.vec3("albedo");
+    let ambient = params.vec3("ambient");
+    let mut material = Material::pbr(&display, PbrTextures::flat(&display)?, albedo, 0.0, 0.5, ambient)?;
+    // the wall's mortar is dark, rough and deep.
+    let wall = PbrTextures {
+        albedo: material::load_texture(&display, "tuto-14-diffuse.jpg")?,
+        metallic: material::flat_texture(&display, [0, 0, 0, 255])?,
+        roughness: from_luminance(&display, "tuto-14-diffuse.jpg", 1.0, 0.5)?,
+        ao: from_luminance(&display, "tuto-14-diffuse.jpg", 0.3, 1.0)?,
+        normal: material::load_texture(&display, "tuto-14-normal.png")?,
+    };
+    let mut textured = Material::pbr(&display, wall, [1.0; 3], 1.0, 1.0, ambient)?;
+    let mut lights = LightBuffer::new(&display)?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+    let (intensity, range) = (params.float("light_intensity"), params.float("light_range"));
+    let light_speed = params.float("light_speed");
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    let (columns, rows) = (7, 5);
+    log::info!("spheres: metallic 0 at the bottom to 1 at the top, roughness 0 on the left to 1 on the right");
+    log::info!("teapots: gold, roughness 0 on the left to 1 on the right");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        material.reload_if_changed(display);
+        textured.reload_if_changed(display);
+
+        frame.clear_color_and_depth((r, g, b, a), 1.0);
+
+        let view = view_matrix(
+            &[0.0, 0.0, -2.0],
+            &[0.0, 0.0, 1.0],
+            &[0.0, 1.0, 0.0]
+        );
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+
+        // four lights in front of the grid, turning around its middle.
+        let angle = light_speed * runner::now().as_secs_f32();
+        let scene: Vec<Light> = (0..4)
+            .map(|i| {
+                let (sin, cos) = (angle + TAU * i as f32 / 4.0 + PI / 4.0).sin_cos();
+                Light::point([4.0 * cos, 3.5 * sin, 3.0], range).with_color([1.0; 3], intensity)
+            })
+            .collect();
+        lights.upload(&scene, view);
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // how far along the columns or rows, 0 to 1.
+        let sweep = |i: usize, count: usize| i as f32 / (count - 1) as f32;
+
+        material.set("albedo_factor", Value::Vec3(albedo));
+        for row in 0..rows {
+            for column in 0..columns {
+                material.set("metallic_factor", Value::Float(sweep(row, rows)));
+                material.set("roughness_factor", Value::Float(sweep(column, columns)));
+                let position = [column as f32 - (columns - 1) as f32 / 2.0, row as f32 - 2.0, 6.0];
+                let uniforms = uniform! {
+                    model: model_matrix(position, 0.4),
+                    view: view,
+                    perspective: perspective,
+                    Lights: lights.buffer()
+                };
+                material.check_bindings(&[sphere.get_bindings()], &uniforms)?;
+                frame.draw(&sphere, &sphere_indices, material.program(), &material.uniforms(&uniforms), &params)?;
+            }
+        }
+
+        material.set("albedo_factor", Value::Vec3([1.0, 0.78, 0.34]));
+        material.set("metallic_factor", Value::Float(1.0));
+        for column in 0..columns {
+            material.set("roughness_factor", Value::Float(sweep(column, columns)));
+            let position = [column as f32 - (columns - 1) as f32 / 2.0, -3.4, 6.0];
+            let uniforms = uniform! {
+                model: model_matrix(position, 0.005),
+                view: view,
+                perspective: perspective,
+                Lights: lights.buffer()
+            };
+            material.check_bindings(&[positions.get_bindings(), normals.get_bindings(), teapot_uvs.get_bindings()], &uniforms)?;
+            frame.draw((&positions, &normals, &teapot_uvs), &indices, material.program(), &material.uniforms(&uniforms), &params)?;
+        }
+
+        let uniforms = uniform! {
+            model: model_matrix([-5.2, 0.0, 6.0], 0.9),
+            view: view,
+            perspective: perspective,
+            Lights: lights.buffer()
+        };
+        textured.check_bindings(&[sphere.get_bindings()], &uniforms)?;
+        frame.draw(&sphere, &sphere_indices, textured.program(), &textured.uniforms(&uniforms), &params)?;
+
+        let uniforms = uniform! {
+            model: model_matrix([5.2, -0.6, 6.0], 0.012),
+            view: view,
+            perspective: perspective,
+            Lights: lights.buffer()
+        };
+        textured.check_bindings(&[positions.get_bindings(), normals.get_bindings(), teapot_uvs.get_bindings()], &uniforms)?;
+        frame.draw((&positions, &normals, &teapot_uvs), &indices, textured.program(), &textured.uniforms(&uniforms), &params)?;
+
+        Ok(())
+    })
+}
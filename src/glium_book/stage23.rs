@@ -0,0 +1,203 @@
+#![allow(dead_code)]
+use std::f32::consts::TAU;
+
+use cgmath::{Matrix4, SquareMatrix};
+use glium::{glutin::{
+    self,
+    event::{ElementState, VirtualKeyCode},
+}, uniform, Surface};
+
+use super::teapot;
+use crate::error::Result;
+use super::assets;
+use super::ibl::{Environment, IblSizes, PREFILTERED_LEVELS};
+use super::material::Value;
+use super::lights::{Light, LightBuffer};
+use super::material::{Material, PbrTextures};
+use super::runner::{self, run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+use super::hot_reload::{HotProgram, shader_file};
+use super::math::{perspective_matrix, view_matrix};
+use super::stage15::fullscreen_quad;
+use super::stage20::sphere;
+use super::stage22::{model_matrix, teapot_tex_coords};
+
+// Image based lighting: stage 22's spheres and teapots again, but lit by a whole sky instead of four points
+// and a flat ambient color (ibl.rs makes the maps, ibl.glsl reads them). Put an equirectangular Radiance
+// file at assets/textures/environment.hdr to be lit by that, otherwise it's a made up sky with a sun. The
+// camera circles the grid so the reflections move. B cycles the background between the environment, its
+// irradiance and the prefiltered levels, to see what the materials see.
+
+const ENVIRONMENT_FILE: &str = "environment.hdr";
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::vec3("albedo", [0.8, 0.1, 0.08], "base color of the spheres"),
+        params::float("environment_intensity", 1.0, "how bright the environment is, background and lighting"),
+        params::float("light_intensity", 0.0, "brightness of four point lights on top of the environment, 0 leaves them out"),
+        params::float("light_range", 15.0, "distance at which a point light has faded out completely"),
+        params::float("camera_speed", 0.1, "how fast the camera circles the grid, 0 holds it still"),
+        params::float("camera_distance", 8.0, "how far the camera is from the middle of the grid"),
+        params::float("environment_size", 512.0, "width of each face of the environment cubemap"),
+        params::float("fov", 60.0, "vertical field of view in degrees"),
+        params::float("znear", 0.1, "near clipping plane, can't see anything nearer than this"),
+        params::float("zfar", 1024.0, "far clipping plane, can't see anything farther than this"),
+    ]
+}
+
+pub fn run(params: &Params) -> Result<()> {
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let window_builder = glutin::window::WindowBuilder::new();
+    let context_builder = glutin::ContextBuilder::new().with_depth_buffer(24);
+    let display = create_display(
+        window_builder,
+        context_builder,
+        &event_loop
+    )?;
+
+    let sizes = IblSizes { environment: params.float("environment_size").max(16.0) as u32, ..Default::default() };
+    let environment = if assets::texture_dir().join(ENVIRONMENT_FILE).exists() {
+        Environment::load(&display, ENVIRONMENT_FILE, sizes)?
+    } else {
+        log::warn!("no assets/textures/{}, using a made up sky", ENVIRONMENT_FILE);
+        Environment::procedural(&display, sizes)?
+    };
+
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let teapot_uvs = glium::VertexBuffer::new(&display, &teapot_tex_coords())?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
+
+    let (sphere_vertices, sphere_indices) = sphere(32, 64);
+    let sphere = glium::VertexBuffer::new(&display, &sphere_vertices)?;
+    let sphere_indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &sphere_indices)?;
+
+    let quad = glium::VertexBuffer::new(&display, &fullscreen_quad())?;
+    let strip = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+    let mut skybox = HotProgram::new(&display, shader_file!("stage15.vert"), shader_file!("ibl_skybox.frag"))?;
+
+    let albedo = params.vec3("albedo");
+    let mut material = Material::pbr_ibl(&display, PbrTextures::flat(&display)?, albedo, 0.0, 0.5)?;
+    let environment_intensity = params.float("environment_intensity");
+    material.set("environment_intensity", Value::Float(environment_intensity));
+    let mut lights = LightBuffer::new(&display)?;
+
+    let (intensity, range) = (params.float("light_intensity"), params.float("light_range"));
+    let (camera_speed, camera_distance) = (params.float("camera_speed"), params.float("camera_distance"));
+    let fov = params.float("fov").to_radians();
+    let (znear, zfar) = (params.float("znear"), params.float("zfar"));
+
+    let (columns, rows) = (7, 5);
+    let center = [0.0, 0.0, 6.0];
+    // 0 is the environment, 1 the irradiance, then every prefiltered level.
+    let backgrounds = 2 + PREFILTERED_LEVELS;
+    let mut background = 0;
+    log::info!("spheres: metallic 0 at the bottom to 1 at the top, roughness 0 on the left to 1 on the right");
+    log::info!("B cycles the background: environment, irradiance, prefiltered by roughness");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Key(VirtualKeyCode::B, ElementState::Pressed) => {
+                background = (background + 1) % backgrounds;
+                match background {
+                    0 => log::info!("background: the environment"),
+                    1 => log::info!("background: irradiance"),
+                    level => log::info!("background: prefiltered for roughness {:.2}", (level - 2) as f32 / (PREFILTERED_LEVELS - 1) as f32),
+                }
+                return Ok(());
+            }
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
+        material.reload_if_changed(display);
+        skybox.reload_if_changed(display);
+
+        frame.clear_depth(1.0);
+
+        // around the grid, a little above it, looking at its middle.
+        let angle = camera_speed * runner::now().as_secs_f32() - TAU / 4.0;
+        let (sin, cos) = angle.sin_cos();
+        let eye = [center[0] + camera_distance * cos, center[1] + 1.0, center[2] + camera_distance * sin];
+        let view = view_matrix(
+            &eye,
+            &[center[0] - eye[0], center[1] - eye[1], center[2] - eye[2]],
+            &[0.0, 1.0, 0.0]
+        );
+        let perspective = perspective_matrix(&*frame, fov, znear, zfar);
+        let inverse_perspective: [[f32; 4]; 4] = Matrix4::from(perspective).invert().unwrap_or_else(Matrix4::identity).into();
+
+        // the background covers everything, so nothing needs clearing but depth.
+        let uniforms = uniform! {
+            background: background.min(2) as i32,
+            background_level: background.saturating_sub(2) as f32,
+            environment_intensity: environment_intensity,
+            inverse_perspective: inverse_perspective,
+            view: view
+        };
+        let uniforms = environment.uniforms(&uniforms);
+        skybox.check_bindings(&[quad.get_bindings()], &uniforms)?;
+        frame.draw(&quad, strip, &skybox, &uniforms, &Default::default())?;
+
+        let scene: Vec<Light> = if intensity > 0.0 {
+            (0..4)
+                .map(|i| {
+                    let (sin, cos) = (TAU * i as f32 / 4.0 + TAU / 8.0).sin_cos();
+                    Light::point([4.0 * cos, 3.5 * sin, 3.0], range).with_color([1.0; 3], intensity)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        lights.upload(&scene, view);
+
+        let params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // how far along the columns or rows, 0 to 1.
+        let sweep = |i: usize, count: usize| i as f32 / (count - 1) as f32;
+
+        material.set("albedo_factor", Value::Vec3(albedo));
+        for row in 0..rows {
+            for column in 0..columns {
+                material.set("metallic_factor", Value::Float(sweep(row, rows)));
+                material.set("roughness_factor", Value::Float(sweep(column, columns)));
+                let position = [column as f32 - (columns - 1) as f32 / 2.0, row as f32 - 2.0, center[2]];
+                let uniforms = uniform! {
+                    model: model_matrix(position, 0.4),
+                    view: view,
+                    perspective: perspective,
+                    Lights: lights.buffer()
+                };
+                let uniforms = environment.uniforms(&uniforms);
+                material.check_bindings(&[sphere.get_bindings()], &uniforms)?;
+                frame.draw(&sphere, &sphere_indices, material.program(), &material.uniforms(&uniforms), &params)?;
+            }
+        }
+
+        material.set("albedo_factor", Value::Vec3([1.0, 0.78, 0.34]));
+        material.set("metallic_factor", Value::Float(1.0));
+        for column in 0..columns {
+            material.set("roughness_factor", Value::Float(sweep(column, columns)));
+            let position = [column as f32 - (columns - 1) as f32 / 2.0, -3.4, center[2]];
+            let uniforms = uniform! {
+                model: model_matrix(position, 0.005),
+                view: view,
+                perspective: perspective,
+                Lights: lights.buffer()
+            };
+            let uniforms = environment.uniforms(&uniforms);
+            material.check_bindings(&[positions.get_bindings(), normals.get_bindings(), teapot_uvs.get_bindings()], &uniforms)?;
+            frame.draw((&positions, &normals, &teapot_uvs), &indices, material.program(), &material.uniforms(&uniforms), &params)?;
+        }
+
+        Ok(())
+    })
+}
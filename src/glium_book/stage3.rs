@@ -1,18 +1,20 @@
 use std::hash::Hasher;
 
 use crossterm::event::KeyCode;
-use glium::{glutin::{
-    self,
-    event::{KeyboardInput, VirtualKeyCode}
-}, Program, Display, backend::Facade, uniform};
+use glium::{glutin, uniform};
 use glium::Surface;
 
 use super::stage2::{
     first_triangle,
     buffer_a_shape,
     dummy_marker,
-    the_stage2_program, Vertex,
+    Vertex,
 };
+use crate::error::Result;
+use super::hot_reload::{HotProgram, shader_file};
+use super::runner::{run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
 
 trait Anime{
     fn translate(&mut self, t: f32);
@@ -26,81 +28,58 @@ impl Anime for [Vertex; 3] {
     }
 }
 
-pub fn vertex_shader_src() -> &'static str {
-    r#"
-        #version 140
-
-        in vec2 position;
-
-        uniform float t; // UNIFORM meaning: global variable whose value is set for a draw call. aka. draw-context const variable.
-
-        void main() {
-            vec2 pos = position;
-            pos.x += t;
-            gl_Position = vec4(pos, 0.0, 1.0);
-        }
-    "#
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.1, 0.1, 0.9, 1.0]),
+    ]
 }
 
-pub fn the_stage3_program(display: &Display) -> Program {
-    Program::from_source(display as &dyn Facade, vertex_shader_src(), super::stage2::fragment_shader_src(), None).unwrap()
-}
-
-pub fn run() {
+pub fn run(params: &Params) -> Result<()> {
     let event_loop = glutin::event_loop::EventLoop::new();
     let window_builder = glutin::window::WindowBuilder::new();
     let context_builder = glutin::ContextBuilder::new();
-    let display = glium::Display::new(
+    let display = create_display(
         window_builder,
         context_builder,
         &event_loop
-    ).unwrap();
+    )?;
 
     let mut t: f32 = -0.5;
     let triangle = first_triangle();
-    let vertex_buffer = buffer_a_shape(&display, &triangle);
-
-    event_loop.run(move |event, _, control_flow| {
-        match event {
-            glutin::event::Event::WindowEvent { event, .. } => match event {
-                glutin::event::WindowEvent::CloseRequested => {
-                    *control_flow = glutin::event_loop::ControlFlow::Exit;
-                    return;
-                },
-                glutin::event::WindowEvent::KeyboardInput {
-                    input: KeyboardInput { virtual_keycode: Some(virtual_code), state, .. },
-                    ..
-                } => match (virtual_code, state) {
-                    (VirtualKeyCode::Escape, _) => {
-                        *control_flow = glutin::event_loop::ControlFlow::Exit;
-                        return;
-                    },
-                    _ => return,
-                },
-                _ => return,
-            },
-            _ => (),
-        }
-        let next_frame_time = std::time::Instant::now() + std::time::Duration::from_nanos(16_666_667);
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
-        
+    let vertex_buffer = buffer_a_shape(&display, &triangle)?;
+
+    let mut program = HotProgram::new(&display, shader_file!("stage3.vert"), shader_file!("stage2.frag"))?;
+
+    let [r, g, b, a] = params.vec4("clear_color");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(16_666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
+
         // update 't'
         t += 0.0002;
         if t > 0.5 {
             t = -0.5;
         }
-        
-        let mut frame = display.draw();
-        frame.clear_color(0.1, 0.1, 0.9, 1.0);
+
+        program.reload_if_changed(display);
+
+        let uniforms = uniform! { t: t }; // The first 't' here is arbitrarily named, except it has to match the uniform inside the shader code.
+
+        frame.clear_color(r, g, b, a);
+
+        program.check_bindings(&[vertex_buffer.get_bindings()], &uniforms)?;
 
         frame.draw(
             &vertex_buffer,
-            &dummy_marker(),
-            &the_stage3_program(&display),
-            &uniform! { t: t }, // The first 't' here is arbitrarily named, except it has to match the uniform inside the shader code.
+            dummy_marker(),
+            &program,
+            &uniforms,
             &Default::default()
-        ).unwrap();
-        
-        frame.finish().unwrap();
-    });
-}
\ No newline at end of file
+        )?;
+
+        Ok(())
+    })
+}
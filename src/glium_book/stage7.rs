@@ -8,87 +8,52 @@ use glium::{glutin::{
 }, buffer, uniform};
 use glium::{
     implement_vertex,
-    Display,
-    backend::Facade,
-    Program,
     Surface,
 };
 
 use super::teapot;
-
-pub fn vertex_shader_src() -> &'static str {
-    r#"
-        #version 140
-
-        in vec3 position;
-        in vec3 normal;
-
-        uniform mat4 matrix;
-
-        void main() {
-            gl_Position = matrix * vec4(position, 1.0); // the position is 4D because: the window space is 3D, and the 3 dimensions are divided by the 4th after the shader has been executed, and is then discarded.
-        }
-    "#
-}
-
-pub fn fragment_shader_src() -> &'static str {
-    // aka. pixel shader
-    r#"
-        #version 140
-
-        out vec4 color;
-
-        void main() {
-            color = vec4(0.98, 0.15, 0.1, 1.0);
-        }
-    "#
+use crate::error::Result;
+use super::hot_reload::{HotProgram, shader_file};
+use super::runner::{run_loop, LoopEvent};
+use super::context::create_display;
+use super::params::{self, Param, Params};
+
+pub fn params() -> Vec<Param> {
+    vec![
+        params::clear_color([0.1, 0.1, 0.9, 1.0]),
+        params::float("model_scale", 0.01, "size of the model"),
+    ]
 }
 
-pub fn the_stage7_program(display: &Display) -> Program {
-    Program::from_source(display as &dyn Facade, vertex_shader_src(), fragment_shader_src(), None).unwrap()
-}
-
-pub fn run() {
+pub fn run(params: &Params) -> Result<()> {
     let event_loop = glutin::event_loop::EventLoop::new();
     let window_builder = glutin::window::WindowBuilder::new();
     let context_builder = glutin::ContextBuilder::new();
-    let display = glium::Display::new(
+    let display = create_display(
         window_builder,
         context_builder,
         &event_loop
-    ).unwrap();
+    )?;
 
-    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES).unwrap();
-    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS).unwrap();
-    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES).unwrap();
+    let positions = glium::VertexBuffer::new(&display, &teapot::VERTICES)?;
+    let normals = glium::VertexBuffer::new(&display, &teapot::NORMALS)?;
+    let indices = glium::IndexBuffer::new(&display, glium::index::PrimitiveType::TrianglesList, &teapot::INDICES)?;
 
-    event_loop.run(move |event, _, control_flow| {
+    let mut program = HotProgram::new(&display, shader_file!("stage7.vert"), shader_file!("stage7.frag"))?;
 
-        match event {
-            glutin::event::Event::WindowEvent { event, .. } => match event {
-                glutin::event::WindowEvent::CloseRequested => {
-                    *control_flow = glutin::event_loop::ControlFlow::Exit;
-                    return;
-                },
-                glutin::event::WindowEvent::KeyboardInput {
-                    input: KeyboardInput { virtual_keycode: Some(virtual_code), state, .. },
-                    ..
-                } => match (virtual_code, state) {
-                    (VirtualKeyCode::Escape, _) => {
-                        *control_flow = glutin::event_loop::ControlFlow::Exit;
-                        return;
-                    }
-                    _ => return,
-                },
-                _ => return,
-            },
-            _ => (),
-        }
+    let [r, g, b, a] = params.vec4("clear_color");
+    let scale = params.float("model_scale");
+
+    run_loop(event_loop, &display, std::time::Duration::from_nanos(666_667), move |display, event| {
+        let frame = match event {
+            LoopEvent::Draw(frame) => frame,
+            _ => return Ok(()),
+        };
 
         let matrix = [
-            [0.0065, 0.0, 0.0, 0.0],
-            [0.0, 0.01, 0.0, 0.0],
-            [0.0, 0.0, 0.01, 0.0],
+            [scale * 0.65, 0.0, 0.0, 0.0],
+            [0.0, scale, 0.0, 0.0],
+            [0.0, 0.0, scale, 0.0],
             [0.0, 0.0, 0.0, 1.0f32],
         ];
 
@@ -96,22 +61,21 @@ pub fn run() {
             matrix: matrix,
         };
 
-        let mut frame = display.draw();
-        frame.clear_color(0.1, 0.1, 0.9, 1.0);
+        program.reload_if_changed(display);
+
+        frame.clear_color(r, g, b, a);
+
+        program.check_bindings(&[positions.get_bindings(), normals.get_bindings()], &uniforms)?;
 
         // Drawing the Teapot!
         frame.draw(
             (&positions, &normals),
             &indices,
-            &the_stage7_program(&display),
+            &program,
             &uniforms,
             &Default::default()
-        ).unwrap();
-
-        frame.finish().unwrap();
-
-        let next_frame_time = std::time::Instant::now() + std::time::Duration::from_nanos(666_667);
+        )?;
 
-        *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_frame_time);
-    });
+        Ok(())
+    })
 }
\ No newline at end of file
@@ -0,0 +1,182 @@
+use std::{
+    io::{stdout, Stdout, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyModifiers},
+    queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use glium::{glutin::dpi::PhysicalSize, Display};
+use image::RgbaImage;
+
+use crate::error::Result;
+use super::trace::OffscreenFrame;
+
+// `--term`: the stage still renders with GL, but into an `OffscreenFrame` (the window is hidden, and a hidden
+// window's pixels can't be read back), and every finished frame gets read back and printed as half blocks,
+// the top pixel of a cell is the `▀` and the bottom one is the background behind it, both in 24-bit color.
+// The frame, and the hidden window for stages that ask it for their size, are kept at the terminal's size
+// (times `SUPERSAMPLE`) so resizing the terminal resizes what the stage renders. A GL context still needs some display server,
+// over ssh without one run it under `xvfb-run` (add LIBGL_ALWAYS_SOFTWARE=1 if there's no GPU either).
+
+/// Rendered pixels per terminal pixel along each axis, averaged down so edges aren't all jaggies.
+const SUPERSAMPLE: u32 = 2;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Makes every stage started after this draw into the terminal instead of a window.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Owns the terminal while a stage is drawing into it, and gives it back when dropped.
+pub struct TermOutput {
+    out: Stdout,
+    /// Terminal size in cells the hidden window was last sized for.
+    cells: (u16, u16),
+    /// The frame `target` hands out, kept between frames while the terminal size stays the same.
+    offscreen: Option<OffscreenFrame>,
+}
+
+impl TermOutput {
+    pub fn new(display: &Display) -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = stdout();
+        crossterm::execute!(out, EnterAlternateScreen, cursor::Hide, Clear(ClearType::All))?;
+
+        display.gl_window().window().set_visible(false);
+
+        let mut output = TermOutput { out, cells: (0, 0), offscreen: None };
+        output.fit(display)?;
+        Ok(output)
+    }
+
+    /// Resizes the hidden window when the terminal changed size.
+    fn fit(&mut self, display: &Display) -> Result<()> {
+        let cells = terminal::size()?;
+        if cells != self.cells {
+            self.cells = cells;
+            let (width, height) = self.pixels();
+            display.gl_window().window().set_inner_size(PhysicalSize::new(width, height));
+            queue!(self.out, ResetColor, Clear(ClearType::All))?;
+        }
+        Ok(())
+    }
+
+    /// Rendered pixels for the current terminal size.
+    fn pixels(&self) -> (u32, u32) {
+        let (columns, rows) = self.cells;
+        (columns as u32 * SUPERSAMPLE, rows as u32 * 2 * SUPERSAMPLE)
+    }
+
+    /// What the stage draws the next frame into, give it back to `present` afterwards.
+    pub fn target(&mut self, display: &Display) -> Result<OffscreenFrame> {
+        match self.offscreen.take() {
+            Some(offscreen) if offscreen.dimensions() == self.pixels() => Ok(offscreen),
+            _ => OffscreenFrame::new(display, self.pixels()),
+        }
+    }
+
+    /// Prints `frame` and keeps it for the next `target`.
+    pub fn present(&mut self, frame: OffscreenFrame) -> Result<()> {
+        self.print(&frame)?;
+        self.offscreen = Some(frame);
+        Ok(())
+    }
+
+    /// Handles terminal input, `false` when the user wants out (q, Escape or ctrl-c, raw mode eats ctrl-c).
+    pub fn poll(&mut self, display: &Display) -> Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) => match key.code {
+                    KeyCode::Esc | KeyCode::Char('q') => return Ok(false),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+                    _ => (),
+                },
+                Event::Resize(..) => self.fit(display)?,
+                _ => (),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Prints a finished frame.
+    pub fn print(&mut self, frame: &OffscreenFrame) -> Result<()> {
+        let image = frame.read()?;
+        let (columns, rows) = (self.cells.0 as usize, self.cells.1 as usize);
+
+        // the terminal might have been resized since the frame was drawn, wait for one that fits.
+        if image.width() < columns as u32 * SUPERSAMPLE || image.height() < rows as u32 * 2 * SUPERSAMPLE {
+            return Ok(());
+        }
+
+        let pixel = |x: usize, y: usize| average(&image, x as u32, y as u32);
+
+        queue!(self.out, cursor::MoveTo(0, 0))?;
+        for row in 0..rows {
+            let mut colors = None;
+            for column in 0..columns {
+                let cell = (pixel(column, row * 2), pixel(column, row * 2 + 1));
+                // only send colors when they change, most frames are mostly background.
+                if colors != Some(cell) {
+                    queue!(self.out, SetForegroundColor(cell.0), SetBackgroundColor(cell.1))?;
+                    colors = Some(cell);
+                }
+                queue!(self.out, Print('▀'))?;
+            }
+            queue!(self.out, ResetColor)?;
+            if row + 1 < rows {
+                queue!(self.out, Print("\r\n"))?;
+            }
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// The average color of the `SUPERSAMPLE` squared pixels behind terminal pixel (`x`, `y`), counted from the top.
+fn average(image: &RgbaImage, x: u32, y: u32) -> Color {
+    let mut sum = [0u32; 3];
+    for dy in 0..SUPERSAMPLE {
+        for dx in 0..SUPERSAMPLE {
+            let pixel = image.get_pixel(x * SUPERSAMPLE + dx, y * SUPERSAMPLE + dy);
+            for (channel, total) in sum.iter_mut().enumerate() {
+                *total += pixel[channel] as u32;
+            }
+        }
+    }
+    let count = SUPERSAMPLE * SUPERSAMPLE;
+    Color::Rgb { r: (sum[0] / count) as u8, g: (sum[1] / count) as u8, b: (sum[2] / count) as u8 }
+}
+
+impl Drop for TermOutput {
+    fn drop(&mut self) {
+        crossterm::execute!(self.out, ResetColor, cursor::Show, LeaveAlternateScreen).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_terminal_pixel_is_the_average_of_the_pixels_behind_it() {
+        // terminal pixel (1, 0) covers the top right 2x2 block.
+        let image = RgbaImage::from_fn(4, 4, |x, y| match (x >= 2, y < 2) {
+            (true, true) => image::Rgba([x as u8 * 10, 200, 0, 255]),
+            _ => image::Rgba([0, 0, 255, 255]),
+        });
+        assert_eq!(average(&image, 1, 0), Color::Rgb { r: 25, g: 200, b: 0 });
+        assert_eq!(average(&image, 0, 1), Color::Rgb { r: 0, g: 0, b: 255 });
+    }
+}
@@ -0,0 +1,464 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    io::{stdout, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use glium::{glutin::event::{ElementState, VirtualKeyCode}, Display};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use super::context::gl_function;
+
+// How long frames take. Every frame gets its CPU time (the stage's draw handler) and the time between it
+// and the previous frame, and, when the driver has timer queries, how long the GPU spent on it. F3 prints
+// a readout of the last second to the terminal. `bench` runs a stage in a hidden window as fast as it can
+// for a fixed number of frames and writes every sample plus avg/p95/p99 to JSON or CSV, so two commits
+// can be compared.
+
+pub const READOUT_KEY: VirtualKeyCode = VirtualKeyCode::F3;
+
+/// Samples kept around for the readout outside of `bench`.
+const KEEP: usize = 1000;
+
+/// What `bench` asked for.
+#[derive(Clone, Debug)]
+pub struct Bench {
+    /// What's being measured, goes into the report.
+    pub label: String,
+    pub frames: usize,
+    /// Frames drawn before measuring starts, the first few are always slow (shader compiles, uploads).
+    pub warmup: usize,
+    /// `.csv` writes CSV, anything else JSON.
+    pub output: PathBuf,
+}
+
+static BENCH: Mutex<Option<Bench>> = Mutex::new(None);
+
+/// Makes the next stage that runs a benchmark.
+pub fn set_bench(bench: Bench) {
+    *BENCH.lock().unwrap() = Some(bench);
+}
+
+pub fn bench() -> Option<Bench> {
+    BENCH.lock().unwrap().clone()
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Sample {
+    pub cpu: Duration,
+    /// Since the previous frame started, none for the first one.
+    pub interval: Option<Duration>,
+    /// Filled in a few frames late, GPU results take a while to come back.
+    pub gpu: Option<Duration>,
+}
+
+/// In milliseconds.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Summary {
+    pub avg: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    pub fn of(durations: impl Iterator<Item = Duration>) -> Option<Summary> {
+        let mut ms: Vec<f64> = durations.map(|duration| duration.as_secs_f64() * 1000.0).collect();
+        if ms.is_empty() {
+            return None;
+        }
+        ms.sort_by(|a, b| a.total_cmp(b));
+
+        // nearest rank, so p99 of 100 frames is the 99th slowest and not an interpolation.
+        let percentile = |p: f64| ms[((p * ms.len() as f64).ceil() as usize).clamp(1, ms.len()) - 1];
+        Some(Summary {
+            avg: ms.iter().sum::<f64>() / ms.len() as f64,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            min: ms[0],
+            max: ms[ms.len() - 1],
+        })
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "avg {:.2}ms  p95 {:.2}ms  p99 {:.2}ms", self.avg, self.p95, self.p99)
+    }
+}
+
+const GL_TIME_ELAPSED: u32 = 0x88BF;
+const GL_QUERY_RESULT: u32 = 0x8866;
+const GL_QUERY_RESULT_AVAILABLE: u32 = 0x8867;
+
+// the signatures are the ones from the GL spec, `GLsizei`/`GLuint`/`GLenum` are i32/u32/u32.
+type GenQueries = unsafe extern "system" fn(i32, *mut u32);
+type DeleteQueries = unsafe extern "system" fn(i32, *const u32);
+type BeginQuery = unsafe extern "system" fn(u32, u32);
+type EndQuery = unsafe extern "system" fn(u32);
+type GetQueryObjectiv = unsafe extern "system" fn(u32, u32, *mut i32);
+type GetQueryObjectui64v = unsafe extern "system" fn(u32, u32, *mut u64);
+
+/// Raw `GL_TIME_ELAPSED` queries around the whole frame. glium only has them per draw call, through
+/// `DrawParameters`, and that would mean touching every stage.
+struct GpuTimer {
+    gen_queries: GenQueries,
+    delete_queries: DeleteQueries,
+    begin_query: BeginQuery,
+    end_query: EndQuery,
+    get_query_objectiv: GetQueryObjectiv,
+    get_query_objectui64v: GetQueryObjectui64v,
+    free: Vec<u32>,
+    /// Queries that were ended but haven't been read yet, with the frame they timed.
+    pending: VecDeque<(u32, usize)>,
+    active: Option<(u32, usize)>,
+}
+
+impl GpuTimer {
+    fn new(display: &Display) -> Option<Self> {
+        use glium::{backend::Facade, Api, CapabilitiesSource, Version};
+
+        if *display.get_opengl_version() < Version(Api::Gl, 3, 3) && !display.get_context().get_extensions().gl_arb_timer_query {
+            return None;
+        }
+
+        unsafe {
+            Some(GpuTimer {
+                gen_queries: gl_function(display, "glGenQueries")?,
+                delete_queries: gl_function(display, "glDeleteQueries")?,
+                begin_query: gl_function(display, "glBeginQuery")?,
+                end_query: gl_function(display, "glEndQuery")?,
+                get_query_objectiv: gl_function(display, "glGetQueryObjectiv")?,
+                get_query_objectui64v: gl_function(display, "glGetQueryObjectui64v")?,
+                free: Vec::new(),
+                pending: VecDeque::new(),
+                active: None,
+            })
+        }
+    }
+
+    fn begin(&mut self, display: &Display, frame: usize) {
+        let query = self.free.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            unsafe { display.exec_in_context(|| (self.gen_queries)(1, &mut query)) };
+            query
+        });
+        unsafe { display.exec_in_context(|| (self.begin_query)(GL_TIME_ELAPSED, query)) };
+        self.active = Some((query, frame));
+    }
+
+    fn end(&mut self, display: &Display) {
+        if let Some(active) = self.active.take() {
+            unsafe { display.exec_in_context(|| (self.end_query)(GL_TIME_ELAPSED)) };
+            self.pending.push_back(active);
+        }
+    }
+
+    /// Results that are ready, `wait` blocks until all of them are.
+    fn collect(&mut self, display: &Display, wait: bool) -> Vec<(usize, Duration)> {
+        let mut results = Vec::new();
+        while let Some(&(query, frame)) = self.pending.front() {
+            let mut available = 0;
+            if !wait {
+                unsafe { display.exec_in_context(|| (self.get_query_objectiv)(query, GL_QUERY_RESULT_AVAILABLE, &mut available)) };
+                if available == 0 {
+                    break;
+                }
+            }
+            let mut nanos = 0u64;
+            unsafe { display.exec_in_context(|| (self.get_query_objectui64v)(query, GL_QUERY_RESULT, &mut nanos)) };
+
+            self.pending.pop_front();
+            self.free.push(query);
+            results.push((frame, Duration::from_nanos(nanos)));
+        }
+        results
+    }
+
+    fn delete(&mut self, display: &Display) {
+        let queries: Vec<u32> = self.free.drain(..).chain(self.pending.drain(..).map(|(query, _)| query)).collect();
+        unsafe { display.exec_in_context(|| (self.delete_queries)(queries.len() as i32, queries.as_ptr())) };
+    }
+}
+
+/// The runner's frame timing.
+pub struct FrameTimer {
+    gpu: Option<GpuTimer>,
+    samples: VecDeque<Sample>,
+    /// Frame number of `samples[0]`.
+    first: usize,
+    frame: usize,
+    /// Drop old samples past `KEEP`, `bench` keeps everything.
+    keep_all: bool,
+    started: Option<Instant>,
+    previous_start: Option<Instant>,
+    readout: bool,
+    last_readout: Instant,
+}
+
+impl FrameTimer {
+    pub fn new(display: &Display, keep_all: bool) -> Self {
+        FrameTimer {
+            gpu: GpuTimer::new(display),
+            samples: VecDeque::new(),
+            first: 0,
+            frame: 0,
+            keep_all,
+            started: None,
+            previous_start: None,
+            readout: false,
+            last_readout: Instant::now(),
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frame
+    }
+
+    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        if key != READOUT_KEY {
+            return false;
+        }
+        if state == ElementState::Pressed {
+            self.readout = !self.readout;
+            if !self.readout {
+                println!();
+            }
+        }
+        true
+    }
+
+    /// Right before the stage draws.
+    pub fn begin(&mut self, display: &Display) {
+        let now = Instant::now();
+        self.started = Some(now);
+        if let Some(gpu) = &mut self.gpu {
+            gpu.begin(display, self.frame);
+        }
+
+        let interval = self.previous_start.map(|previous| now - previous);
+        self.previous_start = Some(now);
+        self.samples.push_back(Sample { interval, ..Sample::default() });
+        if !self.keep_all && self.samples.len() > KEEP {
+            self.samples.pop_front();
+            self.first += 1;
+        }
+    }
+
+    /// Right after the stage drew, before the frame is swapped.
+    pub fn end(&mut self, display: &Display) {
+        let cpu = self.started.take().map(|started| started.elapsed()).unwrap_or_default();
+        if let Some(sample) = self.samples.back_mut() {
+            sample.cpu = cpu;
+        }
+        if let Some(gpu) = &mut self.gpu {
+            gpu.end(display);
+        }
+        self.frame += 1;
+
+        self.collect_gpu(display, false);
+        if self.readout && self.last_readout.elapsed() >= Duration::from_secs(1) {
+            self.last_readout = Instant::now();
+            print!("\r{}    ", self.readout_line());
+            stdout().flush().ok();
+        }
+    }
+
+    fn collect_gpu(&mut self, display: &Display, wait: bool) {
+        let results = match &mut self.gpu {
+            Some(gpu) => gpu.collect(display, wait),
+            None => return,
+        };
+        for (frame, duration) in results {
+            if let Some(sample) = frame.checked_sub(self.first).and_then(|index| self.samples.get_mut(index)) {
+                sample.gpu = Some(duration);
+            }
+        }
+    }
+
+    /// The last second of frames.
+    fn readout_line(&self) -> String {
+        let recent: Vec<&Sample> = self.samples.iter().rev()
+            .scan(Duration::ZERO, |total, sample| {
+                *total += sample.interval.unwrap_or_default();
+                (*total <= Duration::from_secs(1)).then_some(sample)
+            })
+            .collect();
+
+        let mut line = format!("{} fps", recent.len());
+        if let Some(cpu) = Summary::of(recent.iter().map(|sample| sample.cpu)) {
+            write!(line, " | cpu {}", cpu).ok();
+        }
+        match Summary::of(recent.iter().filter_map(|sample| sample.gpu)) {
+            Some(gpu) => write!(line, " | gpu {}", gpu).ok(),
+            None if self.gpu.is_none() => write!(line, " | no gpu timer queries").ok(),
+            None => None,
+        };
+        line
+    }
+
+    /// Waits for outstanding GPU results and hands back every sample that's still kept.
+    pub fn finish(mut self, display: &Display) -> Vec<Sample> {
+        self.collect_gpu(display, true);
+        if let Some(gpu) = &mut self.gpu {
+            gpu.delete(display);
+        }
+        if self.readout {
+            println!();
+        }
+        self.samples.into_iter().collect()
+    }
+}
+
+fn git_commit() -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The JSON `bench` writes.
+#[derive(Serialize)]
+struct Report<'a> {
+    label: &'a str,
+    commit: Option<String>,
+    renderer: String,
+    frames: usize,
+    cpu_ms: Option<Summary>,
+    gpu_ms: Option<Summary>,
+    interval_ms: Option<Summary>,
+    samples: Vec<ReportSample>,
+}
+
+#[derive(Serialize)]
+struct ReportSample {
+    cpu_ms: f64,
+    gpu_ms: Option<f64>,
+    interval_ms: Option<f64>,
+}
+
+impl<'a> Report<'a> {
+    fn new(label: &'a str, commit: Option<String>, renderer: String, samples: &[Sample]) -> Self {
+        Report {
+            label,
+            commit,
+            renderer,
+            frames: samples.len(),
+            cpu_ms: Summary::of(samples.iter().map(|sample| sample.cpu)),
+            gpu_ms: Summary::of(samples.iter().filter_map(|sample| sample.gpu)),
+            interval_ms: Summary::of(samples.iter().filter_map(|sample| sample.interval)),
+            samples: samples.iter().map(|sample| ReportSample {
+                cpu_ms: ms(sample.cpu),
+                gpu_ms: sample.gpu.map(ms),
+                interval_ms: sample.interval.map(ms),
+            }).collect(),
+        }
+    }
+}
+
+/// One row per frame, what `bench` writes for `.csv` outputs.
+fn csv_report(samples: &[Sample]) -> String {
+    let cell = |duration: Option<Duration>| duration.map(|duration| format!("{:.4}", ms(duration))).unwrap_or_default();
+    let mut report = String::from("frame,cpu_ms,gpu_ms,interval_ms\n");
+    for (frame, sample) in samples.iter().enumerate() {
+        writeln!(report, "{},{},{},{}", frame, cell(Some(sample.cpu)), cell(sample.gpu), cell(sample.interval)).ok();
+    }
+    report
+}
+
+/// Prints the summary and writes the report `bench` asked for.
+pub fn write_report(bench: &Bench, display: &Display, samples: &[Sample]) -> Result<()> {
+    let samples = &samples[bench.warmup.min(samples.len())..];
+    let renderer = format!("{} ({})", display.get_opengl_renderer_string(), display.get_opengl_version_string());
+    let report = Report::new(&bench.label, git_commit(), renderer, samples);
+
+    eprintln!("{}: {} frames", bench.label, samples.len());
+    for (name, summary) in [("cpu", report.cpu_ms), ("gpu", report.gpu_ms), ("frame", report.interval_ms)] {
+        match summary {
+            Some(summary) => eprintln!("  {:5} {}", name, summary),
+            None => eprintln!("  {:5} not measured", name),
+        }
+    }
+
+    let csv = bench.output.extension().is_some_and(|extension| extension == "csv");
+    let report = if csv {
+        csv_report(samples)
+    } else {
+        serde_json::to_string_pretty(&report).map_err(|error| Error::write(&bench.output, error))? + "\n"
+    };
+
+    fs::write(&bench.output, report).map_err(|error| Error::write(&bench.output, error))?;
+    eprintln!("wrote {}", bench.output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: impl IntoIterator<Item = u64>) -> Vec<Duration> {
+        values.into_iter().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn nothing_to_summarize() {
+        assert!(Summary::of(std::iter::empty()).is_none());
+    }
+
+    #[test]
+    fn one_sample_is_every_percentile() {
+        let summary = Summary::of(millis([4]).into_iter()).unwrap();
+        assert_eq!([summary.avg, summary.p50, summary.p95, summary.p99, summary.min, summary.max], [4.0; 6]);
+    }
+
+    #[test]
+    fn percentiles_are_nearest_rank() {
+        // shuffled, `of` sorts.
+        let summary = Summary::of(millis((1..=100).rev()).into_iter()).unwrap();
+        assert_eq!([summary.p50, summary.p95, summary.p99, summary.min, summary.max], [50.0, 95.0, 99.0, 1.0, 100.0]);
+        assert_eq!(summary.avg, 50.5);
+
+        // with ten, p95 and p99 both round up to the slowest one.
+        let summary = Summary::of(millis(1..=10).into_iter()).unwrap();
+        assert_eq!([summary.p50, summary.p95, summary.p99], [5.0, 10.0, 10.0]);
+    }
+
+    fn samples() -> Vec<Sample> {
+        vec![
+            Sample { cpu: Duration::from_micros(1500), interval: None, gpu: Some(Duration::from_millis(2)) },
+            Sample { cpu: Duration::from_millis(3), interval: Some(Duration::from_micros(16_667)), gpu: None },
+        ]
+    }
+
+    #[test]
+    fn csv_has_a_row_per_frame_and_leaves_missing_cells_empty() {
+        assert_eq!(
+            csv_report(&samples()),
+            "frame,cpu_ms,gpu_ms,interval_ms\n0,1.5000,2.0000,\n1,3.0000,,16.6670\n"
+        );
+    }
+
+    #[test]
+    fn json_report_has_summaries_and_samples() {
+        let report = serde_json::to_value(Report::new("test", None, "gl".to_string(), &samples())).unwrap();
+        assert_eq!(report["frames"], 2);
+        assert_eq!(report["cpu_ms"]["max"], 3.0);
+        assert_eq!(report["gpu_ms"]["p50"], 2.0);
+        assert_eq!(report["samples"][0]["interval_ms"], serde_json::Value::Null);
+        assert_eq!(report["samples"][1]["cpu_ms"], 3.0);
+    }
+}
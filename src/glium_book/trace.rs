@@ -0,0 +1,928 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    ffi::c_void,
+    fmt::Debug,
+    fs,
+    hash::Hasher,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use glium::{
+    buffer::BufferAnySlice,
+    framebuffer::{DepthRenderBuffer, MultiOutputFrameBuffer, SimpleFrameBuffer},
+    glutin::event::{ElementState, VirtualKeyCode},
+    index::IndicesSource,
+    texture::{DepthFormat, RawImage2d, SrgbTexture2d},
+    uniforms::{MagnifySamplerFilter, SamplerBehavior, UniformValue, Uniforms},
+    vertex::{MultiVerticesSource, VerticesSource},
+    Blend, BlendingFunction, BlitMask, BlitTarget, Display, DrawError, DrawParameters, Frame, GlObject, Handle, Program,
+    Rect, Surface,
+};
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use super::capture::{capture_dir, timestamp, upright};
+use super::context::gl_function;
+use super::material::Value;
+
+// Draw call traces. Stages draw into a `Target`, which is the frame plus, when a frame is being traced,
+// a `Recorder` that writes down every clear and draw before passing it on: which program (by generation,
+// with its binary when the driver hands it out), the vertex and index buffers (their formats and contents),
+// every uniform value (textures as pngs) and the draw parameters. F8 traces the next frame, `--trace`
+// every frame. Each frame becomes captures/trace-<time>/frame-NNNNNN.json, buffer contents, program
+// binaries and textures go next to it in blobs/, named by a hash of their contents so unchanged data
+// isn't written twice. `replay` (replay.rs) draws a traced frame again offscreen and can diff two.
+// Stages that draw passes into framebuffers of their own (shadow maps, a G-buffer, ...) go through
+// `Target::pass`, so those draws are traced too, each op says which target it went to.
+
+pub const TRACE_KEY: VirtualKeyCode = VirtualKeyCode::F8;
+
+static TRACE_ALL: AtomicBool = AtomicBool::new(false);
+
+/// The target ops drawn into the `Target` itself are under, passes have names of their own.
+pub const FRAME: &str = "frame";
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// Every program's generation, by its GL name. GL hands a name out again once its program is gone (a
+/// hot reload does just that), so traces name programs by when they were built instead.
+static GENERATIONS: Mutex<BTreeMap<u64, u64>> = Mutex::new(BTreeMap::new());
+
+/// Makes every stage started after this trace every frame it draws.
+pub fn enable_all() {
+    TRACE_ALL.store(true, Ordering::Relaxed);
+}
+
+/// Gives a newly built program the next generation. `hot_reload::compile` does this for every program.
+pub fn register_program(program: &Program) {
+    register(program_key(program));
+}
+
+/// What traces call `program`.
+pub fn program_name(program: &Program) -> String {
+    format!("program-{}", generation(program_key(program)))
+}
+
+fn program_key(program: &Program) -> u64 {
+    match program.get_id() {
+        Handle::Id(id) => id as u64,
+        Handle::Handle(handle) => handle as usize as u64,
+    }
+}
+
+fn register(key: u64) -> u64 {
+    let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    GENERATIONS.lock().unwrap().insert(key, generation);
+    generation
+}
+
+/// A program that wasn't registered gets a generation the first time it's asked about.
+fn generation(key: u64) -> u64 {
+    let known = GENERATIONS.lock().unwrap().get(&key).copied();
+    known.unwrap_or_else(|| register(key))
+}
+
+/// What stages draw into, a `Frame` (or an `OffscreenFrame`) that can be traced.
+pub struct Target {
+    surface: TargetSurface,
+    recorder: Option<Recorder>,
+}
+
+pub enum TargetSurface {
+    Frame(Frame),
+    Offscreen(OffscreenFrame),
+}
+
+impl Target {
+    pub fn new(frame: Frame, recorder: Option<Recorder>) -> Self {
+        Target { surface: TargetSurface::Frame(frame), recorder }
+    }
+
+    pub fn offscreen(frame: OffscreenFrame, recorder: Option<Recorder>) -> Self {
+        Target { surface: TargetSurface::Offscreen(frame), recorder }
+    }
+
+    /// What has been drawn so far, the right way up. A window's frame is copied out before it's presented,
+    /// once it's the front buffer the pixels of a covered or hidden window can't be read back.
+    pub fn read(&self, display: &Display) -> Result<RgbaImage> {
+        match &self.surface {
+            TargetSurface::Frame(frame) => {
+                let (width, height) = frame.get_dimensions();
+                let copy = OffscreenFrame::new(display, (width, height))?;
+                let whole = BlitTarget { left: 0, bottom: 0, width: width as i32, height: height as i32 };
+                frame.blit_whole_color_to(&copy.framebuffer(), &whole, MagnifySamplerFilter::Nearest);
+                copy.read()
+            }
+            TargetSurface::Offscreen(offscreen) => offscreen.read(),
+        }
+    }
+
+    /// `surface` (one of the stage's own framebuffers) as a surface whose clears and draws are traced
+    /// too, under `name`.
+    pub fn pass<S: Surface>(&mut self, name: &str, surface: S) -> Pass<'_, S> {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.targets.insert(name.to_string(), surface.get_dimensions());
+        }
+        Pass { surface, name: name.to_string(), recorder: self.recorder.as_mut() }
+    }
+
+    /// What was drawn into, to be finished, and the trace of it if there is one.
+    pub fn into_parts(self) -> (TargetSurface, Option<Recorder>) {
+        (self.surface, self.recorder)
+    }
+}
+
+/// A frame drawn into textures rather than the window, for when the window is hidden: whatever of a
+/// hidden window's pixels GL doesn't own can't be read back.
+pub struct OffscreenFrame {
+    display: Display,
+    color: SrgbTexture2d,
+    depth: DepthRenderBuffer,
+}
+
+impl OffscreenFrame {
+    pub fn new(display: &Display, (width, height): (u32, u32)) -> Result<Self> {
+        // sRGB like the window, so the shaders' output gets converted the same way.
+        let color = SrgbTexture2d::empty(display, width, height)?;
+        let depth = DepthRenderBuffer::new(display, DepthFormat::I24, width, height)
+            .map_err(|error| Error::Framebuffer(format!("{:?}", error)))?;
+        let frame = OffscreenFrame { display: display.clone(), color, depth };
+        SimpleFrameBuffer::with_depth_buffer(display, &frame.color, &frame.depth)?;
+        Ok(frame)
+    }
+
+    fn framebuffer(&self) -> SimpleFrameBuffer<'_> {
+        SimpleFrameBuffer::with_depth_buffer(&self.display, &self.color, &self.depth)
+            .expect("the attachments were checked in OffscreenFrame::new")
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.color.dimensions()
+    }
+
+    pub fn read(&self) -> Result<RgbaImage> {
+        upright(self.color.read())
+    }
+}
+
+/// Runs `$body` with `$surface` bound to whichever surface `$target` is.
+macro_rules! on_surface {
+    ($target:expr, $surface:ident => $body:expr) => {
+        match $target {
+            TargetSurface::Frame($surface) => $body,
+            TargetSurface::Offscreen(offscreen) => {
+                let mut framebuffer = offscreen.framebuffer();
+                let $surface = &mut framebuffer;
+                $body
+            }
+        }
+    };
+}
+
+/// `Surface::draw` with vertex sources that were already taken apart, glium only takes them as tuples.
+pub fn draw_sources<S: Surface, U: Uniforms>(
+    surface: &mut S,
+    sources: Vec<VerticesSource>,
+    indices: IndicesSource,
+    program: &Program,
+    uniforms: &U,
+    parameters: &DrawParameters,
+) -> std::result::Result<(), DrawError> {
+    let count = sources.len();
+    let mut sources = sources.into_iter();
+    let mut next = || sources.next().unwrap();
+    match count {
+        1 => surface.draw(next(), indices, program, uniforms, parameters),
+        2 => surface.draw((next(), next()), indices, program, uniforms, parameters),
+        3 => surface.draw((next(), next(), next()), indices, program, uniforms, parameters),
+        4 => surface.draw((next(), next(), next(), next()), indices, program, uniforms, parameters),
+        count => {
+            log::error!("a draw with {} vertex sources can't be traced, it was skipped", count);
+            Ok(())
+        }
+    }
+}
+
+/// A draw into `surface`, written down first when there's a recorder, under the target's name.
+fn record_draw<'a, 'b, S, V, I, U>(
+    surface: &mut S,
+    recorder: Option<(&mut Recorder, &str)>,
+    vertices: V,
+    indices: I,
+    program: &Program,
+    uniforms: &U,
+    parameters: &DrawParameters<'_>,
+) -> std::result::Result<(), DrawError>
+where
+    S: Surface,
+    V: MultiVerticesSource<'b>,
+    I: Into<IndicesSource<'a>>,
+    U: Uniforms,
+{
+    let (recorder, target) = match recorder {
+        Some(recorder) => recorder,
+        None => return surface.draw(vertices, indices, program, uniforms, parameters),
+    };
+
+    let sources: Vec<VerticesSource> = vertices.iter().collect();
+    let indices = indices.into();
+    recorder.draw(target, &sources, &indices, program, uniforms, parameters);
+    let result = draw_sources(surface, sources, indices, program, uniforms, parameters);
+    recorder.draw_result(&result);
+    result
+}
+
+impl Surface for Target {
+    fn clear(&mut self, rect: Option<&Rect>, color: Option<(f32, f32, f32, f32)>, color_srgb: bool, depth: Option<f32>, stencil: Option<i32>) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.clear(FRAME, rect, color, color_srgb, depth, stencil);
+        }
+        on_surface!(&mut self.surface, surface => surface.clear(rect, color, color_srgb, depth, stencil))
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        on_surface!(&self.surface, surface => surface.get_dimensions())
+    }
+
+    fn get_depth_buffer_bits(&self) -> Option<u16> {
+        on_surface!(&self.surface, surface => surface.get_depth_buffer_bits())
+    }
+
+    fn get_stencil_buffer_bits(&self) -> Option<u16> {
+        on_surface!(&self.surface, surface => surface.get_stencil_buffer_bits())
+    }
+
+    fn draw<'a, 'b, V, I, U>(&mut self, vertices: V, indices: I, program: &Program, uniforms: &U, parameters: &DrawParameters<'_>) -> std::result::Result<(), DrawError>
+    where
+        V: MultiVerticesSource<'b>,
+        I: Into<IndicesSource<'a>>,
+        U: Uniforms,
+    {
+        let recorder = self.recorder.as_mut().map(|recorder| (recorder, FRAME));
+        on_surface!(&mut self.surface, surface => record_draw(surface, recorder, vertices, indices, program, uniforms, parameters))
+    }
+
+    fn blit_color<S: Surface>(&self, source_rect: &Rect, target: &S, target_rect: &BlitTarget, filter: MagnifySamplerFilter) {
+        on_surface!(&self.surface, surface => surface.blit_color(source_rect, target, target_rect, filter))
+    }
+
+    fn blit_buffers_from_frame(&self, source_rect: &Rect, target_rect: &BlitTarget, filter: MagnifySamplerFilter, mask: BlitMask) {
+        on_surface!(&self.surface, surface => surface.blit_buffers_from_frame(source_rect, target_rect, filter, mask))
+    }
+
+    fn blit_buffers_from_simple_framebuffer(&self, source: &SimpleFrameBuffer, source_rect: &Rect, target_rect: &BlitTarget, filter: MagnifySamplerFilter, mask: BlitMask) {
+        on_surface!(&self.surface, surface => surface.blit_buffers_from_simple_framebuffer(source, source_rect, target_rect, filter, mask))
+    }
+
+    fn blit_buffers_from_multioutput_framebuffer(&self, source: &MultiOutputFrameBuffer, source_rect: &Rect, target_rect: &BlitTarget, filter: MagnifySamplerFilter, mask: BlitMask) {
+        on_surface!(&self.surface, surface => surface.blit_buffers_from_multioutput_framebuffer(source, source_rect, target_rect, filter, mask))
+    }
+}
+
+/// One of a stage's own framebuffers, drawn into through `Target::pass`.
+pub struct Pass<'t, S> {
+    surface: S,
+    name: String,
+    recorder: Option<&'t mut Recorder>,
+}
+
+impl<S: Surface> Surface for Pass<'_, S> {
+    fn clear(&mut self, rect: Option<&Rect>, color: Option<(f32, f32, f32, f32)>, color_srgb: bool, depth: Option<f32>, stencil: Option<i32>) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.clear(&self.name, rect, color, color_srgb, depth, stencil);
+        }
+        self.surface.clear(rect, color, color_srgb, depth, stencil)
+    }
+
+    fn get_dimensions(&self) -> (u32, u32) {
+        self.surface.get_dimensions()
+    }
+
+    fn get_depth_buffer_bits(&self) -> Option<u16> {
+        self.surface.get_depth_buffer_bits()
+    }
+
+    fn get_stencil_buffer_bits(&self) -> Option<u16> {
+        self.surface.get_stencil_buffer_bits()
+    }
+
+    fn draw<'a, 'b, V, I, U>(&mut self, vertices: V, indices: I, program: &Program, uniforms: &U, parameters: &DrawParameters<'_>) -> std::result::Result<(), DrawError>
+    where
+        V: MultiVerticesSource<'b>,
+        I: Into<IndicesSource<'a>>,
+        U: Uniforms,
+    {
+        let recorder = self.recorder.as_deref_mut().map(|recorder| (recorder, self.name.as_str()));
+        record_draw(&mut self.surface, recorder, vertices, indices, program, uniforms, parameters)
+    }
+
+    fn blit_color<T: Surface>(&self, source_rect: &Rect, target: &T, target_rect: &BlitTarget, filter: MagnifySamplerFilter) {
+        self.surface.blit_color(source_rect, target, target_rect, filter)
+    }
+
+    fn blit_buffers_from_frame(&self, source_rect: &Rect, target_rect: &BlitTarget, filter: MagnifySamplerFilter, mask: BlitMask) {
+        self.surface.blit_buffers_from_frame(source_rect, target_rect, filter, mask)
+    }
+
+    fn blit_buffers_from_simple_framebuffer(&self, source: &SimpleFrameBuffer, source_rect: &Rect, target_rect: &BlitTarget, filter: MagnifySamplerFilter, mask: BlitMask) {
+        self.surface.blit_buffers_from_simple_framebuffer(source, source_rect, target_rect, filter, mask)
+    }
+
+    fn blit_buffers_from_multioutput_framebuffer(&self, source: &MultiOutputFrameBuffer, source_rect: &Rect, target_rect: &BlitTarget, filter: MagnifySamplerFilter, mask: BlitMask) {
+        self.surface.blit_buffers_from_multioutput_framebuffer(source, source_rect, target_rect, filter, mask)
+    }
+}
+
+/// A traced frame, what frame-NNNNNN.json holds. replay.rs reads it back.
+#[derive(Serialize, Deserialize)]
+pub struct TracedFrame {
+    pub frame: usize,
+    pub dimensions: (u32, u32),
+    /// The passes' framebuffers and their sizes, by the name their ops give.
+    #[serde(default)]
+    pub targets: BTreeMap<String, (u32, u32)>,
+    /// By generation, which is what the ops name them by.
+    pub programs: BTreeMap<String, TracedProgram>,
+    pub ops: Vec<Op>,
+    /// Things that couldn't be recorded.
+    pub problems: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TracedProgram {
+    /// Not every driver gives binaries out, the trace is still readable without one, it just won't replay.
+    pub binary: Option<String>,
+    pub binary_format: Option<u32>,
+    pub attributes: BTreeMap<String, String>,
+    pub uniforms: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Op {
+    Clear {
+        #[serde(default = "frame_target")]
+        target: String,
+        rect: Option<[u32; 4]>,
+        color: Option<[f32; 4]>,
+        srgb: bool,
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    },
+    Draw(Box<TracedDraw>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedDraw {
+    /// `FRAME` or the name of a pass.
+    #[serde(default = "frame_target")]
+    pub target: String,
+    /// The name it's under in `TracedFrame::programs`.
+    pub program: String,
+    pub vertices: Vec<TracedVertices>,
+    pub indices: TracedIndices,
+    pub uniforms: BTreeMap<String, TracedUniform>,
+    pub parameters: TracedParameters,
+    /// What glium said when the draw failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Traces from before passes were traced only have the frame.
+fn frame_target() -> String {
+    FRAME.to_string()
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TracedVertices {
+    Buffer { format: Vec<TracedAttribute>, stride: usize, count: usize, per_instance: bool, data: Option<String> },
+    Marker { len: usize, per_instance: bool },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedAttribute {
+    pub name: String,
+    pub offset: usize,
+    pub location: i32,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub normalize: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TracedIndices {
+    Buffer {
+        primitive: String,
+        #[serde(rename = "type")]
+        ty: String,
+        count: usize,
+        data: Option<String>,
+    },
+    None { primitive: String },
+    Multidraw { primitive: String },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TracedUniform {
+    /// Anything the inspector can hold, `ty` is its `Value::type_name`.
+    Value {
+        #[serde(rename = "type")]
+        ty: String,
+        value: Vec<TracedFloat>,
+    },
+    Uint { value: u32 },
+    Texture2d(TracedTexture),
+    SrgbTexture2d(TracedTexture),
+    DepthTexture2d { size: (u32, u32) },
+    Block,
+    Unsupported,
+}
+
+/// JSON has no inf or nan (serde_json writes them as null), a shader getting one of those is worth
+/// seeing though, so they're spelled out instead.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TracedFloat {
+    Number(f32),
+    Text(String),
+}
+
+impl From<f32> for TracedFloat {
+    fn from(x: f32) -> Self {
+        if x.is_finite() { TracedFloat::Number(x) } else { TracedFloat::Text(x.to_string()) }
+    }
+}
+
+impl TracedFloat {
+    pub fn value(&self) -> f32 {
+        match self {
+            TracedFloat::Number(x) => *x,
+            TracedFloat::Text(text) => text.parse().unwrap_or(f32::NAN),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedTexture {
+    pub size: (u32, u32),
+    /// A png, the right way up.
+    pub data: Option<String>,
+    pub sampler: Option<TracedSampler>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedSampler {
+    pub wrap: [String; 3],
+    pub minify: String,
+    pub magnify: String,
+    pub max_anisotropy: u16,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedParameters {
+    pub depth_test: String,
+    pub depth_write: bool,
+    pub depth_range: (f32, f32),
+    pub blend: TracedBlend,
+    pub color_mask: (bool, bool, bool, bool),
+    pub backface_culling: String,
+    pub polygon_mode: String,
+    pub line_width: Option<f32>,
+    pub point_size: Option<f32>,
+    pub multisampling: bool,
+    pub dithering: bool,
+    pub viewport: Option<[u32; 4]>,
+    pub scissor: Option<[u32; 4]>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedBlend {
+    pub color: TracedBlendingFunction,
+    pub alpha: TracedBlendingFunction,
+    pub constant: (f32, f32, f32, f32),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedBlendingFunction {
+    pub function: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+}
+
+pub fn rect_array(rect: Option<&Rect>) -> Option<[u32; 4]> {
+    rect.map(|rect| [rect.left, rect.bottom, rect.width, rect.height])
+}
+
+pub fn debug_name(value: &impl Debug) -> String {
+    format!("{:?}", value)
+}
+
+pub fn traced_blending_function(function: &BlendingFunction) -> TracedBlendingFunction {
+    match function {
+        BlendingFunction::Addition { source, destination }
+        | BlendingFunction::Subtraction { source, destination }
+        | BlendingFunction::ReverseSubtraction { source, destination } => {
+            let name = debug_name(function);
+            TracedBlendingFunction {
+                function: name.split_whitespace().next().unwrap_or_default().to_string(),
+                source: Some(debug_name(source)),
+                destination: Some(debug_name(destination)),
+            }
+        }
+        other => TracedBlendingFunction { function: debug_name(other), source: None, destination: None },
+    }
+}
+
+fn traced_blend(blend: &Blend) -> TracedBlend {
+    TracedBlend {
+        color: traced_blending_function(&blend.color),
+        alpha: traced_blending_function(&blend.alpha),
+        constant: blend.constant_value,
+    }
+}
+
+pub fn traced_sampler(sampler: SamplerBehavior) -> TracedSampler {
+    let (s, t, r) = sampler.wrap_function;
+    TracedSampler {
+        wrap: [debug_name(&s), debug_name(&t), debug_name(&r)],
+        minify: debug_name(&sampler.minify_filter),
+        magnify: debug_name(&sampler.magnify_filter),
+        max_anisotropy: sampler.max_anisotropy,
+    }
+}
+
+pub fn traced_parameters(parameters: &DrawParameters) -> TracedParameters {
+    let depth = &parameters.depth;
+    TracedParameters {
+        depth_test: debug_name(&depth.test),
+        depth_write: depth.write,
+        depth_range: depth.range,
+        blend: traced_blend(&parameters.blend),
+        color_mask: parameters.color_mask,
+        backface_culling: debug_name(&parameters.backface_culling),
+        polygon_mode: debug_name(&parameters.polygon_mode),
+        line_width: parameters.line_width,
+        point_size: parameters.point_size,
+        multisampling: parameters.multisampling,
+        dithering: parameters.dithering,
+        viewport: rect_array(parameters.viewport.as_ref()),
+        scissor: rect_array(parameters.scissor.as_ref()),
+    }
+}
+
+const GL_COPY_READ_BUFFER: u32 = 0x8F36;
+const GL_BUFFER_SIZE: u32 = 0x8764;
+
+type GetIntegerv = unsafe extern "system" fn(u32, *mut i32);
+type BindBuffer = unsafe extern "system" fn(u32, u32);
+type GetBufferParameteriv = unsafe extern "system" fn(u32, u32, *mut i32);
+type GetBufferSubData = unsafe extern "system" fn(u32, isize, isize, *mut c_void);
+
+/// The contents of a buffer glium only gives out untyped. None when the slice isn't the whole buffer,
+/// glium doesn't say where a slice starts.
+fn read_buffer(display: &Display, slice: &BufferAnySlice) -> Option<Vec<u8>> {
+    let (get_integerv, bind_buffer, get_buffer_parameteriv, get_buffer_sub_data) = unsafe {
+        (
+            gl_function::<GetIntegerv>(display, "glGetIntegerv")?,
+            gl_function::<BindBuffer>(display, "glBindBuffer")?,
+            gl_function::<GetBufferParameteriv>(display, "glGetBufferParameteriv")?,
+            gl_function::<GetBufferSubData>(display, "glGetBufferSubData")?,
+        )
+    };
+
+    let (id, size) = (slice.get_id(), slice.get_size());
+    let mut bytes = vec![0u8; size];
+    let whole = unsafe {
+        display.exec_in_context(|| {
+            // glium caches its bindings, so whatever was bound goes back afterwards.
+            // (GL_COPY_READ_BUFFER_BINDING has the same value as GL_COPY_READ_BUFFER.)
+            let mut previous = 0;
+            get_integerv(GL_COPY_READ_BUFFER, &mut previous);
+            bind_buffer(GL_COPY_READ_BUFFER, id);
+
+            let mut total = 0;
+            get_buffer_parameteriv(GL_COPY_READ_BUFFER, GL_BUFFER_SIZE, &mut total);
+            let whole = total as usize == size;
+            if whole {
+                get_buffer_sub_data(GL_COPY_READ_BUFFER, 0, size as isize, bytes.as_mut_ptr() as *mut c_void);
+            }
+
+            bind_buffer(GL_COPY_READ_BUFFER, previous as u32);
+            whole
+        })
+    };
+    whole.then_some(bytes)
+}
+
+/// Writes down one frame's clears and draws.
+pub struct Recorder {
+    display: Display,
+    dir: PathBuf,
+    frame: usize,
+    dimensions: (u32, u32),
+    targets: BTreeMap<String, (u32, u32)>,
+    ops: Vec<Op>,
+    /// By name, each program is written once per frame.
+    programs: BTreeMap<String, TracedProgram>,
+    /// Things that couldn't be recorded, they end up in the trace.
+    problems: Vec<String>,
+}
+
+impl Recorder {
+    fn new(display: &Display, dir: PathBuf, frame: usize) -> Self {
+        Recorder {
+            display: display.clone(),
+            dir,
+            frame,
+            dimensions: display.get_framebuffer_dimensions(),
+            targets: BTreeMap::new(),
+            ops: Vec::new(),
+            programs: BTreeMap::new(),
+            problems: Vec::new(),
+        }
+    }
+
+    /// Writes `bytes` to blobs/ (unless the same bytes are there already) and returns the path for the trace.
+    fn blob(&mut self, bytes: &[u8], extension: &str, write: impl FnOnce(&PathBuf) -> Result<()>) -> Option<String> {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(bytes);
+        let name = format!("blobs/{:016x}.{}", hasher.finish(), extension);
+        let path = self.dir.join(&name);
+
+        if !path.exists() {
+            let written = fs::create_dir_all(self.dir.join("blobs")).map_err(Error::from).and_then(|_| write(&path));
+            if let Err(error) = written {
+                self.problems.push(error.to_string());
+                return None;
+            }
+        }
+        Some(name)
+    }
+
+    fn bytes_blob(&mut self, bytes: &[u8], extension: &str) -> Option<String> {
+        self.blob(bytes, extension, |path| fs::write(path, bytes).map_err(|error| Error::write(path, error)))
+    }
+
+    fn clear(&mut self, target: &str, rect: Option<&Rect>, color: Option<(f32, f32, f32, f32)>, color_srgb: bool, depth: Option<f32>, stencil: Option<i32>) {
+        self.ops.push(Op::Clear {
+            target: target.to_string(),
+            rect: rect_array(rect),
+            color: color.map(|(r, g, b, a)| [r, g, b, a]),
+            srgb: color_srgb,
+            depth,
+            stencil,
+        });
+    }
+
+    fn program(&mut self, program: &Program) -> String {
+        let id = program_name(program);
+        if self.programs.contains_key(&id) {
+            return id;
+        }
+
+        let attributes = program.attributes().map(|(name, attribute)| (name.clone(), debug_name(&attribute.ty))).collect();
+        let uniforms = program.uniforms().map(|(name, uniform)| (name.clone(), debug_name(&uniform.ty))).collect();
+        let (binary, binary_format) = match program.get_binary() {
+            Ok(binary) => (self.bytes_blob(&binary.content, "bin"), Some(binary.format)),
+            Err(error) => {
+                self.problems.push(format!("program {}: no binary, {}", id, error));
+                (None, None)
+            }
+        };
+
+        self.programs.insert(id.clone(), TracedProgram { binary, binary_format, attributes, uniforms });
+        id
+    }
+
+    fn vertices(&mut self, source: &VerticesSource) -> TracedVertices {
+        match source {
+            VerticesSource::VertexBuffer(slice, format, per_instance) => {
+                let format = format.iter().map(|(name, offset, location, ty, normalize)| TracedAttribute {
+                    name: name.to_string(),
+                    offset: *offset,
+                    location: *location,
+                    ty: debug_name(ty),
+                    normalize: *normalize,
+                }).collect();
+                let data = match read_buffer(&self.display, slice) {
+                    Some(bytes) => self.bytes_blob(&bytes, "bin"),
+                    None => {
+                        self.problems.push("a vertex buffer slice couldn't be read".to_string());
+                        None
+                    }
+                };
+                TracedVertices::Buffer {
+                    format,
+                    stride: slice.get_elements_size(),
+                    count: slice.get_elements_count(),
+                    per_instance: *per_instance,
+                    data,
+                }
+            }
+            VerticesSource::Marker { len, per_instance } => TracedVertices::Marker { len: *len, per_instance: *per_instance },
+        }
+    }
+
+    fn indices(&mut self, indices: &IndicesSource) -> TracedIndices {
+        match indices {
+            IndicesSource::IndexBuffer { buffer, data_type, primitives } => {
+                let data = read_buffer(&self.display, buffer).and_then(|bytes| self.bytes_blob(&bytes, "bin"));
+                TracedIndices::Buffer {
+                    primitive: debug_name(primitives),
+                    ty: debug_name(data_type),
+                    count: buffer.get_elements_count(),
+                    data,
+                }
+            }
+            IndicesSource::NoIndices { primitives } => TracedIndices::None { primitive: debug_name(primitives) },
+            other => {
+                self.problems.push("multidraw indices aren't traced".to_string());
+                TracedIndices::Multidraw { primitive: debug_name(&other.get_primitives_type()) }
+            }
+        }
+    }
+
+    fn texture(&mut self, size: (u32, u32), image: RawImage2d<u8>, sampler: Option<SamplerBehavior>) -> TracedTexture {
+        let mut upright = RgbaImage::from_raw(image.width, image.height, image.data.into_owned()).unwrap_or_default();
+        imageops::flip_vertical_in_place(&mut upright);
+        let data = self.blob(upright.as_raw(), "png", |path| upright.save(path).map_err(|error| Error::write(path, error)));
+        TracedTexture { size, data, sampler: sampler.map(traced_sampler) }
+    }
+
+    fn uniform(&mut self, value: &UniformValue) -> TracedUniform {
+        if let Some(value) = Value::from_uniform(value) {
+            let floats = value.floats().into_iter().map(TracedFloat::from).collect();
+            return TracedUniform::Value { ty: value.type_name().to_string(), value: floats };
+        }
+        match *value {
+            UniformValue::UnsignedInt(value) => TracedUniform::Uint { value },
+            UniformValue::Texture2d(texture, sampler) => {
+                TracedUniform::Texture2d(self.texture(texture.dimensions(), texture.read(), sampler))
+            }
+            UniformValue::SrgbTexture2d(texture, sampler) => {
+                TracedUniform::SrgbTexture2d(self.texture(texture.dimensions(), texture.read(), sampler))
+            }
+            UniformValue::DepthTexture2d(texture, _) => TracedUniform::DepthTexture2d { size: texture.dimensions() },
+            UniformValue::Block(..) => TracedUniform::Block,
+            _ => TracedUniform::Unsupported,
+        }
+    }
+
+    fn draw<U: Uniforms>(&mut self, target: &str, sources: &[VerticesSource], indices: &IndicesSource, program: &Program, uniforms: &U, parameters: &DrawParameters) {
+        let program = self.program(program);
+        let vertices = sources.iter().map(|source| self.vertices(source)).collect();
+        let indices = self.indices(indices);
+
+        let mut values = BTreeMap::new();
+        uniforms.visit_values(|name, value| {
+            let traced = self.uniform(&value);
+            values.insert(name.to_string(), traced);
+        });
+
+        self.ops.push(Op::Draw(Box::new(TracedDraw {
+            target: target.to_string(),
+            program,
+            vertices,
+            indices,
+            uniforms: values,
+            parameters: traced_parameters(parameters),
+            error: None,
+        })));
+    }
+
+    fn draw_result(&mut self, result: &std::result::Result<(), DrawError>) {
+        if let (Err(draw_error), Some(Op::Draw(draw))) = (result, self.ops.last_mut()) {
+            draw.error = Some(draw_error.to_string());
+        }
+    }
+
+    /// Writes the frame's json, returns where.
+    fn save(self) -> Result<PathBuf> {
+        let traced = TracedFrame {
+            frame: self.frame,
+            dimensions: self.dimensions,
+            targets: self.targets,
+            programs: self.programs,
+            ops: self.ops,
+            problems: self.problems,
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("frame-{:06}.json", self.frame));
+        let json = serde_json::to_string_pretty(&traced).map_err(|error| Error::write(&path, error))?;
+        fs::write(&path, json + "\n").map_err(|error| Error::write(&path, error))?;
+        Ok(path)
+    }
+}
+
+/// The runner's tracing state.
+pub struct Tracer {
+    all: bool,
+    next: bool,
+    /// Created the first time a frame gets traced.
+    dir: Option<PathBuf>,
+    frame: usize,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Tracer { all: TRACE_ALL.load(Ordering::Relaxed), next: false, dir: None, frame: 0 }
+    }
+}
+
+impl Tracer {
+    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        if key != TRACE_KEY {
+            return false;
+        }
+        if state == ElementState::Pressed {
+            self.next = true;
+        }
+        true
+    }
+
+    /// A recorder when this frame should be traced.
+    pub fn begin_frame(&mut self, display: &Display) -> Option<Recorder> {
+        let frame = self.frame;
+        self.frame += 1;
+        if !self.all && !self.next {
+            return None;
+        }
+
+        let dir = self.dir.get_or_insert_with(|| capture_dir().join(format!("trace-{}", timestamp())));
+        Some(Recorder::new(display, dir.clone(), frame))
+    }
+
+    pub fn save(&mut self, recorder: Recorder) -> Result<()> {
+        let path = recorder.save()?;
+        if self.next {
+            self.next = false;
+            println!("traced frame to {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floats_round_trip_through_json_even_when_not_finite() {
+        let floats: Vec<TracedFloat> = [1.5, f32::INFINITY, f32::NEG_INFINITY, f32::NAN].into_iter().map(TracedFloat::from).collect();
+        let json = serde_json::to_string(&floats).unwrap();
+        assert_eq!(json, r#"[1.5,"inf","-inf","NaN"]"#);
+
+        let back: Vec<f32> = serde_json::from_str::<Vec<TracedFloat>>(&json).unwrap().iter().map(TracedFloat::value).collect();
+        assert_eq!(back[..3], [1.5, f32::INFINITY, f32::NEG_INFINITY]);
+        assert!(back[3].is_nan());
+    }
+
+    #[test]
+    fn a_rebuilt_program_gets_a_new_generation_under_the_same_name() {
+        // far from any name GL would hand out.
+        let key = u64::MAX - 1;
+        let first = register(key);
+        assert_eq!(generation(key), first);
+
+        let second = register(key);
+        assert!(second > first);
+        assert_eq!(generation(key), second);
+    }
+
+    #[test]
+    fn unregistered_programs_keep_the_generation_they_are_first_given() {
+        let key = u64::MAX - 2;
+        let given = generation(key);
+        assert_eq!(generation(key), given);
+    }
+
+    #[test]
+    fn ops_say_which_target_they_went_to() {
+        let op = Op::Clear { target: "shadow-map".to_string(), rect: None, color: None, srgb: false, depth: Some(1.0), stencil: None };
+        let json = serde_json::to_string(&op).unwrap();
+        assert_eq!(json, r#"{"op":"clear","target":"shadow-map","rect":null,"color":null,"srgb":false,"depth":1.0,"stencil":null}"#);
+    }
+
+    #[test]
+    fn ops_without_a_target_went_to_the_frame() {
+        let json = r#"{"op":"clear","rect":null,"color":[0.0,0.0,0.0,1.0],"srgb":false,"depth":null,"stencil":null}"#;
+        match serde_json::from_str(json).unwrap() {
+            Op::Clear { target, .. } => assert_eq!(target, FRAME),
+            Op::Draw(_) => panic!("a clear came back as a draw"),
+        }
+
+        let frame: TracedFrame = serde_json::from_str(r#"{"frame":0,"dimensions":[4,4],"programs":{},"ops":[],"problems":[]}"#).unwrap();
+        assert!(frame.targets.is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    f32::consts::TAU,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use cgmath::{vec3, Vector3};
+
+// Small helpers that don't belong to any one stage or subsystem.
+
+/// A UTC calendar date and the time of day.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CivilDate {
+    pub year: i64,
+    /// 1 to 12.
+    pub month: u32,
+    /// 1 to 31.
+    pub day: u32,
+    pub seconds_of_day: u64,
+    pub millis: u32,
+}
+
+/// `time` as a UTC date, times before 1970 clamp to the epoch.
+pub fn civil_date(time: SystemTime) -> CivilDate {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let days = (since_epoch.as_secs() / 86_400) as i64;
+
+    // days since 1970 to a civil date, http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    CivilDate {
+        year,
+        month: month as u32,
+        day: day as u32,
+        seconds_of_day: since_epoch.as_secs() % 86_400,
+        millis: since_epoch.subsec_millis(),
+    }
+}
+
+/// A small, seedable random number generator. The same PCG hash as the path tracing shader (stage 15).
+pub struct Rng(u32);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Rng(pcg_hash(seed | 1))
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        self.0 = pcg_hash(self.0);
+        unit_f32(self.0)
+    }
+
+    pub fn unit_vector(&mut self) -> Vector3<f32> {
+        let z = self.next_f32() * 2.0 - 1.0;
+        let a = self.next_f32() * TAU;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        vec3(r * a.cos(), r * a.sin(), z)
+    }
+}
+
+/// The top 24 bits of `bits` as a float in [0, 1), all of them fit in the mantissa so it never rounds up to 1.
+fn unit_f32(bits: u32) -> f32 {
+    (bits >> 8) as f32 / 16777216.0
+}
+
+fn pcg_hash(v: u32) -> u32 {
+    let state = v.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(seconds: u64) -> CivilDate {
+        civil_date(UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn civil_date_of_known_days() {
+        assert_eq!(at(0), CivilDate { year: 1970, month: 1, day: 1, seconds_of_day: 0, millis: 0 });
+        // the day after a leap day, and a leap day in a year divisible by 400.
+        assert_eq!((at(951_868_800).year, at(951_868_800).month, at(951_868_800).day), (2000, 3, 1));
+        assert_eq!((at(951_782_400).year, at(951_782_400).month, at(951_782_400).day), (2000, 2, 29));
+        let date = at(1_709_210_096);
+        assert_eq!((date.year, date.month, date.day, date.seconds_of_day), (2024, 2, 29, 45_296));
+    }
+
+    #[test]
+    fn rng_is_deterministic_per_seed() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..16 {
+            let (x, y) = (a.next_f32(), b.next_f32());
+            assert_eq!(x, y);
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+
+    #[test]
+    fn the_largest_hash_is_still_below_one() {
+        assert!(unit_f32(u32::MAX) < 1.0);
+        assert_eq!(unit_f32(0), 0.0);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    io::{stdout, Write},
+    process,
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::error::Result;
+use crate::glium_book::{self, params::{Overrides, Params}};
+use crate::learn_wgpu;
+
+// What you get when running without --tutorial: a list of every tutorial and its stages, arrows to pick
+// one, enter to run it. Stages run in a child process (this same binary with --tutorial/--stage), winit
+// only allows one event loop per process, so that's the only way to get back here once the window closes.
+
+/// A line in the menu, either a tutorial heading or one of its stages.
+enum Item {
+    Tutorial(&'static str),
+    Stage(&'static glium_book::Stage),
+}
+
+fn items() -> Vec<Item> {
+    let mut items = vec![Item::Tutorial(glium_book::GLIUM)];
+    items.extend(glium_book::STAGES.iter().map(Item::Stage));
+    // no stages yet, it's listed so it isn't forgotten about.
+    items.push(Item::Tutorial(learn_wgpu::LEARN_WGPU));
+    items
+}
+
+struct Launcher<'a> {
+    items: Vec<Item>,
+    selected: usize,
+    overrides: &'a Overrides,
+    /// Pass `--term` on to the stages.
+    term: bool,
+    /// How the last stage went, shown under the list.
+    status: String,
+}
+
+impl<'a> Launcher<'a> {
+    fn selected_stage(&self) -> Option<&'static glium_book::Stage> {
+        match self.items[self.selected] {
+            Item::Stage(stage) => Some(stage),
+            Item::Tutorial(_) => None,
+        }
+    }
+
+    /// Moves the selection by `step`, skipping over headings.
+    fn select(&mut self, step: isize) {
+        let count = self.items.len() as isize;
+        let mut index = self.selected as isize;
+        for _ in 0..count {
+            index = (index + step).rem_euclid(count);
+            if matches!(self.items[index as usize], Item::Stage(_)) {
+                self.selected = index as usize;
+                return;
+            }
+        }
+    }
+
+    fn draw(&self) -> Result<()> {
+        let mut out = stdout();
+        queue!(out, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+        queue!(out, Print("up/down to pick a stage, enter to run it, q to quit\r\n\r\n"))?;
+
+        for (index, item) in self.items.iter().enumerate() {
+            match item {
+                Item::Tutorial(name) => {
+                    queue!(out, SetAttribute(Attribute::Bold), Print(name), SetAttribute(Attribute::Reset))?;
+                    if *name == learn_wgpu::LEARN_WGPU {
+                        queue!(out, Print("  (no stages yet)"))?;
+                    }
+                }
+                Item::Stage(stage) => {
+                    if index == self.selected {
+                        queue!(out, SetAttribute(Attribute::Reverse))?;
+                    }
+                    queue!(out, Print(format!("  {:2}  {}", stage.number, stage.title)), SetAttribute(Attribute::Reset))?;
+                }
+            }
+            queue!(out, Print("\r\n"))?;
+        }
+
+        if let Some(stage) = self.selected_stage() {
+            let mut params = Params::new((stage.params)());
+            let description = match params.apply(self.overrides) {
+                Ok(()) => params.describe(),
+                Err(error) => format!("  {}", error),
+            };
+            queue!(out, Print(format!("\r\nparameters of stage {}:\r\n", stage.number)))?;
+            for line in description.lines() {
+                queue!(out, Print(line), Print("\r\n"))?;
+            }
+        }
+
+        if !self.status.is_empty() {
+            queue!(out, Print("\r\n"), Print(&self.status), Print("\r\n"))?;
+        }
+
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Leaves the menu, runs the stage to completion and says how it went.
+    fn launch(&mut self, stage: &glium_book::Stage) -> Result<()> {
+        leave()?;
+
+        let mut command = process::Command::new(std::env::current_exe()?);
+        command.args(["--tutorial", glium_book::GLIUM, "--stage", &stage.number.to_string()]);
+        for file in &self.overrides.files {
+            command.arg("--config").arg(file);
+        }
+        for set in &self.overrides.sets {
+            command.args(["--set", set]);
+        }
+        if self.term {
+            command.arg("--term");
+        }
+        let status = command.status();
+
+        enter()?;
+        self.status = match status {
+            Ok(status) if status.success() => format!("stage {} closed", stage.number),
+            Ok(status) => format!("stage {} failed ({}), quit the menu to see what it printed", stage.number, status),
+            Err(error) => format!("couldn't start stage {}: {}", stage.number, error),
+        };
+        Ok(())
+    }
+}
+
+fn enter() -> Result<()> {
+    terminal::enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen, cursor::Hide)?;
+    Ok(())
+}
+
+fn leave() -> Result<()> {
+    execute!(stdout(), cursor::Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    Ok(())
+}
+
+pub fn run(overrides: &Overrides, term: bool) -> Result<()> {
+    let mut launcher = Launcher { items: items(), selected: 0, overrides, term, status: String::new() };
+    launcher.select(1);
+
+    enter()?;
+    let result = menu(&mut launcher);
+    leave()?;
+    result
+}
+
+fn menu(launcher: &mut Launcher) -> Result<()> {
+    loop {
+        launcher.draw()?;
+        if let Event::Key(key) = event::read()? {
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => launcher.select(-1),
+                KeyCode::Down | KeyCode::Char('j') => launcher.select(1),
+                KeyCode::Enter => {
+                    if let Some(stage) = launcher.selected_stage() {
+                        launcher.launch(stage)?;
+                    }
+                }
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(()),
+                // raw mode swallows ctrl-c, so it's just another key.
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                _ => (),
+            }
+        }
+    }
+}
@@ -5,21 +5,36 @@ use crossterm::{
     style::Print,
 };
 
+use crate::error::{Error, Result};
+
 mod stage1;
 
 const LATEST_COMPLETED_STAGE: usize = 0;
 
 pub const LEARN_WGPU: &str = "lwgpu";
 
-pub fn entrypoint(stage: Option<usize>) {
-    if stage.is_none() {
-        todo!("haven't even started yet! patience...");
+pub fn entrypoint(stage: Option<usize>) -> Result<()> {
+    match stage {
+        // haven't even started yet! patience...
+        None => Err(Error::NotImplemented(format!("the {} tutorial", LEARN_WGPU))),
         // execute!(stdout(), Print(format!("defaulting to latest completed stage: {}", LATEST_COMPLETED_STAGE))).ok();
-    } else {
-        let which_stage = stage.unwrap();
-        execute!(stdout(), Print(format!("doing glium tutorial stage {}! :D", stage.unwrap()))).ok();
-        match which_stage {
-            _ => todo!("I'M WORKING ON IT!"),
+        Some(which_stage) => {
+            execute!(stdout(), Print(format!("doing glium tutorial stage {}! :D", which_stage))).ok();
+            Err(Error::UnknownStage { tutorial: LEARN_WGPU.to_string(), stage: which_stage })
         }
     }
-}
\ No newline at end of file
+}
+
+/// The adapters wgpu finds, for `--info`.
+pub fn print_adapters() {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapters: Vec<wgpu::AdapterInfo> = instance.enumerate_adapters(wgpu::Backends::all()).map(|adapter| adapter.get_info()).collect();
+
+    println!("wgpu adapters");
+    if adapters.is_empty() {
+        println!("  none found");
+    }
+    for info in adapters {
+        println!("  {} ({:?}, {:?}, vendor {:#06x} device {:#06x})", info.name, info.backend, info.device_type, info.vendor, info.device);
+    }
+}
@@ -1,5 +1,7 @@
 
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
 use crossterm::{
     ExecutableCommand,
     terminal::{
@@ -9,34 +11,178 @@ use crossterm::{
     cursor,
 };
 
+mod error;
 mod glium_book;
+mod launcher;
 mod learn_wgpu;
 
+use error::{Error, Result};
+
 /// Collective main entrypoint for running different graphics programming tutorial stages
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Arg {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Which tutorial to run.
+    /// leave it out to pick a stage from a menu instead.
     #[clap(short, long)]
-    tutorial: String,
+    tutorial: Option<String>,
 
     /// identify stage to run in given tutorial.
     /// defaults to latest completed stage for given tutorial.
     #[clap(short, long)]
-    stage: Option<usize>
+    stage: Option<usize>,
+
+    /// override a stage parameter, e.g. `--set fov=75 --set light=-1,0.8,0.9`.
+    #[clap(long = "set", value_name = "NAME=VALUE", multiple_occurrences = true, global = true)]
+    sets: Vec<String>,
+
+    /// load stage parameters from a .toml or .ron file, `--set`s are applied after it.
+    #[clap(long = "config", value_name = "FILE", multiple_occurrences = true, global = true)]
+    configs: Vec<PathBuf>,
+
+    /// draw the stage into this terminal with colored half blocks instead of opening a window.
+    #[clap(long, global = true)]
+    term: bool,
+
+    /// write every frame's draw calls to captures/trace-<time>/ (F8 traces just the next one).
+    #[clap(long, global = true)]
+    trace: bool,
+
+    /// write every key and window event the stage gets, and when each frame was drawn, to FILE.
+    #[clap(long, value_name = "FILE", global = true)]
+    record_input: Option<PathBuf>,
+
+    /// run the stage hidden with the input recorded in FILE instead of live input, then save its last frame
+    /// next to FILE as a png. use the same --set / --config as when recording.
+    #[clap(long, value_name = "FILE", global = true, conflicts_with = "record-input")]
+    play_input: Option<PathBuf>,
+
+    /// print what the GL context (and wgpu) can do on this machine and exit.
+    #[clap(long)]
+    info: bool,
+
+    /// print the parameters of the stage (with overrides applied) instead of running it.
+    #[clap(long)]
+    describe: bool,
 }
 
-fn main() -> crossterm::Result<()>{
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a standalone Shadertoy style fragment shader (one with a `mainImage`) on a fullscreen quad.
+    Shader {
+        /// The image pass.
+        file: PathBuf,
+
+        /// Buffer A pass, rendered before the image pass. Bind it with `// iChannelN: buffer-a`.
+        #[clap(long)]
+        buffer_a: Option<PathBuf>,
+
+        /// Buffer B pass.
+        #[clap(long)]
+        buffer_b: Option<PathBuf>,
+
+        /// Buffer C pass.
+        #[clap(long)]
+        buffer_c: Option<PathBuf>,
+
+        /// Buffer D pass.
+        #[clap(long)]
+        buffer_d: Option<PathBuf>,
+    },
+
+    /// Run a glium stage in a hidden window as fast as it can draw and write its frame times to JSON or CSV.
+    Bench {
+        /// The stage to measure.
+        stage: usize,
+
+        /// How many frames to measure.
+        #[clap(long, default_value_t = 500)]
+        frames: usize,
+
+        /// Frames drawn before measuring starts.
+        #[clap(long, default_value_t = 10)]
+        warmup: usize,
+
+        /// Where the results go, `.csv` for CSV, anything else is JSON. defaults to bench-glium-stageN.json.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Draw a traced frame (a frame-NNNNNN.json from --trace / F8) again offscreen and save it as a png.
+    Replay {
+        /// The frame's json.
+        frame: PathBuf,
+
+        /// Where the png goes, defaults to next to the frame as frame-NNNNNN.replay.png.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Compare with a png or another traced frame, which also lists what changed in the draw calls.
+        #[clap(long)]
+        diff: Option<PathBuf>,
+    },
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     std::io::stdout().execute(Clear(ClearType::All))?;
     std::io::stdout().execute(cursor::MoveTo(0,0))?;
 
     let args = Arg::parse();
 
-    match args.tutorial.to_lowercase().as_str() {
-        glium_book::GLIUM => glium_book::entrypoint(args.stage),
-        learn_wgpu::LEARN_WGPU => glium_book::entrypoint(args.stage),
-        _ => panic!("That's not even a tutorial! For God's Sake!..")
-    };
+    // RUST_LOG=gl=debug for everything the driver says, warnings and errors show up by default.
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    if args.info {
+        glium_book::context::print_info()?;
+        learn_wgpu::print_adapters();
+        return Ok(());
+    }
 
-    Ok(())
+    if args.term {
+        glium_book::term::enable();
+    }
+    if args.trace {
+        glium_book::trace::enable_all();
+    }
+    if let Some(path) = args.record_input {
+        glium_book::input::record(path);
+    }
+    if let Some(path) = args.play_input {
+        glium_book::input::play(path);
+    }
+
+    let overrides = glium_book::params::Overrides { files: args.configs, sets: args.sets };
+
+    match args.command {
+        Some(Command::Shader { file, buffer_a, buffer_b, buffer_c, buffer_d }) => {
+            return glium_book::shadertoy::run(file, [buffer_a, buffer_b, buffer_c, buffer_d]);
+        }
+        Some(Command::Bench { stage, frames, warmup, output }) => {
+            return glium_book::bench(stage, &overrides, frames, warmup, output);
+        }
+        Some(Command::Replay { frame, output, diff }) => {
+            return glium_book::replay::run(&frame, output, diff);
+        }
+        None => (),
+    }
+
+    let tutorial = match args.tutorial {
+        Some(tutorial) => tutorial,
+        None => return launcher::run(&overrides, args.term),
+    };
+    match tutorial.to_lowercase().as_str() {
+        glium_book::GLIUM => glium_book::entrypoint(args.stage, &overrides, args.describe),
+        learn_wgpu::LEARN_WGPU => learn_wgpu::entrypoint(args.stage),
+        _ => Err(Error::UnknownTutorial(tutorial)),
+    }
 }